@@ -312,6 +312,7 @@ impl App {
                 width,
                 cap: beamline::LineCap::Round,
                 color: beamline::Color::new(0.9, 0.4, 0.4, alpha),
+                ..Default::default()
             },
         );
         self.beamline_renderer().borrow_mut().line(
@@ -320,6 +321,7 @@ impl App {
                 width,
                 cap: beamline::LineCap::Square,
                 color: beamline::Color::new(0.4, 0.9, 0.4, alpha),
+                ..Default::default()
             },
         );
         self.beamline_renderer().borrow_mut().line(
@@ -328,6 +330,7 @@ impl App {
                 width,
                 cap: beamline::LineCap::Butt,
                 color: beamline::Color::new(0.4, 0.4, 0.9, alpha),
+                ..Default::default()
             },
         );
         let frac = (tsec.sin() as f32 + 1.0) / 2.0;
@@ -340,6 +343,7 @@ impl App {
                 width: 30.0,
                 cap: beamline::LineCap::Round,
                 color: beamline::Color::new(0.9, 0.4, 0.9, alpha),
+                ..Default::default()
             },
         );
         self.beamline_renderer().borrow_mut().set_draw_tiles(true);
@@ -1,5 +1,5 @@
-#[allow(unused)] // TODO: Development.
 pub mod pushbuf;
+pub mod scan;
 #[allow(unused)] // TODO: Development.
 pub mod stack;
 #[allow(unused)] // TODO: Development.
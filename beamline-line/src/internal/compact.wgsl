@@ -0,0 +1,73 @@
+/**
+ * NAME:    compact.wgsl
+ * PURPOSE: Stream compaction of the non-zero values in an array of `u32`.
+ */
+
+/**
+ * Compaction writes the indices of the non-zero values of `input`, in
+ * order, to `output`:
+ *
+ *  1. `flag_nonzero` writes a one to `positions` for each non-zero value,
+ *     and a zero for each zero value.
+ *  2. `positions` is scanned (see `scan.wgsl`), which gives the position of
+ *     each non-zero value in the output. The total of the scan is the number
+ *     of non-zero values.
+ *  3. `scatter_nonzero` writes the index of each non-zero value to its
+ *     position in `output`.
+ */
+
+/**** BINDINGS ****/
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> input: array<u32>;
+@group(0) @binding(2) var<storage, read_write> positions: array<u32>;
+@group(0) @binding(3) var<storage, read_write> output: array<u32>;
+
+/**** STRUCTS ****/
+
+struct Params {
+    /// Number of values in `input`.
+    len : u32
+};
+
+/// Number of invocations in a workgroup.
+const WORKGROUP_SIZE: u32 = 256;
+
+/**** ENTRY POINTS ****/
+
+/// Flags the non-zero values of the input.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn flag_nonzero(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>
+) {
+    let i = invocation_index(global_id, num_workgroups);
+    if (i < params.len) {
+        positions[i] = u32(input[i] != 0);
+    }
+}
+
+/// Writes the indices of the non-zero values of the input to the output.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn scatter_nonzero(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>
+) {
+    let i = invocation_index(global_id, num_workgroups);
+    if (i < params.len && input[i] != 0) {
+        output[positions[i]] = i;
+    }
+}
+
+/**** PURE FUNCTIONS ****/
+
+/// Computes the linear index of an invocation.
+///
+/// Large arrays need more workgroups than fit in one dimension of a
+/// dispatch, so the workgroups are laid out in two dimensions.
+fn invocation_index(
+    global_id: vec3<u32>,
+    num_workgroups: vec3<u32>
+) -> u32 {
+    return global_id.y * num_workgroups.x * WORKGROUP_SIZE + global_id.x;
+}
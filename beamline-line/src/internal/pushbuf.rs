@@ -1,6 +1,5 @@
 use bytemuck::{bytes_of, NoUninit};
 use core::{marker::PhantomData, num::NonZero};
-use std::sync::Arc;
 use wgpu::{
     util::StagingBelt, Buffer, BufferAddress, BufferUsages, BufferViewMut,
     CommandBuffer, CommandEncoder, CommandEncoderDescriptor, Device,
@@ -49,9 +48,8 @@ use wgpu::{
 ///    frame to be mapped to host memory as soon as the frame begins
 ///    processing.
 ///
+#[derive(Debug)]
 pub struct PushBuf<T> {
-    /// WGPU Device.
-    device: Arc<Device>,
     /// Command encoder for a frame. Between frames, this will be `None`.
     encoder: Option<CommandEncoder>,
     /// WGPU Buffer we ultimately copy our values into.
@@ -94,7 +92,7 @@ where
     /// - `chunk_item_capacity`: Number of items of type `T` that can fit
     ///   in the staging buffer.
     pub fn new(
-        device: Arc<Device>,
+        device: &Device,
         label: Option<&str>,
         usage: BufferUsages,
         buffer_item_capacity: usize,
@@ -105,10 +103,9 @@ where
         debug_assert!(chunk_item_capacity <= buffer_item_capacity);
 
         PushBuf {
-            device: device.clone(),
             encoder: None,
             buffer: create_buffer::<T>(
-                device.clone(),
+                device,
                 label,
                 usage,
                 buffer_item_capacity,
@@ -137,23 +134,37 @@ where
         self.item_count
     }
 
+    /// Returns `true` if no items have been pushed to the buffer in the
+    /// current frame.
+    pub fn is_empty(&self) -> bool {
+        self.item_count == 0
+    }
+
+    /// Returns the number of items of type `T` that can fit in the buffer.
+    pub fn capacity(&self) -> usize {
+        self.buffer_item_capacity
+    }
+
     /// Begins rendering a frame.
     ///
     /// This should be called at the start of rendering a frame. Internally,
     /// it creates a WGPU `CommandEncoder` to manage the buffer operations
     /// for this frame.
-    pub fn begin_frame(&mut self) {
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    pub fn begin_frame(&mut self, device: &Device) {
         #[cfg(debug_assertions)]
         {
             debug_assert!(self.state == State::Created);
             self.check_state();
         }
 
-        self.encoder = Some(self.device.create_command_encoder(
-            &CommandEncoderDescriptor {
+        self.encoder =
+            Some(device.create_command_encoder(&CommandEncoderDescriptor {
                 label: Some("PushBuf command encoder."),
-            },
-        ));
+            }));
 
         #[cfg(debug_assertions)]
         {
@@ -169,8 +180,9 @@ where
     ///
     /// # Parameters
     ///
+    /// - `device`: WGPU Device.
     /// - `value`: The value to append to the buffer.
-    pub fn push(&mut self, value: T) -> Result<(), Error> {
+    pub fn push(&mut self, device: &Device, value: T) -> Result<(), Error> {
         #[cfg(debug_assertions)]
         {
             debug_assert!(self.state == State::InFrame);
@@ -184,7 +196,7 @@ where
 
         // If there is no current staging belt buffer view, create one.
         if self.view.is_none() {
-            self.create_view();
+            self.create_view(device);
         }
 
         // Write the bytes of the value into the staging belt buffer view.
@@ -267,7 +279,7 @@ where
     ///
     /// This requests a staging buffer from the staging belt, and casts it
     /// to a `BufferViewMut<'static>`, into which we can write bytes.
-    fn create_view(&mut self) {
+    fn create_view(&mut self, device: &Device) {
         #[cfg(debug_assertions)]
         {
             debug_assert!(self.state == State::InFrame);
@@ -290,7 +302,7 @@ where
             &self.buffer,
             self.buffer_byte_offset as BufferAddress,
             chunk_size,
-            &self.device,
+            device,
         );
 
         // SAFETY:
@@ -328,7 +340,7 @@ where
     /// Writes `value` into the current view at the current offset.
     fn write_view(&mut self, value: T) {
         debug_assert!(self.view.is_some());
-        debug_assert!(self.chunk_size_bytes() % size_of::<T>() == 0);
+        debug_assert_eq!(self.chunk_size_bytes() % size_of::<T>(), 0);
         debug_assert!(self.view_byte_offset < self.chunk_size_bytes());
         debug_assert!(self.buffer_byte_offset < self.buffer_size_bytes());
         debug_assert!(self.item_count < self.buffer_item_capacity);
@@ -388,7 +400,7 @@ pub enum Error {
 
 /// Creates the main WGPU buffer.
 fn create_buffer<T>(
-    device: Arc<Device>,
+    device: &Device,
     label: Option<&str>,
     usage: BufferUsages,
     buffer_item_capacity: usize,
@@ -428,7 +440,7 @@ mod tests {
     use futures::{channel::oneshot, executor::block_on, future::try_join_all};
     use proptest::prelude::*;
     use rand::prelude::*;
-    use wgpu::{util::DownloadBuffer, BufferSlice, Maintain, MapMode};
+    use wgpu::{BufferSlice, Maintain, MapMode};
 
     proptest! {
         #![proptest_config(ProptestConfig::with_cases(50))]
@@ -458,7 +470,7 @@ mod tests {
             // Set up the GPU for this test.
             let gpu = Gpu::new();
             let mut pushbuf = PushBuf::<u64>::new(
-                gpu.device.clone(),
+                &gpu.device,
                 Some("Test PushBuf"),
                 BufferUsages::COPY_SRC,
                 buffer_item_capacity,
@@ -477,7 +489,7 @@ mod tests {
             let out_buffers: Vec<Buffer> =
                 (0..n_frames)
                     .map(|i| create_buffer::<u64>(
-                        gpu.device.clone(),
+                        &gpu.device,
                         Some(&format!("Test Output Buffer {}", i)),
                         BufferUsages::MAP_READ,
                         n_items
@@ -487,12 +499,12 @@ mod tests {
             // copying the data to the `out_buffers[i]` for each frame.
             for frame in 0..n_frames {
                 // Put the data into the push buffer.
-                pushbuf.begin_frame();
+                pushbuf.begin_frame(&gpu.device);
                 let frame_data = &in_data[frame];
                 frame_data
                     .iter()
                     .for_each(|x| {
-                        let result = pushbuf.push(*x);
+                        let result = pushbuf.push(&gpu.device, *x);
                         assert_eq!(result, Ok(()));
                     });
                 let command_buffer = pushbuf.end_frame();
@@ -533,7 +545,10 @@ mod tests {
                         slice
                             .map_async(
                                 MapMode::Read,
-                                |result| sender.send(result.unwrap()).unwrap()
+                                |result| {
+                                    result.unwrap();
+                                    sender.send(()).unwrap()
+                                }
                             );
                         receiver
                     })
@@ -0,0 +1,496 @@
+use bytemuck::{bytes_of, Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer,
+    BufferAddress, BufferBindingType, BufferDescriptor, BufferUsages,
+    ComputePass, ComputePipeline, ComputePipelineDescriptor, Device,
+    PipelineCompilationOptions, PipelineLayoutDescriptor,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages,
+};
+
+/// Number of values scanned by each workgroup.
+///
+/// This must match `BLOCK_SIZE` in `scan.wgsl`.
+const BLOCK_SIZE: u32 = 512;
+
+/// Number of invocations in a workgroup of the compaction passes.
+///
+/// This must match `WORKGROUP_SIZE` in `compact.wgsl`.
+const COMPACT_WORKGROUP_SIZE: u32 = 256;
+
+/// Exclusive prefix sum (scan) of a GPU buffer of `u32` values.
+///
+/// The scan replaces each value by the sum of the values before it, and
+/// computes the total of all the values. Sums wrap around on overflow.
+///
+/// Each workgroup scans a block of values with a work-efficient (Blelloch)
+/// scan. When there is more than one block, the totals of the blocks are
+/// scanned in turn, over as many levels as are needed to reach a single
+/// block, and the scanned totals are added back onto the blocks below. The
+/// buffers for the levels are allocated when the `Scan` is created, so a
+/// `Scan` is created for a particular buffer and length, and can then be
+/// re-used for every frame.
+#[derive(Debug)]
+pub struct Scan {
+    /// Levels of the scan, starting from the data itself and ending with a
+    /// level which has a single block.
+    levels: Vec<Level>,
+    scan_blocks: ComputePipeline,
+    add_block_offsets: ComputePipeline,
+    /// Maximum number of workgroups in a dimension of a dispatch.
+    max_workgroups: u32,
+}
+
+/// A level of a [`Scan`].
+#[derive(Debug)]
+struct Level {
+    /// Number of blocks in the level.
+    n_blocks: u32,
+    /// Totals of the blocks, which are the data of the next level.
+    block_sums: Buffer,
+    bind_group: BindGroup,
+}
+
+impl Scan {
+    /// Creates a new `Scan`.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `data`: Buffer of `u32` values to scan in place. It must have the
+    ///   `STORAGE` usage.
+    /// - `len`: Number of values to scan, from the start of the buffer.
+    pub fn new(device: &Device, data: &Buffer, len: usize) -> Self {
+        assert!(len > 0);
+        assert!(len * size_of::<u32>() <= data.size() as usize);
+        let mut len = u32::try_from(len).expect("too many values to scan");
+
+        let layout = create_bind_group_layout(
+            device,
+            "Scan bind group layout",
+            &[BufferBindingType::Uniform, storage(false), storage(false)],
+        );
+        let mut levels: Vec<Level> = Vec::new();
+        loop {
+            let n_blocks = len.div_ceil(BLOCK_SIZE);
+            let block_sums = device.create_buffer(&BufferDescriptor {
+                label: Some("Scan block sums"),
+                size: (n_blocks as usize * size_of::<u32>()) as BufferAddress,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let level_data = levels.last().map_or(data, |l| &l.block_sums);
+            let bind_group = create_bind_group(
+                device,
+                "Scan bind group",
+                &layout,
+                &[&create_params(device, len), level_data, &block_sums],
+            );
+            levels.push(Level {
+                n_blocks,
+                block_sums,
+                bind_group,
+            });
+            if n_blocks == 1 {
+                break;
+            }
+            len = n_blocks;
+        }
+
+        let create_pipeline = create_pipelines(
+            device,
+            "Scan",
+            include_str!("scan.wgsl"),
+            &layout,
+        );
+        Scan {
+            levels,
+            scan_blocks: create_pipeline("scan_blocks"),
+            add_block_offsets: create_pipeline("add_block_offsets"),
+            max_workgroups: device
+                .limits()
+                .max_compute_workgroups_per_dimension,
+        }
+    }
+
+    /// Returns a buffer containing the total of the values, as a single
+    /// `u32`, once the scan has run.
+    pub fn total(&self) -> &Buffer {
+        &self.levels.last().unwrap().block_sums
+    }
+
+    /// Records the scan in a compute pass.
+    ///
+    /// # Parameters
+    ///
+    /// - `pass`: The compute pass.
+    pub fn encode(&self, pass: &mut ComputePass<'_>) {
+        pass.set_pipeline(&self.scan_blocks);
+        for level in &self.levels {
+            pass.set_bind_group(0, &level.bind_group, &[]);
+            dispatch(pass, level.n_blocks, self.max_workgroups);
+        }
+        // The top level has a single block, so it has nothing to add.
+        pass.set_pipeline(&self.add_block_offsets);
+        for level in self.levels.iter().rev().skip(1) {
+            pass.set_bind_group(0, &level.bind_group, &[]);
+            dispatch(pass, level.n_blocks, self.max_workgroups);
+        }
+    }
+}
+
+/// Stream compaction of a GPU buffer of `u32` values.
+///
+/// Compaction writes the indices of the non-zero values of the input, in
+/// order, to the start of the output, and counts them. The values can then
+/// be gathered through the indices. It uses a [`Scan`] to find the position
+/// of each index in the output.
+#[derive(Debug)]
+pub struct Compact {
+    /// Scan of the flags of the non-zero values.
+    scan: Scan,
+    bind_group: BindGroup,
+    flag_nonzero: ComputePipeline,
+    scatter_nonzero: ComputePipeline,
+    /// Number of workgroups in each pass.
+    n_workgroups: u32,
+    /// Maximum number of workgroups in a dimension of a dispatch.
+    max_workgroups: u32,
+}
+
+impl Compact {
+    /// Creates a new `Compact`.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `input`: Buffer of `u32` values to compact. It must have the
+    ///   `STORAGE` usage.
+    /// - `output`: Buffer to write the indices of the non-zero values to.
+    ///   It must have the `STORAGE` usage, and room for `len` values.
+    /// - `len`: Number of values to compact, from the start of `input`.
+    pub fn new(
+        device: &Device,
+        input: &Buffer,
+        output: &Buffer,
+        len: usize,
+    ) -> Self {
+        assert!(len > 0);
+        assert!(len * size_of::<u32>() <= input.size() as usize);
+        assert!(len * size_of::<u32>() <= output.size() as usize);
+
+        let positions = device.create_buffer(&BufferDescriptor {
+            label: Some("Compaction positions"),
+            size: (len * size_of::<u32>()) as BufferAddress,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let scan = Scan::new(device, &positions, len);
+
+        let len = len as u32;
+        let layout = create_bind_group_layout(
+            device,
+            "Compaction bind group layout",
+            &[
+                BufferBindingType::Uniform,
+                storage(true),
+                storage(false),
+                storage(false),
+            ],
+        );
+        let bind_group = create_bind_group(
+            device,
+            "Compaction bind group",
+            &layout,
+            &[&create_params(device, len), input, &positions, output],
+        );
+        let create_pipeline = create_pipelines(
+            device,
+            "Compaction",
+            include_str!("compact.wgsl"),
+            &layout,
+        );
+        Compact {
+            scan,
+            bind_group,
+            flag_nonzero: create_pipeline("flag_nonzero"),
+            scatter_nonzero: create_pipeline("scatter_nonzero"),
+            n_workgroups: len.div_ceil(COMPACT_WORKGROUP_SIZE),
+            max_workgroups: device
+                .limits()
+                .max_compute_workgroups_per_dimension,
+        }
+    }
+
+    /// Returns a buffer containing the number of non-zero values, as a
+    /// single `u32`, once the compaction has run.
+    pub fn count(&self) -> &Buffer {
+        self.scan.total()
+    }
+
+    /// Records the compaction in a compute pass.
+    ///
+    /// # Parameters
+    ///
+    /// - `pass`: The compute pass.
+    pub fn encode(&self, pass: &mut ComputePass<'_>) {
+        pass.set_pipeline(&self.flag_nonzero);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        dispatch(pass, self.n_workgroups, self.max_workgroups);
+        self.scan.encode(pass);
+        pass.set_pipeline(&self.scatter_nonzero);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        dispatch(pass, self.n_workgroups, self.max_workgroups);
+    }
+}
+
+/// Parameters of the scan and compaction shaders.
+///
+/// This must match `Params` in `scan.wgsl` and `compact.wgsl`. It is padded
+/// to 16 bytes, which is the minimum size of a uniform buffer on some
+/// platforms.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct Params {
+    len: u32,
+    _padding: [u32; 3],
+}
+
+/// Creates a uniform buffer containing the `Params`.
+fn create_params(device: &Device, len: u32) -> Buffer {
+    device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Scan parameters"),
+        contents: bytes_of(&Params {
+            len,
+            _padding: [0; 3],
+        }),
+        usage: BufferUsages::UNIFORM,
+    })
+}
+
+/// Returns a storage buffer binding type.
+fn storage(read_only: bool) -> BufferBindingType {
+    BufferBindingType::Storage { read_only }
+}
+
+/// Creates a bind group layout for compute shaders, with a buffer binding of
+/// each of the given types.
+fn create_bind_group_layout(
+    device: &Device,
+    label: &str,
+    types: &[BufferBindingType],
+) -> BindGroupLayout {
+    let entries: Vec<BindGroupLayoutEntry> = types
+        .iter()
+        .enumerate()
+        .map(|(binding, ty)| BindGroupLayoutEntry {
+            binding: binding as u32,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: *ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        })
+        .collect();
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some(label),
+        entries: &entries,
+    })
+}
+
+/// Creates a bind group, binding each of the given buffers in full.
+fn create_bind_group(
+    device: &Device,
+    label: &str,
+    layout: &BindGroupLayout,
+    buffers: &[&Buffer],
+) -> BindGroup {
+    let entries: Vec<BindGroupEntry> = buffers
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer)| BindGroupEntry {
+            binding: binding as u32,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries: &entries,
+    })
+}
+
+/// Compiles a shader, and returns a function which creates a compute
+/// pipeline for one of its entry points.
+fn create_pipelines<'a>(
+    device: &'a Device,
+    label: &str,
+    source: &'static str,
+    layout: &BindGroupLayout,
+) -> impl Fn(&str) -> ComputePipeline + 'a {
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(label),
+        source: ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline_layout =
+        device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &[layout],
+            push_constant_ranges: &[],
+        });
+    move |entry_point: &str| {
+        device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some(entry_point),
+            compilation_options: PipelineCompilationOptions::default(),
+            cache: None,
+        })
+    }
+}
+
+/// Dispatches workgroups, laying them out in two dimensions if there are
+/// too many for one.
+///
+/// # Parameters
+///
+/// - `pass`: The compute pass.
+/// - `n_workgroups`: Number of workgroups needed.
+/// - `max_workgroups`: Maximum number of workgroups in a dimension.
+fn dispatch(
+    pass: &mut ComputePass<'_>,
+    n_workgroups: u32,
+    max_workgroups: u32,
+) {
+    let x = n_workgroups.min(max_workgroups);
+    let y = n_workgroups.div_ceil(x);
+    pass.dispatch_workgroups(x, y, 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::tests::gpu::Gpu;
+    use proptest::prelude::*;
+    use rand::prelude::*;
+    use wgpu::CommandEncoderDescriptor;
+
+    /// Creates a storage buffer containing some values.
+    fn create_storage(gpu: &Gpu, values: &[u32]) -> Buffer {
+        gpu.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Test values"),
+            contents: bytemuck::cast_slice(values),
+            usage: BufferUsages::STORAGE
+                | BufferUsages::COPY_SRC
+                | BufferUsages::COPY_DST,
+        })
+    }
+
+    /// Runs a function which records a compute pass, and submits it.
+    fn run(gpu: &Gpu, f: impl FnOnce(&mut ComputePass<'_>)) {
+        let mut encoder =
+            gpu.device
+                .create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("Test encoder"),
+                });
+        f(&mut encoder.begin_compute_pass(&Default::default()));
+        gpu.queue.submit([encoder.finish()]);
+    }
+
+    /// Generates random values, with about `zero_fraction` of them zero.
+    fn random_values(
+        seed: u64,
+        len: usize,
+        max_value: u32,
+        zero_fraction: f64,
+    ) -> Vec<u32> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..len)
+            .map(|_| {
+                if rng.gen_bool(zero_fraction) {
+                    0
+                } else {
+                    rng.gen_range(1..=max_value)
+                }
+            })
+            .collect()
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(24))]
+
+        /// Test the scan against a sequential scan, for lengths which need
+        /// up to three levels.
+        #[test]
+        fn test_scan(
+            seed in u64::MIN..u64::MAX,
+            len in prop_oneof![1usize..2048, 2048usize..300_000],
+            max_value in prop_oneof![Just(1u32), Just(1000), Just(u32::MAX)],
+        ) {
+            let gpu = Gpu::new();
+            let values = random_values(seed, len, max_value, 0.25);
+            let data = create_storage(&gpu, &values);
+            let scan = Scan::new(&gpu.device, &data, len);
+            run(&gpu, |pass| scan.encode(pass));
+
+            let mut expected = Vec::with_capacity(len);
+            let mut total = 0u32;
+            for value in &values {
+                expected.push(total);
+                total = total.wrapping_add(*value);
+            }
+            prop_assert_eq!(gpu.read_buffer::<u32>(&data), expected);
+            prop_assert_eq!(gpu.read_buffer::<u32>(scan.total()), vec![total]);
+        }
+
+        /// Test compaction against a sequential compaction.
+        #[test]
+        fn test_compact(
+            seed in u64::MIN..u64::MAX,
+            len in prop_oneof![1usize..2048, 2048usize..300_000],
+            zero_fraction in 0.0f64..=1.0,
+        ) {
+            let gpu = Gpu::new();
+            let values = random_values(seed, len, u32::MAX, zero_fraction);
+            let input = create_storage(&gpu, &values);
+            let output = create_storage(&gpu, &vec![u32::MAX; len]);
+            let compact = Compact::new(&gpu.device, &input, &output, len);
+            run(&gpu, |pass| compact.encode(pass));
+
+            let expected: Vec<u32> = (0..len as u32)
+                .filter(|i| values[*i as usize] != 0)
+                .collect();
+            let count = expected.len();
+            let indices = gpu.read_buffer::<u32>(&output);
+            prop_assert_eq!(
+                gpu.read_buffer::<u32>(compact.count()),
+                vec![count as u32]
+            );
+            prop_assert_eq!(&indices[..count], &expected[..]);
+        }
+    }
+
+    /// Test that a `Scan` can be run again, on new values.
+    #[test]
+    fn test_scan_reuse() {
+        let gpu = Gpu::new();
+        let len = 5000;
+        let data = create_storage(&gpu, &vec![1; len]);
+        let scan = Scan::new(&gpu.device, &data, len);
+        for _ in 0..2 {
+            gpu.queue.write_buffer(
+                &data,
+                0,
+                bytemuck::cast_slice(&vec![2u32; len]),
+            );
+            run(&gpu, |pass| scan.encode(pass));
+            let expected: Vec<u32> = (0..len as u32).map(|i| 2 * i).collect();
+            assert_eq!(gpu.read_buffer::<u32>(&data), expected);
+            assert_eq!(gpu.read_buffer::<u32>(scan.total()), [2 * len as u32]);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+/**
+ * NAME:    scan.wgsl
+ * PURPOSE: Exclusive prefix sum (scan) over an array of `u32` values.
+ */
+
+/**
+ * The array is scanned in blocks of `BLOCK_SIZE` values, one block per
+ * workgroup, using the work-efficient (Blelloch) up-sweep and down-sweep.
+ * Each workgroup also writes the total of its block to `block_sums`.
+ *
+ * For arrays with more than one block, the block sums are themselves
+ * scanned in the same way (which may take further levels), and then
+ * `add_block_offsets` adds the scanned block sums back onto the blocks.
+ *
+ * Sums wrap around on overflow.
+ */
+
+/**** BINDINGS ****/
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read_write> data: array<u32>;
+@group(0) @binding(2) var<storage, read_write> block_sums: array<u32>;
+
+/**** STRUCTS ****/
+
+struct Params {
+    /// Number of values in `data`.
+    len : u32
+};
+
+/// Number of invocations in a workgroup.
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Number of values in a block. Each invocation handles two values.
+const BLOCK_SIZE: u32 = 512;
+
+var<workgroup> temp: array<u32, BLOCK_SIZE>;
+
+/**** ENTRY POINTS ****/
+
+/// Scans each block of `data` in place, and writes the block totals.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn scan_blocks(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local_i: u32
+) {
+    let block = block_index(workgroup_id, num_workgroups);
+    if (block * BLOCK_SIZE >= params.len) {
+        return;
+    }
+    let base = block * BLOCK_SIZE;
+    let ai = local_i;
+    let bi = local_i + WORKGROUP_SIZE;
+    temp[ai] = load(base + ai);
+    temp[bi] = load(base + bi);
+
+    // Up-sweep: build a tree of partial sums in place.
+    var offset: u32 = 1;
+    for (var d: u32 = BLOCK_SIZE >> 1; d > 0; d = d >> 1) {
+        workgroupBarrier();
+        if (local_i < d) {
+            let a = offset * (2 * local_i + 1) - 1;
+            let b = offset * (2 * local_i + 2) - 1;
+            temp[b] = temp[b] + temp[a];
+        }
+        offset = offset * 2;
+    }
+
+    // The root of the tree is the total of the block.
+    workgroupBarrier();
+    if (local_i == 0) {
+        block_sums[block] = temp[BLOCK_SIZE - 1];
+        temp[BLOCK_SIZE - 1] = 0u;
+    }
+
+    // Down-sweep: distribute the partial sums back down the tree.
+    for (var d: u32 = 1; d < BLOCK_SIZE; d = d * 2) {
+        offset = offset >> 1;
+        workgroupBarrier();
+        if (local_i < d) {
+            let a = offset * (2 * local_i + 1) - 1;
+            let b = offset * (2 * local_i + 2) - 1;
+            let t = temp[a];
+            temp[a] = temp[b];
+            temp[b] = temp[b] + t;
+        }
+    }
+    workgroupBarrier();
+
+    store(base + ai, temp[ai]);
+    store(base + bi, temp[bi]);
+}
+
+/// Adds the scanned block sums to the values of each block.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn add_block_offsets(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local_i: u32
+) {
+    let block = block_index(workgroup_id, num_workgroups);
+    if (block * BLOCK_SIZE >= params.len) {
+        return;
+    }
+    let base = block * BLOCK_SIZE;
+    let block_offset = block_sums[block];
+    for (var i = local_i; i < BLOCK_SIZE; i = i + WORKGROUP_SIZE) {
+        if (base + i < params.len) {
+            data[base + i] = data[base + i] + block_offset;
+        }
+    }
+}
+
+/**** FUNCTIONS USING GLOBALS ****/
+
+/// Loads a value, or zero past the end of the data.
+fn load(i: u32) -> u32 {
+    if (i < params.len) {
+        return data[i];
+    }
+    return 0u;
+}
+
+/// Stores a value, unless it is past the end of the data.
+fn store(i: u32, value: u32) {
+    if (i < params.len) {
+        data[i] = value;
+    }
+}
+
+/**** PURE FUNCTIONS ****/
+
+/// Computes the linear index of a block.
+///
+/// Large arrays have more blocks than fit in one dimension of a dispatch,
+/// so the workgroups are laid out in two dimensions.
+fn block_index(workgroup_id: vec3<u32>, num_workgroups: vec3<u32>) -> u32 {
+    return workgroup_id.y * num_workgroups.x + workgroup_id.x;
+}
@@ -181,7 +181,7 @@ mod tests {
         assert_eq!(stack.deref(), &[100]);
     }
 
-    ///---- Property Testing Stack --------------------------------------------
+    //---- Property Testing Stack --------------------------------------------
 
     // Here, we compare `Stack` against a (more) trivial implementation of the
     // same API, written using `Vec`, called `VectorStack`.
@@ -375,7 +375,7 @@ mod tests {
     /// Run all stack operations on both kinds of stack, and compare the
     /// number of counts of owners.
     fn run_on_stacks_test_ownership_counts<T, const N: usize>(
-        ops: &Vec<StackOp<T>>,
+        ops: &[StackOp<T>],
     ) where
         T: Clone,
     {
@@ -415,7 +415,7 @@ mod tests {
         assert_eq!(vstack.len(), astack.len());
 
         let vslice: &[T] = &vstack.data;
-        let aslice: &[T] = &astack;
+        let aslice: &[T] = astack;
         assert_eq!(vslice, aslice);
     }
 
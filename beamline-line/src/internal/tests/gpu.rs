@@ -1,7 +1,11 @@
+use bytemuck::{cast_slice, Pod};
 use core::default::Default;
-use futures::executor::block_on;
+use futures::{channel::oneshot, executor::block_on};
 use std::sync::Arc;
-use wgpu::{Device, Instance, Queue};
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device,
+    Instance, Maintain, MapMode, Queue,
+};
 
 /// Encapsulates GPU (WGPU) basic classes for testing.
 ///
@@ -33,4 +37,35 @@ impl Gpu {
             queue,
         }
     }
+
+    /// Reads the contents of a buffer back from the GPU, blocking until
+    /// they are available.
+    ///
+    /// The buffer must have the `COPY_SRC` usage.
+    pub fn read_buffer<T: Pod>(&self, buffer: &Buffer) -> Vec<T> {
+        let staging = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Test read buffer"),
+            size: buffer.size(),
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder =
+            self.device
+                .create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("Test read"),
+                });
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+        self.queue.submit([encoder.finish()]);
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = oneshot::channel();
+        slice.map_async(MapMode::Read, |result| {
+            result.unwrap();
+            sender.send(()).unwrap()
+        });
+        let _ = self.device.poll(Maintain::Wait); // Triggers mapping.
+        block_on(receiver).unwrap();
+        let values = cast_slice(&slice.get_mapped_range()).to_vec();
+        values
+    }
 }
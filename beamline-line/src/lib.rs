@@ -1 +1,6 @@
 mod internal;
+
+pub use internal::{
+    pushbuf::PushBuf,
+    scan::{Compact, Scan},
+};
@@ -0,0 +1,93 @@
+//! Tile occupancy of a dense scene of lines with round caps.
+//!
+//! The bounding polygon of a line with round caps treats the caps as square,
+//! and the exact capsule test then removes the tiles which only the corners
+//! of the square reach. This benchmark tiles the same scene with and without
+//! the exact test. Each benchmark is identified by the number of primitives
+//! which it places in tiles, which the line shader must then draw.
+//!
+//! Turning the exact test off needs the `bench` feature:
+//!
+//! ```text
+//! cargo bench -p beamline --features bench
+//! ```
+
+use beamline::{style::StyledLine, tiler::Tiler, Line, LineCap, LineStyle, P2};
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkId, Criterion,
+};
+
+/// Size of the renderable area.
+const AREA: (u32, u32) = (1920, 1080);
+
+/// Size of a tile.
+const TILE: (u32, u32) = (16, 16);
+
+/// Number of lines in the scene.
+const N_LINES: usize = 4000;
+
+/// Returns a dense scene of short, thick lines with round caps, which are
+/// scattered pseudo-randomly over the area.
+fn scene() -> Vec<StyledLine> {
+    let mut state: u32 = 0x2545_f491;
+    let mut next = move || {
+        // Xorshift.
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as f32 / u32::MAX as f32
+    };
+    (0..N_LINES)
+        .map(|_| {
+            let a = P2::new(next() * AREA.0 as f32, next() * AREA.1 as f32);
+            let angle = next() * std::f32::consts::TAU;
+            let length = 4.0 + next() * 60.0;
+            let b = a + length * cgmath::Vector2::new(angle.cos(), angle.sin());
+            StyledLine {
+                line: Line::new(a, b),
+                style: LineStyle {
+                    width: 4.0 + next() * 28.0,
+                    cap: LineCap::Round,
+                    ..Default::default()
+                },
+            }
+        })
+        .collect()
+}
+
+/// Tiles a scene.
+///
+/// # Parameters
+///
+/// - `lines`: The scene.
+/// - `exact`: `true` to test the lines exactly against the tiles.
+///
+/// # Returns
+///
+/// The number of primitives in all of the tiles.
+fn tile(lines: &[StyledLine], exact: bool) -> usize {
+    let mut tiler = Tiler::new(AREA.0, AREA.1, TILE.0, TILE.1);
+    tiler.set_exact_tile_tests(exact);
+    for line in lines {
+        tiler.add(line.clone());
+    }
+    let (_, _, line_indices) = tiler.drain();
+    line_indices.len()
+}
+
+fn bench_tile_occupancy(c: &mut Criterion) {
+    let lines = scene();
+    let mut group = c.benchmark_group("tile_occupancy");
+    for (name, exact) in [("bounding_polygons", false), ("exact", true)] {
+        let n_primitives = tile(&lines, exact);
+        group.bench_with_input(
+            BenchmarkId::new(name, n_primitives),
+            &exact,
+            |b, exact| b.iter(|| tile(black_box(&lines), *exact)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_tile_occupancy);
+criterion_main!(benches);
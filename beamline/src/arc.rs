@@ -0,0 +1,329 @@
+//! Circular arcs.
+
+use crate::{
+    path::MAX_FLATTEN_SEGMENTS, polyline::Polyline, transform, M3, P2, V2,
+};
+use cgmath::Transform;
+use std::f32::consts::{PI, TAU};
+
+/// Circular arc.
+///
+/// An arc is the part of a circle between two angles. Angles are in
+/// radians, measured anti-clockwise from the positive x axis, and the arc
+/// runs anti-clockwise from its start angle to its end angle. An arc which
+/// spans a full turn or more is a whole circle.
+///
+/// To construct an arc, use [`CircularArc::new`] or [`CircularArc::circle`].
+#[derive(Debug, Clone)]
+pub struct CircularArc {
+    center: P2,
+    radius: f32,
+    /// Start angle of the arc.
+    start_angle: f32,
+    /// Angle spanned by the arc. This is in the range `(0, 2*PI]`, where
+    /// `2*PI` is a whole circle.
+    sweep: f32,
+}
+impl CircularArc {
+    /// Creates a new arc.
+    ///
+    /// If `end_angle` is less than `start_angle`, the arc runs clockwise
+    /// from `start_angle` to `end_angle`. This is the same as an
+    /// anti-clockwise arc from `end_angle` to `start_angle`, so the start and
+    /// end of the arc are swapped.
+    ///
+    /// # Parameters
+    ///
+    /// - `center`: Center of the circle.
+    /// - `radius`: Radius of the circle. This must be positive.
+    /// - `start_angle`: Angle of the start of the arc.
+    /// - `end_angle`: Angle of the end of the arc. This must differ from
+    ///   `start_angle`.
+    ///
+    /// # Returns
+    ///
+    /// A new arc.
+    pub fn new(
+        center: P2,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+    ) -> Self {
+        assert!(radius > 0.0);
+        assert!(start_angle.is_finite() && end_angle.is_finite());
+        assert!(start_angle != end_angle);
+        let (start_angle, end_angle) = if end_angle < start_angle {
+            (end_angle, start_angle)
+        } else {
+            (start_angle, end_angle)
+        };
+        CircularArc {
+            center,
+            radius,
+            start_angle,
+            sweep: (end_angle - start_angle).min(TAU),
+        }
+    }
+
+    /// Creates a whole circle.
+    ///
+    /// # Parameters
+    ///
+    /// - `center`: Center of the circle.
+    /// - `radius`: Radius of the circle. This must be positive.
+    pub fn circle(center: P2, radius: f32) -> Self {
+        CircularArc::new(center, radius, 0.0, TAU)
+    }
+
+    /// Returns the center of the circle.
+    pub fn center(&self) -> P2 {
+        self.center
+    }
+
+    /// Returns the radius of the circle.
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Returns the start angle of the arc.
+    pub fn start_angle(&self) -> f32 {
+        self.start_angle
+    }
+
+    /// Returns the end angle of the arc.
+    pub fn end_angle(&self) -> f32 {
+        self.start_angle + self.sweep
+    }
+
+    /// Returns the angle spanned by the arc, which is at most `2*PI`.
+    pub fn sweep(&self) -> f32 {
+        self.sweep
+    }
+
+    /// Returns `true` if the arc is a whole circle.
+    pub fn is_circle(&self) -> bool {
+        self.sweep >= TAU
+    }
+
+    /// Returns the point on the circle at an angle.
+    pub fn point_at_angle(&self, angle: f32) -> P2 {
+        self.center + self.radius * V2::new(angle.cos(), angle.sin())
+    }
+
+    /// Returns the start point of the arc.
+    pub fn start(&self) -> P2 {
+        self.point_at_angle(self.start_angle())
+    }
+
+    /// Returns the end point of the arc.
+    pub fn end(&self) -> P2 {
+        self.point_at_angle(self.end_angle())
+    }
+
+    /// Splits the arc into equal pieces.
+    ///
+    /// # Parameters
+    ///
+    /// - `n`: Number of pieces. This must be at least 1.
+    pub fn split(&self, n: usize) -> Vec<CircularArc> {
+        assert!(n > 0);
+        let sweep = self.sweep / n as f32;
+        (0..n)
+            .map(|i| CircularArc {
+                center: self.center,
+                radius: self.radius,
+                start_angle: self.start_angle + i as f32 * sweep,
+                sweep,
+            })
+            .collect()
+    }
+
+    /// Returns the arc mapped through an affine transform, if its image is
+    /// another circular arc.
+    ///
+    /// This is the case when the transform is a similarity: a uniform scale
+    /// and rotation, without reflection. Other transforms map the arc to a
+    /// part of an ellipse, or reverse its direction.
+    ///
+    /// # Parameters
+    ///
+    /// - `matrix`: Affine transform to apply.
+    ///
+    /// # Returns
+    ///
+    /// The transformed arc, or `None` if the transform is not a similarity.
+    pub fn transformed(&self, matrix: &M3) -> Option<CircularArc> {
+        let (scale, rotation) = transform::similarity(matrix)?;
+        Some(CircularArc {
+            center: matrix.transform_point(self.center),
+            radius: self.radius * scale,
+            start_angle: self.start_angle + rotation,
+            sweep: self.sweep,
+        })
+    }
+
+    /// Flattens the arc into a polyline.
+    ///
+    /// An arc becomes an open polyline from its start to its end, and a
+    /// whole circle becomes a closed polyline.
+    ///
+    /// # Parameters
+    ///
+    /// - `tolerance`: Maximum distance between the arc and the segments of
+    ///   the polyline. This must be positive.
+    pub fn flatten(&self, tolerance: f32) -> Polyline {
+        assert!(tolerance > 0.0);
+        let max_sweep = 2.0 * (1.0 - (tolerance / self.radius).min(1.0)).acos();
+        let n = ((self.sweep / max_sweep).ceil() as usize)
+            .clamp(3, MAX_FLATTEN_SEGMENTS);
+        let point = |i: usize| {
+            self.point_at_angle(
+                self.start_angle + self.sweep * i as f32 / n as f32,
+            )
+        };
+        if self.is_circle() {
+            Polyline::closed((0..n).map(point).collect())
+        } else {
+            Polyline::new((0..=n).map(point).collect())
+        }
+    }
+
+    /// Returns the control points of a piece of the arc.
+    ///
+    /// These are the start point, the intersection of the tangents at the
+    /// ends, and the end point. The arc lies within the triangle that they
+    /// form. The arc must span less than half a turn.
+    pub fn control_points(&self) -> [P2; 3] {
+        assert!(self.sweep < PI);
+        let mid_angle = self.start_angle + self.sweep / 2.0;
+        let distance = self.radius / (self.sweep / 2.0).cos();
+        let mid =
+            self.center + distance * V2::new(mid_angle.cos(), mid_angle.sin());
+        [self.start(), mid, self.end()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::parity::{check_scene, pixel_at, Shape},
+        Color, Ellipse, LineCap, LineStyle, SoftwareRenderer,
+    };
+
+    /// Returns a scene of translucent arcs with all cap styles, a circle and
+    /// ellipses.
+    fn arc_scene() -> Vec<(Shape, LineStyle)> {
+        let style = |width, cap, color| LineStyle {
+            width,
+            cap,
+            color,
+            ..Default::default()
+        };
+        vec![
+            (
+                Shape::Arc(CircularArc::new(
+                    P2::new(40.0, 50.0),
+                    30.0,
+                    0.3,
+                    4.0,
+                )),
+                style(8.0, LineCap::Butt, Color::new(0.9, 0.4, 0.4, 0.8)),
+            ),
+            (
+                Shape::Arc(CircularArc::new(
+                    P2::new(75.0, 40.0),
+                    25.0,
+                    2.0,
+                    -0.5,
+                )),
+                style(5.0, LineCap::Square, Color::new(0.4, 0.9, 0.4, 0.6)),
+            ),
+            (
+                Shape::Arc(CircularArc::new(
+                    P2::new(110.0, 60.0),
+                    20.0,
+                    -1.0,
+                    4.5,
+                )),
+                style(6.0, LineCap::Round, Color::new(0.4, 0.4, 0.9, 0.8)),
+            ),
+            (
+                Shape::Arc(CircularArc::circle(P2::new(120.0, 25.0), 15.0)),
+                style(3.0, LineCap::Round, Color::new(1.0, 1.0, 0.2, 0.5)),
+            ),
+            (
+                Shape::Ellipse(Ellipse::new(
+                    P2::new(75.0, 70.0),
+                    50.0,
+                    18.0,
+                    0.4,
+                )),
+                style(4.0, LineCap::Butt, Color::new(0.2, 1.0, 1.0, 0.7)),
+            ),
+            (
+                Shape::Ellipse(Ellipse::new(
+                    P2::new(30.0, 70.0),
+                    5.0,
+                    25.0,
+                    0.0,
+                )),
+                style(7.0, LineCap::Butt, Color::new(1.0, 0.2, 1.0, 0.6)),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_arcs_match_gpu() {
+        let image = check_scene(wgpu::TextureFormat::Rgba8Unorm, &arc_scene());
+        // The first arc covers its start, at an angle of 0.3, and leaves the
+        // rest of its circle empty.
+        assert_eq!(pixel_at(&image, 68.5, 60.5), [184, 82, 82, 204]);
+        assert_eq!(pixel_at(&image, 48.5, 21.5), [0, 0, 0, 0]);
+        // The circle is closed.
+        assert_eq!(pixel_at(&image, 135.5, 25.5), [128, 128, 26, 128]);
+        // The butt cap of the first arc ends at its end angle.
+        assert_eq!(pixel_at(&image, 18.5, 28.5), [184, 82, 82, 204]);
+        assert_eq!(pixel_at(&image, 21.5, 25.5), [0, 0, 0, 0]);
+        // The second arc turns clockwise from its start angle to its end
+        // angle, which leaves the bottom of its circle empty, and its square
+        // cap reaches past its end.
+        assert_eq!(pixel_at(&image, 75.5, 15.5), [0, 0, 0, 0]);
+        assert_eq!(pixel_at(&image, 96.5, 26.5), [61, 138, 61, 153]);
+        // The round cap of the third arc reaches past its end.
+        assert_eq!(pixel_at(&image, 107.5, 39.5), [82, 82, 184, 204]);
+        // The ellipses pass through the ends of their rotated axes.
+        assert_eq!(pixel_at(&image, 121.0, 89.0), [36, 178, 178, 178]);
+        assert_eq!(pixel_at(&image, 67.5, 86.0), [36, 178, 178, 178]);
+        assert_eq!(pixel_at(&image, 30.0, 94.5), [153, 31, 153, 153]);
+        assert_eq!(pixel_at(&image, 35.0, 70.0), [153, 31, 153, 153]);
+    }
+
+    /// A circle renders the same as an ellipse with equal radii, and as an
+    /// arc spanning more than a whole turn.
+    #[test]
+    fn test_circle_matches_ellipse() {
+        let center = P2::new(75.0, 50.0);
+        let style = LineStyle {
+            width: 6.0,
+            color: Color::new(0.8, 0.8, 0.8, 0.9),
+            ..Default::default()
+        };
+
+        let mut software = SoftwareRenderer::new(
+            wgpu::TextureFormat::Rgba8Unorm,
+            150,
+            100,
+            16,
+            16,
+        );
+        software.arc(CircularArc::circle(center, 40.0), &style);
+        let circle = software.render();
+        software.ellipse(Ellipse::new(center, 40.0, 40.0, 1.0), &style);
+        let ellipse = software.render();
+        software.arc(CircularArc::new(center, 40.0, -1.0, 9.0), &style);
+        let arc = software.render();
+        assert!(circle.max_difference(&ellipse) <= 2);
+        assert_eq!(circle.max_difference(&arc), 0);
+    }
+}
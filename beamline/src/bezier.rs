@@ -0,0 +1,407 @@
+//! Quadratic and cubic Bézier curves.
+
+use crate::{transform::Transformable, M3, P2};
+use cgmath::{EuclideanSpace, MetricSpace, Transform};
+
+/// Quadratic Bézier curve.
+///
+/// The curve starts at `p0`, ends at `p2`, and is pulled towards the control
+/// point `p1`.
+///
+/// To construct a quadratic Bézier curve, use [`QuadraticBezier::new`].
+#[derive(Debug, Clone)]
+pub struct QuadraticBezier {
+    p0: P2,
+    p1: P2,
+    p2: P2,
+}
+impl QuadraticBezier {
+    /// Creates a new quadratic Bézier curve.
+    ///
+    /// # Parameters
+    ///
+    /// - `p0`: Start point of the curve.
+    /// - `p1`: Control point of the curve.
+    /// - `p2`: End point of the curve.
+    ///
+    /// # Returns
+    ///
+    /// A new quadratic Bézier curve.
+    pub fn new(p0: P2, p1: P2, p2: P2) -> Self {
+        QuadraticBezier { p0, p1, p2 }
+    }
+
+    /// Returns the start point of the curve.
+    pub fn start(&self) -> P2 {
+        self.p0
+    }
+
+    /// Returns the end point of the curve.
+    pub fn end(&self) -> P2 {
+        self.p2
+    }
+
+    /// Returns the control points of the curve, including the end points.
+    pub fn control_points(&self) -> [P2; 3] {
+        [self.p0, self.p1, self.p2]
+    }
+
+    /// Evaluate the curve at parameter value `t`.
+    ///
+    /// A value of `t=0` corresponds to the start of the curve, while a value
+    /// of `t=1` corresponds to the end of the curve.
+    ///
+    /// # Parameters
+    ///
+    /// - `t`: Parameter value at which to evaluate the curve.
+    ///
+    /// # Returns
+    ///
+    /// Point of the curve at `t`.
+    pub fn eval_param(&self, t: f32) -> P2 {
+        blossom(&self.control_points(), &[t, t])
+    }
+
+    /// Returns the part of the curve between two parameter values.
+    ///
+    /// # Parameters
+    ///
+    /// - `t0`: Parameter value at the start of the part.
+    /// - `t1`: Parameter value at the end of the part.
+    ///
+    /// # Returns
+    ///
+    /// A curve which traces the same points as this curve between `t0` and
+    /// `t1`.
+    pub fn sub_curve(&self, t0: f32, t1: f32) -> Self {
+        let cp = self.control_points();
+        QuadraticBezier {
+            p0: blossom(&cp, &[t0, t0]),
+            p1: blossom(&cp, &[t0, t1]),
+            p2: blossom(&cp, &[t1, t1]),
+        }
+    }
+}
+
+impl Transformable for QuadraticBezier {
+    fn transformed(&self, matrix: &M3) -> Self {
+        let [p0, p1, p2] =
+            self.control_points().map(|p| matrix.transform_point(p));
+        QuadraticBezier::new(p0, p1, p2)
+    }
+}
+
+/// Cubic Bézier curve.
+///
+/// The curve starts at `p0`, ends at `p3`, and is pulled towards the control
+/// points `p1` and `p2`.
+///
+/// To construct a cubic Bézier curve, use [`CubicBezier::new`].
+#[derive(Debug, Clone)]
+pub struct CubicBezier {
+    p0: P2,
+    p1: P2,
+    p2: P2,
+    p3: P2,
+}
+impl CubicBezier {
+    /// Creates a new cubic Bézier curve.
+    ///
+    /// # Parameters
+    ///
+    /// - `p0`: Start point of the curve.
+    /// - `p1`: First control point of the curve.
+    /// - `p2`: Second control point of the curve.
+    /// - `p3`: End point of the curve.
+    ///
+    /// # Returns
+    ///
+    /// A new cubic Bézier curve.
+    pub fn new(p0: P2, p1: P2, p2: P2, p3: P2) -> Self {
+        CubicBezier { p0, p1, p2, p3 }
+    }
+
+    /// Returns the start point of the curve.
+    pub fn start(&self) -> P2 {
+        self.p0
+    }
+
+    /// Returns the end point of the curve.
+    pub fn end(&self) -> P2 {
+        self.p3
+    }
+
+    /// Returns the control points of the curve, including the end points.
+    pub fn control_points(&self) -> [P2; 4] {
+        [self.p0, self.p1, self.p2, self.p3]
+    }
+
+    /// Evaluate the curve at parameter value `t`.
+    ///
+    /// See [`QuadraticBezier::eval_param`].
+    pub fn eval_param(&self, t: f32) -> P2 {
+        blossom(&self.control_points(), &[t, t, t])
+    }
+
+    /// Returns the part of the curve between two parameter values.
+    ///
+    /// See [`QuadraticBezier::sub_curve`].
+    pub fn sub_curve(&self, t0: f32, t1: f32) -> Self {
+        let cp = self.control_points();
+        CubicBezier {
+            p0: blossom(&cp, &[t0, t0, t0]),
+            p1: blossom(&cp, &[t0, t0, t1]),
+            p2: blossom(&cp, &[t0, t1, t1]),
+            p3: blossom(&cp, &[t1, t1, t1]),
+        }
+    }
+}
+
+impl Transformable for CubicBezier {
+    fn transformed(&self, matrix: &M3) -> Self {
+        let [p0, p1, p2, p3] =
+            self.control_points().map(|p| matrix.transform_point(p));
+        CubicBezier::new(p0, p1, p2, p3)
+    }
+}
+
+/// Returns the length of the control polygon of a curve.
+///
+/// This is an upper bound on the length of the curve.
+pub fn control_polygon_length(control_points: &[P2]) -> f32 {
+    control_points
+        .windows(2)
+        .map(|pair| pair[0].distance(pair[1]))
+        .sum()
+}
+
+/// Evaluates the blossom (polar form) of a Bézier curve.
+///
+/// This is de Casteljau's algorithm, using a different parameter value at
+/// each level. When all parameters are equal to `t`, it evaluates the curve
+/// at `t`. The control points of the part of a curve between `t0` and `t1`
+/// are the blossom evaluated at each combination of `t0` and `t1`.
+///
+/// # Parameters
+///
+/// - `control_points`: Control points of the curve.
+/// - `params`: Parameter values. There must be one fewer of these than there
+///   are control points.
+fn blossom(control_points: &[P2], params: &[f32]) -> P2 {
+    assert_eq!(control_points.len(), params.len() + 1);
+    let mut points: Vec<P2> = control_points.to_vec();
+    for (level, t) in params.iter().enumerate() {
+        for i in 0..points.len() - level - 1 {
+            points[i] = P2::from_vec(
+                points[i].to_vec() * (1.0 - t) + points[i + 1].to_vec() * *t,
+            );
+        }
+    }
+    points[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::parity::{check_scene, pixel_at, Shape},
+        Color, Line, LineCap, LineStyle, SoftwareRenderer,
+    };
+
+    fn assert_close(a: P2, b: P2) {
+        assert!(a.distance(b) < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_quadratic_eval() {
+        let curve = QuadraticBezier::new(
+            P2::new(0.0, 0.0),
+            P2::new(1.0, 2.0),
+            P2::new(2.0, 0.0),
+        );
+        assert_close(curve.eval_param(0.0), curve.start());
+        assert_close(curve.eval_param(0.5), P2::new(1.0, 1.0));
+        assert_close(curve.eval_param(1.0), curve.end());
+    }
+
+    #[test]
+    fn test_sub_curve() {
+        let curve = CubicBezier::new(
+            P2::new(0.0, 0.0),
+            P2::new(1.0, 3.0),
+            P2::new(4.0, -2.0),
+            P2::new(5.0, 1.0),
+        );
+        let part = curve.sub_curve(0.25, 0.75);
+        for i in 0..=10 {
+            let s = i as f32 / 10.0;
+            assert_close(part.eval_param(s), curve.eval_param(0.25 + 0.5 * s));
+        }
+
+        let curve = QuadraticBezier::new(
+            P2::new(0.0, 0.0),
+            P2::new(1.0, 2.0),
+            P2::new(2.0, 0.0),
+        );
+        let part = curve.sub_curve(0.5, 1.0);
+        for i in 0..=10 {
+            let s = i as f32 / 10.0;
+            assert_close(part.eval_param(s), curve.eval_param(0.5 + 0.5 * s));
+        }
+    }
+
+    /// Returns a scene of translucent curves with all cap styles.
+    fn curve_scene() -> Vec<(Shape, LineStyle)> {
+        let style = |width, cap, color| LineStyle {
+            width,
+            cap,
+            color,
+            ..Default::default()
+        };
+        vec![
+            (
+                Shape::Quadratic(QuadraticBezier::new(
+                    P2::new(10.0, 10.0),
+                    P2::new(75.0, 120.0),
+                    P2::new(140.0, 10.0),
+                )),
+                style(8.0, LineCap::Butt, Color::new(0.9, 0.4, 0.4, 0.8)),
+            ),
+            (
+                Shape::Quadratic(QuadraticBezier::new(
+                    P2::new(20.0, 90.0),
+                    P2::new(50.0, 60.0),
+                    P2::new(80.0, 30.0),
+                )),
+                style(5.0, LineCap::Square, Color::new(0.4, 0.9, 0.4, 0.6)),
+            ),
+            (
+                Shape::Cubic(CubicBezier::new(
+                    P2::new(10.0, 50.0),
+                    P2::new(60.0, 110.0),
+                    P2::new(90.0, -10.0),
+                    P2::new(140.0, 60.0),
+                )),
+                style(6.0, LineCap::Round, Color::new(0.4, 0.4, 0.9, 0.8)),
+            ),
+            (
+                // A cubic with a loop.
+                Shape::Cubic(CubicBezier::new(
+                    P2::new(90.0, 85.0),
+                    P2::new(160.0, 40.0),
+                    P2::new(80.0, 40.0),
+                    P2::new(140.0, 90.0),
+                )),
+                style(4.0, LineCap::Square, Color::new(1.0, 1.0, 0.2, 0.5)),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_curves_match_gpu() {
+        let image =
+            check_scene(wgpu::TextureFormat::Rgba8Unorm, &curve_scene());
+        // The quadratic curve passes through its points at t = 0.25 and
+        // t = 0.5, and not through the inside of its control polygon.
+        assert_eq!(pixel_at(&image, 42.5, 51.5), [184, 82, 82, 204]);
+        assert_eq!(pixel_at(&image, 75.5, 65.5), [184, 82, 82, 204]);
+        assert_eq!(pixel_at(&image, 75.5, 55.5), [0, 0, 0, 0]);
+        // Its butt cap ends at the start point.
+        assert_eq!(pixel_at(&image, 11.5, 12.5), [184, 82, 82, 204]);
+        assert_eq!(pixel_at(&image, 8.5, 7.5), [0, 0, 0, 0]);
+        // The cubic curve passes through its point at t = 0.5, and its round
+        // cap reaches behind its start point.
+        assert_eq!(pixel_at(&image, 75.0, 51.0), [82, 82, 184, 204]);
+        assert_eq!(pixel_at(&image, 8.5, 48.5), [82, 82, 184, 204]);
+        // The cubic curve with a loop passes through its point at t = 0.5,
+        // and is blended once where it crosses itself.
+        assert_eq!(pixel_at(&image, 118.5, 51.5), [128, 128, 26, 128]);
+        assert_eq!(pixel_at(&image, 117.0, 62.5), [128, 128, 26, 128]);
+    }
+
+    /// Straight curves render the same as lines, for all cap styles.
+    #[test]
+    fn test_straight_curves_match_lines() {
+        let (a, b) = (P2::new(20.0, 30.0), P2::new(120.0, 70.0));
+        let at = |t: f32| a + t * (b - a);
+        for cap in [LineCap::Butt, LineCap::Round, LineCap::Square] {
+            let style = LineStyle {
+                width: 9.0,
+                cap,
+                color: Color::new(0.8, 0.8, 0.8, 0.9),
+                ..Default::default()
+            };
+            let render = |shape: Shape| {
+                let mut software = SoftwareRenderer::new(
+                    wgpu::TextureFormat::Rgba8Unorm,
+                    150,
+                    100,
+                    16,
+                    16,
+                );
+                match shape {
+                    Shape::Line(line) => software.line(line, &style),
+                    Shape::Quadratic(curve) => {
+                        software.quadratic(curve, &style)
+                    }
+                    Shape::Cubic(curve) => software.cubic(curve, &style),
+                    Shape::Polyline(polyline) => {
+                        software.polyline(&polyline, &style)
+                    }
+                    Shape::Arc(arc) => software.arc(arc, &style),
+                    Shape::Ellipse(ellipse) => {
+                        software.ellipse(ellipse, &style)
+                    }
+                    Shape::Fill(path, fill_style) => {
+                        software.fill(&path, &fill_style)
+                    }
+                    _ => unreachable!("only curves are compared"),
+                }
+                software.render()
+            };
+
+            let line = render(Shape::Line(Line::new(a, b)));
+            let quadratic =
+                render(Shape::Quadratic(QuadraticBezier::new(a, at(0.5), b)));
+            let cubic = render(Shape::Cubic(CubicBezier::new(
+                a,
+                at(0.25),
+                at(0.75),
+                b,
+            )));
+            assert!(line.max_difference(&quadratic) <= 2, "{:?}", cap);
+            assert!(line.max_difference(&cubic) <= 2, "{:?}", cap);
+        }
+    }
+
+    /// A cubic curve which is a degree-elevated quadratic curve renders the
+    /// same as the quadratic curve.
+    #[test]
+    fn test_elevated_cubic_matches_quadratic() {
+        let (p0, p1, p2) = (
+            P2::new(10.0, 10.0),
+            P2::new(60.0, 130.0),
+            P2::new(140.0, 20.0),
+        );
+        let c1 = p0 + (p1 - p0) * (2.0 / 3.0);
+        let c2 = p2 + (p1 - p2) * (2.0 / 3.0);
+        let style = LineStyle {
+            width: 7.0,
+            cap: LineCap::Square,
+            ..Default::default()
+        };
+
+        let mut software = SoftwareRenderer::new(
+            wgpu::TextureFormat::Rgba8Unorm,
+            150,
+            100,
+            16,
+            16,
+        );
+        software.quadratic(QuadraticBezier::new(p0, p1, p2), &style);
+        let quadratic = software.render();
+        software.cubic(CubicBezier::new(p0, c1, c2, p2), &style);
+        let cubic = software.render();
+        assert!(quadratic.max_difference(&cubic) <= 2);
+    }
+}
@@ -0,0 +1,1057 @@
+//! GPU binning: assigning primitives to tiles with compute shaders.
+//!
+//! The [`Tiler`](crate::tiler::Tiler) normally assigns each primitive to
+//! the tiles that it intersects as it is added. For scenes with very many
+//! primitives, the separating axis tests against the tiles can instead be
+//! run on the GPU. The tiler then only computes the bounding polygons of the
+//! primitives, and the [`Binner`] tests them against the tiles, allocates
+//! the line index array, and writes the tile info and line index buffers
+//! which are read by the line shader. The primitives themselves are
+//! uploaded once each, whichever tiles they are in. The choice is made with a
+//! [`TilingBackend`].
+
+use crate::{
+    buffers::{Buffers, StyledLine, TileInfo},
+    primitive::StyledPrimitive,
+    renderer::bind_group_layout_entry,
+    tiler::BinRecord,
+    types::ceil_div_u32,
+};
+use beamline_line::{Compact, PushBuf, Scan};
+use bytemuck::{bytes_of, cast_slice, NoUninit, Pod, Zeroable};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::mpsc,
+};
+use wgpu::util::DeviceExt;
+
+/// Number of invocations in a workgroup of the binning shaders.
+///
+/// This must match `WORKGROUP_SIZE` in `binning.wgsl`.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Number of values which the buffers of the binning shaders can first
+/// hold.
+const INITIAL_CAPACITY: usize = 64;
+
+/// Number of values which are copied to the buffers of the binning shaders
+/// at a time.
+const CHUNK_CAPACITY: usize = 256;
+
+/// Label of the buffer of records.
+const RECORDS_LABEL: &str = "Beamline: Binning records";
+
+/// Label of the buffer of references to bounding polygons.
+const POLYGONS_LABEL: &str = "Beamline: Binning polygons";
+
+/// Label of the buffer of bounding polygon vertices.
+const POINTS_LABEL: &str = "Beamline: Binning points";
+
+/// Message of the pushes into buffers which were reserved for the values.
+const RESERVED: &str = "the buffer was reserved for the values";
+
+/// Backend which assigns primitives to tiles when rendering (see
+/// [`crate::Renderer::set_tiling_backend`]).
+///
+/// Whichever backend is used, the tiles are drawn by the same line shader.
+/// Retained-mode scenes are always tiled on the CPU.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TilingBackend {
+    /// Primitives are assigned to tiles on the CPU, as they are added. This
+    /// suits small scenes, and needs no compute shaders.
+    #[default]
+    Cpu,
+    /// Primitives are assigned to tiles by compute shaders, which suits
+    /// scenes with very many primitives. Lines with round caps and round
+    /// joins are tested exactly, as they are on the CPU. Fills, clipped
+    /// shapes, arcs and ellipses are still assigned to tiles on the CPU.
+    Gpu,
+    /// Primitives are assigned to tiles both on the CPU, as by
+    /// [`TilingBackend::Cpu`], and by compute shaders, and the results are
+    /// compared (see [`crate::Renderer::tiling_mismatches`]). The CPU
+    /// results are drawn.
+    /// Reading the GPU results back blocks until the GPU has finished, so
+    /// this is intended for debugging on native platforms.
+    Verify,
+}
+
+/// Tile whose primitives differ between CPU tiling and GPU binning, as
+/// found by [`TilingBackend::Verify`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TilingMismatch {
+    /// X (horizontal) coordinate of the tile.
+    pub tile_x: u32,
+    /// Y (vertical) coordinate of the tile.
+    pub tile_y: u32,
+    /// Number of primitives which CPU tiling assigned to the tile.
+    pub cpu_lines: u32,
+    /// Number of primitives which GPU binning assigned to the tile.
+    pub gpu_lines: u32,
+}
+
+/// Compute pipelines and buffers which assign primitives to tiles on the
+/// GPU.
+///
+/// A `Binner` is created for a particular area and tile size, and can then
+/// be re-used for every frame. The buffers which hold the records of each
+/// frame are kept between frames, and are only re-allocated, along with
+/// their bind groups, when they are too small.
+#[derive(Debug)]
+pub(crate) struct Binner {
+    area_width: u32,
+    area_height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    n_x_tiles: u32,
+    n_y_tiles: u32,
+    /// Uniform buffer of the binning dimensions.
+    dimensions: wgpu::Buffer,
+    /// Number of primitives in each tile.
+    tile_counts: wgpu::Buffer,
+    /// Number of primitives which have been written to each tile.
+    tile_cursors: wgpu::Buffer,
+    /// Start of each tile's primitives in the line index array, followed by
+    /// the total number of primitives.
+    tile_starts: wgpu::Buffer,
+    /// Linear indices of the tiles which contain any primitives.
+    occupied_tiles: wgpu::Buffer,
+    /// Arguments of the indirect draw of the tiles.
+    draw_args: wgpu::Buffer,
+    /// Scan of `tile_starts`.
+    scan: Scan,
+    /// Compaction of `tile_counts` into `occupied_tiles`.
+    compact: Compact,
+    /// Records of the primitives to bin.
+    records: PushBuf<GpuBinRecord>,
+    /// References to the bounding polygons of the records.
+    polygons: PushBuf<PolygonRef>,
+    /// Vertices of the bounding polygons.
+    points: PushBuf<[f32; 2]>,
+    /// Number of words that `bitmasks` can hold.
+    bitmasks_capacity: u32,
+    /// Bit per tile of each record's range, set if the record intersects
+    /// the tile.
+    bitmasks: wgpu::Buffer,
+    bin_layout: wgpu::BindGroupLayout,
+    scatter_layout: wgpu::BindGroupLayout,
+    fill_layout: wgpu::BindGroupLayout,
+    /// Bind group of the records pass, if none of its buffers has been
+    /// re-allocated since it was created.
+    bin_bind_group: Option<wgpu::BindGroup>,
+    /// Bind group of the scatter pass, with the [`Buffers::generation`] of
+    /// the buffers which it binds.
+    scatter_bind_group: Option<(u64, wgpu::BindGroup)>,
+    /// Bind group of the tiles pass, with the [`Buffers::generation`] of
+    /// the buffers which it binds.
+    fill_bind_group: Option<(u64, wgpu::BindGroup)>,
+    bin_records: wgpu::ComputePipeline,
+    scatter_records: wgpu::ComputePipeline,
+    fill_tiles: wgpu::ComputePipeline,
+    /// Maximum number of workgroups in a dimension of a dispatch.
+    max_workgroups: u32,
+}
+impl Binner {
+    /// Creates a new `Binner`.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `area_width`: Width of the renderable area.
+    /// - `area_height`: Height of the renderable area.
+    /// - `tile_width`: Width of a single tile.
+    /// - `tile_height`: Height of a single tile.
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        area_width: u32,
+        area_height: u32,
+        tile_width: u32,
+        tile_height: u32,
+    ) -> Self {
+        let n_x_tiles = ceil_div_u32(area_width, tile_width);
+        let n_y_tiles = ceil_div_u32(area_height, tile_height);
+        let n_tiles = (n_x_tiles * n_y_tiles) as usize;
+
+        let u32_buffer = |label, len: usize, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: (len * size_of::<u32>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE | usage,
+                mapped_at_creation: false,
+            })
+        };
+        let tile_counts = u32_buffer(
+            "Beamline: Binning tile counts",
+            n_tiles,
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+        );
+        let tile_cursors = u32_buffer(
+            "Beamline: Binning tile cursors",
+            n_tiles,
+            wgpu::BufferUsages::COPY_DST,
+        );
+        let tile_starts = u32_buffer(
+            "Beamline: Binning tile starts",
+            n_tiles + 1,
+            wgpu::BufferUsages::COPY_DST,
+        );
+        let occupied_tiles = u32_buffer(
+            "Beamline: Binning occupied tiles",
+            n_tiles,
+            wgpu::BufferUsages::empty(),
+        );
+        let scan = Scan::new(device, &tile_starts, n_tiles + 1);
+        let compact =
+            Compact::new(device, &tile_counts, &occupied_tiles, n_tiles);
+
+        let dimensions = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Beamline: Binning dimensions uniform"),
+            size: size_of::<Dimensions>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let draw_args =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Beamline: Binning draw arguments"),
+                contents: cast_slice(&[6u32, 0, 0, 0]),
+                usage: wgpu::BufferUsages::INDIRECT
+                    | wgpu::BufferUsages::COPY_DST,
+            });
+
+        use wgpu::BufferBindingType::{Storage, Uniform};
+        let read = Storage { read_only: true };
+        let read_write = Storage { read_only: false };
+        let create_layout =
+            |label, entries: &[(u32, wgpu::BufferBindingType)]| {
+                let entries: Vec<wgpu::BindGroupLayoutEntry> = entries
+                    .iter()
+                    .map(|(binding, ty)| {
+                        bind_group_layout_entry(
+                            *binding,
+                            wgpu::ShaderStages::COMPUTE,
+                            *ty,
+                        )
+                    })
+                    .collect();
+                device.create_bind_group_layout(
+                    &wgpu::BindGroupLayoutDescriptor {
+                        label: Some(label),
+                        entries: &entries,
+                    },
+                )
+            };
+        // The bindings are numbered as in `binning.wgsl`. Each pass only
+        // binds the buffers it uses, which keeps the number of storage
+        // buffers within the default limit.
+        let bin_layout = create_layout(
+            "Beamline: Binning records layout",
+            &[
+                (0, Uniform),
+                (1, read),
+                (2, read),
+                (3, read),
+                (4, read_write),
+                (5, read_write),
+            ],
+        );
+        let scatter_layout = create_layout(
+            "Beamline: Binning scatter layout",
+            &[
+                (0, Uniform),
+                (1, read),
+                (4, read_write),
+                (6, read),
+                (10, read_write),
+                (11, read_write),
+            ],
+        );
+        let fill_layout = create_layout(
+            "Beamline: Binning tiles layout",
+            &[
+                (0, Uniform),
+                (1, read),
+                (6, read),
+                (7, read),
+                (8, read),
+                (9, read_write),
+                (10, read_write),
+            ],
+        );
+
+        let shader =
+            device.create_shader_module(wgpu::include_wgsl!("binning.wgsl"));
+        let create_pipeline = |layout: &wgpu::BindGroupLayout, entry_point| {
+            let pipeline_layout = device.create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some("Beamline: Binning pipeline layout"),
+                    bind_group_layouts: &[layout],
+                    push_constant_ranges: &[],
+                },
+            );
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let bin_records = create_pipeline(&bin_layout, "bin_records");
+        let scatter_records =
+            create_pipeline(&scatter_layout, "scatter_records");
+        let fill_tiles = create_pipeline(&fill_layout, "fill_tiles");
+
+        Binner {
+            area_width,
+            area_height,
+            tile_width,
+            tile_height,
+            n_x_tiles,
+            n_y_tiles,
+            dimensions,
+            tile_counts,
+            tile_cursors,
+            tile_starts,
+            occupied_tiles,
+            draw_args,
+            scan,
+            compact,
+            records: create_pushbuf(device, RECORDS_LABEL, INITIAL_CAPACITY),
+            polygons: create_pushbuf(device, POLYGONS_LABEL, INITIAL_CAPACITY),
+            points: create_pushbuf(device, POINTS_LABEL, INITIAL_CAPACITY),
+            bitmasks_capacity: INITIAL_CAPACITY as u32,
+            bitmasks: create_bitmasks_buffer(device, INITIAL_CAPACITY as u32),
+            bin_layout,
+            scatter_layout,
+            fill_layout,
+            bin_bind_group: None,
+            scatter_bind_group: None,
+            fill_bind_group: None,
+            bin_records,
+            scatter_records,
+            fill_tiles,
+            max_workgroups: device
+                .limits()
+                .max_compute_workgroups_per_dimension,
+        }
+    }
+
+    /// Checks if the binner was created for an area and tile size.
+    pub(crate) fn has_size(
+        &self,
+        area_width: u32,
+        area_height: u32,
+        tile_width: u32,
+        tile_height: u32,
+    ) -> bool {
+        (
+            self.area_width,
+            self.area_height,
+            self.tile_width,
+            self.tile_height,
+        ) == (area_width, area_height, tile_width, tile_height)
+    }
+
+    /// Returns the buffer of arguments for drawing the tiles with
+    /// [`wgpu::RenderPass::draw_indirect`], once binning has run.
+    pub(crate) fn draw_args(&self) -> &wgpu::Buffer {
+        &self.draw_args
+    }
+
+    /// Records the binning of a frame.
+    ///
+    /// The primitives are written to the line array, and the records are
+    /// flattened into the buffers of the shaders. Both are pushed through
+    /// staging buffers, whose copies are submitted to the queue straight
+    /// away. The tile info and line index buffers are grown if they might
+    /// not be large enough, and are then filled by the compute passes.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `queue`: WGPU queue to enqueue the buffer writes.
+    /// - `encoder`: Command encoder to which the passes should be recorded.
+    /// - `primitives`: Primitives from
+    ///   [`crate::tiler::Tiler::take_bin_records`].
+    /// - `records`: Records from [`crate::tiler::Tiler::take_bin_records`].
+    /// - `buffers`: Buffers to write the tile info, line array and line
+    ///   indices to.
+    pub(crate) fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        primitives: Vec<StyledPrimitive>,
+        records: Vec<BinRecord>,
+        buffers: &mut Buffers,
+    ) {
+        buffers.write_line_array(device, queue, primitives);
+
+        // The buffers of the records are grown before they are pushed to.
+        // Only the bind groups of re-allocated buffers are re-created.
+        let n_polygons = records.iter().map(|r| r.polygons.len()).sum();
+        let n_points = records
+            .iter()
+            .flat_map(|r| &r.polygons)
+            .map(|polygon| polygon.vertices().len())
+            .sum();
+        let mut reallocated = reserve_pushbuf(
+            device,
+            &mut self.records,
+            RECORDS_LABEL,
+            records.len(),
+        );
+        reallocated |= reserve_pushbuf(
+            device,
+            &mut self.polygons,
+            POLYGONS_LABEL,
+            n_polygons,
+        );
+        reallocated |=
+            reserve_pushbuf(device, &mut self.points, POINTS_LABEL, n_points);
+
+        // Flatten the records into the arrays of the shaders. Each record
+        // has a bit per tile of its range in the bitmasks, and at most one
+        // line index per tile of its range.
+        self.records.begin_frame(device);
+        self.polygons.begin_frame(device);
+        self.points.begin_frame(device);
+        let mut n_words: u32 = 0;
+        let mut index_capacity: u32 = 0;
+        for record in records {
+            let (x_tiles, y_tiles) =
+                (record.tiles.x_tiles(), record.tiles.y_tiles());
+            let n_tiles = (x_tiles.end() - x_tiles.start() + 1)
+                * (y_tiles.end() - y_tiles.start() + 1);
+            let (capsule_start, capsule_end, capsule_radius) =
+                match &record.capsule {
+                    Some((line, radius)) => (
+                        [line.start().x, line.start().y],
+                        [line.end().x, line.end().y],
+                        *radius,
+                    ),
+                    None => ([0.0; 2], [0.0; 2], 0.0),
+                };
+            let gpu_record = GpuBinRecord {
+                min_tile_x: *x_tiles.start(),
+                min_tile_y: *y_tiles.start(),
+                max_tile_x: *x_tiles.end(),
+                max_tile_y: *y_tiles.end(),
+                first_polygon: self.polygons.len() as u32,
+                n_polygons: record.polygons.len() as u32,
+                first_word: n_words,
+                primitive: record.primitive,
+                capsule_start,
+                capsule_end,
+                capsule_radius,
+                has_capsule: record.capsule.is_some() as u32,
+            };
+            self.records.push(device, gpu_record).expect(RESERVED);
+            for polygon in &record.polygons {
+                let polygon_ref = PolygonRef {
+                    first_vertex: self.points.len() as u32,
+                    n_vertices: polygon.vertices().len() as u32,
+                };
+                self.polygons.push(device, polygon_ref).expect(RESERVED);
+                for p in polygon.vertices() {
+                    self.points.push(device, [p.x, p.y]).expect(RESERVED);
+                }
+            }
+            n_words += n_tiles.div_ceil(32);
+            index_capacity += n_tiles;
+        }
+        let n_records = self.records.len() as u32;
+        queue.submit([
+            self.records.end_frame(),
+            self.polygons.end_frame(),
+            self.points.end_frame(),
+        ]);
+        self.records.recall();
+        self.polygons.recall();
+        self.points.recall();
+        if self.bitmasks_capacity < n_words {
+            self.bitmasks_capacity = n_words.max(2 * self.bitmasks_capacity);
+            self.bitmasks =
+                create_bitmasks_buffer(device, self.bitmasks_capacity);
+            reallocated = true;
+        }
+
+        let n_tiles = self.n_x_tiles * self.n_y_tiles;
+        buffers.reserve_tile_info(device, n_tiles);
+        buffers.reserve_line_indices(device, index_capacity.max(1));
+        let dimensions = Dimensions {
+            n_records,
+            n_x_tiles: self.n_x_tiles,
+            n_y_tiles: self.n_y_tiles,
+            tile_width: self.tile_width,
+            tile_height: self.tile_height,
+            index_capacity,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.dimensions, 0, bytes_of(&dimensions));
+
+        if reallocated {
+            self.bin_bind_group = None;
+            self.scatter_bind_group = None;
+            self.fill_bind_group = None;
+        }
+        for bind_group in
+            [&mut self.scatter_bind_group, &mut self.fill_bind_group]
+        {
+            if bind_group.as_ref().is_some_and(|(generation, _)| {
+                *generation != buffers.generation()
+            }) {
+                *bind_group = None;
+            }
+        }
+        let bin_bind_group = self.bin_bind_group.get_or_insert_with(|| {
+            create_bind_group(
+                device,
+                &self.bin_layout,
+                &[
+                    (0, &self.dimensions),
+                    (1, self.records.buffer()),
+                    (2, self.polygons.buffer()),
+                    (3, self.points.buffer()),
+                    (4, &self.bitmasks),
+                    (5, &self.tile_counts),
+                ],
+            )
+        });
+        let (_, scatter_bind_group) =
+            self.scatter_bind_group.get_or_insert_with(|| {
+                let bind_group = create_bind_group(
+                    device,
+                    &self.scatter_layout,
+                    &[
+                        (0, &self.dimensions),
+                        (1, self.records.buffer()),
+                        (4, &self.bitmasks),
+                        (6, &self.tile_starts),
+                        (10, buffers.line_indices_buffer()),
+                        (11, &self.tile_cursors),
+                    ],
+                );
+                (buffers.generation(), bind_group)
+            });
+        let (_, fill_bind_group) =
+            self.fill_bind_group.get_or_insert_with(|| {
+                let bind_group = create_bind_group(
+                    device,
+                    &self.fill_layout,
+                    &[
+                        (0, &self.dimensions),
+                        (1, self.records.buffer()),
+                        (6, &self.tile_starts),
+                        (7, &self.occupied_tiles),
+                        (8, self.compact.count()),
+                        (9, buffers.tile_info_buffer()),
+                        (10, buffers.line_indices_buffer()),
+                    ],
+                );
+                (buffers.generation(), bind_group)
+            });
+
+        // Pass 1: count the primitives in each tile.
+        let bitmasks_size =
+            (n_words as usize * size_of::<u32>()) as wgpu::BufferAddress;
+        if bitmasks_size > 0 {
+            encoder.clear_buffer(&self.bitmasks, 0, Some(bitmasks_size));
+        }
+        encoder.clear_buffer(&self.tile_counts, 0, None);
+        encoder.clear_buffer(&self.tile_cursors, 0, None);
+        encoder.clear_buffer(&self.tile_starts, 0, None);
+        {
+            let mut pass = begin_compute_pass(encoder);
+            pass.set_pipeline(&self.bin_records);
+            pass.set_bind_group(0, &*bin_bind_group, &[]);
+            dispatch(&mut pass, dimensions.n_records, self.max_workgroups);
+        }
+
+        // Pass 2: allocate the line index array to the tiles, and find the
+        // occupied tiles. The last start, after the counts, stays at zero,
+        // so that it becomes the total.
+        let counts_size =
+            (n_tiles as usize * size_of::<u32>()) as wgpu::BufferAddress;
+        encoder.copy_buffer_to_buffer(
+            &self.tile_counts,
+            0,
+            &self.tile_starts,
+            0,
+            counts_size,
+        );
+        {
+            let mut pass = begin_compute_pass(encoder);
+            self.scan.encode(&mut pass);
+            self.compact.encode(&mut pass);
+        }
+        // The number of occupied tiles is the number of instances to draw.
+        encoder.copy_buffer_to_buffer(
+            self.compact.count(),
+            0,
+            &self.draw_args,
+            4,
+            4,
+        );
+
+        // Pass 3: write the records into the tiles which they intersect.
+        {
+            let mut pass = begin_compute_pass(encoder);
+            pass.set_pipeline(&self.scatter_records);
+            pass.set_bind_group(0, &*scatter_bind_group, &[]);
+            dispatch(&mut pass, dimensions.n_records, self.max_workgroups);
+        }
+
+        // Pass 4: fill the tiles, in the order of the records.
+        {
+            let mut pass = begin_compute_pass(encoder);
+            pass.set_pipeline(&self.fill_tiles);
+            pass.set_bind_group(0, &*fill_bind_group, &[]);
+            dispatch(&mut pass, n_tiles, self.max_workgroups);
+        }
+    }
+
+    /// Reads the results of binning back to the CPU.
+    ///
+    /// The command buffer containing the passes recorded by
+    /// [`Binner::encode`] must have been submitted before this is called.
+    /// This method blocks until the GPU has finished, so it is only
+    /// suitable for native platforms.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `queue`: WGPU queue to enqueue the copies.
+    /// - `buffers`: Buffers which were passed to [`Binner::encode`].
+    ///
+    /// # Returns
+    ///
+    /// The tile info, the line array and the line indices which were
+    /// written. Only the part of the line array which the indices refer to
+    /// is read.
+    pub(crate) fn read_back(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        buffers: &Buffers,
+    ) -> (Vec<TileInfo>, Vec<StyledLine>, Vec<u32>) {
+        let n_tile_infos: Vec<u32> =
+            read_buffer(device, queue, self.compact.count(), 1);
+        let tile_infos: Vec<TileInfo> = read_buffer(
+            device,
+            queue,
+            buffers.tile_info_buffer(),
+            n_tile_infos[0] as usize,
+        );
+        let n_lines = tile_infos
+            .iter()
+            .map(|info| info.start_index + info.n_lines)
+            .max()
+            .unwrap_or(0);
+        let line_indices: Vec<u32> = read_buffer(
+            device,
+            queue,
+            buffers.line_indices_buffer(),
+            n_lines as usize,
+        );
+        let n_primitives =
+            line_indices.iter().map(|i| i + 1).max().unwrap_or(0);
+        let lines = read_buffer(
+            device,
+            queue,
+            buffers.lines_buffer(),
+            n_primitives as usize,
+        );
+        (tile_infos, lines, line_indices)
+    }
+}
+
+/// Compares the tiles produced by CPU tiling and GPU binning.
+///
+/// # Parameters
+///
+/// - `cpu`: Tile info, line array and line indices from CPU tiling.
+/// - `gpu`: Tile info, line array and line indices from GPU binning.
+///
+/// # Returns
+///
+/// The tiles whose primitives differ, in row order. The primitives are
+/// compared through the line indices, so the two line arrays may hold them
+/// in different orders.
+pub(crate) fn compare_tiles(
+    cpu: (&[TileInfo], &[StyledLine], &[u32]),
+    gpu: (&[TileInfo], &[StyledLine], &[u32]),
+) -> Vec<TilingMismatch> {
+    let tiles_of = |(tile_infos, lines, line_indices): (
+        &[TileInfo],
+        &[StyledLine],
+        &[u32],
+    )|
+     -> BTreeMap<(u32, u32), Vec<u8>> {
+        tile_infos
+            .iter()
+            .map(|info| {
+                let range = info.start_index as usize
+                    ..(info.start_index + info.n_lines) as usize;
+                let bytes = line_indices
+                    .get(range)
+                    .unwrap_or(&[])
+                    .iter()
+                    .flat_map(|i| {
+                        lines.get(*i as usize).map_or(&[][..], bytes_of)
+                    })
+                    .copied()
+                    .collect();
+                ((info.tile_y, info.tile_x), bytes)
+            })
+            .collect()
+    };
+    let cpu_tiles = tiles_of(cpu);
+    let gpu_tiles = tiles_of(gpu);
+    let line_size = size_of::<StyledLine>();
+    cpu_tiles
+        .keys()
+        .chain(gpu_tiles.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter_map(|key| {
+            let cpu_bytes = cpu_tiles.get(key).map_or(&[][..], |bytes| bytes);
+            let gpu_bytes = gpu_tiles.get(key).map_or(&[][..], |bytes| bytes);
+            (cpu_bytes != gpu_bytes).then(|| TilingMismatch {
+                tile_x: key.1,
+                tile_y: key.0,
+                cpu_lines: (cpu_bytes.len() / line_size) as u32,
+                gpu_lines: (gpu_bytes.len() / line_size) as u32,
+            })
+        })
+        .collect()
+}
+
+/// GPU version of the binning dimensions, for the dimensions uniform
+/// buffer.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct Dimensions {
+    n_records: u32,
+    n_x_tiles: u32,
+    n_y_tiles: u32,
+    tile_width: u32,
+    tile_height: u32,
+    index_capacity: u32,
+    _padding: [u32; 2],
+}
+
+/// GPU version of a [`BinRecord`].
+///
+/// The capsule is only tested if `has_capsule` is not zero.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GpuBinRecord {
+    min_tile_x: u32,
+    min_tile_y: u32,
+    max_tile_x: u32,
+    max_tile_y: u32,
+    first_polygon: u32,
+    n_polygons: u32,
+    first_word: u32,
+    primitive: u32,
+    capsule_start: [f32; 2],
+    capsule_end: [f32; 2],
+    capsule_radius: f32,
+    has_capsule: u32,
+}
+
+/// GPU reference to the vertices of a bounding polygon, in the points
+/// buffer.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct PolygonRef {
+    first_vertex: u32,
+    n_vertices: u32,
+}
+
+/// Ensure that a buffer of the binning shaders can hold a number of
+/// values.
+///
+/// If the buffer is not large enough, it is re-created with at least twice
+/// its capacity, as the [`Buffers`] are grown.
+///
+/// # Returns
+///
+/// `true` if the buffer was re-created.
+fn reserve_pushbuf<T: NoUninit>(
+    device: &wgpu::Device,
+    pushbuf: &mut PushBuf<T>,
+    label: &str,
+    len: usize,
+) -> bool {
+    if pushbuf.capacity() >= len {
+        return false;
+    }
+    *pushbuf = create_pushbuf(device, label, len.max(2 * pushbuf.capacity()));
+    true
+}
+
+/// Create a buffer of values for the binning shaders, which are pushed to
+/// it every frame.
+///
+/// # Parameters
+///
+/// - `device`: WGPU Device.
+/// - `label`: Label of the buffer.
+/// - `capacity`: Number of values that the buffer can store.
+fn create_pushbuf<T: NoUninit>(
+    device: &wgpu::Device,
+    label: &str,
+    capacity: usize,
+) -> PushBuf<T> {
+    PushBuf::new(
+        device,
+        Some(label),
+        wgpu::BufferUsages::STORAGE,
+        capacity,
+        capacity.min(CHUNK_CAPACITY),
+    )
+}
+
+/// Create the buffer of the records' bitmasks.
+///
+/// # Parameters
+///
+/// - `device`: WGPU Device.
+/// - `capacity`: Number of `u32` words that the buffer can store.
+fn create_bitmasks_buffer(
+    device: &wgpu::Device,
+    capacity: u32,
+) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Beamline: Binning bitmasks"),
+        size: (capacity as usize * size_of::<u32>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Create a bind group of whole buffers for the binning shaders.
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    entries: &[(u32, &wgpu::Buffer)],
+) -> wgpu::BindGroup {
+    let entries: Vec<wgpu::BindGroupEntry> = entries
+        .iter()
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding: *binding,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Beamline: Binning bind group"),
+        layout,
+        entries: &entries,
+    })
+}
+
+/// Begin a compute pass for binning.
+fn begin_compute_pass(
+    encoder: &mut wgpu::CommandEncoder,
+) -> wgpu::ComputePass<'_> {
+    encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("Beamline: Binning pass"),
+        timestamp_writes: None,
+    })
+}
+
+/// Dispatch enough workgroups for a number of invocations.
+///
+/// The workgroups are laid out in two dimensions if there are more than
+/// fit in one.
+fn dispatch(
+    pass: &mut wgpu::ComputePass<'_>,
+    n_invocations: u32,
+    max_workgroups: u32,
+) {
+    let n_workgroups = n_invocations.div_ceil(WORKGROUP_SIZE);
+    if n_workgroups == 0 {
+        return;
+    }
+    let x = n_workgroups.min(max_workgroups);
+    pass.dispatch_workgroups(x, n_workgroups.div_ceil(x), 1);
+}
+
+/// Read the start of a buffer back to the CPU, blocking until it is
+/// available.
+///
+/// # Parameters
+///
+/// - `device`: WGPU Device.
+/// - `queue`: WGPU queue to enqueue the copy.
+/// - `buffer`: Buffer to read, which must have the `COPY_SRC` usage.
+/// - `len`: Number of values to read.
+fn read_buffer<T: Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    len: usize,
+) -> Vec<T> {
+    let size = (len * size_of::<T>()) as wgpu::BufferAddress;
+    if size == 0 {
+        return Vec::new();
+    }
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Beamline: Binning readback buffer"),
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Beamline: Binning readback command encoder"),
+        });
+    encoder.copy_buffer_to_buffer(buffer, 0, &readback, 0, size);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = readback.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        sender.send(result).unwrap();
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .expect("Binning readback was never mapped.")
+        .expect("Could not map binning readback buffer.");
+    let values = cast_slice(&slice.get_mapped_range()).to_vec();
+    readback.unmap();
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        style, tests::gpu::Gpu, tiler::Tiler, Line, LineCap, LineStyle, P2,
+    };
+    use proptest::prelude::*;
+
+    /// Strategy for a random line with any cap, which may start and end
+    /// outside the area.
+    fn line_strategy(
+        area_width: u32,
+        area_height: u32,
+    ) -> impl Strategy<Value = style::StyledLine> {
+        let point = (
+            -40.0..area_width as f32 + 40.0,
+            -40.0..area_height as f32 + 40.0,
+        );
+        (
+            point.clone(),
+            point,
+            0.5f32..24.0,
+            prop_oneof![
+                Just(LineCap::Butt),
+                Just(LineCap::Round),
+                Just(LineCap::Square)
+            ],
+        )
+            .prop_map(|(a, b, width, cap)| style::StyledLine {
+                line: Line::new(P2::new(a.0, a.1), P2::new(b.0, b.1)),
+                style: LineStyle {
+                    width,
+                    cap,
+                    ..Default::default()
+                },
+            })
+    }
+
+    /// Area and tile size, as the arguments of [`Binner::new`].
+    type Size = (u32, u32, u32, u32);
+
+    /// Strategy for an area, a tile size, and several frames of random
+    /// lines in the area.
+    fn scene_strategy(
+    ) -> impl Strategy<Value = (Size, Vec<Vec<style::StyledLine>>)> {
+        (1u32..400, 1u32..300, 4u32..40, 4u32..40).prop_flat_map(|size| {
+            let lines =
+                prop::collection::vec(line_strategy(size.0, size.1), 0..300);
+            (Just(size), prop::collection::vec(lines, 1..4))
+        })
+    }
+
+    /// Tiles lines with the CPU `Tiler`.
+    ///
+    /// # Returns
+    ///
+    /// The tile info, line array and line indices, as they are uploaded.
+    fn tiler_tiles(
+        size: Size,
+        lines: &[style::StyledLine],
+    ) -> (Vec<TileInfo>, Vec<StyledLine>, Vec<u32>) {
+        let mut tiler = Tiler::new(size.0, size.1, size.2, size.3);
+        for line in lines {
+            tiler.add(line.clone());
+        }
+        let (tile_infos, primitives, line_indices) = tiler.drain();
+        (
+            tile_infos
+                .into_iter()
+                .map(TileInfo::new_from_tiler_tileinfo)
+                .collect(),
+            primitives
+                .into_iter()
+                .map(StyledLine::new_from_styled_primitive)
+                .collect(),
+            line_indices,
+        )
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        /// GPU binning assigns the same primitives to each tile as the CPU
+        /// `Tiler`, on random scenes.
+        ///
+        /// Several frames of random lines are binned by the same `Binner`,
+        /// so that stale bitmasks or counts from an earlier frame would show
+        /// up as differences.
+        #[test]
+        fn test_binning_matches_tiler((size, frames) in scene_strategy()) {
+            let gpu = Gpu::new();
+            let mut binner =
+                Binner::new(&gpu.device, size.0, size.1, size.2, size.3);
+            let mut buffers = Buffers::new(&gpu.device, 1, 1);
+            let mut tiler = Tiler::new(size.0, size.1, size.2, size.3);
+            tiler.set_defer_binning(true);
+            for lines in frames {
+                for line in &lines {
+                    tiler.add(line.clone());
+                }
+                let (primitives, records) = tiler.take_bin_records();
+                let mut encoder = gpu.device.create_command_encoder(
+                    &wgpu::CommandEncoderDescriptor {
+                        label: Some("Binning test command encoder"),
+                    },
+                );
+                binner.encode(
+                    &gpu.device,
+                    &gpu.queue,
+                    &mut encoder,
+                    primitives,
+                    records,
+                    &mut buffers,
+                );
+                gpu.queue.submit(std::iter::once(encoder.finish()));
+                let binned =
+                    binner.read_back(&gpu.device, &gpu.queue, &buffers);
+
+                let tiled = tiler_tiles(size, &lines);
+                let mismatches = compare_tiles(
+                    (&tiled.0, &tiled.1, &tiled.2),
+                    (&binned.0, &binned.1, &binned.2),
+                );
+                prop_assert_eq!(mismatches, []);
+                prop_assert_eq!(binned.0.len(), tiled.0.len());
+            }
+        }
+    }
+}
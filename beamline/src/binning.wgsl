@@ -0,0 +1,522 @@
+/**
+ * NAME:     binning.wgsl
+ * PURPOSE:  Compute shaders which assign primitives to tiles, and gather
+ *           them into the tile info and line index arrays of the line
+ *           shader.
+ */
+
+/**
+ * Binning runs as these passes, which are dispatched in order:
+ *
+ *  1. `bin_records` runs once per record. It tests the record's bounding
+ *     polygons and capsule against each tile of its range, sets a bit in
+ *     the record's bitmask for each tile that it intersects, and counts the
+ *     records in each tile.
+ *  2. The counts are copied to `tile_starts` and scanned, which gives the
+ *     start of each tile's primitives in the line index array. The counts are
+ *     also compacted into the linear indices of the occupied tiles, and
+ *     their number.
+ *  3. `scatter_records` runs once per record. It writes the index of the
+ *     record into the line index array, in each tile that the record
+ *     intersects, at a slot taken from the tile's cursor.
+ *  4. `fill_tiles` runs once per occupied tile. It writes the tile info,
+ *     sorts the tile's record indices back into the order of the records,
+ *     and replaces them with the indices of the records' primitives.
+ *
+ * The work of the last two passes is proportional to the number of records
+ * in each tile, rather than to the number of records in the scene.
+ *
+ * The primitives themselves are uploaded to the line array once each, before
+ * binning, and are not touched by these shaders.
+ *
+ * The bitmasks, the counts and the cursors must be cleared before the first
+ * pass.
+ *
+ * The separating axis test matches `Tiler::tile_intersects_polygon`, with
+ * the candidate axes of `with_separating_axes` in `tiler.rs`, which are
+ * found from the vertices of each polygon. The capsule test matches
+ * `Tiler::tile_intersects_capsule`.
+ */
+
+/**** BINDINGS ************************************************************* */
+
+@group(0) @binding(0)  var<uniform>             dimensions     : Dimensions;
+@group(0) @binding(1)  var<storage, read>       records        : array<BinRecord>;
+@group(0) @binding(2)  var<storage, read>       polygons       : array<PolygonRef>;
+@group(0) @binding(3)  var<storage, read>       points         : array<vec2f>;
+@group(0) @binding(4)  var<storage, read_write> bitmasks       : array<u32>;
+@group(0) @binding(5)  var<storage, read_write> tile_counts    : array<atomic<u32>>;
+@group(0) @binding(6)  var<storage, read>       tile_starts    : array<u32>;
+@group(0) @binding(7)  var<storage, read>       occupied_tiles : array<u32>;
+@group(0) @binding(8)  var<storage, read>       n_tile_infos   : u32;
+@group(0) @binding(9)  var<storage, read_write> tile_infos     : array<TileInfo>;
+@group(0) @binding(10) var<storage, read_write> line_indices   : array<u32>;
+@group(0) @binding(11) var<storage, read_write> tile_cursors   : array<atomic<u32>>;
+
+/**** STRUCTS ****************************************************************/
+
+/// Dimensions of the binning problem.
+///
+/// The line index array has room for `index_capacity` indices.
+struct Dimensions {
+    n_records      : u32,
+    n_tiles_x      : u32,
+    n_tiles_y      : u32,
+    tile_width     : u32,
+    tile_height    : u32,
+    index_capacity : u32,
+    _padding0      : u32,
+    _padding1      : u32
+};
+
+/// Record of a primitive to bin.
+///
+/// The record's bitmask starts at `first_word`, and has one bit per tile of
+/// its range, in row order. A record with no polygons is in every tile of
+/// its range. If `has_capsule` is not zero, the record is only in the tiles
+/// which also intersect its capsule: the points within `capsule_radius` of
+/// the segment from `capsule_start` to `capsule_end`. Its primitive is at
+/// index `primitive` of the line array.
+struct BinRecord {
+    min_tile_x     : u32,
+    min_tile_y     : u32,
+    max_tile_x     : u32,
+    max_tile_y     : u32,
+    first_polygon  : u32,
+    n_polygons     : u32,
+    first_word     : u32,
+    primitive      : u32,
+    capsule_start  : vec2f,
+    capsule_end    : vec2f,
+    capsule_radius : f32,
+    has_capsule    : u32
+};
+
+/// Bounding polygon of a record: the range of `points` holding its
+/// vertices.
+struct PolygonRef {
+    first_vertex : u32,
+    n_vertices   : u32
+};
+
+/// Candidate separating axes of a bounding polygon, and the projections of
+/// the polygon onto them.
+///
+/// Bit `i` of `edges` is set if edge `i` of the polygon is an axis, and
+/// `intervals[i]` is then the projection onto it. Only the first 32 edges
+/// fit, and `polygon_intersects_tile` checks any further edges itself.
+/// `x_interval` and `y_interval` are the projections onto the edges of the
+/// tiles.
+struct PolygonAxes {
+    edges      : u32,
+    intervals  : array<vec2f, 32>,
+    x_interval : vec2f,
+    y_interval : vec2f
+};
+
+/// Tile information, as it is consumed by the line shader.
+struct TileInfo {
+    tile_x      : u32,
+    tile_y      : u32,
+    start_index : u32,
+    n_lines     : u32
+};
+
+/**** CONSTANTS **************************************************************/
+
+/// Number of invocations in a workgroup.
+const WORKGROUP_SIZE : u32 = 64u;
+
+/**** COMPUTE SHADERS ********************************************************/
+
+/// Pass 1: Finds the tiles that each record intersects.
+@compute @workgroup_size(WORKGROUP_SIZE) fn bin_records(
+    @builtin(global_invocation_id) global_id      : vec3<u32>,
+    @builtin(num_workgroups)       num_workgroups : vec3<u32>
+) {
+    let record_i = invocation_index(global_id, num_workgroups);
+    if (record_i >= dimensions.n_records) {
+        return;
+    }
+    let record = records[record_i];
+
+    // Set the bits of the tiles which intersect any of the bounding
+    // polygons. The axes of each polygon, and its projections onto them,
+    // are found once rather than for every tile.
+    for (var i = 0u; i < record.n_polygons; i++) {
+        let polygon = polygons[record.first_polygon + i];
+        var axes = polygon_axes(polygon);
+        for (var y = record.min_tile_y; y <= record.max_tile_y; y++) {
+            for (var x = record.min_tile_x; x <= record.max_tile_x; x++) {
+                let bit = range_bit(record, x, y);
+                let word = record.first_word + bit / 32u;
+                let mask = 1u << (bit % 32u);
+                if ((bitmasks[word] & mask) == 0u
+                    && polygon_intersects_tile(polygon, &axes, x, y)) {
+                    bitmasks[word] = bitmasks[word] | mask;
+                }
+            }
+        }
+    }
+
+    // Count the record in the tiles which it intersects. A record with no
+    // polygons is in every tile of its range, and a record with a capsule
+    // is only in the tiles which also intersect the capsule.
+    for (var y = record.min_tile_y; y <= record.max_tile_y; y++) {
+        for (var x = record.min_tile_x; x <= record.max_tile_x; x++) {
+            let bit = range_bit(record, x, y);
+            let word = record.first_word + bit / 32u;
+            let mask = 1u << (bit % 32u);
+            var intersects = record.n_polygons == 0u
+                || (bitmasks[word] & mask) != 0u;
+            if (intersects && record.has_capsule != 0u) {
+                intersects = capsule_intersects_tile(record, x, y);
+            }
+            if (intersects) {
+                bitmasks[word] = bitmasks[word] | mask;
+                atomicAdd(&tile_counts[y * dimensions.n_tiles_x + x], 1u);
+            } else {
+                bitmasks[word] = bitmasks[word] & ~mask;
+            }
+        }
+    }
+}
+
+/// Pass 3: Writes the index of each record into the line index array, in
+/// the tiles which it intersects.
+///
+/// The records of a tile are written in the order in which they take slots
+/// from the tile's cursor, which `fill_tiles` then corrects. Records which
+/// do not fit in the line index array are dropped.
+@compute @workgroup_size(WORKGROUP_SIZE) fn scatter_records(
+    @builtin(global_invocation_id) global_id      : vec3<u32>,
+    @builtin(num_workgroups)       num_workgroups : vec3<u32>
+) {
+    let record_i = invocation_index(global_id, num_workgroups);
+    if (record_i >= dimensions.n_records) {
+        return;
+    }
+    let record = records[record_i];
+
+    for (var y = record.min_tile_y; y <= record.max_tile_y; y++) {
+        for (var x = record.min_tile_x; x <= record.max_tile_x; x++) {
+            let bit = range_bit(record, x, y);
+            let word = bitmasks[record.first_word + bit / 32u];
+            if ((word & (1u << (bit % 32u))) != 0u) {
+                let tile_i = y * dimensions.n_tiles_x + x;
+                let slot = atomicAdd(&tile_cursors[tile_i], 1u);
+                let line_i = tile_starts[tile_i] + slot;
+                if (line_i < dimensions.index_capacity) {
+                    line_indices[line_i] = record_i;
+                }
+            }
+        }
+    }
+}
+
+/// Pass 4: Writes the tile info of each occupied tile, and turns the record
+/// indices of its part of the line index array into the indices of their
+/// primitives, in the order of the records.
+@compute @workgroup_size(WORKGROUP_SIZE) fn fill_tiles(
+    @builtin(global_invocation_id) global_id      : vec3<u32>,
+    @builtin(num_workgroups)       num_workgroups : vec3<u32>
+) {
+    let info_i = invocation_index(global_id, num_workgroups);
+    if (info_i >= n_tile_infos) {
+        return;
+    }
+
+    let tile_i = occupied_tiles[info_i];
+    let x = tile_i % dimensions.n_tiles_x;
+    let y = tile_i / dimensions.n_tiles_x;
+    let start = min(tile_starts[tile_i], dimensions.index_capacity);
+    let end = min(tile_starts[tile_i + 1u], dimensions.index_capacity);
+    tile_infos[info_i] = TileInfo(x, y, start, end - start);
+
+    sort_line_indices(start, end);
+    for (var line_i = start; line_i < end; line_i++) {
+        line_indices[line_i] = records[line_indices[line_i]].primitive;
+    }
+}
+
+/**** FUNCTIONS USING GLOBALS ************************************************/
+
+/// Finds the candidate separating axes of a bounding polygon, and projects
+/// the polygon onto them.
+fn polygon_axes(polygon: PolygonRef) -> PolygonAxes {
+    var axes: PolygonAxes;
+    for (var i = 0u; i < min(polygon.n_vertices, 32u); i++) {
+        if (is_edge_axis(polygon, i)) {
+            axes.edges = axes.edges | (1u << i);
+            let axis = polygon_edge(polygon, i);
+            axes.intervals[i] = polygon_interval(polygon, axis);
+        }
+    }
+    axes.x_interval = polygon_interval(polygon, vec2f(1.0, 0.0));
+    axes.y_interval = polygon_interval(polygon, vec2f(0.0, 1.0));
+    return axes;
+}
+
+/// Checks if a bounding polygon intersects a tile, using the separating
+/// axis test.
+///
+/// The candidate axes are the edges of the polygon which are found by
+/// `polygon_axes`, followed by the edges of the tile.
+fn polygon_intersects_tile(
+    polygon: PolygonRef,
+    axes: ptr<function, PolygonAxes>,
+    x: u32,
+    y: u32
+) -> bool {
+    let tile_size = vec2f(f32(dimensions.tile_width), f32(dimensions.tile_height));
+    let tile_min = tile_size * vec2f(f32(x), f32(y));
+    let tile_max = tile_min + tile_size;
+
+    var intersects = true;
+    for (var i = 0u; i < polygon.n_vertices && intersects; i++) {
+        if (i < 32u) {
+            if (((*axes).edges & (1u << i)) != 0u) {
+                let axis = polygon_edge(polygon, i);
+                intersects = !is_separating_axis(
+                    (*axes).intervals[i], tile_min, tile_max, axis
+                );
+            }
+        } else if (is_edge_axis(polygon, i)) {
+            let axis = polygon_edge(polygon, i);
+            intersects = !is_separating_axis(
+                polygon_interval(polygon, axis), tile_min, tile_max, axis
+            );
+        }
+    }
+    return intersects
+        && !is_separating_axis(
+            (*axes).x_interval, tile_min, tile_max, vec2f(1.0, 0.0)
+        )
+        && !is_separating_axis(
+            (*axes).y_interval, tile_min, tile_max, vec2f(0.0, 1.0)
+        );
+}
+
+/// Checks if an edge of a bounding polygon is one of its candidate
+/// separating axes.
+///
+/// This matches `Polygon::edge_axes`: degenerate edges, and edges which are
+/// parallel to an earlier edge, are skipped. Only squared magnitudes are
+/// compared, so that they are rounded in the same way as on the CPU.
+fn is_edge_axis(polygon: PolygonRef, i: u32) -> bool {
+    let edge = polygon_edge(polygon, i);
+    let edge2 = edge.x * edge.x + edge.y * edge.y;
+    if (!(edge2 > 0.0)) {
+        return false;
+    }
+    for (var j = 0u; j < i; j++) {
+        let earlier = polygon_edge(polygon, j);
+        let earlier2 = earlier.x * earlier.x + earlier.y * earlier.y;
+        let cross = perp_dot(earlier, edge);
+        if (earlier2 > 0.0 && cross * cross <= 1e-12 * earlier2 * edge2) {
+            return false;
+        }
+    }
+    return true;
+}
+
+/// Computes an edge of a bounding polygon, from vertex `i` to the next
+/// vertex.
+fn polygon_edge(polygon: PolygonRef, i: u32) -> vec2f {
+    let start = points[polygon.first_vertex + i];
+    let end = points[polygon.first_vertex + (i + 1u) % polygon.n_vertices];
+    return end - start;
+}
+
+/// Projects a bounding polygon onto the normal of an axis, as the interval
+/// between its lowest and highest projected vertices.
+fn polygon_interval(polygon: PolygonRef, axis: vec2f) -> vec2f {
+    let direction = vec2f(-axis.y, axis.x);
+
+    var interval = vec2f(project(points[polygon.first_vertex], direction));
+    for (var j = 1u; j < polygon.n_vertices; j++) {
+        let p = project(points[polygon.first_vertex + j], direction);
+        interval = vec2f(min(interval.x, p), max(interval.y, p));
+    }
+    return interval;
+}
+
+/// Checks if an axis separates a bounding polygon from a tile, given the
+/// projection of the polygon onto the axis (see `polygon_interval`).
+fn is_separating_axis(
+    polygon_interval: vec2f,
+    tile_min: vec2f,
+    tile_max: vec2f,
+    axis: vec2f
+) -> bool {
+    let direction = vec2f(-axis.y, axis.x);
+
+    let corners = vec4f(
+        project(tile_min, direction),
+        project(vec2f(tile_max.x, tile_min.y), direction),
+        project(tile_max, direction),
+        project(vec2f(tile_min.x, tile_max.y), direction)
+    );
+    let tile_interval = vec2f(
+        min(min(corners.x, corners.y), min(corners.z, corners.w)),
+        max(max(corners.x, corners.y), max(corners.z, corners.w))
+    );
+
+    return polygon_interval.y < tile_interval.x || tile_interval.y < polygon_interval.x;
+}
+
+/// Checks exactly if a record's capsule intersects a tile.
+///
+/// This matches `Tiler::tile_intersects_capsule`: the tile intersects the
+/// capsule if the segment enters the tile, or if an end of the segment or a
+/// corner of the tile is within the radius. Only squared distances are
+/// compared, so that the same products and sums are rounded in the same way
+/// as on the CPU.
+fn capsule_intersects_tile(record: BinRecord, x: u32, y: u32) -> bool {
+    let tile_size = vec2f(f32(dimensions.tile_width), f32(dimensions.tile_height));
+    let tile_min = tile_size * vec2f(f32(x), f32(y));
+    let tile_max = tile_min + tile_size;
+    let corners = array<vec2f, 4>(
+        tile_min,
+        vec2f(tile_max.x, tile_min.y),
+        tile_max,
+        vec2f(tile_min.x, tile_max.y)
+    );
+    let a = record.capsule_start;
+    let b = record.capsule_end;
+    let radius2 = record.capsule_radius * record.capsule_radius;
+
+    if (segment_enters_rect(a, b, corners)) {
+        return true;
+    }
+    if (rect_point_distance2(tile_min, tile_max, a) <= radius2
+        || rect_point_distance2(tile_min, tile_max, b) <= radius2) {
+        return true;
+    }
+    for (var i = 0; i < 4; i++) {
+        if (segment_point_is_within(a, b, corners[i], radius2)) {
+            return true;
+        }
+    }
+    return false;
+}
+
+/// Sorts part of the line index array into increasing order.
+///
+/// This is a heapsort, which needs no more storage, and takes a number of
+/// steps proportional to `n log n` for `n` indices. The indices are distinct,
+/// so it does not matter that it is not stable.
+fn sort_line_indices(start: u32, end: u32) {
+    let n = end - start;
+    for (var root = n / 2u; root > 0u; root--) {
+        sift_down(start, root - 1u, n);
+    }
+    for (var heap_end = n; heap_end > 1u; heap_end--) {
+        swap_line_indices(start, start + heap_end - 1u);
+        sift_down(start, 0u, heap_end - 1u);
+    }
+}
+
+/// Moves an index of a max-heap, of the `n` line indices from `start`, down
+/// from `root` until it is no smaller than its children.
+fn sift_down(start: u32, root: u32, n: u32) {
+    var parent = root;
+    loop {
+        var child = 2u * parent + 1u;
+        if (child >= n) {
+            break;
+        }
+        if (child + 1u < n
+            && line_indices[start + child] < line_indices[start + child + 1u]) {
+            child++;
+        }
+        if (line_indices[start + parent] >= line_indices[start + child]) {
+            break;
+        }
+        swap_line_indices(start + parent, start + child);
+        parent = child;
+    }
+}
+
+/// Swaps two entries of the line index array.
+fn swap_line_indices(i: u32, j: u32) {
+    let index = line_indices[i];
+    line_indices[i] = line_indices[j];
+    line_indices[j] = index;
+}
+
+/**** PURE FUNCTIONS *********************************************************/
+
+/// Checks if the segment from `a` to `b` has any point in a rectangle,
+/// including its edges. This matches `segment_enters_rect` in `tiler.rs`.
+fn segment_enters_rect(a: vec2f, b: vec2f, corners: array<vec2f, 4>) -> bool {
+    let tile_min = corners[0];
+    let tile_max = corners[2];
+    if (max(a.x, b.x) < tile_min.x
+        || min(a.x, b.x) > tile_max.x
+        || max(a.y, b.y) < tile_min.y
+        || min(a.y, b.y) > tile_max.y) {
+        return false;
+    }
+    let e = b - a;
+    var min_side = perp_dot(e, corners[0] - a);
+    var max_side = min_side;
+    for (var i = 1; i < 4; i++) {
+        let side = perp_dot(e, corners[i] - a);
+        min_side = min(min_side, side);
+        max_side = max(max_side, side);
+    }
+    return min_side <= 0.0 && max_side >= 0.0;
+}
+
+/// Squared distance between a point and a rectangle, which is zero for
+/// points inside it.
+fn rect_point_distance2(tile_min: vec2f, tile_max: vec2f, p: vec2f) -> f32 {
+    let d = max(max(tile_min - p, p - tile_max), vec2f(0.0));
+    return d.x * d.x + d.y * d.y;
+}
+
+/// Checks if a point is within a distance of the segment from `a` to `b`,
+/// given the square of the distance. This matches `segment_point_is_within`
+/// in `tiler.rs`.
+fn segment_point_is_within(a: vec2f, b: vec2f, p: vec2f, distance2: f32) -> bool {
+    let e = b - a;
+    let w = p - a;
+    let t = w.x * e.x + w.y * e.y;
+    let e2 = e.x * e.x + e.y * e.y;
+    if (t <= 0.0) {
+        return w.x * w.x + w.y * w.y <= distance2;
+    }
+    if (t >= e2) {
+        let v = p - b;
+        return v.x * v.x + v.y * v.y <= distance2;
+    }
+    let c = perp_dot(e, w);
+    return c * c <= distance2 * e2;
+}
+
+/// Computes the index of a tile's bit in a record's bitmask, in row order
+/// over the record's range of tiles.
+fn range_bit(record: BinRecord, x: u32, y: u32) -> u32 {
+    let range_width = record.max_tile_x - record.min_tile_x + 1u;
+    return (y - record.min_tile_y) * range_width + (x - record.min_tile_x);
+}
+
+/// Computes the perpendicular dot product of two vectors: the z component
+/// of their cross product.
+fn perp_dot(u: vec2f, v: vec2f) -> f32 {
+    return u.x * v.y - u.y * v.x;
+}
+
+/// Projects a point onto a direction.
+fn project(p: vec2f, direction: vec2f) -> f32 {
+    return p.x * direction.x + p.y * direction.y;
+}
+
+/// Computes the linear index of an invocation.
+///
+/// Large problems need more workgroups than fit in one dimension of a
+/// dispatch, so the workgroups are laid out in two dimensions.
+fn invocation_index(global_id: vec3<u32>, num_workgroups: vec3<u32>) -> u32 {
+    return global_id.y * num_workgroups.x * WORKGROUP_SIZE + global_id.x;
+}
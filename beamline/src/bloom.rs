@@ -0,0 +1,651 @@
+//! Bloom: light from bright lines bleeding into their surroundings.
+//!
+//! When bloom is enabled (see [`crate::Renderer::set_bloom`]), lines are
+//! drawn into a floating-point HDR scene texture instead of the output. The
+//! parts of the scene which are brighter than a threshold are extracted into
+//! the first of a chain of bloom levels, each half the size of the previous
+//! one. Each level is blurred with a separable Gaussian kernel and then
+//! downsampled into the next. Finally, the levels are upsampled, added to
+//! the scene, and the result is tone-mapped and blended over the output.
+
+use crate::{
+    buffers::BloomOptions,
+    renderer::{create_fullscreen_pipeline, HDR_FORMAT},
+};
+use bytemuck::bytes_of;
+
+/// Operator which maps HDR colors into the displayable range.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ToneMap {
+    /// Clamp each channel to one. Colors which are not too bright are
+    /// unchanged, but bright colors lose their hue.
+    Clamp = 0,
+    /// Reinhard's operator, `c / (1 + c)`. This compresses all colors,
+    /// darkening those which are not bright.
+    Reinhard = 1,
+    /// Narkowicz's fit of the ACES filmic curve, which keeps more contrast
+    /// than Reinhard's operator.
+    Aces = 2,
+}
+
+/// Bloom post-process.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bloom {
+    /// Brightness above which pixels bloom. The brightness of a pixel is the
+    /// largest of its premultiplied red, green and blue channels, so lines
+    /// brighter than one (see [`crate::Color`]) can bloom with a threshold
+    /// of one.
+    pub threshold: f32,
+    /// Strength of the bloom added to the scene.
+    pub intensity: f32,
+    /// Number of bloom levels. Each level is half the size of the previous
+    /// one, and spreads light twice as far. This is limited by the size of
+    /// the renderable area.
+    pub levels: u32,
+    /// Operator applied after the bloom is added to the scene.
+    pub tone_map: ToneMap,
+}
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom {
+            threshold: 0.6,
+            intensity: 1.0,
+            levels: 5,
+            tone_map: ToneMap::Aces,
+        }
+    }
+}
+impl Bloom {
+    /// Returns the GPU options for this bloom.
+    ///
+    /// # Parameters
+    ///
+    /// - `n_levels`: Number of bloom levels actually used (see
+    ///   [`level_sizes`]).
+    pub(crate) fn options(&self, n_levels: u32) -> BloomOptions {
+        BloomOptions {
+            threshold: self.threshold,
+            intensity: self.intensity,
+            n_levels,
+            tone_map: self.tone_map as u32,
+        }
+    }
+}
+
+/// Returns the sizes of the bloom levels for a renderable area.
+///
+/// The first level is half the size of the area, rounded down, and each
+/// further level is half the size of the previous one, like the mip levels
+/// of a texture. Levels stop when both dimensions reach one.
+///
+/// # Parameters
+///
+/// - `area_width`: Width of the renderable area.
+/// - `area_height`: Height of the renderable area.
+/// - `levels`: Number of levels requested. At least one level is used.
+pub(crate) fn level_sizes(
+    area_width: u32,
+    area_height: u32,
+    levels: u32,
+) -> Vec<(u32, u32)> {
+    let width = (area_width / 2).max(1);
+    let height = (area_height / 2).max(1);
+    let max_levels = 32 - width.max(height).leading_zeros();
+    (0..levels.clamp(1, max_levels))
+        .map(|level| ((width >> level).max(1), (height >> level).max(1)))
+        .collect()
+}
+
+/// Textures and pipelines of the bloom post-process.
+#[derive(Debug)]
+pub(crate) struct BloomTarget {
+    width: u32,
+    height: u32,
+    levels: u32,
+    n_levels: u32,
+    /// View of the HDR scene texture, into which lines are drawn.
+    scene_view: wgpu::TextureView,
+    /// Views of each bloom level.
+    level_views: Vec<wgpu::TextureView>,
+    /// Views of each level of the texture holding horizontally-blurred
+    /// bloom levels.
+    scratch_views: Vec<wgpu::TextureView>,
+    /// View of every bloom level, for compositing.
+    levels_view: wgpu::TextureView,
+    options_buffer: wgpu::Buffer,
+    pass_layout: wgpu::BindGroupLayout,
+    composite_layout: wgpu::BindGroupLayout,
+    extract_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    blur_horizontal_pipeline: wgpu::RenderPipeline,
+    blur_vertical_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+}
+impl BloomTarget {
+    /// Creates a new `BloomTarget`.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `width`: Width of the scene texture.
+    /// - `height`: Height of the scene texture.
+    /// - `levels`: Number of bloom levels requested (see [`level_sizes`]).
+    /// - `output_format`: Format of the textures that the result is blended
+    ///   over.
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        levels: u32,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let sizes = level_sizes(width, height, levels);
+        let n_levels = sizes.len() as u32;
+        let create_texture = |label, width, height, mip_level_count| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HDR_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+        };
+        let mip_views = |texture: &wgpu::Texture| {
+            (0..n_levels)
+                .map(|level| {
+                    texture.create_view(&wgpu::TextureViewDescriptor {
+                        base_mip_level: level,
+                        mip_level_count: Some(1),
+                        ..Default::default()
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let scene =
+            create_texture("Beamline: Bloom scene texture", width, height, 1);
+        let (level_width, level_height) = sizes[0];
+        let levels_texture = create_texture(
+            "Beamline: Bloom levels texture",
+            level_width,
+            level_height,
+            n_levels,
+        );
+        let scratch = create_texture(
+            "Beamline: Bloom scratch texture",
+            level_width,
+            level_height,
+            n_levels,
+        );
+
+        let options_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Beamline: Bloom options buffer"),
+            size: std::mem::size_of::<BloomOptions>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float {
+                    filterable: false,
+                },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let options_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let pass_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Beamline: Bloom pass bind group layout."),
+                entries: &[options_entry, texture_entry(1)],
+            });
+        let composite_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Beamline: Bloom composite bind group layout."),
+                entries: &[options_entry, texture_entry(1), texture_entry(2)],
+            });
+
+        let shader =
+            device.create_shader_module(wgpu::include_wgsl!("bloom.wgsl"));
+        let pass_pipeline = |entry_point| {
+            create_fullscreen_pipeline(
+                device,
+                "Beamline: Bloom pass pipeline",
+                &shader,
+                entry_point,
+                &[&pass_layout],
+                HDR_FORMAT,
+                None,
+            )
+        };
+        let extract_pipeline = pass_pipeline("fs_extract");
+        let downsample_pipeline = pass_pipeline("fs_downsample");
+        let blur_horizontal_pipeline = pass_pipeline("fs_blur_horizontal");
+        let blur_vertical_pipeline = pass_pipeline("fs_blur_vertical");
+        let composite_pipeline = create_fullscreen_pipeline(
+            device,
+            "Beamline: Bloom composite pipeline",
+            &shader,
+            "fs_composite",
+            &[&composite_layout],
+            output_format,
+            Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+        );
+
+        BloomTarget {
+            width,
+            height,
+            levels,
+            n_levels,
+            scene_view: scene
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+            level_views: mip_views(&levels_texture),
+            scratch_views: mip_views(&scratch),
+            levels_view: levels_texture
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+            options_buffer,
+            pass_layout,
+            composite_layout,
+            extract_pipeline,
+            downsample_pipeline,
+            blur_horizontal_pipeline,
+            blur_vertical_pipeline,
+            composite_pipeline,
+        }
+    }
+
+    /// Returns whether this target was created for a renderable area and
+    /// number of levels.
+    ///
+    /// # Parameters
+    ///
+    /// - `width`: Width of the renderable area.
+    /// - `height`: Height of the renderable area.
+    /// - `levels`: Number of bloom levels requested.
+    pub fn matches(&self, width: u32, height: u32, levels: u32) -> bool {
+        self.width == width && self.height == height && self.levels == levels
+    }
+
+    /// Returns a view of the HDR scene texture, for drawing lines into.
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.scene_view
+    }
+
+    /// Records a render pass which clears the scene texture to transparent
+    /// black.
+    ///
+    /// # Parameters
+    ///
+    /// - `encoder`: Command encoder to which the pass should be recorded.
+    pub fn clear_scene(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Beamline: Bloom scene clear pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.scene_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+    }
+
+    /// Records the passes which extract, blur and composite the bloom of a
+    /// scene.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `encoder`: Command encoder to which the passes should be recorded.
+    /// - `queue`: WGPU Queue, used to write the bloom options.
+    /// - `bloom`: Bloom options.
+    /// - `scene`: View of the HDR scene. This is either
+    ///   [`BloomTarget::scene_view`], or another texture of the same size
+    ///   and format.
+    /// - `output_texture`: Texture view to blend the result over.
+    pub fn apply(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        bloom: &Bloom,
+        scene: &wgpu::TextureView,
+        output_texture: &wgpu::TextureView,
+    ) {
+        queue.write_buffer(
+            &self.options_buffer,
+            0,
+            bytes_of(&bloom.options(self.n_levels)),
+        );
+
+        let pass_bind_group = |source: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Beamline: Bloom pass bind group."),
+                layout: &self.pass_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.options_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                ],
+            })
+        };
+
+        // Every pass covers its whole target, so there is nothing to load.
+        const CLEAR: wgpu::LoadOp<wgpu::Color> =
+            wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT);
+        for level in 0..self.n_levels as usize {
+            let (pipeline, source) = match level {
+                0 => (&self.extract_pipeline, scene),
+                _ => (&self.downsample_pipeline, &self.level_views[level - 1]),
+            };
+            let target = &self.level_views[level];
+            let scratch = &self.scratch_views[level];
+            fullscreen_pass(
+                encoder,
+                pipeline,
+                &pass_bind_group(source),
+                target,
+                CLEAR,
+            );
+            fullscreen_pass(
+                encoder,
+                &self.blur_horizontal_pipeline,
+                &pass_bind_group(target),
+                scratch,
+                CLEAR,
+            );
+            fullscreen_pass(
+                encoder,
+                &self.blur_vertical_pipeline,
+                &pass_bind_group(scratch),
+                target,
+                CLEAR,
+            );
+        }
+
+        let composite_bind_group =
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Beamline: Bloom composite bind group."),
+                layout: &self.composite_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.options_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(scene),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(
+                            &self.levels_view,
+                        ),
+                    },
+                ],
+            });
+        fullscreen_pass(
+            encoder,
+            &self.composite_pipeline,
+            &composite_bind_group,
+            output_texture,
+            wgpu::LoadOp::Load,
+        );
+    }
+}
+
+/// Records a render pass which draws a single fullscreen triangle.
+///
+/// # Parameters
+///
+/// - `encoder`: Command encoder to which the pass should be recorded.
+/// - `pipeline`: Pipeline to draw with.
+/// - `bind_group`: Bind group 0 of the pipeline.
+/// - `target`: Texture view to draw into.
+/// - `load`: How the target is loaded.
+fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    target: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+) {
+    let mut render_pass =
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Beamline: Bloom pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{gpu::Gpu, parity::pixel_at},
+        Color, Image, Line, LineStyle, Phosphor, Renderer, SoftwareRenderer,
+        P2, V2,
+    };
+
+    #[test]
+    fn test_level_sizes() {
+        assert_eq!(
+            level_sizes(150, 100, 5),
+            vec![(75, 50), (37, 25), (18, 12), (9, 6), (4, 3)]
+        );
+        assert_eq!(level_sizes(8, 3, 10), vec![(4, 1), (2, 1), (1, 1)]);
+        assert_eq!(level_sizes(1, 1, 0), vec![(1, 1)]);
+    }
+
+    /// Returns a scene of crossing lines, some brighter than one.
+    fn bloom_scene() -> Vec<(Line, LineStyle)> {
+        vec![
+            (
+                Line::new(P2::new(10.0, 20.0), P2::new(140.0, 80.0)),
+                LineStyle {
+                    width: 4.0,
+                    color: Color::new(3.0, 1.5, 0.5, 1.0),
+                    ..Default::default()
+                },
+            ),
+            (
+                Line::new(P2::new(10.0, 80.0), P2::new(140.0, 20.0)),
+                LineStyle {
+                    width: 6.0,
+                    color: Color::new(0.2, 0.6, 2.0, 0.7),
+                    ..Default::default()
+                },
+            ),
+            (
+                Line::new(P2::new(75.0, 5.0), P2::new(75.0, 95.0)),
+                LineStyle {
+                    width: 2.0,
+                    color: Color::new(0.8, 0.8, 0.8, 1.0),
+                    ..Default::default()
+                },
+            ),
+        ]
+    }
+
+    /// Renders the bloom scene for several frames on the GPU and in
+    /// software, and checks that every frame matches. The lines move
+    /// between frames, so that persistence leaves trails.
+    ///
+    /// # Returns
+    ///
+    /// The image of the last frame rendered on the GPU.
+    fn check_against_gpu(
+        texture_format: wgpu::TextureFormat,
+        bloom: Bloom,
+        persistence: Option<Phosphor>,
+    ) -> Image {
+        const TOLERANCE: u8 = 2;
+
+        let gpu = Gpu::new();
+        let mut renderer =
+            Renderer::new(&gpu.device, texture_format, 150, 100, 16, 16);
+        let mut software =
+            SoftwareRenderer::new(texture_format, 150, 100, 16, 16);
+        renderer.set_bloom(Some(bloom));
+        software.set_bloom(Some(bloom));
+        renderer.set_persistence(persistence);
+        software.set_persistence(persistence);
+        let mut gpu_image = Image::new(150, 100);
+        for frame in 0..3 {
+            let offset = V2::new(0.0, 7.0 * frame as f32);
+            for (line, style) in bloom_scene() {
+                let line =
+                    Line::new(line.start() + offset, line.end() + offset);
+                renderer.line(line.clone(), &style);
+                software.line(line, &style);
+            }
+            gpu_image = renderer.render_offscreen(&gpu.device, &gpu.queue);
+            let cpu_image = software.render();
+            let difference = gpu_image.max_difference(&cpu_image);
+            assert!(
+                difference <= TOLERANCE,
+                "GPU and software images differ by {} in frame {}",
+                difference,
+                frame
+            );
+        }
+        gpu_image
+    }
+
+    #[test]
+    fn test_bloom_matches_gpu() {
+        // Pixels in the middle of the bright orange line and of the gray
+        // line, with each tone map.
+        let mut centers = Vec::new();
+        for tone_map in [ToneMap::Clamp, ToneMap::Reinhard, ToneMap::Aces] {
+            let bloom = Bloom {
+                tone_map,
+                ..Default::default()
+            };
+            let image =
+                check_against_gpu(wgpu::TextureFormat::Rgba8Unorm, bloom, None);
+            // Bloom spreads the orange of the bright line to pixels beyond
+            // its edge, less the further they are.
+            let [r, g, b, a] = pixel_at(&image, 40.5, 51.5);
+            assert!(r > g && g > b && a > 0, "{:?}", [r, g, b, a]);
+            assert!(pixel_at(&image, 40.5, 52.5)[3] < a);
+            centers.push([
+                pixel_at(&image, 40.5, 47.5),
+                pixel_at(&image, 75.5, 30.5),
+            ]);
+        }
+        let [clamp, reinhard, aces] = [centers[0], centers[1], centers[2]];
+        // Clamping turns the bright line yellow. Reinhard's operator keeps
+        // its hue, but nearly halves the brightness of the gray line, and
+        // ACES keeps more of both.
+        assert_eq!(clamp[0][..2], [255, 255]);
+        let [r, g, b, _] = reinhard[0];
+        assert!(r < 255 && r > g && g > b, "{:?}", reinhard);
+        assert!(reinhard[1][0] < clamp[1][0] / 5 * 3, "{:?}", reinhard);
+        assert!(aces[0][1] < clamp[0][1] && aces[0][1] > reinhard[0][1]);
+        assert!(aces[1][0] > reinhard[1][0], "{:?}", aces);
+    }
+
+    #[test]
+    fn test_bloom_matches_gpu_srgb() {
+        let bloom = Bloom {
+            threshold: 1.0,
+            intensity: 2.0,
+            levels: 3,
+            ..Default::default()
+        };
+        check_against_gpu(wgpu::TextureFormat::Rgba8UnormSrgb, bloom, None);
+    }
+
+    #[test]
+    fn test_bloom_matches_gpu_with_persistence() {
+        check_against_gpu(
+            wgpu::TextureFormat::Rgba8Unorm,
+            Bloom::default(),
+            Some(Phosphor::P1),
+        );
+    }
+
+    /// Bloom spreads light from bright lines, and an image of lines which
+    /// are not brighter than one, without any bloom or tone mapping, matches
+    /// one rendered without post-processing.
+    #[test]
+    fn test_bloom_spreads_light() {
+        let render = |bloom, lines: &[(Line, LineStyle)]| {
+            let mut software = SoftwareRenderer::new(
+                wgpu::TextureFormat::Rgba8Unorm,
+                150,
+                100,
+                16,
+                16,
+            );
+            software.set_bloom(bloom);
+            for (line, style) in lines {
+                software.line(line.clone(), style);
+            }
+            software.render()
+        };
+        let scene = bloom_scene();
+        let unbloomed = Bloom {
+            threshold: 10.0,
+            tone_map: ToneMap::Clamp,
+            ..Default::default()
+        };
+        let dim_lines = &scene[2..];
+        assert!(
+            render(None, dim_lines)
+                .max_difference(&render(Some(unbloomed), dim_lines))
+                <= 1
+        );
+
+        // A pixel near the bright line is only lit by its bloom.
+        let plain = render(None, &scene);
+        let bloomed = render(Some(Bloom::default()), &scene);
+        assert_eq!(plain.pixel(40, 62), [0, 0, 0, 0]);
+        let [r, g, b, a] = bloomed.pixel(40, 62);
+        assert!(r > g && g > b && a >= r, "{:?}", [r, g, b, a]);
+    }
+}
@@ -0,0 +1,180 @@
+/**
+ * NAME:     bloom.wgsl
+ * PURPOSE:  Shaders which extract, blur and composite the bloom of an HDR
+ *           scene.
+ */
+
+/**** BINDINGS ************************************************************* */
+
+@group(0) @binding(0) var<uniform> bloom_options : BloomOptions;
+@group(0) @binding(1) var          source        : texture_2d<f32>;
+@group(0) @binding(2) var          bloom_levels  : texture_2d<f32>;
+
+/**** STRUCTS ****************************************************************/
+
+/// Bloom Options
+struct BloomOptions {
+    threshold : f32,
+    intensity : f32,
+    n_levels  : u32,
+    tone_map  : u32
+};
+
+/**** CONSTANTS **************************************************************/
+
+/// Number of texels on each side of the center of the blur kernel.
+const BLUR_RADIUS : i32 = 4;
+
+/// Standard deviation of the blur kernel, in texels.
+const BLUR_SIGMA : f32 = 2.0;
+
+/// Tone mapping operators (see `tone_map`).
+const TONE_MAP_CLAMP    : u32 = 0u;
+const TONE_MAP_REINHARD : u32 = 1u;
+const TONE_MAP_ACES     : u32 = 2u;
+
+/**** VERTEX SHADER **********************************************************/
+
+/// Produces a triangle which covers the whole framebuffer.
+@vertex fn vs_fullscreen(
+    @builtin(vertex_index) vertex_index : u32
+) -> @builtin(position) vec4f {
+    let uv = vec2f(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+/**** FRAGMENT SHADERS *******************************************************/
+
+/// Downsamples the scene to the first bloom level, keeping only the parts
+/// which are brighter than the threshold.
+@fragment fn fs_extract(
+    @builtin(position) position : vec4f
+) -> @location(0) vec4f {
+    return bright_pass(downsample(vec2i(position.xy)));
+}
+
+/// Downsamples a bloom level to the next level.
+@fragment fn fs_downsample(
+    @builtin(position) position : vec4f
+) -> @location(0) vec4f {
+    return downsample(vec2i(position.xy));
+}
+
+/// Blurs a bloom level horizontally.
+@fragment fn fs_blur_horizontal(
+    @builtin(position) position : vec4f
+) -> @location(0) vec4f {
+    return blur(vec2i(position.xy), vec2i(1, 0));
+}
+
+/// Blurs a bloom level vertically.
+@fragment fn fs_blur_vertical(
+    @builtin(position) position : vec4f
+) -> @location(0) vec4f {
+    return blur(vec2i(position.xy), vec2i(0, 1));
+}
+
+/// Adds the bloom levels to the scene, and tone-maps the result.
+///
+/// The scene contains premultiplied colors, and so does the output. Light
+/// from the bloom makes the output more opaque.
+@fragment fn fs_composite(
+    @builtin(position) position : vec4f
+) -> @location(0) vec4f {
+    let scene = textureLoad(source, vec2i(position.xy), 0);
+
+    var bloom = vec3f(0.0, 0.0, 0.0);
+    for (var level = 0u; level < bloom_options.n_levels; level++) {
+        bloom += upsample(level, position.xy).rgb;
+    }
+    bloom *= bloom_options.intensity / f32(bloom_options.n_levels);
+
+    let color = tone_map(scene.rgb + bloom);
+    let alpha = clamp(max(scene.a, max(color.r, max(color.g, color.b))), 0.0, 1.0);
+    return vec4f(min(color, vec3f(alpha)), alpha);
+}
+
+/**** FUNCTIONS **************************************************************/
+
+/// Loads a texel of the source texture, clamping the coordinates to its
+/// edges.
+fn load_source(coord : vec2i) -> vec4f {
+    let size = vec2i(textureDimensions(source));
+    return textureLoad(source, clamp(coord, vec2i(0, 0), size - 1), 0);
+}
+
+/// Averages the 2x2 block of source texels under a destination texel.
+fn downsample(coord : vec2i) -> vec4f {
+    let s = 2 * coord;
+    return 0.25 * (
+        load_source(s) +
+        load_source(s + vec2i(1, 0)) +
+        load_source(s + vec2i(0, 1)) +
+        load_source(s + vec2i(1, 1))
+    );
+}
+
+/// Scales a color so that only its brightness above the threshold remains.
+///
+/// The brightness of a color is its largest channel.
+fn bright_pass(color : vec4f) -> vec4f {
+    let brightness = max(color.r, max(color.g, color.b));
+    let excess = max(brightness - bloom_options.threshold, 0.0);
+    return color * (excess / max(brightness, 1e-4));
+}
+
+/// Weight of a tap of the blur kernel, before normalization.
+fn blur_weight(offset : i32) -> f32 {
+    let x = f32(offset);
+    return exp(-x * x / (2.0 * BLUR_SIGMA * BLUR_SIGMA));
+}
+
+/// Blurs the source texture along one direction, using a normalized
+/// Gaussian kernel.
+fn blur(coord : vec2i, direction : vec2i) -> vec4f {
+    var sum = vec4f(0.0, 0.0, 0.0, 0.0);
+    var total = 0.0;
+    for (var i = -BLUR_RADIUS; i <= BLUR_RADIUS; i++) {
+        let weight = blur_weight(i);
+        sum += weight * load_source(coord + i * direction);
+        total += weight;
+    }
+    return sum / total;
+}
+
+/// Bilinearly interpolates a bloom level at a framebuffer position.
+///
+/// Level `n` is `2^(n + 1)` times smaller than the framebuffer.
+fn upsample(level : u32, position : vec2f) -> vec4f {
+    let scale = f32(2u << level);
+    let size = vec2i(textureDimensions(bloom_levels, level));
+    let q = position / scale - 0.5;
+    let base = floor(q);
+    let f = q - base;
+    let c = vec2i(base);
+    let max_coord = size - 1;
+    let l = i32(level);
+    let c00 = textureLoad(bloom_levels, clamp(c, vec2i(0, 0), max_coord), l);
+    let c10 = textureLoad(bloom_levels, clamp(c + vec2i(1, 0), vec2i(0, 0), max_coord), l);
+    let c01 = textureLoad(bloom_levels, clamp(c + vec2i(0, 1), vec2i(0, 0), max_coord), l);
+    let c11 = textureLoad(bloom_levels, clamp(c + vec2i(1, 1), vec2i(0, 0), max_coord), l);
+    return mix(mix(c00, c10, f.x), mix(c01, c11, f.x), f.y);
+}
+
+/// Maps an HDR color into the displayable range.
+fn tone_map(color : vec3f) -> vec3f {
+    switch (bloom_options.tone_map) {
+        case TONE_MAP_REINHARD: {
+            return color / (1.0 + color);
+        }
+        case TONE_MAP_ACES: {
+            // Narkowicz's fit of the ACES filmic curve.
+            let numerator = color * (2.51 * color + 0.03);
+            let denominator = color * (2.43 * color + 0.59) + 0.14;
+            return clamp(numerator / denominator, vec3f(0.0), vec3f(1.0));
+        }
+        case TONE_MAP_CLAMP, default: {
+            return clamp(color, vec3f(0.0), vec3f(1.0));
+        }
+    }
+}
@@ -1,5 +1,17 @@
-use crate::{style, tiler, Color};
+use crate::{
+    primitive::{Primitive, StyledPrimitive, MAX_POLYGON_VERTICES},
+    style::MAX_DASH_LENGTHS,
+    tiler, Color, LineCap, P2,
+};
+use beamline_line::PushBuf;
 use bytemuck::{bytes_of, cast_slice, Pod, Zeroable};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Source of the values of [`Buffers::generation`].
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Number of lines which are copied to the line array buffer at a time.
+const LINE_CHUNK_CAPACITY: u32 = 256;
 
 #[derive(Debug)]
 pub struct Buffers {
@@ -7,21 +19,50 @@ pub struct Buffers {
     shader_options_buffer: wgpu::Buffer,
     tile_info_capacity: u32,
     tile_info_buffer: wgpu::Buffer,
-    lines_buffer_capacity: u32,
-    lines_buffer: wgpu::Buffer,
+    lines_buffer: PushBuf<StyledLine>,
+    line_indices_capacity: u32,
+    line_indices_buffer: wgpu::Buffer,
+    /// Generation of the tile info and line index buffers (see
+    /// [`Buffers::generation`]).
+    generation: u64,
 }
 impl Buffers {
-    pub fn new(device: &wgpu::Device, tile_info_capacity: u32, lines_buffer_capacity: u32) -> Self {
+    /// Creates new buffers.
+    ///
+    /// The line index buffer starts with the same capacity as the line
+    /// array buffer.
+    pub fn new(
+        device: &wgpu::Device,
+        tile_info_capacity: u32,
+        lines_buffer_capacity: u32,
+    ) -> Self {
         Buffers {
             viewport_buffer: create_viewport_buffer(device),
             shader_options_buffer: create_shader_options_buffer(device),
             tile_info_capacity,
-            tile_info_buffer: create_tile_info_buffer(device, tile_info_capacity),
-            lines_buffer_capacity,
+            tile_info_buffer: create_tile_info_buffer(
+                device,
+                tile_info_capacity,
+            ),
             lines_buffer: create_line_buffer(device, lines_buffer_capacity),
+            line_indices_capacity: lines_buffer_capacity,
+            line_indices_buffer: create_line_indices_buffer(
+                device,
+                lines_buffer_capacity,
+            ),
+            generation: next_generation(),
         }
     }
 
+    /// Returns the generation of the tile info and line index buffers.
+    ///
+    /// This changes whenever either of them is re-allocated, and differs
+    /// between `Buffers`, so that bind groups of them can be kept until it
+    /// changes.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
     /// Returns a reference to the viewport buffer.
     pub fn viewport_buffer(&self) -> &wgpu::Buffer {
         &self.viewport_buffer
@@ -39,7 +80,12 @@ impl Buffers {
 
     /// Returns a reference to the lines buffer.
     pub fn lines_buffer(&self) -> &wgpu::Buffer {
-        &self.lines_buffer
+        self.lines_buffer.buffer()
+    }
+
+    /// Returns a reference to the line index buffer.
+    pub fn line_indices_buffer(&self) -> &wgpu::Buffer {
+        &self.line_indices_buffer
     }
 
     /// Write the viewport parameters into the viewport buffer.
@@ -86,14 +132,17 @@ impl Buffers {
         tile_background: Color,
         tile_edges: Color,
     ) {
-        let shader_options = ShaderOptions {
+        let shader_options = ShaderOptions::new(
             antialias_width,
-            draw_tiles: if draw_tiles { 1 } else { 0 },
-            tile_background: tile_background.as_array(),
-            tile_edges: tile_edges.as_array(),
-            _padding: [0.0, 0.0],
-        };
-        queue.write_buffer(&self.shader_options_buffer, 0, bytes_of(&shader_options));
+            draw_tiles,
+            tile_background,
+            tile_edges,
+        );
+        queue.write_buffer(
+            &self.shader_options_buffer,
+            0,
+            bytes_of(&shader_options),
+        );
     }
 
     /// Write tile info to its buffer.
@@ -118,36 +167,205 @@ impl Buffers {
 
         let gpu_tile_info: Vec<TileInfo> = tile_info
             .into_iter()
-            .map(|tile_info| TileInfo::new_from_tiler_tileinfo(tile_info))
+            .map(TileInfo::new_from_tiler_tileinfo)
             .collect();
-        queue.write_buffer(&self.tile_info_buffer, 0, cast_slice(&gpu_tile_info));
+        queue.write_buffer(
+            &self.tile_info_buffer,
+            0,
+            cast_slice(&gpu_tile_info),
+        );
     }
 
     /// Write line array to its buffer.
     ///
     /// If the line array buffer is not large enough, it is re-allocated with
-    /// a large enough capacity.
+    /// a large enough capacity. The lines are pushed through staging buffers,
+    /// whose copies are submitted to the queue straight away, ahead of any
+    /// command encoder which draws them.
     ///
     /// # Parameters
     ///
     /// - `device`: WGPU Device.
     /// - `queue`: WGPU queue to enqueue the buffer write.
-    /// - `styled_lines`: Styled line structs from the [`Tiler`].
+    /// - `styled_lines`: Styled primitive structs from the [`Tiler`].
     pub fn write_line_array(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        styled_lines: Vec<style::StyledLine>,
+        styled_lines: Vec<StyledPrimitive>,
     ) {
-        if self.lines_buffer_capacity < styled_lines.len() as u32 {
+        if self.lines_buffer_capacity() < styled_lines.len() as u32 {
             self.grow_lines(device, styled_lines.len() as u32);
         }
 
+        self.lines_buffer.begin_frame(device);
+        for styled_line in styled_lines {
+            let gpu_styled_line =
+                StyledLine::new_from_styled_primitive(styled_line);
+            self.lines_buffer
+                .push(device, gpu_styled_line)
+                .expect("the line array buffer was grown to fit the lines");
+        }
+        queue.submit(std::iter::once(self.lines_buffer.end_frame()));
+        self.lines_buffer.recall();
+    }
+
+    /// Write styled primitives into part of the line array buffer.
+    ///
+    /// The rest of the buffer is left unchanged. The buffer must be large
+    /// enough (see [`Buffers::reserve_lines`]).
+    ///
+    /// # Parameters
+    ///
+    /// - `queue`: WGPU queue to enqueue the buffer write.
+    /// - `offset`: Index of the first line to write.
+    /// - `styled_lines`: Styled primitive structs to write.
+    pub fn write_lines_at(
+        &self,
+        queue: &wgpu::Queue,
+        offset: u32,
+        styled_lines: Vec<StyledPrimitive>,
+    ) {
+        assert!(
+            offset as usize + styled_lines.len()
+                <= self.lines_buffer_capacity() as usize
+        );
         let gpu_styled_lines: Vec<StyledLine> = styled_lines
             .into_iter()
-            .map(|styled_line| StyledLine::new_from_style_line(styled_line))
+            .map(StyledLine::new_from_styled_primitive)
             .collect();
-        queue.write_buffer(&self.lines_buffer, 0, cast_slice(&gpu_styled_lines));
+        let struct_sz =
+            std::mem::size_of::<StyledLine>() as wgpu::BufferAddress;
+        queue.write_buffer(
+            self.lines_buffer.buffer(),
+            offset as wgpu::BufferAddress * struct_sz,
+            cast_slice(&gpu_styled_lines),
+        );
+    }
+
+    /// Write the indices of the lines of each tile to their buffer.
+    ///
+    /// If the line index buffer is not large enough, it is re-allocated with
+    /// a large enough capacity.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `queue`: WGPU queue to enqueue the buffer write.
+    /// - `line_indices`: Indices into the line array, from the [`Tiler`].
+    pub fn write_line_indices(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        line_indices: Vec<u32>,
+    ) {
+        if self.line_indices_capacity < line_indices.len() as u32 {
+            self.grow_line_indices(device, line_indices.len() as u32);
+        }
+        queue.write_buffer(
+            &self.line_indices_buffer,
+            0,
+            cast_slice(&line_indices),
+        );
+    }
+
+    /// Write line indices into part of the line index buffer.
+    ///
+    /// The rest of the buffer is left unchanged. The buffer must be large
+    /// enough (see [`Buffers::reserve_line_indices`]).
+    ///
+    /// # Parameters
+    ///
+    /// - `queue`: WGPU queue to enqueue the buffer write.
+    /// - `offset`: Index of the first line index to write.
+    /// - `line_indices`: Indices into the line array to write.
+    pub fn write_line_indices_at(
+        &self,
+        queue: &wgpu::Queue,
+        offset: u32,
+        line_indices: Vec<u32>,
+    ) {
+        assert!(
+            offset as usize + line_indices.len()
+                <= self.line_indices_capacity as usize
+        );
+        let index_sz = std::mem::size_of::<u32>() as wgpu::BufferAddress;
+        queue.write_buffer(
+            &self.line_indices_buffer,
+            offset as wgpu::BufferAddress * index_sz,
+            cast_slice(&line_indices),
+        );
+    }
+
+    /// Ensure that the tile info buffer can hold a number of tiles.
+    ///
+    /// If the buffer is not large enough, it is re-allocated with at least
+    /// twice its capacity, and its contents are lost.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `capacity`: Number of tiles that the buffer must hold.
+    pub fn reserve_tile_info(&mut self, device: &wgpu::Device, capacity: u32) {
+        if self.tile_info_capacity < capacity {
+            self.grow_tile_info(
+                device,
+                capacity.max(2 * self.tile_info_capacity),
+            );
+        }
+    }
+
+    /// Ensure that the line array buffer can hold a number of lines.
+    ///
+    /// If the buffer is not large enough, it is re-allocated with at least
+    /// twice its capacity, and its contents are lost.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `capacity`: Number of lines that the buffer must hold.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the buffer was re-allocated.
+    pub fn reserve_lines(
+        &mut self,
+        device: &wgpu::Device,
+        capacity: u32,
+    ) -> bool {
+        if self.lines_buffer_capacity() >= capacity {
+            return false;
+        }
+        self.grow_lines(device, capacity.max(2 * self.lines_buffer_capacity()));
+        true
+    }
+
+    /// Ensure that the line index buffer can hold a number of indices.
+    ///
+    /// If the buffer is not large enough, it is re-allocated with at least
+    /// twice its capacity, and its contents are lost.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `capacity`: Number of indices that the buffer must hold.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the buffer was re-allocated.
+    pub fn reserve_line_indices(
+        &mut self,
+        device: &wgpu::Device,
+        capacity: u32,
+    ) -> bool {
+        if self.line_indices_capacity >= capacity {
+            return false;
+        }
+        self.grow_line_indices(
+            device,
+            capacity.max(2 * self.line_indices_capacity),
+        );
+        true
     }
 
     /// Grow the tile info buffer to a new size.
@@ -160,6 +378,7 @@ impl Buffers {
         assert!(new_capacity > self.tile_info_capacity);
         self.tile_info_buffer = create_tile_info_buffer(device, new_capacity);
         self.tile_info_capacity = new_capacity;
+        self.generation = next_generation();
     }
 
     /// Grow the line array buffer to a new size.
@@ -169,10 +388,33 @@ impl Buffers {
     /// - `device`: WGPU Device.
     /// - `new_capacity`: New size of the buffer.
     fn grow_lines(&mut self, device: &wgpu::Device, new_capacity: u32) {
-        assert!(new_capacity > self.lines_buffer_capacity);
+        assert!(new_capacity > self.lines_buffer_capacity());
         self.lines_buffer = create_line_buffer(device, new_capacity);
-        self.lines_buffer_capacity = new_capacity;
     }
+
+    /// Returns the number of lines that the line array buffer can hold.
+    fn lines_buffer_capacity(&self) -> u32 {
+        self.lines_buffer.capacity() as u32
+    }
+
+    /// Grow the line index buffer to a new size.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `new_capacity`: New size of the buffer.
+    fn grow_line_indices(&mut self, device: &wgpu::Device, new_capacity: u32) {
+        assert!(new_capacity > self.line_indices_capacity);
+        self.line_indices_buffer =
+            create_line_indices_buffer(device, new_capacity);
+        self.line_indices_capacity = new_capacity;
+        self.generation = next_generation();
+    }
+}
+
+/// Returns a new generation for [`Buffers::generation`].
+fn next_generation() -> u64 {
+    NEXT_GENERATION.fetch_add(1, Ordering::Relaxed)
 }
 
 /// Create the viewport uniform buffer.
@@ -203,7 +445,10 @@ fn create_shader_options_buffer(device: &wgpu::Device) -> wgpu::Buffer {
 ///
 /// - `device`: WGPU Device.
 /// - `capacity`: Number of `TileInfo` structs that the buffer can store.
-fn create_tile_info_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+fn create_tile_info_buffer(
+    device: &wgpu::Device,
+    capacity: u32,
+) -> wgpu::Buffer {
     use wgpu::BufferAddress;
     let struct_sz = std::mem::size_of::<TileInfo>() as BufferAddress;
     let buf_sz_bytes = struct_sz * capacity as BufferAddress;
@@ -211,7 +456,9 @@ fn create_tile_info_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer
     device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("Beamline: Tile info buffer"),
         size: buf_sz_bytes,
-        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC,
         mapped_at_creation: false,
     })
 }
@@ -222,15 +469,39 @@ fn create_tile_info_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer
 ///
 /// - `device`: WGPU Device.
 /// - `capacity`: Number of `StyledLine` structs that the buffer can store.
-fn create_line_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+fn create_line_buffer(
+    device: &wgpu::Device,
+    capacity: u32,
+) -> PushBuf<StyledLine> {
+    PushBuf::new(
+        device,
+        Some("Beamline: Line array buffer"),
+        wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        capacity as usize,
+        capacity.min(LINE_CHUNK_CAPACITY) as usize,
+    )
+}
+
+/// Create the line index buffer.
+///
+/// # Parameters
+///
+/// - `device`: WGPU Device.
+/// - `capacity`: Number of `u32` indices that the buffer can store.
+fn create_line_indices_buffer(
+    device: &wgpu::Device,
+    capacity: u32,
+) -> wgpu::Buffer {
     use wgpu::BufferAddress;
-    let struct_sz = std::mem::size_of::<StyledLine>() as BufferAddress;
-    let buf_sz_bytes = struct_sz * capacity as BufferAddress;
+    let index_sz = std::mem::size_of::<u32>() as BufferAddress;
+    let buf_sz_bytes = index_sz * capacity as BufferAddress;
 
     device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("Beamline: Line array buffer"),
+        label: Some("Beamline: Line index buffer"),
         size: buf_sz_bytes,
-        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC,
         mapped_at_creation: false,
     })
 }
@@ -238,32 +509,58 @@ fn create_line_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
 /// GPU version of the viewport information, for the viewport uniform buffer.
 #[repr(C)]
 #[derive(Default, Debug, Copy, Clone, Pod, Zeroable)]
-struct Viewport {
-    area_width: u32,
-    area_height: u32,
-    tile_width: u32,
-    tile_height: u32,
+pub(crate) struct Viewport {
+    pub(crate) area_width: u32,
+    pub(crate) area_height: u32,
+    pub(crate) tile_width: u32,
+    pub(crate) tile_height: u32,
 }
 
 /// GPU version of shader options.
 #[repr(C)]
 #[derive(Default, Debug, Copy, Clone, Pod, Zeroable)]
-struct ShaderOptions {
-    tile_background: [f32; 4], // 16 bytes
-    tile_edges: [f32; 4],      // 16 bytes
-    antialias_width: f32,      // 4 bytes
-    draw_tiles: u32,           // 4 bytes
+pub(crate) struct ShaderOptions {
+    pub(crate) tile_background: [f32; 4], // 16 bytes
+    pub(crate) tile_edges: [f32; 4],      // 16 bytes
+    pub(crate) antialias_width: f32,      // 4 bytes
+    pub(crate) draw_tiles: u32,           // 4 bytes
     _padding: [f32; 2],
 }
+impl ShaderOptions {
+    pub fn new(
+        antialias_width: f32,
+        draw_tiles: bool,
+        tile_background: Color,
+        tile_edges: Color,
+    ) -> Self {
+        ShaderOptions {
+            antialias_width,
+            draw_tiles: if draw_tiles { 1 } else { 0 },
+            tile_background: tile_background.as_array(),
+            tile_edges: tile_edges.as_array(),
+            _padding: [0.0, 0.0],
+        }
+    }
+}
+
+/// GPU version of the bloom options, for the bloom uniform buffer.
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, Pod, Zeroable)]
+pub(crate) struct BloomOptions {
+    pub(crate) threshold: f32,
+    pub(crate) intensity: f32,
+    pub(crate) n_levels: u32,
+    pub(crate) tone_map: u32,
+}
 
 /// GPU version of the tile info.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
-struct TileInfo {
-    tile_x: u32,
-    tile_y: u32,
-    start_index: u32,
-    n_lines: u32,
+pub(crate) struct TileInfo {
+    pub(crate) tile_x: u32,
+    pub(crate) tile_y: u32,
+    pub(crate) start_index: u32,
+    pub(crate) n_lines: u32,
 }
 impl TileInfo {
     pub fn new_from_tiler_tileinfo(tile_info: tiler::TileInfo) -> Self {
@@ -276,26 +573,292 @@ impl TileInfo {
     }
 }
 
-/// GPU version of styled line.
+/// GPU version of a styled primitive.
+///
+/// This is named after the styled line, since most primitives are straight
+/// line segments. The `kind` field indicates which fields are used:
+///
+/// - [`PRIMITIVE_SEGMENT`]: `start`, `end`, `start_cap`, `end_cap`,
+///   `end_width` and the dash fields. The width of the segment changes
+///   linearly from `width` at its start to `end_width` at its end.
+/// - [`PRIMITIVE_POLYGON`]: `n_points` and `points`.
+/// - [`PRIMITIVE_DISC`]: `start` (the center of the disc).
+/// - [`PRIMITIVE_QUADRATIC`] and [`PRIMITIVE_CUBIC`]: `start_cap`, `end_cap`,
+///   `n_points` and `points` (the control points of the curve).
+/// - [`PRIMITIVE_ARC`]: `start` (the center of the circle), `start_cap`,
+///   `end_cap`, `points[0]` (the radius, and half of the angle spanned by the
+///   arc) and `points[1]` (a unit vector towards the middle of the arc).
+/// - [`PRIMITIVE_ELLIPSE`]: `start` (the center of the ellipse), `points[0]`
+///   (the radii of the ellipse) and `points[1]` (a unit vector along the
+///   first axis of the ellipse).
+/// - [`PRIMITIVE_FILL`]: `winding` (the winding number at the tile's
+///   reference point) and `fill_rule`.
+/// - [`PRIMITIVE_FILL_EDGE`]: `start` and `end`.
+/// - [`PRIMITIVE_MARKER`]: `start` (the tip), `end` (a unit vector pointing
+///   out of the stroke), `marker` (the shape of the marker) and `points[0]`
+///   (the length of the marker, and zero).
+/// - [`PRIMITIVE_CLIP`]: `winding` (the winding number of the clip path at
+///   the tile's reference point) and `fill_rule`. It is followed by the
+///   edges of the clip path, as [`PRIMITIVE_FILL_EDGE`] primitives.
+///
+/// A primitive has a gradient if `interpolation` is non-zero. In that case,
+/// its color is interpolated from `color` to `end_color`, in the color
+/// space given by `interpolation`. It is a fraction `gradient_start` of the
+/// way from `color` to `end_color` at the start of the primitive, and a
+/// fraction `gradient_end` of the way at its end.
+///
+/// A primitive has a glow if `glow_radius` is positive. The glow extends
+/// `glow_radius` beyond the edge of the primitive, and its opacity is
+/// `glow_intensity` times the falloff curve given by `glow_falloff`.
+///
+/// A segment is dashed if `dash_count` is non-zero. In that case,
+/// `dash_lengths` contains `dash_count` alternating dash and gap lengths,
+/// `dash_phase` is the position in the dash pattern at the start of the
+/// segment, and `dash_cap` is the cap applied to the ends of dashes.
+///
+/// Every primitive is clipped to the rectangle from `clip_min` to
+/// `clip_max`, which is unbounded if it is not clipped by a rectangle.
+///
+/// The primitives of a layer whose `layer_opacity` is below one are
+/// composited together, and then faded by it as a group.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
-struct StyledLine {
-    start: [f32; 2],     // 8 bytes
-    end: [f32; 2],       // 8 bytes
-    width: f32,          // 4 bytes
-    cap: u32,            // 4 bytes
-    _padding0: [f32; 2], // 4 bytes
-    color: [f32; 4],     // 16 bytes
+pub(crate) struct StyledLine {
+    pub(crate) start: [f32; 2],        // 8 bytes
+    pub(crate) end: [f32; 2],          // 8 bytes
+    pub(crate) width: f32,             // 4 bytes
+    pub(crate) start_cap: u32,         // 4 bytes
+    pub(crate) end_cap: u32,           // 4 bytes
+    pub(crate) kind: u32,              // 4 bytes
+    pub(crate) color: [f32; 4],        // 16 bytes
+    pub(crate) shape: u32,             // 4 bytes
+    pub(crate) n_points: u32,          // 4 bytes
+    pub(crate) points: [[f32; 2]; 6],  // 48 bytes
+    pub(crate) dash_cap: u32,          // 4 bytes
+    pub(crate) dash_count: u32,        // 4 bytes
+    pub(crate) dash_phase: f32,        // 4 bytes
+    pub(crate) dash_lengths: [f32; 8], // 32 bytes
+    pub(crate) winding: i32,           // 4 bytes
+    pub(crate) fill_rule: u32,         // 4 bytes
+    pub(crate) gradient_start: f32,    // 4 bytes
+    pub(crate) end_color: [f32; 4],    // 16 bytes
+    pub(crate) gradient_end: f32,      // 4 bytes
+    pub(crate) interpolation: u32,     // 4 bytes
+    pub(crate) end_width: f32,         // 4 bytes
+    pub(crate) marker: u32,            // 4 bytes
+    pub(crate) glow_radius: f32,       // 4 bytes
+    pub(crate) glow_falloff: u32,      // 4 bytes
+    pub(crate) glow_intensity: f32,    // 4 bytes
+    pub(crate) blend_mode: u32,        // 4 bytes
+    pub(crate) clip_min: [f32; 2],     // 8 bytes
+    pub(crate) clip_max: [f32; 2],     // 8 bytes
+    pub(crate) layer: u32,             // 4 bytes
+    pub(crate) layer_opacity: f32,     // 4 bytes
+    _padding: [f32; 2],
 }
 impl StyledLine {
-    pub fn new_from_style_line(styled_line: style::StyledLine) -> Self {
-        StyledLine {
-            start: [styled_line.line.start().x, styled_line.line.start().y],
-            end: [styled_line.line.end().x, styled_line.line.end().y],
-            width: styled_line.style.width,
-            cap: styled_line.style.cap as u32,
-            _padding0: [0.0, 0.0],
-            color: styled_line.style.color.as_array(),
+    pub fn new_from_styled_primitive(
+        styled_primitive: StyledPrimitive,
+    ) -> Self {
+        let mut gpu_line = StyledLine {
+            start: [0.0, 0.0],
+            end: [0.0, 0.0],
+            width: styled_primitive.style.width,
+            start_cap: 0,
+            end_cap: 0,
+            kind: 0,
+            color: styled_primitive.style.color.as_array(),
+            shape: styled_primitive.shape,
+            n_points: 0,
+            points: [[0.0, 0.0]; MAX_POLYGON_VERTICES],
+            dash_cap: styled_primitive.style.cap as u32,
+            dash_count: 0,
+            dash_phase: 0.0,
+            dash_lengths: [0.0; MAX_DASH_LENGTHS],
+            winding: 0,
+            fill_rule: 0,
+            gradient_start: 0.0,
+            end_color: [0.0; 4],
+            gradient_end: 0.0,
+            interpolation: 0,
+            end_width: styled_primitive.style.width,
+            marker: 0,
+            glow_radius: 0.0,
+            glow_falloff: 0,
+            glow_intensity: 0.0,
+            blend_mode: styled_primitive.style.blend_mode as u32,
+            clip_min: [-f32::MAX, -f32::MAX],
+            clip_max: [f32::MAX, f32::MAX],
+            layer: styled_primitive.layer.index(),
+            layer_opacity: styled_primitive.layer_opacity,
+            _padding: [0.0, 0.0],
+        };
+        if let Some(clip_rect) = styled_primitive.clip_rect {
+            gpu_line.clip_min = [clip_rect.min.x, clip_rect.min.y];
+            gpu_line.clip_max = [clip_rect.max.x, clip_rect.max.y];
+        }
+        if let Some(glow) = styled_primitive.style.glow {
+            gpu_line.glow_radius = glow.radius;
+            gpu_line.glow_falloff = glow.falloff as u32;
+            gpu_line.glow_intensity = glow.intensity;
+        }
+        if let (Some(span), Some(gradient)) =
+            (styled_primitive.gradient, &styled_primitive.style.gradient)
+        {
+            gpu_line.color = span.start_color.as_array();
+            gpu_line.end_color = span.end_color.as_array();
+            gpu_line.gradient_start = span.start;
+            gpu_line.gradient_end = span.end;
+            gpu_line.interpolation = gradient.interpolation() as u32;
+        }
+        match styled_primitive.primitive {
+            Primitive::Segment {
+                line,
+                start_cap,
+                end_cap,
+                distance,
+                start_width,
+                end_width,
+            } => {
+                gpu_line.kind = PRIMITIVE_SEGMENT;
+                gpu_line.width = start_width;
+                gpu_line.end_width = end_width;
+                gpu_line.start = [line.start().x, line.start().y];
+                gpu_line.end = [line.end().x, line.end().y];
+                gpu_line.start_cap = start_cap as u32;
+                gpu_line.end_cap = end_cap as u32;
+                if let Some(dash) = styled_primitive.style.dash {
+                    let lengths = dash.lengths();
+                    gpu_line.dash_count = lengths.len() as u32;
+                    gpu_line.dash_phase = dash.offset() + distance;
+                    gpu_line.dash_lengths[..lengths.len()]
+                        .copy_from_slice(lengths);
+                }
+            }
+            Primitive::Polygon(vertices) => {
+                assert!(vertices.len() <= MAX_POLYGON_VERTICES);
+                gpu_line.kind = PRIMITIVE_POLYGON;
+                gpu_line.n_points = vertices.len() as u32;
+                for (point, vertex) in gpu_line.points.iter_mut().zip(vertices)
+                {
+                    *point = [vertex.x, vertex.y];
+                }
+            }
+            Primitive::Disc { center, width } => {
+                gpu_line.kind = PRIMITIVE_DISC;
+                gpu_line.start = [center.x, center.y];
+                gpu_line.width = width;
+                gpu_line.end_width = width;
+            }
+            Primitive::Quadratic {
+                curve,
+                start_cap,
+                end_cap,
+            } => {
+                gpu_line.kind = PRIMITIVE_QUADRATIC;
+                gpu_line.set_curve(&curve.control_points(), start_cap, end_cap);
+            }
+            Primitive::Cubic {
+                curve,
+                start_cap,
+                end_cap,
+            } => {
+                gpu_line.kind = PRIMITIVE_CUBIC;
+                gpu_line.set_curve(&curve.control_points(), start_cap, end_cap);
+            }
+            Primitive::Arc {
+                arc,
+                start_cap,
+                end_cap,
+            } => {
+                let mid_angle = arc.start_angle() + arc.sweep() / 2.0;
+                gpu_line.kind = PRIMITIVE_ARC;
+                gpu_line.start = [arc.center().x, arc.center().y];
+                gpu_line.start_cap = start_cap as u32;
+                gpu_line.end_cap = end_cap as u32;
+                gpu_line.points[0] = [arc.radius(), arc.sweep() / 2.0];
+                gpu_line.points[1] = [mid_angle.cos(), mid_angle.sin()];
+            }
+            Primitive::Ellipse(ellipse) => {
+                let (axis_x, _) = ellipse.axes();
+                gpu_line.kind = PRIMITIVE_ELLIPSE;
+                gpu_line.start = [ellipse.center().x, ellipse.center().y];
+                gpu_line.points[0] = [ellipse.radius_x(), ellipse.radius_y()];
+                gpu_line.points[1] = [axis_x.x, axis_x.y];
+            }
+            Primitive::Fill { winding, rule } => {
+                gpu_line.kind = PRIMITIVE_FILL;
+                gpu_line.winding = winding;
+                gpu_line.fill_rule = rule as u32;
+            }
+            Primitive::Clip { winding, rule } => {
+                gpu_line.kind = PRIMITIVE_CLIP;
+                gpu_line.winding = winding;
+                gpu_line.fill_rule = rule as u32;
+            }
+            Primitive::FillEdge(line) => {
+                gpu_line.kind = PRIMITIVE_FILL_EDGE;
+                gpu_line.start = [line.start().x, line.start().y];
+                gpu_line.end = [line.end().x, line.end().y];
+            }
+            Primitive::Marker {
+                marker,
+                tip,
+                direction,
+                width,
+            } => {
+                gpu_line.kind = PRIMITIVE_MARKER;
+                gpu_line.start = [tip.x, tip.y];
+                gpu_line.end = [direction.x, direction.y];
+                gpu_line.width = width;
+                gpu_line.end_width = width;
+                gpu_line.marker = marker.shape as u32;
+                gpu_line.points[0] = [marker.length(width), 0.0];
+            }
+        }
+        gpu_line
+    }
+
+    /// Sets the fields used by curve primitives.
+    fn set_curve(
+        &mut self,
+        control_points: &[P2],
+        start_cap: LineCap,
+        end_cap: LineCap,
+    ) {
+        let first = control_points[0];
+        let last = control_points[control_points.len() - 1];
+        self.start = [first.x, first.y];
+        self.end = [last.x, last.y];
+        self.start_cap = start_cap as u32;
+        self.end_cap = end_cap as u32;
+        self.n_points = control_points.len() as u32;
+        for (point, control_point) in self.points.iter_mut().zip(control_points)
+        {
+            *point = [control_point.x, control_point.y];
         }
     }
 }
+
+/// GPU primitive kind: a straight segment.
+pub(crate) const PRIMITIVE_SEGMENT: u32 = 1;
+/// GPU primitive kind: a filled polygon.
+pub(crate) const PRIMITIVE_POLYGON: u32 = 2;
+/// GPU primitive kind: a filled disc.
+pub(crate) const PRIMITIVE_DISC: u32 = 3;
+/// GPU primitive kind: a quadratic Bézier curve.
+pub(crate) const PRIMITIVE_QUADRATIC: u32 = 4;
+/// GPU primitive kind: a cubic Bézier curve.
+pub(crate) const PRIMITIVE_CUBIC: u32 = 5;
+/// GPU primitive kind: a circular arc.
+pub(crate) const PRIMITIVE_ARC: u32 = 6;
+/// GPU primitive kind: an ellipse.
+pub(crate) const PRIMITIVE_ELLIPSE: u32 = 7;
+/// GPU primitive kind: a tile of a filled path.
+pub(crate) const PRIMITIVE_FILL: u32 = 8;
+/// GPU primitive kind: an edge of a filled path.
+pub(crate) const PRIMITIVE_FILL_EDGE: u32 = 9;
+/// GPU primitive kind: a marker at an end of a stroke.
+pub(crate) const PRIMITIVE_MARKER: u32 = 10;
+/// GPU primitive kind: a tile of a clip path.
+pub(crate) const PRIMITIVE_CLIP: u32 = 11;
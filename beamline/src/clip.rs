@@ -0,0 +1,238 @@
+//! Clip regions, which limit where shapes are drawn.
+//!
+//! Clips are pushed onto a stack in the [`crate::tiler::Tiler`], and every
+//! shape which is added while a clip is on the stack is only drawn inside
+//! it. Nested clips intersect. Rectangles are tested exactly for each pixel
+//! by the shader, while paths are antialiased in the same way as fills.
+//! Both cull the tiles which are outside them.
+
+use crate::P2;
+
+/// Axis-aligned clip rectangle, in pixels.
+///
+/// The shader covers each pixel by the fraction of its area which is inside
+/// the rectangle, so that edges on pixel boundaries are sharp and other
+/// edges are antialiased.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ClipRect {
+    /// Corner of the rectangle with the smallest coordinates.
+    pub min: P2,
+    /// Corner of the rectangle with the largest coordinates.
+    pub max: P2,
+}
+impl ClipRect {
+    /// Creates a clip rectangle from two opposite corners.
+    ///
+    /// # Parameters
+    ///
+    /// - `a`: A corner of the rectangle.
+    /// - `b`: The opposite corner of the rectangle.
+    ///
+    /// # Returns
+    ///
+    /// A new clip rectangle.
+    pub fn new(a: P2, b: P2) -> Self {
+        ClipRect {
+            min: P2::new(a.x.min(b.x), a.y.min(b.y)),
+            max: P2::new(a.x.max(b.x), a.y.max(b.y)),
+        }
+    }
+
+    /// Returns the intersection of two clip rectangles.
+    ///
+    /// If they do not overlap, the intersection is empty (see
+    /// [`ClipRect::is_empty`]).
+    pub fn intersection(&self, other: &ClipRect) -> ClipRect {
+        ClipRect {
+            min: P2::new(
+                self.min.x.max(other.min.x),
+                self.min.y.max(other.min.y),
+            ),
+            max: P2::new(
+                self.max.x.min(other.max.x),
+                self.max.y.min(other.max.y),
+            ),
+        }
+    }
+
+    /// Returns `true` if the rectangle contains no area.
+    pub fn is_empty(&self) -> bool {
+        self.min.x >= self.max.x || self.min.y >= self.max.y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::parity::{check_scene, pixel_at, Shape},
+        Color, Ellipse, FillRule, FillStyle, Glow, GlowFalloff, Line, LineCap,
+        LineStyle, Path, SoftwareRenderer,
+    };
+
+    #[test]
+    fn test_intersection() {
+        let a = ClipRect::new(P2::new(10.0, 40.0), P2::new(0.0, 0.0));
+        assert_eq!(a.min, P2::new(0.0, 0.0));
+        assert_eq!(a.max, P2::new(10.0, 40.0));
+        let b = ClipRect::new(P2::new(5.0, -5.0), P2::new(20.0, 30.0));
+        let c = a.intersection(&b);
+        assert_eq!(c, ClipRect::new(P2::new(5.0, 0.0), P2::new(10.0, 30.0)));
+        assert!(!c.is_empty());
+        let d = ClipRect::new(P2::new(15.0, 0.0), P2::new(20.0, 5.0));
+        assert!(a.intersection(&d).is_empty());
+    }
+
+    /// Returns a scene of lines and fills inside nested clip rectangles and
+    /// clip paths, including a rectangle with fractional edges.
+    fn clip_scene() -> Vec<(Shape, LineStyle)> {
+        let style = LineStyle {
+            width: 6.0,
+            cap: LineCap::Round,
+            color: Color::new(0.9, 0.6, 0.2, 0.9),
+            glow: Some(Glow::new(3.0, GlowFalloff::Linear, 0.6)),
+            ..Default::default()
+        };
+        let fill = FillStyle {
+            color: Color::new(0.3, 0.5, 0.9, 0.8),
+            ..Default::default()
+        };
+        let star = Path::polygon(&[
+            P2::new(75.0, 95.0),
+            P2::new(95.0, 10.0),
+            P2::new(30.0, 65.0),
+            P2::new(120.0, 65.0),
+            P2::new(55.0, 10.0),
+        ]);
+        let square = Path::polygon(&[
+            P2::new(5.0, 5.0),
+            P2::new(145.0, 5.0),
+            P2::new(145.0, 95.0),
+            P2::new(5.0, 95.0),
+        ]);
+        vec![
+            (
+                Shape::PushClipRect(P2::new(10.3, 12.0), P2::new(140.0, 87.6)),
+                style.clone(),
+            ),
+            (
+                Shape::Line(Line::new(
+                    P2::new(0.0, 50.0),
+                    P2::new(150.0, 40.0),
+                )),
+                style.clone(),
+            ),
+            (Shape::PushClipPath(star, FillRule::EvenOdd), style.clone()),
+            (Shape::Fill(square, fill), style.clone()),
+            (
+                Shape::Ellipse(Ellipse::new(
+                    P2::new(75.0, 50.0),
+                    40.0,
+                    25.0,
+                    0.3,
+                )),
+                style.clone(),
+            ),
+            (Shape::PopClip, style.clone()),
+            (
+                Shape::Line(Line::new(
+                    P2::new(20.0, 0.0),
+                    P2::new(130.0, 100.0),
+                )),
+                style.clone(),
+            ),
+            (Shape::PopClip, style.clone()),
+            (
+                Shape::Line(Line::new(
+                    P2::new(0.0, 95.0),
+                    P2::new(150.0, 95.0),
+                )),
+                style,
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_clips_match_gpu() {
+        let image = check_scene(wgpu::TextureFormat::Rgba8Unorm, &clip_scene());
+        // The first line is cut off at the left of the clip rectangle.
+        assert_eq!(pixel_at(&image, 5.5, 49.5), [0, 0, 0, 0]);
+        assert_eq!(pixel_at(&image, 20.5, 48.5), [207, 138, 46, 230]);
+        // The square is filled in the points of the even-odd star, but not
+        // in its center.
+        assert_eq!(pixel_at(&image, 75.5, 85.5), [61, 102, 184, 204]);
+        assert_eq!(pixel_at(&image, 75.5, 38.5), [0, 0, 0, 0]);
+        // The last line is drawn after the clips are popped.
+        assert_eq!(pixel_at(&image, 5.5, 95.5), [207, 138, 46, 230]);
+        // The clip rectangle covers 0.7 of the pixels at its left and 0.6 of
+        // those at its top, which keep that much of the lines beneath them.
+        assert_eq!(pixel_at(&image, 10.5, 49.5), [145, 96, 32, 161]);
+        assert_eq!(pixel_at(&image, 116.5, 87.5), [124, 83, 28, 138]);
+        // The second line is only clipped by the rectangle once the star is
+        // popped.
+        assert_eq!(pixel_at(&image, 25.5, 11.5), [0, 0, 0, 0]);
+        assert_eq!(pixel_at(&image, 36.5, 15.5), [207, 138, 46, 230]);
+        assert_eq!(pixel_at(&image, 117.5, 88.5), [0, 0, 0, 0]);
+    }
+
+    /// A clip rectangle on pixel boundaries leaves the pixels inside it as
+    /// they are without the clip, and clears the pixels outside it.
+    #[test]
+    fn test_clip_rect_on_pixel_boundaries() {
+        const WIDTH: u32 = 150;
+        const HEIGHT: u32 = 100;
+
+        let style = LineStyle {
+            width: 8.0,
+            color: Color::new(0.9, 0.9, 0.9, 1.0),
+            glow: Some(Glow::new(4.0, GlowFalloff::Gaussian, 1.0)),
+            ..Default::default()
+        };
+        let draw = |renderer: &mut SoftwareRenderer| {
+            renderer.line(
+                Line::new(P2::new(0.0, 0.0), P2::new(150.0, 100.0)),
+                &style,
+            );
+            renderer.fill(
+                &Path::polygon(&[
+                    P2::new(20.0, 80.0),
+                    P2::new(60.0, 10.0),
+                    P2::new(90.0, 90.0),
+                ]),
+                &FillStyle::default(),
+            );
+        };
+        let new_renderer = || {
+            SoftwareRenderer::new(
+                wgpu::TextureFormat::Rgba8Unorm,
+                WIDTH,
+                HEIGHT,
+                16,
+                16,
+            )
+        };
+        let mut clipped = new_renderer();
+        clipped.push_clip_rect(P2::new(30.0, 20.0), P2::new(100.0, 70.0));
+        draw(&mut clipped);
+        clipped.pop_clip();
+        let mut unclipped = new_renderer();
+        draw(&mut unclipped);
+
+        let clipped = clipped.render();
+        let unclipped = unclipped.render();
+        let background = new_renderer().render();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                // Rows of the image are counted from the top.
+                let inside = (30..100).contains(&x)
+                    && (HEIGHT - 70..HEIGHT - 20).contains(&y);
+                let expected = if inside { &unclipped } else { &background };
+                assert_eq!(
+                    clipped.pixel(x, y),
+                    expected.pixel(x, y),
+                    "at ({x}, {y})"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Ellipses.
+
+use crate::{
+    transform::{linear_part, unit_circle_image, Transformable},
+    M3, P2, V2,
+};
+use cgmath::{InnerSpace, Matrix2, Transform};
+
+/// Ellipse.
+///
+/// An ellipse has a radius along each of its two axes. Its first axis is
+/// rotated anti-clockwise from the x axis by an angle (in radians).
+///
+/// To construct an ellipse, use [`Ellipse::new`].
+#[derive(Debug, Clone)]
+pub struct Ellipse {
+    center: P2,
+    radius_x: f32,
+    radius_y: f32,
+    rotation: f32,
+}
+impl Ellipse {
+    /// Creates a new ellipse.
+    ///
+    /// # Parameters
+    ///
+    /// - `center`: Center of the ellipse.
+    /// - `radius_x`: Radius along the first axis. This must be positive.
+    /// - `radius_y`: Radius along the second axis. This must be positive.
+    /// - `rotation`: Angle of the first axis, anti-clockwise from the x axis.
+    ///
+    /// # Returns
+    ///
+    /// A new ellipse.
+    pub fn new(
+        center: P2,
+        radius_x: f32,
+        radius_y: f32,
+        rotation: f32,
+    ) -> Self {
+        assert!(radius_x > 0.0);
+        assert!(radius_y > 0.0);
+        assert!(rotation.is_finite());
+        Ellipse {
+            center,
+            radius_x,
+            radius_y,
+            rotation,
+        }
+    }
+
+    /// Returns the center of the ellipse.
+    pub fn center(&self) -> P2 {
+        self.center
+    }
+
+    /// Returns the radius along the first axis.
+    pub fn radius_x(&self) -> f32 {
+        self.radius_x
+    }
+
+    /// Returns the radius along the second axis.
+    pub fn radius_y(&self) -> f32 {
+        self.radius_y
+    }
+
+    /// Returns the angle of the first axis, anti-clockwise from the x axis.
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    /// Returns unit vectors along the first and second axes.
+    pub fn axes(&self) -> (V2, V2) {
+        let (sin, cos) = self.rotation.sin_cos();
+        (V2::new(cos, sin), V2::new(-sin, cos))
+    }
+
+    /// Returns the signed distance from a point to the ellipse.
+    ///
+    /// The distance is negative inside the ellipse. This is the same
+    /// computation as `sdf_ellipse_local` in the shader.
+    ///
+    /// # Parameters
+    ///
+    /// - `p`: Point to measure the distance to.
+    pub fn signed_distance(&self, p: P2) -> f32 {
+        let (axis_x, axis_y) = self.axes();
+        let w = p - self.center;
+        let local = V2::new(w.dot(axis_x), w.dot(axis_y));
+        sdf_ellipse_local(V2::new(self.radius_x, self.radius_y), local)
+    }
+}
+
+impl Transformable for Ellipse {
+    /// Returns the ellipse mapped through an affine transform, which is
+    /// always another ellipse.
+    fn transformed(&self, matrix: &M3) -> Self {
+        let (axis_x, axis_y) = self.axes();
+        let axes =
+            Matrix2::from_cols(self.radius_x * axis_x, self.radius_y * axis_y);
+        let (rotation, radius_x, radius_y) =
+            unit_circle_image(linear_part(matrix) * axes);
+        Ellipse::new(
+            matrix.transform_point(self.center),
+            radius_x,
+            radius_y,
+            rotation,
+        )
+    }
+}
+
+/// Returns the signed distance to an axis-aligned ellipse centered on the
+/// origin.
+///
+/// The closest point on the ellipse is found iteratively. This is the CPU
+/// version of `sdf_ellipse_local` in the shader, and must be kept in sync
+/// with it.
+///
+/// Based on: https://github.com/0xfaded/ellipse_demo
+///
+/// # Parameters
+///
+/// - `ab`: Radii of the ellipse.
+/// - `p`: Location, relative to the center of the ellipse.
+pub(crate) fn sdf_ellipse_local(ab: V2, p: V2) -> f32 {
+    let q = V2::new(p.x.abs(), p.y.abs());
+    let mut t = V2::new(
+        std::f32::consts::FRAC_1_SQRT_2,
+        std::f32::consts::FRAC_1_SQRT_2,
+    );
+    for _ in 0..ELLIPSE_ITERATIONS {
+        let x = ab.x * t.x;
+        let y = ab.y * t.y;
+        let ex = (ab.x * ab.x - ab.y * ab.y) * t.x * t.x * t.x / ab.x;
+        let ey = (ab.y * ab.y - ab.x * ab.x) * t.y * t.y * t.y / ab.y;
+        let r = V2::new(x - ex, y - ey).magnitude();
+        let qe = V2::new(q.x - ex, q.y - ey);
+        let qm = qe.magnitude().max(ELLIPSE_TINY);
+        t = V2::new(
+            ((qe.x * r / qm + ex) / ab.x).clamp(0.0, 1.0),
+            ((qe.y * r / qm + ey) / ab.y).clamp(0.0, 1.0),
+        );
+        t /= t.magnitude();
+    }
+
+    let closest = V2::new(ab.x * t.x, ab.y * t.y);
+    let dist = (q - closest).magnitude();
+    let k = (q.x / ab.x) * (q.x / ab.x) + (q.y / ab.y) * (q.y / ab.y);
+    if k < 1.0 {
+        -dist
+    } else {
+        dist
+    }
+}
+
+/// Number of iterations used to find the closest point on an ellipse.
+const ELLIPSE_ITERATIONS: u32 = 4;
+
+/// Smallest distance used as a divisor when finding the closest point on an
+/// ellipse.
+const ELLIPSE_TINY: f32 = 1.0e-12;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_distance() {
+        let ellipse = Ellipse::new(P2::new(10.0, 20.0), 8.0, 3.0, 0.0);
+        assert!(
+            (ellipse.signed_distance(P2::new(10.0, 20.0)) + 3.0).abs() < 1e-4
+        );
+        assert!(
+            (ellipse.signed_distance(P2::new(20.0, 20.0)) - 2.0).abs() < 1e-4
+        );
+        assert!(
+            (ellipse.signed_distance(P2::new(10.0, 25.0)) - 2.0).abs() < 1e-4
+        );
+
+        let rotated = Ellipse::new(
+            P2::new(0.0, 0.0),
+            8.0,
+            3.0,
+            std::f32::consts::FRAC_PI_2,
+        );
+        assert!(
+            (rotated.signed_distance(P2::new(0.0, 10.0)) - 2.0).abs() < 1e-4
+        );
+        assert!(
+            (rotated.signed_distance(P2::new(4.0, 0.0)) - 1.0).abs() < 1e-4
+        );
+
+        let circle = Ellipse::new(P2::new(0.0, 0.0), 5.0, 5.0, 0.3);
+        for i in 0..16 {
+            let angle = i as f32 * 0.4;
+            let p = P2::new(7.0 * angle.cos(), 7.0 * angle.sin());
+            assert!((circle.signed_distance(p) - 2.0).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! RGBA8 images.
+
+/// An image with 8-bit RGBA pixels.
+///
+/// Pixels are stored row-by-row, with no padding between rows. The first row
+/// is the top of the image (the same order as a WGPU framebuffer), so it is
+/// vertically flipped relative to beamline coordinates, where `y` increases
+/// upwards.
+///
+/// To construct an image, use [`Image::new`] or [`Image::from_rgba8`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+impl Image {
+    /// Creates a new, fully-transparent image.
+    ///
+    /// # Parameters
+    ///
+    /// - `width`: Width of the image in pixels.
+    /// - `height`: Height of the image in pixels.
+    pub fn new(width: u32, height: u32) -> Self {
+        Image {
+            width,
+            height,
+            data: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// Creates an image from tightly-packed RGBA8 data.
+    ///
+    /// # Parameters
+    ///
+    /// - `width`: Width of the image in pixels.
+    /// - `height`: Height of the image in pixels.
+    /// - `data`: Pixel data. This must contain exactly `width * height * 4`
+    ///   bytes, with the top row first.
+    pub fn from_rgba8(width: u32, height: u32, data: Vec<u8>) -> Self {
+        assert_eq!(data.len(), width as usize * height as usize * 4);
+        Image {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Returns the width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the raw RGBA8 data of the image.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Consumes the image, returning its raw RGBA8 data.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Returns the RGBA value of a pixel.
+    ///
+    /// # Parameters
+    ///
+    /// - `x`: Column of the pixel, from the left.
+    /// - `y`: Row of the pixel, from the top.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = self.pixel_offset(x, y);
+        [
+            self.data[i],
+            self.data[i + 1],
+            self.data[i + 2],
+            self.data[i + 3],
+        ]
+    }
+
+    /// Sets the RGBA value of a pixel.
+    ///
+    /// # Parameters
+    ///
+    /// - `x`: Column of the pixel, from the left.
+    /// - `y`: Row of the pixel, from the top.
+    /// - `rgba`: New value of the pixel.
+    pub fn set_pixel(&mut self, x: u32, y: u32, rgba: [u8; 4]) {
+        let i = self.pixel_offset(x, y);
+        self.data[i..i + 4].copy_from_slice(&rgba);
+    }
+
+    /// Returns the largest absolute difference between any channel of any
+    /// pixel in two images.
+    ///
+    /// This is intended for comparing images within a tolerance; for
+    /// example, comparing GPU output against [`crate::software`] output.
+    ///
+    /// # Parameters
+    ///
+    /// - `other`: Image to compare against. This must have the same size.
+    pub fn max_difference(&self, other: &Image) -> u8 {
+        assert_eq!(self.width, other.width);
+        assert_eq!(self.height, other.height);
+        self.data
+            .iter()
+            .zip(other.data.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the byte offset of a pixel in the data array.
+    fn pixel_offset(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width);
+        assert!(y < self.height);
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+}
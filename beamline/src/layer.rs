@@ -0,0 +1,245 @@
+//! Named layers, which order shapes independently of when they are added.
+//!
+//! Every shape is drawn in a layer. Within each tile, the shapes of layers
+//! with a lower z index are drawn beneath the shapes of layers with a higher
+//! one, whatever order they were added in. Layers with the same z index are
+//! drawn in the order in which they were created, and the shapes within a
+//! layer are drawn in the order in which they were added.
+//!
+//! Layers are created by the [`crate::tiler::Tiler`], which starts with a
+//! single layer: [`LayerId::DEFAULT`].
+
+/// Name of the default layer.
+pub const DEFAULT_LAYER_NAME: &str = "default";
+
+/// Identifier of a layer.
+///
+/// Identifiers are handed out by [`crate::tiler::Tiler::add_layer`], and
+/// are only meaningful to the tiler which created them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LayerId(u32);
+impl LayerId {
+    /// The default layer, with a z index of zero, which shapes are drawn in
+    /// until another layer is selected.
+    pub const DEFAULT: LayerId = LayerId(0);
+
+    /// Creates an identifier from the index of a layer.
+    pub(crate) fn new(index: usize) -> Self {
+        LayerId(u32::try_from(index).expect("too many layers"))
+    }
+
+    /// Returns the index of the layer, in the order in which the layers
+    /// were created.
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+/// A layer of shapes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    /// Unique name of the layer.
+    name: String,
+    /// Z index of the layer. Layers with higher z indices are drawn on top.
+    pub z: i32,
+    /// Whether the shapes of the layer are drawn.
+    pub visible: bool,
+    /// Group opacity of the layer, from zero to one.
+    ///
+    /// The shapes of a layer with an opacity below one are composited
+    /// together, and the result is faded by the opacity as a unit. This
+    /// means that overlapping shapes within the layer do not show through
+    /// each other, and also that blend modes within the layer do not apply
+    /// to the layers beneath it.
+    pub opacity: f32,
+}
+impl Layer {
+    /// Creates a new visible, opaque layer.
+    ///
+    /// # Parameters
+    ///
+    /// - `name`: Unique name of the layer.
+    /// - `z`: Z index of the layer. Layers with higher z indices are drawn
+    ///   on top.
+    ///
+    /// # Returns
+    ///
+    /// A new layer.
+    pub fn new(name: &str, z: i32) -> Self {
+        Layer {
+            name: name.to_string(),
+            z,
+            visible: true,
+            opacity: 1.0,
+        }
+    }
+
+    /// Returns the name of the layer.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::parity::{check_scene, pixel_at, Shape},
+        BlendMode, CircularArc, Color, Ellipse, FillStyle, Line, LineCap,
+        LineStyle, Path, SoftwareRenderer, P2,
+    };
+
+    /// Returns a scene of overlapping shapes in layers, which are added out
+    /// of z order, with group opacities and blend modes.
+    fn layer_scene() -> Vec<(Shape, LineStyle)> {
+        let style = |width, color, blend_mode| LineStyle {
+            width,
+            cap: LineCap::Round,
+            color,
+            blend_mode,
+            ..Default::default()
+        };
+        let fill = FillStyle {
+            color: Color::new(0.2, 0.3, 0.8, 1.0),
+            ..Default::default()
+        };
+        let background = Path::polygon(&[
+            P2::new(10.0, 10.0),
+            P2::new(140.0, 10.0),
+            P2::new(140.0, 90.0),
+            P2::new(10.0, 90.0),
+        ]);
+        let red =
+            style(14.0, Color::new(0.9, 0.1, 0.1, 1.0), BlendMode::Normal);
+        let green =
+            style(10.0, Color::new(0.1, 0.9, 0.2, 0.8), BlendMode::Additive);
+        vec![
+            (Shape::Layer("overlay", 2, 0.5), red.clone()),
+            (
+                Shape::Line(Line::new(
+                    P2::new(20.0, 20.0),
+                    P2::new(130.0, 80.0),
+                )),
+                red.clone(),
+            ),
+            (
+                Shape::Line(Line::new(
+                    P2::new(20.0, 80.0),
+                    P2::new(130.0, 20.0),
+                )),
+                red.clone(),
+            ),
+            (Shape::Layer("background", -1, 1.0), red.clone()),
+            (Shape::Fill(background, fill), red.clone()),
+            (Shape::Layer("default", 0, 0.7), red.clone()),
+            (
+                Shape::Ellipse(Ellipse::new(
+                    P2::new(75.0, 50.0),
+                    40.0,
+                    20.0,
+                    0.0,
+                )),
+                green.clone(),
+            ),
+            (
+                Shape::Line(Line::new(
+                    P2::new(75.0, 10.0),
+                    P2::new(75.0, 90.0),
+                )),
+                green,
+            ),
+            (Shape::Layer("overlay", 2, 0.5), red.clone()),
+            (
+                Shape::Arc(CircularArc::circle(P2::new(75.0, 50.0), 30.0)),
+                red.clone(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_layers_match_gpu() {
+        let image =
+            check_scene(wgpu::TextureFormat::Rgba8Unorm, &layer_scene());
+        // The red overlay line is faded to half over the blue background,
+        // although the background was added later.
+        assert_eq!(pixel_at(&image, 30.5, 25.5), [140, 51, 115, 255]);
+        assert_eq!(pixel_at(&image, 5.5, 5.5), [0, 0, 0, 0]);
+        // The green lines of the default layer are added together within
+        // the layer, which is then faded to 0.7 over the background: where
+        // they cross, green saturates.
+        assert_eq!(pixel_at(&image, 75.5, 40.5), [37, 162, 118, 255]);
+        assert_eq!(pixel_at(&image, 75.5, 70.5), [44, 255, 118, 255]);
+    }
+
+    /// Overlapping shapes in a layer with a group opacity are faded as a
+    /// unit, so that they do not show through each other, and layers are
+    /// drawn in z order whatever order their shapes are added in.
+    #[test]
+    fn test_layer_group_opacity_and_order() {
+        let new_renderer = || {
+            SoftwareRenderer::new(
+                wgpu::TextureFormat::Rgba8Unorm,
+                150,
+                100,
+                16,
+                16,
+            )
+        };
+        let style = LineStyle {
+            width: 20.0,
+            color: Color::new(0.9, 0.4, 0.1, 1.0),
+            ..Default::default()
+        };
+        let underlay = LineStyle {
+            width: 40.0,
+            color: Color::new(0.1, 0.2, 0.9, 1.0),
+            ..Default::default()
+        };
+        let horizontal = Line::new(P2::new(20.0, 50.0), P2::new(130.0, 50.0));
+        let vertical = Line::new(P2::new(75.0, 10.0), P2::new(75.0, 90.0));
+        let background = Line::new(P2::new(0.0, 50.0), P2::new(150.0, 50.0));
+
+        let mut renderer = new_renderer();
+        let overlay = renderer.add_layer("overlay", 1);
+        renderer.layer_mut(overlay).opacity = 0.5;
+        renderer.set_layer(overlay);
+        renderer.line(horizontal.clone(), &style);
+        renderer.line(vertical.clone(), &style);
+        renderer.set_layer(LayerId::DEFAULT);
+        renderer.line(background.clone(), &underlay);
+        let image = renderer.render();
+
+        // The crossing is the same color as either line on its own.
+        let crossing = image.pixel(75, 50);
+        assert_eq!(crossing, image.pixel(40, 50));
+        assert_eq!(crossing, image.pixel(75, 35));
+
+        // The same scene, added in z order with a translucent color instead
+        // of a group opacity, only differs at the crossing.
+        let mut expected = new_renderer();
+        expected.line(background.clone(), &underlay);
+        let translucent = LineStyle {
+            color: Color::new(0.9, 0.4, 0.1, 0.5),
+            ..style.clone()
+        };
+        expected.line(horizontal.clone(), &translucent);
+        expected.line(vertical, &translucent);
+        let expected = expected.render();
+        assert_eq!(image.pixel(40, 50), expected.pixel(40, 50));
+        assert_eq!(image.pixel(75, 35), expected.pixel(75, 35));
+        assert_ne!(image.pixel(75, 50), expected.pixel(75, 50));
+
+        // Hiding the layer removes its shapes.
+        renderer.layer_mut(overlay).visible = false;
+        renderer.set_layer(overlay);
+        renderer.line(horizontal, &style);
+        renderer.set_layer(LayerId::DEFAULT);
+        renderer.line(background.clone(), &underlay);
+        let mut background_only = new_renderer();
+        background_only.line(background, &underlay);
+        assert_eq!(
+            renderer.render().max_difference(&background_only.render()),
+            0
+        );
+    }
+}
@@ -1,17 +1,71 @@
+pub mod arc;
 pub mod bbox;
+pub mod bezier;
+pub mod binning;
+pub mod bloom;
 pub mod buffers;
+pub mod clip;
+pub mod ellipse;
+pub mod image;
 pub mod interval;
+pub mod layer;
 pub mod line;
+pub mod marker;
+pub mod offscreen;
+pub mod path;
+pub mod persistence;
 pub mod polygon;
+pub mod polyline;
+pub mod primitive;
 pub mod renderer;
+pub mod scene;
+pub mod software;
 pub mod style;
 pub mod tiler;
+pub mod transform;
 pub mod types;
 
+#[cfg(test)]
+mod tests;
+
+pub use arc::CircularArc;
+pub use bezier::CubicBezier;
+pub use bezier::QuadraticBezier;
+pub use binning::TilingBackend;
+pub use binning::TilingMismatch;
+pub use bloom::Bloom;
+pub use bloom::ToneMap;
+pub use clip::ClipRect;
+pub use ellipse::Ellipse;
+pub use image::Image;
+pub use layer::Layer;
+pub use layer::LayerId;
 pub use line::Line;
+pub use offscreen::OffscreenTarget;
+pub use path::Path;
+pub use persistence::Phosphor;
+pub use polyline::Polyline;
 pub use renderer::Renderer;
+pub use scene::Scene;
+pub use scene::SceneShape;
+pub use scene::ShapeHandle;
+pub use software::SoftwareRenderer;
+pub use style::BlendMode;
 pub use style::Color;
+pub use style::ColorInterpolation;
+pub use style::DashPattern;
+pub use style::FillRule;
+pub use style::FillStyle;
+pub use style::Glow;
+pub use style::GlowFalloff;
+pub use style::Gradient;
 pub use style::LineCap;
+pub use style::LineJoin;
 pub use style::LineStyle;
+pub use style::Marker;
+pub use style::MarkerShape;
+pub use style::StrokeScaling;
+pub use transform::Camera;
+pub use types::M3;
 pub use types::P2;
 pub use types::V2;
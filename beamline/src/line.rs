@@ -1,4 +1,5 @@
-use crate::{P2, V2};
+use crate::{transform::Transformable, M3, P2, V2};
+use cgmath::Transform;
 
 /// Line.
 ///
@@ -65,3 +66,11 @@ impl Line {
         self.a + t * self.ab_vec()
     }
 }
+impl Transformable for Line {
+    fn transformed(&self, matrix: &M3) -> Self {
+        Line::new(
+            matrix.transform_point(self.a),
+            matrix.transform_point(self.b),
+        )
+    }
+}
@@ -10,6 +10,7 @@
 @group(1) @binding(0) var<uniform>       shader_options : ShaderOptions;
 @group(1) @binding(1) var<storage, read> tile_infos     : array<TileInfo>;
 @group(1) @binding(2) var<storage, read> lines          : array<StyledLine>;
+@group(1) @binding(3) var<storage, read> line_indices   : array<u32>;
 
 /**** STRUCTS ****************************************************************/
 
@@ -30,6 +31,10 @@ struct ShaderOptions {
 };
 
 /// Tile Information
+///
+/// The lines of a tile are `lines[line_indices[i]]`, for `i` from
+/// `start_index` to `start_index + n_lines`. A line in several tiles is only
+/// stored once in `lines`.
 struct TileInfo {
     tile_x      : u32,
     tile_y      : u32,
@@ -38,12 +43,48 @@ struct TileInfo {
 };
 
 /// Styled Line
+///
+/// This is a styled primitive. Which of the fields are used depends on the
+/// `kind` of the primitive (see `sdf_styled_line`). A segment is dashed if
+/// `dash_count` is non-zero (see `sdf_dashed_segment`). The `winding` and
+/// `fill_rule` are only used by fills and clip paths (see
+/// `render_all_lines`). A primitive is clipped to the rectangle from
+/// `clip_min` to `clip_max` (see `clip_rect_coverage`). A primitive
+/// has a gradient if `interpolation` is non-zero (see `styled_line_color`),
+/// and a glow if `glow_radius` is positive (see `glow_line_factor`). The
+/// `blend_mode` determines how it is composited (see `blend_over`), and the
+/// `layer` and `layer_opacity` how it is grouped (see `render_all_lines`).
 struct StyledLine {
-    start   : vec2f,
-    end     : vec2f,
-    width   : f32,
-    cap     : u32,
-    color   : vec4f
+    start        : vec2f,
+    end          : vec2f,
+    width        : f32,
+    start_cap    : u32,
+    end_cap      : u32,
+    kind         : u32,
+    color        : vec4f,
+    shape        : u32,
+    n_points     : u32,
+    points       : array<vec2f, 6>,
+    dash_cap     : u32,
+    dash_count   : u32,
+    dash_phase   : f32,
+    dash_lengths : array<f32, 8>,
+    winding        : i32,
+    fill_rule      : u32,
+    gradient_start : f32,
+    end_color      : vec4f,
+    gradient_end   : f32,
+    interpolation  : u32,
+    end_width      : f32,
+    marker         : u32,
+    glow_radius    : f32,
+    glow_falloff   : u32,
+    glow_intensity : f32,
+    blend_mode     : u32,
+    clip_min       : vec2f,
+    clip_max       : vec2f,
+    layer          : u32,
+    layer_opacity  : f32
 };
 
 /// Closest Line
@@ -97,6 +138,7 @@ struct VertexOutput {
     let fg_color = render_all_lines(
         tile_info.start_index,
         tile_info.n_lines,
+        tile_fill_reference(tile_info.tile_x, tile_info.tile_y),
         p
     );
 
@@ -112,14 +154,13 @@ struct VertexOutput {
             tile_shortest_edge_distance_uv(in.uv) * f32(viewport.tile_width)
             - TILE_EDGE_WIDTH
         );
-        let edge_color = vec4f(
-            shader_options.tile_edges.xyx,
-            shader_options.tile_edges.w * edge_amount
-        );
-        bg_color = alpha_over(shader_options.tile_background, edge_color);
+        let edge_color = premultiply(shader_options.tile_edges) * edge_amount;
+        bg_color = alpha_over(premultiply(shader_options.tile_background), edge_color);
     }
 
     // Alpha-composite the foreground line over any background tile color.
+    // The output has premultiplied alpha, to match the blend state of the
+    // render pipeline.
     return alpha_over(fg_color, bg_color);
 }
 
@@ -196,55 +237,91 @@ fn framebuffer_to_beamline(
     );
 }
 
+/// Converts a color with non-premultiplied alpha to premultiplied alpha.
+fn premultiply(color : vec4f) -> vec4f {
+    return vec4f(color.xyz * color.w, color.w);
+}
+
 /// Alpha-over composite operation.
 ///
-/// This composites `a` over `b`, where neither has a pre-multiplied alpha.
+/// This composites `a` over `b`, where both have premultiplied alpha.
 ///
 /// # Parameters
 ///
-/// - `a`: Top color to composite (non-premultiplied alpha).
-/// - `b`: Bottom color to composite (non-premultiplied alpha).
+/// - `a`: Top color to composite (premultiplied alpha).
+/// - `b`: Bottom color to composite (premultiplied alpha).
 ///
 /// # Returns
 ///
-/// `a over b` composite operation.
+/// `a over b` composite operation (premultiplied alpha).
 fn alpha_over(
     a : vec4f,
     b : vec4f
 ) -> vec4f {
-    let a_alpha = a.w;
-    let b_alpha = b.w;
-    let out_alpha = clamp(a_alpha + b_alpha * (1.0 - a_alpha), 0.0, 1.0);
-
-    return vec4f(
-        alpha_over_channel(a_alpha, b_alpha, out_alpha, a.x, b.x),
-        alpha_over_channel(a_alpha, b_alpha, out_alpha, a.y, b.y),
-        alpha_over_channel(a_alpha, b_alpha, out_alpha, a.z, b.z),
-        out_alpha
-    );
+    return a + b * (1.0 - a.w);
 }
 
-/// Alpha-over compositing operation for a single channel.
+/// Composites a color over another using a blend mode.
+///
+/// For the separable blend modes, the result inside both colors is the
+/// blend function of their channels, and elsewhere it is whichever color
+/// is present, as for `alpha_over`. Additive blending instead sums both
+/// colors.
 ///
 /// # Parameters
 ///
-/// - `a_alpha`: The alpha value of the `a` color.
-/// - `b_alpha`: The alpha value of the `b` color.
-/// - `out_alpha`: Output alpha (`a_alpha + b_alpha * (1.0 - a_alpha)`)
-/// - `a_comp`: Channel of the `a` color to operate on.
-/// - `b_comp`: Channel of the `b` color to operate on.
+/// - `mode`: Blend mode (see the `BLEND_MODE_` constants).
+/// - `a`: Top color to composite (premultiplied alpha).
+/// - `b`: Bottom color to composite (premultiplied alpha).
 ///
 /// # Returns
 ///
-/// `a over b` for one channel (ie. red, green or blue).
-fn alpha_over_channel(
-    a_alpha   : f32,
-    b_alpha   : f32,
-    out_alpha : f32,
-    a_comp    : f32,
-    b_comp    : f32
-) -> f32 {
-    return (a_comp * a_alpha + b_comp * b_alpha * (1.0 - a_alpha)) / out_alpha;
+/// `a` blended over `b` (premultiplied alpha).
+fn blend_over(
+    mode : u32,
+    a    : vec4f,
+    b    : vec4f
+) -> vec4f {
+    if (mode == BLEND_MODE_ADDITIVE) {
+        return vec4f(a.xyz + b.xyz, min(a.w + b.w, 1.0));
+    }
+    let color = a.xyz * (1.0 - b.w) + b.xyz * (1.0 - a.w) + blend_channels(mode, a, b);
+    return vec4f(color, a.w + b.w - a.w * b.w);
+}
+
+/// Blend function of a separable blend mode, where two colors overlap.
+///
+/// This is the blend function of the non-premultiplied colors, multiplied
+/// by both alphas. It is written so that it never divides by an alpha.
+///
+/// # Parameters
+///
+/// - `mode`: Blend mode (see the `BLEND_MODE_` constants).
+/// - `a`: Top color (premultiplied alpha).
+/// - `b`: Bottom color (premultiplied alpha).
+///
+/// # Returns
+///
+/// The blended color channels.
+fn blend_channels(
+    mode : u32,
+    a    : vec4f,
+    b    : vec4f
+) -> vec3f {
+    switch (mode) {
+        case BLEND_MODE_SCREEN: {
+            return a.xyz * b.w + b.xyz * a.w - a.xyz * b.xyz;
+        }
+        case BLEND_MODE_MAX: {
+            return max(a.xyz * b.w, b.xyz * a.w);
+        }
+        case BLEND_MODE_MULTIPLY: {
+            return a.xyz * b.xyz;
+        }
+        default: {
+            return a.xyz * b.w;
+        }
+    }
 }
 
 /// Returns the shortest distance to the edge of a tile in uv space.
@@ -287,45 +364,499 @@ fn line_factor(
     return 1.0 - smoothstep(-aw2, aw2, dist);
 }
 
+/// Line edge step for a line which may have a glow.
+///
+/// The glow is added outside the antialiased edge of the line, so that the
+/// line is covered by either its core or its glow.
+///
+/// # Parameters
+///
+/// - `antialias_width`: Antialiasing width; the width of the smoothstep.
+/// - `styled_line`: The line, which supplies the glow parameters.
+/// - `dist`: Signed distance function value.
+///
+/// # Returns
+///
+/// Line edge step, including the glow.
+fn glow_line_factor(
+    antialias_width : f32,
+    styled_line     : StyledLine,
+    dist            : f32
+) -> f32 {
+    let core = line_factor(antialias_width, dist);
+    if (styled_line.glow_radius <= 0.0) {
+        return core;
+    }
+    let t = clamp(dist / styled_line.glow_radius, 0.0, 1.0);
+    let glow = min(
+        styled_line.glow_intensity * glow_falloff(styled_line.glow_falloff, t),
+        1.0
+    );
+    return core + (1.0 - core) * glow;
+}
+
+/// Returns the strength of a glow.
+///
+/// If the falloff curve is invalid for any reason, the fallback is to use a
+/// linear falloff.
+///
+/// # Parameters
+///
+/// - `falloff`: Falloff curve of the glow.
+/// - `t`: Distance from the edge of the line, as a fraction of the radius
+///   of the glow, in `[0.0, 1.0]`.
+///
+/// # Returns
+///
+/// The strength of the glow, which is 1.0 at `t = 0.0` and 0.0 at
+/// `t = 1.0`.
+fn glow_falloff(
+    falloff : u32,
+    t       : f32
+) -> f32 {
+    let s = 1.0 - t;
+    if (falloff == GLOW_FALLOFF_QUADRATIC) {
+        return s * s;
+    } else if (falloff == GLOW_FALLOFF_GAUSSIAN) {
+        let floor = exp(-GLOW_GAUSSIAN_K);
+        return (exp(-GLOW_GAUSSIAN_K * t * t) - floor) / (1.0 - floor);
+    }
+    // GLOW_FALLOFF_LINEAR, and fallback
+    return s;
+}
+
+/// Fetch a line of a tile, through the line index buffer.
+///
+/// # Globals Used
+///
+/// - `lines`
+/// - `line_indices`
+///
+/// # Parameters
+///
+/// - `i`: Index of the line in the line index buffer.
+fn tile_line(i: u32) -> StyledLine {
+    return lines[line_indices[i]];
+}
+
 /// Render all lines in a tile.
 ///
 /// Tiles are rendered from bottom to top, compositing them over each other.
 ///
+/// Consecutive lines which belong to the same shape (eg. the segments and
+/// joins of a polyline) are combined by taking the union of their signed
+/// distance functions, and are then composited once as a single shape. The
+/// shape takes the color of whichever of its lines is nearest to `p`, which
+/// only differ if the shape has a gradient.
+///
+/// A filled path is a `PRIMITIVE_FILL`, followed by the edges of the path
+/// which are near the tile. The winding number of the path around `p` is
+/// found from the winding number around the tile's reference point, and the
+/// signed distance is the distance to the nearest edge, negated inside the
+/// path. A fill with no edges is inside the path everywhere in the tile.
+///
+/// A shape may be preceded by `PRIMITIVE_CLIP`s, each followed by the edges
+/// of a clip path near the tile. The coverage of each clip path is found in
+/// the same way as for a fill, and multiplies the coverage of the shape, as
+/// does the coverage of the shape's clip rectangle.
+///
+/// Shapes are grouped into layers. The shapes of a layer with a group
+/// opacity below one are composited together, and the group is then faded
+/// by its opacity and composited over the layers beneath it. The shapes of
+/// other layers are composited directly.
+///
 /// # Globals Used
 ///
 /// - `lines`
+/// - `line_indices`
 ///
 /// # Parameters
 ///
 /// - `start_index`: Start index of lines in the tile.
 /// - `n_lines`: Number of lines in the tile.
+/// - `reference`: Reference point of the tile, for filled paths.
 /// - `p`: Current position.
 fn render_all_lines(
     start_index : u32,
     n_lines     : u32,
+    reference   : vec2f,
     p           : vec2f
 ) -> vec4f {
     var color = vec4f(0.0, 0.0, 0.0, 0.0);
+    var group = vec4f(0.0, 0.0, 0.0, 0.0);
+    var group_layer = NO_LAYER;
+    var group_opacity = 1.0;
     let end_index: u32 = start_index + n_lines;
-    for (var i: u32 = start_index; i < end_index; i = i + 1) {
-        let line = lines[i];
-        let line_alpha_mul = line_factor(
-            shader_options.antialias_width,
-            sdf_styled_line(line, p)
-        );
-        let line_color = vec4f(line.color.xyz, line.color.w * line_alpha_mul);
+    var i: u32 = start_index;
+    while (i < end_index) {
+        var line = tile_line(i);
+        i = i + 1;
+
+        var clip = 1.0;
+        while (line.kind == PRIMITIVE_CLIP && i < end_index) {
+            var winding = line.winding;
+            var dist = DIST_FAR;
+            while (i < end_index && tile_line(i).kind == PRIMITIVE_FILL_EDGE) {
+                let edge = tile_line(i);
+                winding = winding + fill_winding(edge.start, edge.end, reference, p);
+                dist = min(dist, sdf_rounded_line(edge.start, edge.end, 0.0, p));
+                i = i + 1;
+            }
+            let clip_sdf = select(dist, -dist, fill_inside(line.fill_rule, winding));
+            clip = clip * line_factor(shader_options.antialias_width, clip_sdf);
+            line = tile_line(i);
+            i = i + 1;
+        }
+
+        if (line.layer != group_layer) {
+            color = alpha_over(group * group_opacity, color);
+            group = vec4f(0.0, 0.0, 0.0, 0.0);
+            group_layer = line.layer;
+            group_opacity = line.layer_opacity;
+        }
+
+        var sdf: f32;
+        var shape_color = line.color;
+        if (line.kind == PRIMITIVE_FILL) {
+            // Count the edges between the reference point and `p`, and find
+            // the nearest edge.
+            var winding = line.winding;
+            var dist = DIST_FAR;
+            while (i < end_index && tile_line(i).shape == line.shape) {
+                let edge = tile_line(i);
+                winding = winding + fill_winding(edge.start, edge.end, reference, p);
+                dist = min(dist, sdf_rounded_line(edge.start, edge.end, 0.0, p));
+                i = i + 1;
+            }
+            sdf = select(dist, -dist, fill_inside(line.fill_rule, winding));
+        } else {
+            // Union the SDFs of all consecutive lines in the same shape. The
+            // color of the shape is the color of its nearest line.
+            sdf = sdf_styled_line(line, p);
+            shape_color = styled_line_color(line, p);
+            while (i < end_index && tile_line(i).shape == line.shape) {
+                let line_sdf = sdf_styled_line(tile_line(i), p);
+                if (line_sdf < sdf) {
+                    sdf = line_sdf;
+                    shape_color = styled_line_color(tile_line(i), p);
+                }
+                i = i + 1;
+            }
+        }
+
+        let line_alpha_mul = glow_line_factor(shader_options.antialias_width, line, sdf)
+            * clip * clip_rect_coverage(line, p);
+        let line_color = premultiply(shape_color) * line_alpha_mul;
         if (line_color.w > THRESHOLD_MIN_ALPHA) {
-            color = alpha_over(line_color, color);
+            if (group_opacity < 1.0) {
+                group = blend_over(line.blend_mode, line_color, group);
+            } else {
+                color = blend_over(line.blend_mode, line_color, color);
+            }
+        }
+    }
+    return alpha_over(group * group_opacity, color);
+}
+
+/// Returns the fraction of the pixel at a location which is inside the clip
+/// rectangle of a styled line.
+///
+/// # Parameters
+///
+/// - `styled_line`: The line, which supplies the clip rectangle.
+/// - `p`: Location of the center of the pixel.
+fn clip_rect_coverage(
+    styled_line : StyledLine,
+    p           : vec2f
+) -> f32 {
+    let low = max(styled_line.clip_min, p - 0.5);
+    let high = min(styled_line.clip_max, p + 0.5);
+    let size = max(high - low, vec2f(0.0, 0.0));
+    return size.x * size.y;
+}
+
+/// Returns the reference point of a tile, for filled paths.
+///
+/// This is the center of the pixel in the bottom-right corner of the tile.
+/// The tiler computes the winding number of each filled path around it.
+///
+/// # Globals Used
+///
+/// - `viewport`
+///
+/// # Parameters
+///
+/// - `tile_x`: x index of the tile
+/// - `tile_y`: y index of the tile
+fn tile_fill_reference(
+    tile_x : u32,
+    tile_y : u32
+) -> vec2f {
+    return vec2f(
+        f32((tile_x + 1) * viewport.tile_width) - 0.5,
+        f32(tile_y * viewport.tile_height) + 0.5
+    );
+}
+
+/// Returns the change in winding number due to one edge of a filled path,
+/// between the reference point of a tile and a point in the tile.
+///
+/// Winding numbers are counted along a ray towards positive x, where edges
+/// crossing upwards count as +1, and edges crossing downwards count as -1.
+/// The winding number around `p` is the winding number around `reference`,
+/// plus the edges crossed on a path from `reference` up to the height of
+/// `p`, and then left to `p`. The edges crossed on this path are inside the
+/// tile, so all of them are in the tile's list of lines.
+///
+/// The path must run up and left from `reference`, which is true for all
+/// pixels in the tile.
+///
+/// # Parameters
+///
+/// - `a`: Start of the edge.
+/// - `b`: End of the edge.
+/// - `reference`: Reference point of the tile.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The winding number of the edge around `p`, minus its winding number
+/// around `reference`.
+fn fill_winding(
+    a         : vec2f,
+    b         : vec2f,
+    reference : vec2f,
+    p         : vec2f
+) -> i32 {
+    var winding: i32 = 0;
+
+    // Crossing the edge upwards, along the vertical part of the path. This
+    // adds one if the edge runs towards positive x. The ray test treats
+    // points as if they were moved right by a tiny distance, and up by a
+    // much tinier distance. Where the edge passes exactly through an end of
+    // the vertical part, the same is done here, so the slope of the edge
+    // decides whether it is crossed.
+    if ((a.x <= reference.x) != (b.x <= reference.x)) {
+        let y = a.y + (reference.x - a.x) * (b.y - a.y) / (b.x - a.x);
+        let rising = (b.y - a.y) * (b.x - a.x) > 0.0;
+        let above_start = y > reference.y || (y == reference.y && rising);
+        let below_end = y < p.y || (y == p.y && !rising);
+        if (above_start && below_end) {
+            winding = winding + select(-1, 1, b.x > a.x);
+        }
+    }
+
+    // The edge crosses the ray from `p`, but not the ray from the top of
+    // the vertical part of the path.
+    if ((a.y <= p.y) != (b.y <= p.y)) {
+        let x = a.x + (p.y - a.y) * (b.x - a.x) / (b.y - a.y);
+        if (x > p.x && x <= reference.x) {
+            winding = winding + select(-1, 1, b.y > a.y);
         }
     }
-    return color;
+
+    return winding;
+}
+
+/// Checks whether a winding number is inside a filled path.
+///
+/// # Parameters
+///
+/// - `fill_rule`: Fill rule of the path.
+/// - `winding`: Winding number of the path around a point.
+fn fill_inside(
+    fill_rule : u32,
+    winding   : i32
+) -> bool {
+    if (fill_rule == FILL_RULE_EVEN_ODD) {
+        return (winding & 1) != 0;
+    }
+    return winding != 0;
+}
+
+/// Returns the color of a styled line at a location.
+///
+/// If the line has a gradient, its color is interpolated between `color`
+/// and `end_color`, according to how far along the line the closest point
+/// to `p` is.
+///
+/// # Parameters
+///
+/// - `styled_line`: The line to examine.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// Color of the line at `p` (non-premultiplied alpha).
+fn styled_line_color(
+    styled_line : StyledLine,
+    p           : vec2f
+) -> vec4f {
+    if (styled_line.interpolation == 0) {
+        return styled_line.color;
+    }
+    let t = mix(
+        styled_line.gradient_start,
+        styled_line.gradient_end,
+        styled_line_param(styled_line, p)
+    );
+    return interpolate_color(
+        styled_line.color,
+        styled_line.end_color,
+        t,
+        styled_line.interpolation
+    );
+}
+
+/// Returns how far along a styled line its closest point to a location is.
+///
+/// This is the parameter of the closest point for segments and curves, and
+/// the fraction of the angle spanned for arcs. Polygons, discs and ellipses
+/// have no direction, so this is always zero for them.
+///
+/// # Parameters
+///
+/// - `styled_line`: The line to examine.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// How far along the line the closest point is, in `[0.0, 1.0]`.
+fn styled_line_param(
+    styled_line : StyledLine,
+    p           : vec2f
+) -> f32 {
+    let cp = styled_line.points;
+    if (styled_line.kind == PRIMITIVE_POLYGON
+        || styled_line.kind == PRIMITIVE_DISC
+        || styled_line.kind == PRIMITIVE_ELLIPSE
+        || styled_line.kind == PRIMITIVE_MARKER) {
+        return 0.0;
+    } else if (styled_line.kind == PRIMITIVE_QUADRATIC) {
+        return closest_param_quadratic(cp[0], cp[1], cp[2], p);
+    } else if (styled_line.kind == PRIMITIVE_CUBIC) {
+        return closest_param_cubic(cp[0], cp[1], cp[2], cp[3], p);
+    } else if (styled_line.kind == PRIMITIVE_ARC) {
+        return arc_param(styled_line.start, cp[0].y, cp[1], p);
+    }
+    // PRIMITIVE_SEGMENT, and fallback
+    let pa = p - styled_line.start;
+    let ba = styled_line.end - styled_line.start;
+    return clamp(dot(pa, ba) / dot(ba, ba), 0.0, 1.0);
+}
+
+/// Returns how far along an arc its closest point to a location is.
+///
+/// # Parameters
+///
+/// - `center`: Center of the circle.
+/// - `half_angle`: Half of the angle spanned by the arc.
+/// - `mid_dir`: Unit vector from the center towards the middle of the arc.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The fraction of the angle spanned by the arc, from its start to the
+/// closest point, in `[0.0, 1.0]`.
+fn arc_param(
+    center     : vec2f,
+    half_angle : f32,
+    mid_dir    : vec2f,
+    p          : vec2f
+) -> f32 {
+    let w = p - center;
+    let angle = atan2(dot(w, vec2f(mid_dir.y, -mid_dir.x)), dot(w, mid_dir));
+    return clamp(0.5 - angle / (2.0 * half_angle), 0.0, 1.0);
+}
+
+/// Interpolates between two colors.
+///
+/// The color components are interpolated in the given color space, while
+/// alpha is always interpolated linearly.
+///
+/// # Parameters
+///
+/// - `a`: Color at `t = 0`.
+/// - `b`: Color at `t = 1`.
+/// - `t`: Interpolation parameter.
+/// - `interpolation`: Color space (one of the `COLOR_INTERPOLATION_*`
+///   constants).
+///
+/// # Returns
+///
+/// The interpolated color.
+fn interpolate_color(
+    a             : vec4f,
+    b             : vec4f,
+    t             : f32,
+    interpolation : u32
+) -> vec4f {
+    let alpha = mix(a.w, b.w, t);
+    if (interpolation == COLOR_INTERPOLATION_SRGB) {
+        let rgb = mix(srgb_encode(a.xyz), srgb_encode(b.xyz), t);
+        return vec4f(srgb_decode(rgb), alpha);
+    } else if (interpolation == COLOR_INTERPOLATION_OKLAB) {
+        let lab = mix(linear_to_oklab(a.xyz), linear_to_oklab(b.xyz), t);
+        return vec4f(oklab_to_linear(lab), alpha);
+    }
+    // COLOR_INTERPOLATION_LINEAR, and fallback
+    return vec4f(mix(a.xyz, b.xyz, t), alpha);
+}
+
+/// Encodes linear color components using the sRGB transfer function.
+fn srgb_encode(c: vec3f) -> vec3f {
+    let encoded = 1.055 * pow(max(c, vec3f(0.0)), vec3f(1.0 / 2.4)) - 0.055;
+    return select(encoded, c * 12.92, c <= vec3f(0.0031308));
+}
+
+/// Decodes sRGB-encoded color components to linear components.
+fn srgb_decode(c: vec3f) -> vec3f {
+    let decoded = pow(max((c + 0.055) / 1.055, vec3f(0.0)), vec3f(2.4));
+    return select(decoded, c / 12.92, c <= vec3f(0.04045));
+}
+
+/// Converts linear sRGB color components to OKLab.
+///
+/// Based on: https://bottosson.github.io/posts/oklab/
+fn linear_to_oklab(c: vec3f) -> vec3f {
+    let l = cbrt(0.4122214708 * c.x + 0.5363325363 * c.y + 0.0514459929 * c.z);
+    let m = cbrt(0.2119034982 * c.x + 0.6806995451 * c.y + 0.1073969566 * c.z);
+    let s = cbrt(0.0883024619 * c.x + 0.2817188376 * c.y + 0.6299787005 * c.z);
+    return vec3f(
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s
+    );
+}
+
+/// Converts OKLab color components to linear sRGB.
+///
+/// Based on: https://bottosson.github.io/posts/oklab/
+fn oklab_to_linear(lab: vec3f) -> vec3f {
+    let l_ = lab.x + 0.3963377774 * lab.y + 0.2158037573 * lab.z;
+    let m_ = lab.x - 0.1055613458 * lab.y - 0.0638541728 * lab.z;
+    let s_ = lab.x - 0.0894841775 * lab.y - 1.2914855480 * lab.z;
+    let l = l_ * l_ * l_;
+    let m = m_ * m_ * m_;
+    let s = s_ * s_ * s_;
+    return vec3f(
+         4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s
+    );
+}
+
+/// Returns the cube root of a value, which may be negative.
+fn cbrt(x: f32) -> f32 {
+    return sign(x) * pow(max(abs(x), CBRT_TINY), 1.0 / 3.0);
 }
 
 /// Returns the signed distance function for a styled line.
 ///
-/// This accounts for the end-cap style of the line, which has to form part
-/// of the signed distance function. If the end cap style is invalid for any
-/// reason, the fallback is to use a rounded line style.
+/// This dispatches on the kind of primitive: a segment, a polygon, a disc, a
+/// curve, an arc or an ellipse. If the kind is invalid for any reason, the
+/// fallback is to treat the primitive as a segment. Fills are handled by
+/// `render_all_lines` instead.
 ///
 /// # Parameters
 ///
@@ -341,95 +872,1139 @@ fn sdf_styled_line(
 ) -> f32 {
     let width_2 = styled_line.width / 2.0;
 
-    // Switch operation depending on the end cap.
-    if (styled_line.cap == END_CAP_BUTT) {
-        return sdf_square_line(
+    if (styled_line.kind == PRIMITIVE_POLYGON) {
+        return sdf_polygon(styled_line.points, styled_line.n_points, p);
+    } else if (styled_line.kind == PRIMITIVE_DISC) {
+        return length(p - styled_line.start) - width_2;
+    } else if (styled_line.kind == PRIMITIVE_QUADRATIC) {
+        let cp = styled_line.points;
+        return sdf_quadratic(
+            cp[0],
+            cp[1],
+            cp[2],
+            width_2,
+            styled_line.start_cap,
+            styled_line.end_cap,
+            p
+        );
+    } else if (styled_line.kind == PRIMITIVE_ARC) {
+        let params = styled_line.points;
+        return sdf_arc(
             styled_line.start,
-            styled_line.end,
+            params[0].x,
+            params[0].y,
+            params[1],
             width_2,
-            0.0,
+            styled_line.start_cap,
+            styled_line.end_cap,
             p
         );
-    } else if (styled_line.cap == END_CAP_SQUARE) {
-        return sdf_square_line(
+    } else if (styled_line.kind == PRIMITIVE_ELLIPSE) {
+        let params = styled_line.points;
+        return sdf_ellipse(styled_line.start, params[0], params[1], width_2, p);
+    } else if (styled_line.kind == PRIMITIVE_MARKER) {
+        return sdf_marker(
             styled_line.start,
             styled_line.end,
             width_2,
+            styled_line.marker,
+            styled_line.points[0].x,
+            p
+        );
+    } else if (styled_line.kind == PRIMITIVE_CUBIC) {
+        let cp = styled_line.points;
+        return sdf_cubic(
+            cp[0],
+            cp[1],
+            cp[2],
+            cp[3],
             width_2,
+            styled_line.start_cap,
+            styled_line.end_cap,
             p
         );
+    } else if (styled_line.dash_count > 0) {
+        return sdf_dashed_segment(styled_line, p);
     } else {
-        // END_CAP_ROUND, and fallback
-        return sdf_rounded_line(
+        // PRIMITIVE_SEGMENT, and fallback
+        return sdf_segment(
             styled_line.start,
             styled_line.end,
             width_2,
+            styled_line.end_width / 2.0,
+            styled_line.start_cap,
+            styled_line.end_cap,
             p
         );
     }
 }
 
-/// Returns the signed distance function for a rounded line.
+/// Returns the signed distance function for a segment with end caps.
 ///
-/// Based on: https://iquilezles.org/articles/distfunctions2d/
+/// This accounts for the end-cap style at each end of the segment, which
+/// has to form part of the signed distance function. If an end cap style is
+/// invalid for any reason, the fallback is to use a rounded end.
+///
+/// A rounded end is formed as the union of a disc with the rectangular body
+/// of the segment. Tapered segments, whose half-width differs at each end,
+/// are handled by `sdf_tapered_segment`.
 ///
 /// # Parameters
 ///
-/// - `start`: Start coordinate of the line.
-/// - `end`: End coordinate of the line.
-/// - `radius`: Radius (half-width) of the line.
+/// - `start`: Start coordinate of the segment.
+/// - `end`: End coordinate of the segment.
+/// - `start_half_width`: Half of the width at the start of the segment.
+/// - `end_half_width`: Half of the width at the end of the segment.
+/// - `start_cap`: End cap at the start of the segment.
+/// - `end_cap`: End cap at the end of the segment.
 /// - `p`: Location.
 ///
 /// # Returns
 ///
 /// The signed distance function evaluated at `p`.
-fn sdf_rounded_line(
-    start  : vec2f,
-    end    : vec2f,
-    radius : f32,
-    p      : vec2f
+fn sdf_segment(
+    start            : vec2f,
+    end              : vec2f,
+    start_half_width : f32,
+    end_half_width   : f32,
+    start_cap        : u32,
+    end_cap          : u32,
+    p                : vec2f
 ) -> f32 {
-    let pa = p - start;
-    let ba = end - start;
-    let h = clamp(dot(pa, ba) / dot(ba, ba), 0.0, 1.0);
-    let line_dist = length(pa - ba * h);
+    if (start_half_width != end_half_width) {
+        return sdf_tapered_segment(
+            start,
+            end,
+            start_half_width,
+            end_half_width,
+            start_cap,
+            end_cap,
+            p
+        );
+    }
 
-    return line_dist - radius;
+    let half_width  = start_half_width;
+    let start_round = start_cap != END_CAP_BUTT && start_cap != END_CAP_SQUARE;
+    let end_round   = end_cap   != END_CAP_BUTT && end_cap   != END_CAP_SQUARE;
+    if (start_round && end_round) {
+        return sdf_rounded_line(start, end, half_width, p);
+    }
+
+    let extend_start = select(0.0, half_width, start_cap == END_CAP_SQUARE);
+    let extend_end   = select(0.0, half_width, end_cap   == END_CAP_SQUARE);
+    var dist = sdf_square_line(start, end, half_width, extend_start, extend_end, p);
+    if (start_round) {
+        dist = min(dist, length(p - start) - half_width);
+    }
+    if (end_round) {
+        dist = min(dist, length(p - end) - half_width);
+    }
+    return dist;
 }
 
-/// Returns the signed distance function for a square-capped line.
+/// Returns the signed distance function for a tapered segment with end
+/// caps.
 ///
-/// Based on: https://iquilezles.org/articles/distfunctions2d/
+/// The body of the segment is a trapezoid, whose half-width changes
+/// linearly from `start_half_width` to `end_half_width`. A square end
+/// extends the trapezoid by the half-width at that end, keeping that
+/// half-width, and a rounded end is a disc with that radius. A segment with
+/// rounded ends at both ends is instead the convex hull of the two discs (an
+/// uneven capsule), so that its sides are tangent to both discs. If an end
+/// cap style is invalid for any reason, the fallback is to use a rounded
+/// end.
 ///
-/// TODO
-fn sdf_square_line(
-    start      : vec2f,
-    end        : vec2f,
-    half_width : f32,
-    extend     : f32,
-    p          : vec2f
+/// # Parameters
+///
+/// - `start`: Start coordinate of the segment.
+/// - `end`: End coordinate of the segment.
+/// - `start_half_width`: Half of the width at the start of the segment.
+/// - `end_half_width`: Half of the width at the end of the segment.
+/// - `start_cap`: End cap at the start of the segment.
+/// - `end_cap`: End cap at the end of the segment.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`.
+fn sdf_tapered_segment(
+    start            : vec2f,
+    end              : vec2f,
+    start_half_width : f32,
+    end_half_width   : f32,
+    start_cap        : u32,
+    end_cap          : u32,
+    p                : vec2f
 ) -> f32 {
-    let v = end - start;
-    let a = length(v);
-    let d = v / a;
-    let l = a + 2.0 * extend;
+    let start_round = start_cap != END_CAP_BUTT && start_cap != END_CAP_SQUARE;
+    let end_round   = end_cap   != END_CAP_BUTT && end_cap   != END_CAP_SQUARE;
+    if (start_round && end_round) {
+        return sdf_uneven_capsule(start, end, start_half_width, end_half_width, p);
+    }
 
-    //let a = start - extend * d;
-    // let b = end + extend * d;
-    //    l = l + 2.0 * extend;
+    // Coordinates of `p` along the segment, and away from its center line.
+    let v = end - start;
+    let len = length(v);
+    let d = v / len;
+    let w = p - start;
+    let q = vec2f(dot(w, d), abs(d.x * w.y - d.y * w.x));
 
-    var q = p - (start + end) / 2.0;
-        q = mat2x2f(d.x, -d.y, d.y, d.x) * q;
-        q = abs(q) - vec2f(0.5 * l, half_width);
-    return length(max(q, vec2f(0.0, 0.0))) + min(max(q.x, q.y), 0.0);
+    let extend_start = select(0.0, start_half_width, start_cap == END_CAP_SQUARE);
+    let extend_end   = select(0.0, end_half_width,   end_cap   == END_CAP_SQUARE);
+    var dist = sdf_tapered_body(
+        len,
+        start_half_width,
+        end_half_width,
+        extend_start,
+        extend_end,
+        q
+    );
+    if (start_round) {
+        dist = min(dist, length(p - start) - start_half_width);
+    }
+    if (end_round) {
+        dist = min(dist, length(p - end) - end_half_width);
+    }
+    return dist;
 }
 
-/**** CONSTANTS **************************************************************/
+/// Returns the signed distance function for the body of a tapered segment.
+///
+/// The body is a trapezoid, which is symmetric about the segment and whose
+/// parallel sides pass through its ends, extended at each end by a
+/// rectangle with the half-width of that end. The body is symmetric, so
+/// only the upper half of its outline needs to be considered.
+///
+/// # Parameters
+///
+/// - `len`: Length of the segment.
+/// - `ra`: Half of the width at the start of the segment.
+/// - `rb`: Half of the width at the end of the segment.
+/// - `extend_a`: Distance by which the body extends beyond the start.
+/// - `extend_b`: Distance by which the body extends beyond the end.
+/// - `q`: Location along the segment, and absolute distance of the location
+///   from the segment's center line.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at the location.
+fn sdf_tapered_body(
+    len      : f32,
+    ra       : f32,
+    rb       : f32,
+    extend_a : f32,
+    extend_b : f32,
+    q        : vec2f
+) -> f32 {
+    let x0 = -extend_a;
+    let x1 = len + extend_b;
+    var dist = distance_to_segment(vec2f(x0, 0.0), vec2f(x0, ra), q);
+    dist = min(dist, distance_to_segment(vec2f(x0, ra), vec2f(0.0, ra), q));
+    dist = min(dist, distance_to_segment(vec2f(0.0, ra), vec2f(len, rb), q));
+    dist = min(dist, distance_to_segment(vec2f(len, rb), vec2f(x1, rb), q));
+    dist = min(dist, distance_to_segment(vec2f(x1, rb), vec2f(x1, 0.0), q));
 
-/// Different types of end cap.
-const END_CAP_BUTT   : u32 = 1;
-const END_CAP_ROUND  : u32 = 2;
-const END_CAP_SQUARE : u32 = 3;
+    let height = select(select(mix(ra, rb, q.x / len), rb, q.x > len), ra, q.x < 0.0);
+    let inside = q.x > x0 && q.x < x1 && q.y < height;
+    return select(dist, -dist, inside);
+}
+
+/// Returns the distance from a point to a line segment.
+///
+/// The segment may have zero length.
+///
+/// # Parameters
+///
+/// - `a`: Start of the segment.
+/// - `b`: End of the segment.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The distance from `p` to the closest point on the segment.
+fn distance_to_segment(
+    a : vec2f,
+    b : vec2f,
+    p : vec2f
+) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let h = clamp(dot(pa, ba) / max(dot(ba, ba), CURVE_TINY), 0.0, 1.0);
+    return length(pa - ba * h);
+}
+
+/// Returns the signed distance function for an uneven capsule: the convex
+/// hull of two discs.
+///
+/// If one disc contains the other, the capsule is the larger disc.
+///
+/// Based on: https://iquilezles.org/articles/distfunctions2d/
+///
+/// # Parameters
+///
+/// - `a`: Center of the first disc.
+/// - `b`: Center of the second disc.
+/// - `ra`: Radius of the first disc.
+/// - `rb`: Radius of the second disc.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`.
+fn sdf_uneven_capsule(
+    a  : vec2f,
+    b  : vec2f,
+    ra : f32,
+    rb : f32,
+    p  : vec2f
+) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let h  = dot(ba, ba);
+    let dr = ra - rb;
+    if (dr * dr >= h) {
+        return min(length(pa) - ra, length(p - b) - rb);
+    }
+
+    // Coordinates of `p` across and along the capsule, as fractions of its
+    // length, and the outward normal of its sides, scaled by its length.
+    let q = vec2f(abs(dot(pa, vec2f(ba.y, -ba.x))), dot(pa, ba)) / h;
+    let c = vec2f(sqrt(h - dr * dr), dr);
+
+    let k = c.x * q.y - c.y * q.x;
+    if (k < 0.0) {
+        return sqrt(h * dot(q, q)) - ra;
+    } else if (k > c.x) {
+        return sqrt(h * (dot(q, q) + 1.0 - 2.0 * q.y)) - rb;
+    }
+    return dot(c, q) - ra;
+}
+
+/// Returns the signed distance function for a dashed segment.
+///
+/// The dash pattern is evaluated along the segment, starting from
+/// `dash_phase`. Each dash is a piece of the segment with `dash_cap` at both
+/// ends, except that where a dash is cut off by an end of the segment, the
+/// cap of the segment is used at that end instead.
+///
+/// Only the dashes in the repeat of the pattern that contains the closest
+/// point on the segment, and in the repeats either side of it, are
+/// evaluated. Dashes in other repeats cannot be closer. If the segment is
+/// tapered, each dash tapers with it.
+///
+/// # Parameters
+///
+/// - `styled_line`: The segment to examine.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`.
+fn sdf_dashed_segment(
+    styled_line : StyledLine,
+    p           : vec2f
+) -> f32 {
+    let start            = styled_line.start;
+    let v                = styled_line.end - start;
+    let len              = length(v);
+    let d                = v / len;
+    let start_half_width = styled_line.width / 2.0;
+    let end_half_width   = styled_line.end_width / 2.0;
+    var lengths          = styled_line.dash_lengths;
+
+    // Coordinates of `p` along and across the segment.
+    let t = dot(p - start, d);
+    let n = abs(d.x * (p.y - start.y) - d.y * (p.x - start.x));
+
+    var period = 0.0;
+    for (var i: u32 = 0; i < styled_line.dash_count; i = i + 1) {
+        period = period + lengths[i];
+    }
+
+    // Position along the segment of the start of the repeat which contains
+    // the closest point on the segment.
+    let s    = styled_line.dash_phase + clamp(t, 0.0, len);
+    let base = floor(s / period) * period - styled_line.dash_phase;
+
+    var dist = DIST_FAR;
+    for (var k: i32 = -1; k <= 1; k = k + 1) {
+        var u = base + f32(k) * period;
+        for (var i: u32 = 0; i + 1 < styled_line.dash_count; i = i + 2) {
+            let dash_start = u;
+            let dash_end   = u + lengths[i];
+            u = dash_end + lengths[i + 1];
+            if (dash_end < 0.0 || dash_start > len) {
+                continue;
+            }
+
+            let a     = max(dash_start, 0.0);
+            let b     = min(dash_end, len);
+            let cap_a = select(styled_line.dash_cap, styled_line.start_cap, dash_start <= 0.0);
+            let cap_b = select(styled_line.dash_cap, styled_line.end_cap,   dash_end   >= len);
+            let hw_a  = mix(start_half_width, end_half_width, a / len);
+            let hw_b  = mix(start_half_width, end_half_width, b / len);
+            dist = min(dist, sdf_dash(a, b, hw_a, hw_b, cap_a, cap_b, t, n));
+        }
+    }
+    return dist;
+}
+
+/// Returns the signed distance function for a single dash.
+///
+/// The dash is specified in coordinates along its segment, and `p` is
+/// specified in the same coordinates. If an end cap style is invalid for
+/// any reason, the fallback is to use a rounded end. A dash with zero length
+/// and butt caps at both ends is empty. A tapered dash, whose half-width
+/// differs at each end, is handled by `sdf_tapered_segment`.
+///
+/// # Parameters
+///
+/// - `a`: Start of the dash along the segment.
+/// - `b`: End of the dash along the segment.
+/// - `half_width_a`: Half of the width at the start of the dash.
+/// - `half_width_b`: Half of the width at the end of the dash.
+/// - `cap_a`: End cap at the start of the dash.
+/// - `cap_b`: End cap at the end of the dash.
+/// - `t`: Location along the segment.
+/// - `n`: Absolute distance of the location from the segment's center line.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at the location.
+fn sdf_dash(
+    a            : f32,
+    b            : f32,
+    half_width_a : f32,
+    half_width_b : f32,
+    cap_a        : u32,
+    cap_b        : u32,
+    t            : f32,
+    n            : f32
+) -> f32 {
+    if (half_width_a != half_width_b) {
+        return sdf_tapered_segment(
+            vec2f(a, 0.0),
+            vec2f(b, 0.0),
+            half_width_a,
+            half_width_b,
+            cap_a,
+            cap_b,
+            vec2f(t, n)
+        );
+    }
+
+    let half_width = half_width_a;
+    let round_a  = cap_a != END_CAP_BUTT && cap_a != END_CAP_SQUARE;
+    let round_b  = cap_b != END_CAP_BUTT && cap_b != END_CAP_SQUARE;
+    let extend_a = select(0.0, half_width, cap_a == END_CAP_SQUARE);
+    let extend_b = select(0.0, half_width, cap_b == END_CAP_SQUARE);
+
+    // Rectangular body of the dash, including any square caps.
+    var dist = DIST_FAR;
+    if (b - a + extend_a + extend_b > 0.0) {
+        let q = vec2f(max(a - extend_a - t, t - b - extend_b), n - half_width);
+        dist = length(max(q, vec2f(0.0, 0.0))) + min(max(q.x, q.y), 0.0);
+    }
+    if (round_a) {
+        dist = min(dist, length(vec2f(t - a, n)) - half_width);
+    }
+    if (round_b) {
+        dist = min(dist, length(vec2f(t - b, n)) - half_width);
+    }
+    return dist;
+}
+
+/// Returns the signed distance function for a rounded line.
+///
+/// Based on: https://iquilezles.org/articles/distfunctions2d/
+///
+/// # Parameters
+///
+/// - `start`: Start coordinate of the line.
+/// - `end`: End coordinate of the line.
+/// - `radius`: Radius (half-width) of the line.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`.
+fn sdf_rounded_line(
+    start  : vec2f,
+    end    : vec2f,
+    radius : f32,
+    p      : vec2f
+) -> f32 {
+    let pa = p - start;
+    let ba = end - start;
+    let h = clamp(dot(pa, ba) / dot(ba, ba), 0.0, 1.0);
+    let line_dist = length(pa - ba * h);
+
+    return line_dist - radius;
+}
+
+/// Returns the signed distance function for a square-capped line.
+///
+/// Based on: https://iquilezles.org/articles/distfunctions2d/
+///
+/// # Parameters
+///
+/// - `start`: Start coordinate of the line.
+/// - `end`: End coordinate of the line.
+/// - `half_width`: Half of the width of the line.
+/// - `extend_start`: Distance by which the line extends beyond `start`.
+/// - `extend_end`: Distance by which the line extends beyond `end`.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`.
+fn sdf_square_line(
+    start        : vec2f,
+    end          : vec2f,
+    half_width   : f32,
+    extend_start : f32,
+    extend_end   : f32,
+    p            : vec2f
+) -> f32 {
+    let v = end - start;
+    let a = length(v);
+    let d = v / a;
+    let l = a + extend_start + extend_end;
+    let center = (start + end) / 2.0 + d * (extend_end - extend_start) / 2.0;
+
+    var q = p - center;
+        q = mat2x2f(d.x, -d.y, d.y, d.x) * q;
+        q = abs(q) - vec2f(0.5 * l, half_width);
+    return length(max(q, vec2f(0.0, 0.0))) + min(max(q.x, q.y), 0.0);
+}
+
+/// Returns the signed distance function for a marker at an end of a stroke.
+///
+/// The vertices of the shapes must match those in `bounding_polygon` in
+/// `marker.rs`. If the shape is invalid for any reason, the fallback is to
+/// use a circle.
+///
+/// # Parameters
+///
+/// - `tip`: End of the stroke.
+/// - `direction`: Unit vector pointing out of the stroke.
+/// - `half_width`: Half of the width of the stroke at its end.
+/// - `shape`: Shape of the marker.
+/// - `marker_length`: Length of the marker.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`.
+fn sdf_marker(
+    tip           : vec2f,
+    direction     : vec2f,
+    half_width    : f32,
+    shape         : u32,
+    marker_length : f32,
+    p             : vec2f
+) -> f32 {
+    let n = vec2f(-direction.y, direction.x);
+    let l2 = marker_length / 2.0;
+    let back = tip - marker_length * direction;
+    var vertices = array<vec2f, 6>();
+
+    if (shape == MARKER_ARROW) {
+        vertices[0] = tip;
+        vertices[1] = back + l2 * n;
+        vertices[2] = back - l2 * n;
+        return sdf_polygon(vertices, 3u, p);
+    } else if (shape == MARKER_OPEN_ARROW) {
+        return min(
+            sdf_rounded_line(tip, back + l2 * n, half_width, p),
+            sdf_rounded_line(tip, back - l2 * n, half_width, p)
+        );
+    } else if (shape == MARKER_DIAMOND) {
+        vertices[0] = tip + l2 * direction;
+        vertices[1] = tip + l2 * n;
+        vertices[2] = tip - l2 * direction;
+        vertices[3] = tip - l2 * n;
+        return sdf_polygon(vertices, 4u, p);
+    } else if (shape == MARKER_BAR) {
+        return sdf_square_line(tip - l2 * n, tip + l2 * n, half_width, 0.0, 0.0, p);
+    }
+    // MARKER_CIRCLE, and fallback
+    return length(p - tip) - l2;
+}
+
+/// Returns the signed distance function for a quadratic Bézier curve.
+///
+/// # Parameters
+///
+/// - `p0`: Start point of the curve.
+/// - `p1`: Control point of the curve.
+/// - `p2`: End point of the curve.
+/// - `half_width`: Half of the width of the curve.
+/// - `start_cap`: End cap at the start of the curve.
+/// - `end_cap`: End cap at the end of the curve.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`.
+fn sdf_quadratic(
+    p0         : vec2f,
+    p1         : vec2f,
+    p2         : vec2f,
+    half_width : f32,
+    start_cap  : u32,
+    end_cap    : u32,
+    p          : vec2f
+) -> f32 {
+    let t = closest_param_quadratic(p0, p1, p2, p);
+    return sdf_curve(
+        eval_quadratic(p0, p1, p2, t),
+        p0,
+        -curve_direction(p1 - p0, p2 - p0, p2 - p0),
+        p2,
+        curve_direction(p2 - p1, p2 - p0, p2 - p0),
+        half_width,
+        start_cap,
+        end_cap,
+        p
+    );
+}
+
+/// Returns the signed distance function for a cubic Bézier curve.
+///
+/// # Parameters
+///
+/// - `p0`: Start point of the curve.
+/// - `p1`: First control point of the curve.
+/// - `p2`: Second control point of the curve.
+/// - `p3`: End point of the curve.
+/// - `half_width`: Half of the width of the curve.
+/// - `start_cap`: End cap at the start of the curve.
+/// - `end_cap`: End cap at the end of the curve.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`.
+fn sdf_cubic(
+    p0         : vec2f,
+    p1         : vec2f,
+    p2         : vec2f,
+    p3         : vec2f,
+    half_width : f32,
+    start_cap  : u32,
+    end_cap    : u32,
+    p          : vec2f
+) -> f32 {
+    let t = closest_param_cubic(p0, p1, p2, p3, p);
+    return sdf_curve(
+        eval_cubic(p0, p1, p2, p3, t),
+        p0,
+        -curve_direction(p1 - p0, p2 - p0, p3 - p0),
+        p3,
+        curve_direction(p3 - p2, p3 - p1, p3 - p0),
+        half_width,
+        start_cap,
+        end_cap,
+        p
+    );
+}
+
+/// Returns the signed distance function for a curve, given the closest
+/// point on the curve.
+///
+/// Away from its ends, the stroke of a curve is every point within
+/// `half_width` of the curve. Round caps are formed automatically, because
+/// the closest point on the curve to points beyond its ends is one of the
+/// ends. Butt and square caps are formed by `sdf_curve_end`.
+///
+/// # Parameters
+///
+/// - `closest`: Closest point on the curve to `p`.
+/// - `start`: Start point of the curve.
+/// - `start_dir`: Unit vector pointing out of the start of the curve.
+/// - `end`: End point of the curve.
+/// - `end_dir`: Unit vector pointing out of the end of the curve.
+/// - `half_width`: Half of the width of the curve.
+/// - `start_cap`: End cap at the start of the curve.
+/// - `end_cap`: End cap at the end of the curve.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`.
+fn sdf_curve(
+    closest    : vec2f,
+    start      : vec2f,
+    start_dir  : vec2f,
+    end        : vec2f,
+    end_dir    : vec2f,
+    half_width : f32,
+    start_cap  : u32,
+    end_cap    : u32,
+    p          : vec2f
+) -> f32 {
+    var dist = length(p - closest) - half_width;
+    dist = sdf_curve_end(dist, start, start_dir, half_width, start_cap, p);
+    dist = sdf_curve_end(dist, end,   end_dir,   half_width, end_cap,   p);
+    return dist;
+}
+
+/// Applies an end cap to the signed distance function of a curve.
+///
+/// Near the end of the curve, the stroke is treated as a straight strip
+/// along the direction of the curve at its end. Beyond the end, the signed
+/// distance function of the strip (extended by the cap) replaces that of
+/// the curve. Before the end, the curve is cut off by the end of the strip.
+/// Round caps need no changes.
+///
+/// # Parameters
+///
+/// - `dist`: Signed distance function of the curve, without the cap.
+/// - `point`: End point of the curve.
+/// - `dir`: Unit vector pointing out of the end of the curve.
+/// - `half_width`: Half of the width of the curve.
+/// - `cap`: End cap.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`, including the cap.
+fn sdf_curve_end(
+    dist       : f32,
+    point      : vec2f,
+    dir        : vec2f,
+    half_width : f32,
+    cap        : u32,
+    p          : vec2f
+) -> f32 {
+    let round  = cap != END_CAP_BUTT && cap != END_CAP_SQUARE;
+    let extend = select(0.0, half_width, cap == END_CAP_SQUARE);
+    let w      = p - point;
+    if (round || length(w) > 2.0 * half_width + extend + CURVE_CAP_MARGIN) {
+        return dist;
+    }
+
+    let beyond = dot(w, dir);
+    if (beyond <= 0.0) {
+        return max(dist, beyond - extend);
+    }
+    let q = vec2f(beyond - extend, abs(dir.x * w.y - dir.y * w.x) - half_width);
+    return length(max(q, vec2f(0.0, 0.0))) + min(max(q.x, q.y), 0.0);
+}
+
+/// Returns the signed distance function for a circular arc.
+///
+/// The arc is mirrored about the line through its middle, so that only the
+/// end on one side has to be considered. Its start is on the positive side,
+/// and its end on the negative side.
+///
+/// Based on: https://iquilezles.org/articles/distfunctions2d/
+///
+/// # Parameters
+///
+/// - `center`: Center of the circle.
+/// - `radius`: Radius of the circle.
+/// - `half_angle`: Half of the angle spanned by the arc. If this is `PI` or
+///   more, the arc is a whole circle, with no caps.
+/// - `mid_dir`: Unit vector from the center towards the middle of the arc.
+/// - `half_width`: Half of the width of the arc.
+/// - `start_cap`: End cap at the start of the arc.
+/// - `end_cap`: End cap at the end of the arc.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`.
+fn sdf_arc(
+    center     : vec2f,
+    radius     : f32,
+    half_angle : f32,
+    mid_dir    : vec2f,
+    half_width : f32,
+    start_cap  : u32,
+    end_cap    : u32,
+    p          : vec2f
+) -> f32 {
+    let w = p - center;
+    if (half_angle >= PI) {
+        return abs(length(w) - radius) - half_width;
+    }
+
+    // Coordinates with the middle of the arc along the y axis, mirrored.
+    let x = dot(w, vec2f(mid_dir.y, -mid_dir.x));
+    let q = vec2f(abs(x), dot(w, mid_dir));
+
+    let sc = vec2f(sin(half_angle), cos(half_angle));
+    let end_point = radius * sc;
+    var dist = abs(length(q) - radius) - half_width;
+    if (sc.y * q.x > sc.x * q.y) {
+        dist = length(q - end_point) - half_width;
+    }
+
+    let cap = select(end_cap, start_cap, x >= 0.0);
+    return sdf_curve_end(dist, end_point, vec2f(sc.y, -sc.x), half_width, cap, q);
+}
+
+/// Returns the signed distance function for the outline of an ellipse.
+///
+/// # Parameters
+///
+/// - `center`: Center of the ellipse.
+/// - `radii`: Radii of the ellipse.
+/// - `axis`: Unit vector along the first axis of the ellipse.
+/// - `half_width`: Half of the width of the outline.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`.
+fn sdf_ellipse(
+    center     : vec2f,
+    radii      : vec2f,
+    axis       : vec2f,
+    half_width : f32,
+    p          : vec2f
+) -> f32 {
+    let w = p - center;
+    let local = vec2f(dot(w, axis), dot(w, vec2f(-axis.y, axis.x)));
+    return abs(sdf_ellipse_local(radii, local)) - half_width;
+}
+
+/// Returns the signed distance to an axis-aligned ellipse centered on the
+/// origin.
+///
+/// The closest point on the ellipse is found iteratively, which is robust
+/// for all ellipses, including circles.
+///
+/// Based on: https://github.com/0xfaded/ellipse_demo
+///
+/// # Parameters
+///
+/// - `ab`: Radii of the ellipse.
+/// - `p`: Location, relative to the center of the ellipse.
+///
+/// # Returns
+///
+/// The signed distance to the ellipse, which is negative inside it.
+fn sdf_ellipse_local(
+    ab : vec2f,
+    p  : vec2f
+) -> f32 {
+    let q = abs(p);
+    var t = vec2f(0.70710678, 0.70710678);
+    for (var i: u32 = 0; i < ELLIPSE_ITERATIONS; i = i + 1) {
+        let x  = ab.x * t.x;
+        let y  = ab.y * t.y;
+        let ex = (ab.x * ab.x - ab.y * ab.y) * t.x * t.x * t.x / ab.x;
+        let ey = (ab.y * ab.y - ab.x * ab.x) * t.y * t.y * t.y / ab.y;
+        let r  = length(vec2f(x - ex, y - ey));
+        let qe = vec2f(q.x - ex, q.y - ey);
+        let qm = max(length(qe), ELLIPSE_TINY);
+        t = clamp((qe * r / qm + vec2f(ex, ey)) / ab, vec2f(0.0), vec2f(1.0));
+        t = t / length(t);
+    }
+
+    let dist = length(q - ab * t);
+    let k = dot(q / ab, q / ab);
+    return select(dist, -dist, k < 1.0);
+}
+
+/// Returns the direction of the end of a curve.
+///
+/// The direction of the end of a Bézier curve is towards the nearest
+/// control point which does not coincide with the end. The candidates are
+/// supplied in order, and the first which is not (nearly) zero is used.
+///
+/// # Parameters
+///
+/// - `a`: First candidate direction.
+/// - `b`: Second candidate direction.
+/// - `c`: Third candidate direction.
+///
+/// # Returns
+///
+/// A unit vector in the direction of the first non-zero candidate, or the
+/// x axis if all candidates are zero.
+fn curve_direction(
+    a : vec2f,
+    b : vec2f,
+    c : vec2f
+) -> vec2f {
+    if (dot(a, a) > CURVE_EPSILON) {
+        return normalize(a);
+    } else if (dot(b, b) > CURVE_EPSILON) {
+        return normalize(b);
+    } else if (dot(c, c) > CURVE_EPSILON) {
+        return normalize(c);
+    }
+    return vec2f(1.0, 0.0);
+}
+
+/// Evaluates a quadratic Bézier curve.
+fn eval_quadratic(
+    p0 : vec2f,
+    p1 : vec2f,
+    p2 : vec2f,
+    t  : f32
+) -> vec2f {
+    let s = 1.0 - t;
+    return s * s * p0 + 2.0 * s * t * p1 + t * t * p2;
+}
+
+/// Evaluates a cubic Bézier curve.
+fn eval_cubic(
+    p0 : vec2f,
+    p1 : vec2f,
+    p2 : vec2f,
+    p3 : vec2f,
+    t  : f32
+) -> vec2f {
+    let s = 1.0 - t;
+    return s * s * s * p0 + 3.0 * s * s * t * p1 + 3.0 * s * t * t * p2 + t * t * t * p3;
+}
+
+/// Returns the parameter of the closest point on a quadratic Bézier curve.
+///
+/// The closest point is found analytically, by solving the cubic equation
+/// for the stationary points of the distance.
+///
+/// Based on: https://iquilezles.org/articles/distfunctions2d/
+///
+/// # Parameters
+///
+/// - `p0`: Start point of the curve.
+/// - `p1`: Control point of the curve.
+/// - `p2`: End point of the curve.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The parameter, in `[0.0, 1.0]`, of the point on the curve closest to `p`.
+fn closest_param_quadratic(
+    p0 : vec2f,
+    p1 : vec2f,
+    p2 : vec2f,
+    p  : vec2f
+) -> f32 {
+    let a = p1 - p0;
+    let b = p0 - 2.0 * p1 + p2;
+    let c = a * 2.0;
+    let d = p0 - p;
+
+    // A (nearly) straight curve, with its control point at the middle, has
+    // no quadratic term, and the cubic equation degenerates.
+    if (dot(b, b) <= CURVE_STRAIGHT * dot(c, c)) {
+        if (dot(c, c) <= CURVE_EPSILON) {
+            return 0.0;
+        }
+        return clamp(-dot(d, c) / dot(c, c), 0.0, 1.0);
+    }
+
+    let kk = 1.0 / dot(b, b);
+    let kx = kk * dot(a, b);
+    let ky = kk * (2.0 * dot(a, a) + dot(d, b)) / 3.0;
+    let kz = kk * dot(d, a);
+    let pp = ky - kx * kx;
+    let q  = kx * (2.0 * kx * kx - 3.0 * ky) + kz;
+    let h  = q * q + 4.0 * pp * pp * pp;
+    if (h >= 0.0) {
+        // One real root.
+        let hs = sqrt(h);
+        let x  = (vec2f(hs, -hs) - q) / 2.0;
+        let uv = sign(x) * pow(max(abs(x), vec2f(CURVE_TINY)), vec2f(1.0 / 3.0));
+        return clamp(uv.x + uv.y - kx, 0.0, 1.0);
+    }
+
+    // Three real roots. The third root cannot be the closest.
+    let z  = sqrt(-pp);
+    let v  = acos(clamp(q / (pp * z * 2.0), -1.0, 1.0)) / 3.0;
+    let m  = cos(v);
+    let n  = sin(v) * 1.732050808;
+    let t  = clamp(vec2f(m + m, -n - m) * z - kx, vec2f(0.0), vec2f(1.0));
+    let qx = d + (c + b * t.x) * t.x;
+    let qy = d + (c + b * t.y) * t.y;
+    return select(t.y, t.x, dot(qx, qx) <= dot(qy, qy));
+}
+
+/// Returns the parameter of the closest point on a cubic Bézier curve.
+///
+/// The closest point is found numerically. The curve is sampled at
+/// `CUBIC_SAMPLES` evenly-spaced parameter values to find a starting point,
+/// which is then refined with `CUBIC_NEWTON_STEPS` steps of Newton's method
+/// on the derivative of the squared distance.
+///
+/// # Parameters
+///
+/// - `p0`: Start point of the curve.
+/// - `p1`: First control point of the curve.
+/// - `p2`: Second control point of the curve.
+/// - `p3`: End point of the curve.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The parameter, in `[0.0, 1.0]`, of the point on the curve closest to `p`.
+fn closest_param_cubic(
+    p0 : vec2f,
+    p1 : vec2f,
+    p2 : vec2f,
+    p3 : vec2f,
+    p  : vec2f
+) -> f32 {
+    var best_t = 0.0;
+    var best_d = dot(p0 - p, p0 - p);
+    for (var i: u32 = 1; i <= CUBIC_SAMPLES; i = i + 1) {
+        let t = f32(i) / f32(CUBIC_SAMPLES);
+        let w = eval_cubic(p0, p1, p2, p3, t) - p;
+        let d = dot(w, w);
+        if (d < best_d) {
+            best_t = t;
+            best_d = d;
+        }
+    }
+
+    var t = best_t;
+    for (var i: u32 = 0; i < CUBIC_NEWTON_STEPS; i = i + 1) {
+        let s  = 1.0 - t;
+        let w  = eval_cubic(p0, p1, p2, p3, t) - p;
+        let d1 = 3.0 * (s * s * (p1 - p0) + 2.0 * s * t * (p2 - p1) + t * t * (p3 - p2));
+        let d2 = 6.0 * (s * (p2 - 2.0 * p1 + p0) + t * (p3 - 2.0 * p2 + p1));
+        let f  = dot(w, d1);
+        let df = dot(d1, d1) + dot(w, d2);
+        if (df > 0.0) {
+            t = clamp(t - f / df, 0.0, 1.0);
+        }
+    }
+
+    // Newton's method can diverge; fall back to the best sample if it did.
+    let w = eval_cubic(p0, p1, p2, p3, t) - p;
+    return select(best_t, t, dot(w, w) < best_d);
+}
+
+/// Returns the signed distance function for a simple polygon.
+///
+/// Based on: https://iquilezles.org/articles/distfunctions2d/
+///
+/// # Parameters
+///
+/// - `points`: Vertices of the polygon.
+/// - `n_points`: Number of vertices used in `points`.
+/// - `p`: Location.
+///
+/// # Returns
+///
+/// The signed distance function evaluated at `p`.
+fn sdf_polygon(
+    points   : array<vec2f, 6>,
+    n_points : u32,
+    p        : vec2f
+) -> f32 {
+    var v = points;
+    var d = dot(p - v[0], p - v[0]);
+    var s = 1.0;
+    var j = n_points - 1;
+    for (var i: u32 = 0; i < n_points; i = i + 1) {
+        let e = v[j] - v[i];
+        let w = p - v[i];
+        let b = w - e * clamp(dot(w, e) / dot(e, e), 0.0, 1.0);
+        d = min(d, dot(b, b));
+        let c = vec3<bool>(p.y >= v[i].y, p.y < v[j].y, e.x * w.y > e.y * w.x);
+        if (all(c) || !any(c)) {
+            s = -s;
+        }
+        j = i;
+    }
+    return s * sqrt(d);
+}
+
+/**** CONSTANTS **************************************************************/
+
+/// Different kinds of primitive.
+const PRIMITIVE_SEGMENT   : u32 = 1;
+const PRIMITIVE_POLYGON   : u32 = 2;
+const PRIMITIVE_DISC      : u32 = 3;
+const PRIMITIVE_QUADRATIC : u32 = 4;
+const PRIMITIVE_CUBIC     : u32 = 5;
+const PRIMITIVE_ARC       : u32 = 6;
+const PRIMITIVE_ELLIPSE   : u32 = 7;
+const PRIMITIVE_FILL      : u32 = 8;
+const PRIMITIVE_FILL_EDGE : u32 = 9;
+const PRIMITIVE_MARKER    : u32 = 10;
+const PRIMITIVE_CLIP      : u32 = 11;
+
+/// Different shapes of marker.
+const MARKER_ARROW      : u32 = 1;
+const MARKER_OPEN_ARROW : u32 = 2;
+const MARKER_CIRCLE     : u32 = 3;
+const MARKER_DIAMOND    : u32 = 4;
+const MARKER_BAR        : u32 = 5;
+
+/// Different types of end cap.
+const END_CAP_BUTT   : u32 = 1;
+const END_CAP_ROUND  : u32 = 2;
+const END_CAP_SQUARE : u32 = 3;
+
+/// Different fill rules.
+const FILL_RULE_NON_ZERO : u32 = 1;
+const FILL_RULE_EVEN_ODD : u32 = 2;
+
+/// Color spaces for interpolating gradients.
+const COLOR_INTERPOLATION_SRGB   : u32 = 1;
+const COLOR_INTERPOLATION_LINEAR : u32 = 2;
+const COLOR_INTERPOLATION_OKLAB  : u32 = 3;
+
+/// Falloff curves of glows.
+const GLOW_FALLOFF_LINEAR    : u32 = 1;
+const GLOW_FALLOFF_QUADRATIC : u32 = 2;
+const GLOW_FALLOFF_GAUSSIAN  : u32 = 3;
+
+/// Blend modes.
+const BLEND_MODE_NORMAL   : u32 = 1;
+const BLEND_MODE_ADDITIVE : u32 = 2;
+const BLEND_MODE_SCREEN   : u32 = 3;
+const BLEND_MODE_MAX      : u32 = 4;
+const BLEND_MODE_MULTIPLY : u32 = 5;
+
+/// Exponent of the Gaussian glow falloff at the radius of the glow.
+const GLOW_GAUSSIAN_K : f32 = 4.5;
+
+/// Smallest magnitude passed to `pow` by `cbrt`.
+const CBRT_TINY : f32 = 1.0e-30;
+
+/// Layer index which no line has.
+const NO_LAYER : u32 = 0xffffffffu;
+
+/// Distance used for "infinitely far away".
+const DIST_FAR : f32 = 1.0e30;
+
+/// Squared length below which curve directions are considered to be zero.
+const CURVE_EPSILON : f32 = 1.0e-8;
+
+/// Ratio of squared lengths below which a quadratic curve is straight.
+const CURVE_STRAIGHT : f32 = 1.0e-8;
+
+/// Smallest magnitude passed to `pow`, which is undefined at zero.
+const CURVE_TINY : f32 = 1.0e-30;
+
+/// Distance beyond the stroke within which curve end caps are evaluated.
+const CURVE_CAP_MARGIN : f32 = 2.0;
+
+/// Number of iterations used to find the closest point on an ellipse.
+const ELLIPSE_ITERATIONS : u32 = 4;
+
+/// Smallest distance used as a divisor when finding the closest point on an
+/// ellipse.
+const ELLIPSE_TINY : f32 = 1.0e-12;
+
+/// Pi.
+const PI : f32 = 3.14159265;
+
+/// Number of samples used to find the closest point on a cubic curve.
+const CUBIC_SAMPLES : u32 = 16;
+
+/// Number of Newton steps used to find the closest point on a cubic curve.
+const CUBIC_NEWTON_STEPS : u32 = 4;
 
 /// Width of edges drawn on the tiles.
 const TILE_EDGE_WIDTH : f32 = 3.0;
@@ -0,0 +1,420 @@
+//! Markers at the ends of strokes.
+//!
+//! Markers are added to the shape of a stroke as [`Primitive::Marker`]s.
+//! Filled arrowheads are narrower than the stroke near their tips, so the
+//! stroke is trimmed back from an end with an arrowhead (see
+//! [`trim_distance`]).
+
+use crate::{
+    polygon::Polygon,
+    primitive::{GradientSpan, Primitive},
+    style::{LineStyle, Marker, MarkerShape},
+    types::v2_rot90_anticlockwise,
+    P2, V2,
+};
+use cgmath::{InnerSpace, MetricSpace};
+
+/// Largest fraction of the length of a stroke which is trimmed from each of
+/// its ends.
+pub const MAX_TRIM_FRACTION: f32 = 0.45;
+
+/// Number of bisection steps used to find where to trim a curve.
+const TRIM_BISECTION_STEPS: u32 = 24;
+
+/// One end of a stroke.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct StrokeEnd {
+    /// Position of the end.
+    pub point: P2,
+    /// Unit vector pointing out of the stroke, along its tangent.
+    pub direction: V2,
+    /// Width of the stroke at the end.
+    pub width: f32,
+    /// Part of the style's gradient at the end, if the style has a gradient.
+    pub gradient: Option<GradientSpan>,
+}
+
+/// Returns the distance by which a stroke is trimmed back from an end.
+///
+/// Within one line width of its tip, a filled arrowhead is narrower than the
+/// stroke, so the stroke must stop short of the tip. Other markers cover the
+/// end of the stroke, so it is not trimmed.
+///
+/// # Parameters
+///
+/// - `marker`: Marker at the end, if there is one.
+/// - `width`: Width of the stroke at the end.
+/// - `length`: Length of the stroke, or of the piece of it at the end. The
+///   distance is at most [`MAX_TRIM_FRACTION`] of this.
+pub(crate) fn trim_distance(
+    marker: Option<Marker>,
+    width: f32,
+    length: f32,
+) -> f32 {
+    match marker {
+        Some(marker) if marker.shape == MarkerShape::Arrow => width
+            .min(marker.length(width))
+            .min(MAX_TRIM_FRACTION * length),
+        _ => 0.0,
+    }
+}
+
+/// Returns the parameters between which a curve is drawn, after trimming it
+/// for its markers.
+///
+/// The parameter at each end is found by bisection, assuming that the
+/// distance from that end increases along the part of the curve nearest to
+/// it. Each end is trimmed by no more than [`MAX_TRIM_FRACTION`] of the
+/// curve's parameter range.
+///
+/// # Parameters
+///
+/// - `style`: Style of the curve.
+/// - `control_points`: Control points of the curve.
+/// - `eval`: Evaluates the curve at a parameter value.
+///
+/// # Returns
+///
+/// The parameters of the start and end of the trimmed curve.
+pub(crate) fn curve_trim_params(
+    style: &LineStyle,
+    control_points: &[P2],
+    eval: impl Fn(f32) -> P2,
+) -> (f32, f32) {
+    let length = crate::bezier::control_polygon_length(control_points);
+    let start = control_points[0];
+    let end = control_points[control_points.len() - 1];
+    let search = |distance: f32, from: P2, toward: f32| {
+        if distance <= 0.0 {
+            return 0.0;
+        }
+        let (mut lo, mut hi) = (0.0, MAX_TRIM_FRACTION);
+        for _ in 0..TRIM_BISECTION_STEPS {
+            let mid = (lo + hi) / 2.0;
+            if eval((1.0 - toward) * mid + toward * (1.0 - mid)).distance(from)
+                < distance
+            {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        hi
+    };
+    let t0 = search(
+        trim_distance(style.start_marker, style.width, length),
+        start,
+        0.0,
+    );
+    let t1 = search(
+        trim_distance(style.end_marker, style.width, length),
+        end,
+        1.0,
+    );
+    (t0, 1.0 - t1)
+}
+
+/// Returns unit vectors pointing out of a curve at its start and its end.
+///
+/// These are along the tangents of the curve, which point towards the
+/// nearest control points which differ from the ends.
+///
+/// # Parameters
+///
+/// - `control_points`: Control points of the curve.
+pub(crate) fn curve_end_directions(control_points: &[P2]) -> (V2, V2) {
+    let start = control_points[0];
+    let end = control_points[control_points.len() - 1];
+    let start_direction = control_points
+        .iter()
+        .find(|p| **p != start)
+        .map_or(V2::new(-1.0, 0.0), |p| (start - p).normalize());
+    let end_direction = control_points
+        .iter()
+        .rev()
+        .find(|p| **p != end)
+        .map_or(V2::new(1.0, 0.0), |p| (end - p).normalize());
+    (start_direction, end_direction)
+}
+
+/// Returns the primitives of the markers at the ends of a stroke.
+///
+/// Each primitive is paired with the part of the style's gradient at its
+/// end. Ends with zero width have no marker.
+///
+/// # Parameters
+///
+/// - `style`: Style of the stroke.
+/// - `start`: Start of the stroke.
+/// - `end`: End of the stroke.
+pub(crate) fn end_markers(
+    style: &LineStyle,
+    start: StrokeEnd,
+    end: StrokeEnd,
+) -> Vec<(Primitive, Option<GradientSpan>)> {
+    [(style.start_marker, start), (style.end_marker, end)]
+        .into_iter()
+        .filter_map(|(marker, stroke_end)| {
+            let marker = marker.filter(|_| stroke_end.width > 0.0)?;
+            let primitive = Primitive::Marker {
+                marker,
+                tip: stroke_end.point,
+                direction: stroke_end.direction,
+                width: stroke_end.width,
+            };
+            Some((primitive, stroke_end.gradient))
+        })
+        .collect()
+}
+
+/// Returns a polygon which bounds a marker.
+///
+/// The polygon is exact for every shape except circles, which are bounded
+/// by a square, and open arrows, whose rounded ends are bounded by squares.
+/// The vertices of the shapes must match those in `sdf_marker` in the
+/// shader.
+///
+/// # Parameters
+///
+/// - `marker`: The marker.
+/// - `tip`: End of the stroke which the marker decorates.
+/// - `direction`: Unit vector pointing out of the stroke.
+/// - `width`: Width of the stroke at the end.
+pub(crate) fn bounding_polygon(
+    marker: &Marker,
+    tip: P2,
+    direction: V2,
+    width: f32,
+) -> Polygon {
+    let length = marker.length(width);
+    let (d, n) = (direction, v2_rot90_anticlockwise(direction));
+    let (l2, w2) = (length / 2.0, width / 2.0);
+    match marker.shape {
+        MarkerShape::Arrow => {
+            let back = tip - length * d;
+            Polygon::new(vec![tip, back + l2 * n, back - l2 * n])
+        }
+        MarkerShape::OpenArrow => {
+            let back = tip - length * d;
+            let mut points = Vec::with_capacity(12);
+            for p in [tip, back + l2 * n, back - l2 * n] {
+                for (x, y) in [(-w2, -w2), (w2, -w2), (w2, w2), (-w2, w2)] {
+                    points.push(p + V2::new(x, y));
+                }
+            }
+            Polygon::convex_hull(&points)
+        }
+        MarkerShape::Circle => Polygon::new(vec![
+            tip + V2::new(-l2, -l2),
+            tip + V2::new(l2, -l2),
+            tip + V2::new(l2, l2),
+            tip + V2::new(-l2, l2),
+        ]),
+        MarkerShape::Diamond => Polygon::new(vec![
+            tip + l2 * d,
+            tip + l2 * n,
+            tip - l2 * d,
+            tip - l2 * n,
+        ]),
+        MarkerShape::Bar => Polygon::new(vec![
+            tip - w2 * d - l2 * n,
+            tip + w2 * d - l2 * n,
+            tip + w2 * d + l2 * n,
+            tip - w2 * d + l2 * n,
+        ]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QuadraticBezier;
+    use crate::{
+        tests::parity::{check_scene, pixel_at, Shape},
+        CircularArc, Color, ColorInterpolation, CubicBezier, DashPattern,
+        Gradient, Line, LineJoin, Polyline, SoftwareRenderer,
+    };
+
+    #[test]
+    fn test_curve_trim_params() {
+        let curve = QuadraticBezier::new(
+            P2::new(0.0, 0.0),
+            P2::new(50.0, 80.0),
+            P2::new(100.0, 0.0),
+        );
+        let style = LineStyle {
+            width: 4.0,
+            start_marker: Some(Marker::new(MarkerShape::Arrow, 3.0)),
+            end_marker: Some(Marker::new(MarkerShape::Circle, 3.0)),
+            ..Default::default()
+        };
+        let (t0, t1) =
+            curve_trim_params(&style, &curve.control_points(), |t| {
+                curve.eval_param(t)
+            });
+        assert!(
+            (curve.eval_param(t0).distance(curve.start()) - 4.0).abs() < 1e-3
+        );
+        assert_eq!(t1, 1.0);
+
+        let (start, end) = curve_end_directions(&curve.control_points());
+        assert!((start - V2::new(-50.0, -80.0).normalize()).magnitude() < 1e-6);
+        assert!((end - V2::new(50.0, -80.0).normalize()).magnitude() < 1e-6);
+    }
+
+    /// Returns a scene of strokes of every kind with markers at their ends.
+    fn marker_scene() -> Vec<(Shape, LineStyle)> {
+        let marker = |shape, size| Some(Marker::new(shape, size));
+        let style = |start_marker, end_marker, color| LineStyle {
+            width: 3.0,
+            start_marker,
+            end_marker,
+            color,
+            ..Default::default()
+        };
+        vec![
+            (
+                Shape::Line(Line::new(
+                    P2::new(10.0, 10.0),
+                    P2::new(60.0, 25.0),
+                )),
+                style(
+                    marker(MarkerShape::Bar, 3.0),
+                    marker(MarkerShape::Arrow, 3.0),
+                    Color::new(0.9, 0.4, 0.4, 0.8),
+                ),
+            ),
+            (
+                Shape::Line(Line::new(
+                    P2::new(80.0, 12.0),
+                    P2::new(140.0, 30.0),
+                )),
+                LineStyle {
+                    end_width: Some(1.0),
+                    gradient: Some(Gradient::new(
+                        Color::new(1.0, 1.0, 0.2, 1.0),
+                        Color::new(0.2, 0.4, 1.0, 1.0),
+                        ColorInterpolation::Oklab,
+                    )),
+                    ..style(
+                        marker(MarkerShape::Arrow, 2.0),
+                        marker(MarkerShape::Circle, 3.0),
+                        Color::new(1.0, 1.0, 1.0, 1.0),
+                    )
+                },
+            ),
+            (
+                Shape::Polyline(Polyline::new(vec![
+                    P2::new(10.0, 40.0),
+                    P2::new(40.0, 60.0),
+                    P2::new(70.0, 40.0),
+                ])),
+                LineStyle {
+                    join: LineJoin::Miter { limit: 4.0 },
+                    dash: Some(DashPattern::new(&[8.0, 3.0], 0.0)),
+                    ..style(
+                        marker(MarkerShape::Diamond, 4.0),
+                        marker(MarkerShape::OpenArrow, 5.0),
+                        Color::new(0.4, 0.9, 0.4, 0.7),
+                    )
+                },
+            ),
+            (
+                Shape::Quadratic(QuadraticBezier::new(
+                    P2::new(85.0, 45.0),
+                    P2::new(110.0, 80.0),
+                    P2::new(140.0, 45.0),
+                )),
+                style(
+                    marker(MarkerShape::Arrow, 3.0),
+                    marker(MarkerShape::Arrow, 3.0),
+                    Color::new(0.4, 0.4, 0.9, 0.8),
+                ),
+            ),
+            (
+                Shape::Cubic(CubicBezier::new(
+                    P2::new(10.0, 75.0),
+                    P2::new(30.0, 100.0),
+                    P2::new(50.0, 60.0),
+                    P2::new(70.0, 90.0),
+                )),
+                style(
+                    marker(MarkerShape::Circle, 2.0),
+                    marker(MarkerShape::Arrow, 4.0),
+                    Color::new(1.0, 0.2, 1.0, 0.6),
+                ),
+            ),
+            (
+                Shape::Arc(CircularArc::new(
+                    P2::new(110.0, 75.0),
+                    15.0,
+                    0.3,
+                    3.5,
+                )),
+                style(
+                    marker(MarkerShape::Arrow, 3.0),
+                    marker(MarkerShape::Bar, 3.0),
+                    Color::new(0.2, 1.0, 1.0, 0.7),
+                ),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_markers_match_gpu() {
+        let image =
+            check_scene(wgpu::TextureFormat::Rgba8Unorm, &marker_scene());
+        // The bar at the start and the arrowhead at the end of the first line
+        // cover points 3.5 and 2.3 from its center, beyond its half width of
+        // 1.5.
+        assert_eq!(pixel_at(&image, 9.5, 13.5), [184, 82, 82, 204]);
+        assert!(pixel_at(&image, 53.5, 25.5)[3] > 180);
+        // The diamond at the start of the polyline covers a point 4.3 out
+        // from its end, but not one 5.7 from its end and off its diagonals.
+        assert_eq!(pixel_at(&image, 6.5, 37.5), [71, 161, 71, 178]);
+        assert_eq!(pixel_at(&image, 8.5, 34.5), [0, 0, 0, 0]);
+        // The open arrow at its end covers a point on one of its sides, but
+        // not one between its sides and outside the stroke.
+        assert_eq!(pixel_at(&image, 65.5, 47.5), [71, 161, 71, 178]);
+        assert_eq!(pixel_at(&image, 60.5, 49.5), [0, 0, 0, 0]);
+        // The circle at the start of the cubic has a radius of 3, and covers
+        // a point 2.1 behind its start.
+        assert_eq!(pixel_at(&image, 8.5, 73.5), [153, 31, 153, 153]);
+        assert_eq!(pixel_at(&image, 5.5, 75.5), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_arrowhead_is_not_covered_by_stroke() {
+        let mut software = SoftwareRenderer::new(
+            wgpu::TextureFormat::Rgba8Unorm,
+            150,
+            100,
+            16,
+            16,
+        );
+        software.line(
+            Line::new(P2::new(10.0, 50.0), P2::new(120.0, 50.0)),
+            &LineStyle {
+                width: 8.0,
+                end_marker: Some(Marker::new(MarkerShape::Arrow, 3.0)),
+                ..Default::default()
+            },
+        );
+        let image = software.render();
+
+        // The arrowhead is 24 long and 24 wide, so 4.5 behind its tip it is
+        // 4.5 wide, which is narrower than the stroke. Rows are flipped
+        // relative to beamline coordinates.
+        assert_eq!(image.pixel(115, 49)[3], 255);
+        assert_eq!(image.pixel(115, 46)[3], 0);
+        assert_eq!(image.pixel(115, 53)[3], 0);
+        assert_eq!(image.pixel(122, 49)[3], 0);
+        // Behind the arrowhead, the stroke has its full width.
+        assert_eq!(image.pixel(96, 47)[3], 255);
+        assert_eq!(image.pixel(90, 47)[3], 255);
+        assert_eq!(image.pixel(90, 44)[3], 0);
+        // Near its back, the arrowhead is much wider than the stroke.
+        assert_eq!(image.pixel(97, 41)[3], 255);
+        assert_eq!(image.pixel(97, 58)[3], 255);
+    }
+}
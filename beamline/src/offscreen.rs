@@ -0,0 +1,244 @@
+//! Offscreen render targets with CPU readback.
+
+use crate::image::Image;
+use std::sync::mpsc;
+
+/// Offscreen render target.
+///
+/// An `OffscreenTarget` owns a texture that can be rendered into, and a
+/// buffer into which the texture can be copied so that its pixels can be read
+/// back on the CPU.
+///
+/// The intended lifecycle of an `OffscreenTarget` is as follows:
+///
+/// 1. Create it using [`OffscreenTarget::new`].
+/// 2. Record a clear using [`OffscreenTarget::clear`].
+/// 3. Render into [`OffscreenTarget::view`].
+/// 4. Record a copy to the readback buffer using
+///    [`OffscreenTarget::copy_to_readback`].
+/// 5. Submit the command buffer.
+/// 6. Read the pixels using [`OffscreenTarget::read_image`].
+///
+/// [`crate::Renderer::render_offscreen`] performs all of these steps.
+#[derive(Debug)]
+pub struct OffscreenTarget {
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    readback_buffer: wgpu::Buffer,
+    /// Number of bytes in a row of the readback buffer. This is padded to
+    /// [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`].
+    padded_bytes_per_row: u32,
+}
+impl OffscreenTarget {
+    /// Creates a new `OffscreenTarget`.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `width`: Width of the target texture.
+    /// - `height`: Height of the target texture.
+    /// - `format`: Format of the target texture. This must be one of the
+    ///   8-bit RGBA or BGRA formats (see [`is_supported_format`]).
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        assert!(width > 0);
+        assert!(height > 0);
+        assert!(
+            is_supported_format(format),
+            "Unsupported offscreen texture format: {:?}",
+            format
+        );
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Beamline: Offscreen texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let padded_bytes_per_row = padded_bytes_per_row(width);
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Beamline: Offscreen readback buffer"),
+            size: padded_bytes_per_row as wgpu::BufferAddress
+                * height as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        OffscreenTarget {
+            width,
+            height,
+            format,
+            texture,
+            view,
+            readback_buffer,
+            padded_bytes_per_row,
+        }
+    }
+
+    /// Returns the width of the target.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the target.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the texture format of the target.
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    /// Returns a reference to the target texture.
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// Returns a view of the target texture, for use as a render attachment.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Records a render pass which clears the target to transparent black.
+    ///
+    /// # Parameters
+    ///
+    /// - `encoder`: Command encoder to which the clear should be recorded.
+    pub fn clear(&self, encoder: &mut wgpu::CommandEncoder) {
+        let color_attachment = wgpu::RenderPassColorAttachment {
+            view: &self.view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            },
+        };
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Beamline: Offscreen clear pass"),
+            color_attachments: &[Some(color_attachment)],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+    }
+
+    /// Records a copy of the target texture into the readback buffer.
+    ///
+    /// # Parameters
+    ///
+    /// - `encoder`: Command encoder to which the copy should be recorded.
+    pub fn copy_to_readback(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Reads the readback buffer back to the CPU as an RGBA8 image.
+    ///
+    /// The command buffer containing the copy recorded by
+    /// [`OffscreenTarget::copy_to_readback`] must have been submitted before
+    /// this is called. This method blocks until the GPU has finished, so it
+    /// is only suitable for native platforms.
+    ///
+    /// Row padding is removed, and BGRA formats are swizzled to RGBA.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    ///
+    /// # Returns
+    ///
+    /// The contents of the target texture.
+    pub fn read_image(&self, device: &wgpu::Device) -> Image {
+        let slice = self.readback_buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).unwrap();
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .expect("Offscreen readback was never mapped.")
+            .expect("Could not map offscreen readback buffer.");
+
+        let unpadded_bytes_per_row = self.width as usize * 4;
+        let mut data =
+            Vec::with_capacity(unpadded_bytes_per_row * self.height as usize);
+        {
+            let mapped = slice.get_mapped_range();
+            for row in mapped.chunks_exact(self.padded_bytes_per_row as usize) {
+                data.extend_from_slice(&row[..unpadded_bytes_per_row]);
+            }
+        }
+        self.readback_buffer.unmap();
+
+        if is_bgra(self.format) {
+            for pixel in data.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Image::from_rgba8(self.width, self.height, data)
+    }
+}
+
+/// Checks if a texture format can be used for an [`OffscreenTarget`].
+///
+/// The supported formats are the 8-bit-per-channel RGBA and BGRA formats,
+/// in both their linear and sRGB variants.
+pub fn is_supported_format(format: wgpu::TextureFormat) -> bool {
+    use wgpu::TextureFormat::{
+        Bgra8Unorm, Bgra8UnormSrgb, Rgba8Unorm, Rgba8UnormSrgb,
+    };
+    matches!(
+        format,
+        Rgba8Unorm | Rgba8UnormSrgb | Bgra8Unorm | Bgra8UnormSrgb
+    )
+}
+
+/// Checks if a texture format stores its channels in BGRA order.
+fn is_bgra(format: wgpu::TextureFormat) -> bool {
+    use wgpu::TextureFormat::{Bgra8Unorm, Bgra8UnormSrgb};
+    matches!(format, Bgra8Unorm | Bgra8UnormSrgb)
+}
+
+/// Computes the number of bytes per row of a readback buffer.
+///
+/// Texture-to-buffer copies require that rows are aligned to
+/// [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`] bytes.
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (width * 4).div_ceil(align) * align
+}
@@ -0,0 +1,491 @@
+//! Closed paths, for filling.
+
+use crate::{
+    bezier::{CubicBezier, QuadraticBezier},
+    transform::Transformable,
+    Line, M3, P2,
+};
+use cgmath::{EuclideanSpace, InnerSpace, Transform};
+
+/// Closed path: a set of closed contours which together bound a region.
+///
+/// Each contour is a sequence of straight segments and Bézier curves. Every
+/// contour is closed, whether or not [`Path::close`] is called: the end of
+/// the contour is connected back to its start with a straight segment. The
+/// contours may overlap and intersect each other and themselves; which
+/// regions are filled is decided by the [`crate::FillRule`] of the style.
+///
+/// Curves are flattened into straight segments as they are added, so that
+/// their distance from the true curve is no more than
+/// [`FLATTEN_TOLERANCE`].
+///
+/// To construct a path, use [`Path::new`] and its building methods, or
+/// [`Path::polygon`].
+#[derive(Debug, Clone)]
+pub struct Path {
+    /// Vertices of each contour.
+    contours: Vec<Vec<P2>>,
+    /// Whether the last contour has been closed, so that the next point
+    /// starts a new contour.
+    closed: bool,
+}
+impl Path {
+    /// Creates a new empty path.
+    pub fn new() -> Self {
+        Path {
+            contours: Vec::new(),
+            closed: true,
+        }
+    }
+
+    /// Creates a path containing a single polygon.
+    ///
+    /// # Parameters
+    ///
+    /// - `vertices`: Vertices of the polygon. There must be at least 3.
+    ///
+    /// # Returns
+    ///
+    /// A new path.
+    pub fn polygon(vertices: &[P2]) -> Self {
+        assert!(vertices.len() >= 3);
+        let mut path = Path::new();
+        path.move_to(vertices[0]);
+        for vertex in &vertices[1..] {
+            path.line_to(*vertex);
+        }
+        path.close();
+        path
+    }
+
+    /// Starts a new contour at a point.
+    pub fn move_to(&mut self, p: P2) -> &mut Self {
+        self.contours.push(vec![p]);
+        self.closed = false;
+        self
+    }
+
+    /// Adds a straight segment from the current point to `p`.
+    ///
+    /// If there is no current contour, `p` starts a new one.
+    pub fn line_to(&mut self, p: P2) -> &mut Self {
+        if self.closed {
+            return self.move_to(p);
+        }
+        self.current_contour().push(p);
+        self
+    }
+
+    /// Adds a quadratic Bézier curve from the current point to `p2`, with
+    /// control point `p1`.
+    ///
+    /// There must be a current contour.
+    pub fn quad_to(&mut self, p1: P2, p2: P2) -> &mut Self {
+        let p0 = self.current_point();
+        let curve = QuadraticBezier::new(p0, p1, p2);
+        let n = flatten_segment_count(&curve.control_points(), 2);
+        for i in 1..=n {
+            let p = curve.eval_param(i as f32 / n as f32);
+            self.current_contour().push(p);
+        }
+        self
+    }
+
+    /// Adds a cubic Bézier curve from the current point to `p3`, with
+    /// control points `p1` and `p2`.
+    ///
+    /// There must be a current contour.
+    pub fn cubic_to(&mut self, p1: P2, p2: P2, p3: P2) -> &mut Self {
+        let p0 = self.current_point();
+        let curve = CubicBezier::new(p0, p1, p2, p3);
+        let n = flatten_segment_count(&curve.control_points(), 3);
+        for i in 1..=n {
+            let p = curve.eval_param(i as f32 / n as f32);
+            self.current_contour().push(p);
+        }
+        self
+    }
+
+    /// Closes the current contour.
+    ///
+    /// The next point added starts a new contour.
+    pub fn close(&mut self) -> &mut Self {
+        self.closed = true;
+        self
+    }
+
+    /// Returns the vertices of each contour.
+    pub fn contours(&self) -> &[Vec<P2>] {
+        &self.contours
+    }
+
+    /// Returns the edges of all contours, including the edge which closes
+    /// each contour.
+    ///
+    /// Edges keep the direction of their contour, which determines the
+    /// winding numbers of the regions they bound. Edges of zero length are
+    /// omitted.
+    pub fn edges(&self) -> Vec<Line> {
+        let mut edges = Vec::new();
+        for contour in &self.contours {
+            let n = contour.len();
+            for i in 0..n {
+                let (a, b) = (contour[i], contour[(i + 1) % n]);
+                if a != b {
+                    edges.push(Line::new(a, b));
+                }
+            }
+        }
+        edges
+    }
+
+    /// Returns the last point of the current contour.
+    fn current_point(&self) -> P2 {
+        assert!(!self.closed, "path has no current contour");
+        *self.contours.last().unwrap().last().unwrap()
+    }
+
+    /// Returns the current contour.
+    fn current_contour(&mut self) -> &mut Vec<P2> {
+        self.contours.last_mut().unwrap()
+    }
+}
+
+impl Transformable for Path {
+    /// Returns a copy of the path, mapped through an affine transform.
+    ///
+    /// Curves have already been flattened, so they are flattened to within
+    /// [`FLATTEN_TOLERANCE`] scaled by the transform.
+    fn transformed(&self, matrix: &M3) -> Self {
+        let contours = self
+            .contours
+            .iter()
+            .map(|contour| {
+                contour.iter().map(|p| matrix.transform_point(*p)).collect()
+            })
+            .collect();
+        Path {
+            contours,
+            closed: self.closed,
+        }
+    }
+}
+
+impl Default for Path {
+    /// Returns a new empty path.
+    fn default() -> Self {
+        Path::new()
+    }
+}
+
+/// Maximum distance between a curve and the segments it is flattened into.
+pub const FLATTEN_TOLERANCE: f32 = 0.1;
+
+/// Maximum number of segments that a curve is flattened into.
+pub const MAX_FLATTEN_SEGMENTS: usize = 256;
+
+/// Returns the number of segments needed to flatten a curve.
+///
+/// This uses Wang's formula, which bounds the distance between a Bézier
+/// curve and the segments between equally-spaced points on it, using the
+/// largest second difference of its control points.
+///
+/// # Parameters
+///
+/// - `control_points`: Control points of the curve.
+/// - `degree`: Degree of the curve.
+fn flatten_segment_count(control_points: &[P2], degree: usize) -> usize {
+    let max_second_difference = control_points
+        .windows(3)
+        .map(|w| {
+            (w[0].to_vec() - 2.0 * w[1].to_vec() + w[2].to_vec()).magnitude()
+        })
+        .fold(0.0, f32::max);
+    let d = degree as f32;
+    let n = (d * (d - 1.0) / 8.0 * max_second_difference / FLATTEN_TOLERANCE)
+        .sqrt()
+        .ceil();
+    if n.is_nan() {
+        return 1;
+    }
+    (n as usize).clamp(1, MAX_FLATTEN_SEGMENTS)
+}
+
+/// Returns the winding number of a set of edges around a point.
+///
+/// This counts the edges which cross a horizontal ray from the point towards
+/// positive x. Edges crossing upwards count as +1, and edges crossing
+/// downwards count as -1, so that anti-clockwise contours wind around the
+/// points inside them once. An edge crosses the ray if one of its ends is at
+/// or below the point, and the other end is above it. This is the same
+/// test that the shader uses (see `fill_winding`).
+///
+/// # Parameters
+///
+/// - `edges`: Edges of a path.
+/// - `p`: Point to compute the winding number of.
+pub fn winding_number(edges: &[Line], p: P2) -> i32 {
+    edges
+        .iter()
+        .filter_map(|edge| ray_crossing(edge, p.y).filter(|(x, _)| *x > p.x))
+        .map(|(_, direction)| direction)
+        .sum()
+}
+
+/// Finds where an edge crosses a horizontal line.
+///
+/// # Parameters
+///
+/// - `edge`: The edge.
+/// - `y`: Height of the horizontal line.
+///
+/// # Returns
+///
+/// `None` if the edge does not cross the line, or the x coordinate of the
+/// crossing and the direction of the edge (+1 upwards, or -1 downwards).
+pub(crate) fn ray_crossing(edge: &Line, y: f32) -> Option<(f32, i32)> {
+    let (a, b) = (edge.start(), edge.end());
+    if (a.y <= y) == (b.y <= y) {
+        return None;
+    }
+    let x = a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y);
+    let direction = if b.y > a.y { 1 } else { -1 };
+    Some((x, direction))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        software::sdf_rounded_line,
+        tests::parity::{check_scene, pixel_at, Shape},
+        Color, FillRule, FillStyle, Line, LineStyle, SoftwareRenderer, V2,
+    };
+    use cgmath::MetricSpace;
+
+    #[test]
+    fn test_winding_number() {
+        // An anti-clockwise square, and a clockwise square inside it.
+        let mut path = Path::polygon(&[
+            P2::new(0.0, 0.0),
+            P2::new(10.0, 0.0),
+            P2::new(10.0, 10.0),
+            P2::new(0.0, 10.0),
+        ]);
+        path.move_to(P2::new(2.0, 2.0))
+            .line_to(P2::new(2.0, 8.0))
+            .line_to(P2::new(8.0, 8.0))
+            .line_to(P2::new(8.0, 2.0))
+            .close();
+        let edges = path.edges();
+        assert_eq!(edges.len(), 8);
+        assert_eq!(winding_number(&edges, P2::new(1.0, 5.0)), 1);
+        assert_eq!(winding_number(&edges, P2::new(5.0, 5.0)), 0);
+        assert_eq!(winding_number(&edges, P2::new(-1.0, 5.0)), 0);
+        assert_eq!(winding_number(&edges, P2::new(5.0, 0.0)), 1);
+        assert_eq!(winding_number(&edges, P2::new(5.0, 10.0)), 0);
+    }
+
+    #[test]
+    fn test_flattened_curves_are_close() {
+        let (p0, p1, p2, p3) = (
+            P2::new(0.0, 0.0),
+            P2::new(20.0, 100.0),
+            P2::new(80.0, -50.0),
+            P2::new(100.0, 0.0),
+        );
+        let mut path = Path::new();
+        path.move_to(p0).cubic_to(p1, p2, p3).close();
+        let curve = CubicBezier::new(p0, p1, p2, p3);
+        let vertices = &path.contours()[0];
+        assert!(vertices.len() > 10);
+        for pair in vertices.windows(2) {
+            // The midpoint of each segment is close to the curve.
+            let mid = pair[0].midpoint(pair[1]);
+            let nearest = (0..=1000)
+                .map(|i| curve.eval_param(i as f32 / 1000.0).distance(mid))
+                .fold(f32::INFINITY, f32::min);
+            assert!(nearest < FLATTEN_TOLERANCE + 0.05);
+        }
+    }
+
+    /// Returns a five-pointed star, drawn as a self-intersecting polygon,
+    /// whose center is wound around twice.
+    fn star(center: P2, radius: f32) -> Path {
+        let vertices: Vec<P2> = (0..5)
+            .map(|i| {
+                let angle = std::f32::consts::FRAC_PI_2
+                    + i as f32 * 4.0 * std::f32::consts::PI / 5.0;
+                center + radius * V2::new(angle.cos(), angle.sin())
+            })
+            .collect();
+        Path::polygon(&vertices)
+    }
+
+    /// Returns a scene of translucent fills, using both fill rules.
+    fn fill_scene() -> Vec<(Shape, LineStyle)> {
+        let fill = |path, color, rule| {
+            (
+                Shape::Fill(
+                    path,
+                    FillStyle {
+                        color,
+                        rule,
+                        ..Default::default()
+                    },
+                ),
+                LineStyle::default(),
+            )
+        };
+
+        // A rounded shape with a square hole, which has the opposite
+        // direction to the outside.
+        let mut blob = Path::new();
+        blob.move_to(P2::new(60.0, 10.0))
+            .cubic_to(
+                P2::new(150.0, 0.0),
+                P2::new(150.0, 100.0),
+                P2::new(90.0, 90.0),
+            )
+            .quad_to(P2::new(40.0, 100.0), P2::new(60.0, 10.0))
+            .close();
+        blob.move_to(P2::new(85.0, 35.0))
+            .line_to(P2::new(85.0, 60.0))
+            .line_to(P2::new(110.0, 60.0))
+            .line_to(P2::new(110.0, 35.0))
+            .close();
+
+        vec![
+            fill(
+                Path::polygon(&[
+                    P2::new(5.0, 5.0),
+                    P2::new(70.0, 12.0),
+                    P2::new(40.0, 60.0),
+                ]),
+                Color::new(0.9, 0.4, 0.4, 0.8),
+                FillRule::NonZero,
+            ),
+            fill(
+                star(P2::new(35.0, 60.0), 33.0),
+                Color::new(0.4, 0.9, 0.4, 0.6),
+                FillRule::EvenOdd,
+            ),
+            fill(
+                star(P2::new(120.0, 70.0), 28.0),
+                Color::new(0.4, 0.4, 0.9, 0.8),
+                FillRule::NonZero,
+            ),
+            fill(blob, Color::new(1.0, 1.0, 0.2, 0.5), FillRule::NonZero),
+        ]
+    }
+
+    #[test]
+    fn test_fills_match_gpu() {
+        let image = check_scene(wgpu::TextureFormat::Rgba8Unorm, &fill_scene());
+        // The convex triangle is filled inside its lowest edge, and not
+        // beyond it.
+        assert_eq!(pixel_at(&image, 38.5, 12.5), [184, 82, 82, 204]);
+        assert_eq!(pixel_at(&image, 38.5, 5.5), [0, 0, 0, 0]);
+        // The center of the even-odd star is empty, and its points are
+        // filled.
+        assert_eq!(pixel_at(&image, 35.5, 60.5), [0, 0, 0, 0]);
+        assert_eq!(pixel_at(&image, 35.5, 85.5), [61, 138, 61, 153]);
+        // The center of the non-zero star is filled, beneath the blob.
+        assert_eq!(pixel_at(&image, 120.5, 70.5), [168, 168, 117, 230]);
+        // The hole of the blob winds the other way, so it is empty.
+        assert_eq!(pixel_at(&image, 97.5, 47.5), [0, 0, 0, 0]);
+        assert_eq!(pixel_at(&image, 70.5, 30.5), [128, 128, 26, 128]);
+    }
+
+    /// Pixels which are not near an edge are filled exactly when the path
+    /// winds around their centers according to the fill rule, including in
+    /// tiles that are filled solidly.
+    #[test]
+    fn test_fill_matches_winding_numbers() {
+        for rule in [FillRule::NonZero, FillRule::EvenOdd] {
+            for (shape, _) in fill_scene() {
+                let Shape::Fill(path, _) = shape else {
+                    unreachable!()
+                };
+                let style = FillStyle {
+                    color: Color::new(1.0, 1.0, 1.0, 1.0),
+                    rule,
+                    ..Default::default()
+                };
+                let mut software = SoftwareRenderer::new(
+                    wgpu::TextureFormat::Rgba8Unorm,
+                    150,
+                    100,
+                    16,
+                    16,
+                );
+                software.fill(&path, &style);
+                let image = software.render();
+
+                let edges = path.edges();
+                for y in 0..100 {
+                    for x in 0..150 {
+                        let p = P2::new(x as f32 + 0.5, y as f32 + 0.5);
+                        let near_edge = edges.iter().any(|edge| {
+                            sdf_rounded_line(
+                                edge.start().to_vec(),
+                                edge.end().to_vec(),
+                                0.0,
+                                p.to_vec(),
+                            ) < 1.0
+                        });
+                        if near_edge {
+                            continue;
+                        }
+                        let inside = rule.is_inside(winding_number(&edges, p));
+                        let alpha = image.pixel(x, 99 - y)[3];
+                        assert_eq!(
+                            alpha,
+                            if inside { 255 } else { 0 },
+                            "{:?} at {:?}",
+                            rule,
+                            p
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// The antialiased edges of a filled rectangle are the same as those of
+    /// a butt-capped line which covers the same rectangle.
+    #[test]
+    fn test_fill_edges_match_lines() {
+        let color = Color::new(0.8, 0.8, 0.8, 0.9);
+        let mut software = SoftwareRenderer::new(
+            wgpu::TextureFormat::Rgba8Unorm,
+            150,
+            100,
+            16,
+            16,
+        );
+        software.fill(
+            &Path::polygon(&[
+                P2::new(20.3, 30.6),
+                P2::new(110.7, 30.6),
+                P2::new(110.7, 71.2),
+                P2::new(20.3, 71.2),
+            ]),
+            &FillStyle {
+                color,
+                ..Default::default()
+            },
+        );
+        let fill = software.render();
+        software.line(
+            Line::new(P2::new(20.3, 50.9), P2::new(110.7, 50.9)),
+            &LineStyle {
+                width: 40.6,
+                color,
+                ..Default::default()
+            },
+        );
+        let line = software.render();
+        assert!(fill.max_difference(&line) <= 2);
+    }
+}
@@ -0,0 +1,422 @@
+//! Phosphor persistence: the afterglow of lines drawn in earlier frames.
+//!
+//! When persistence is enabled (see [`crate::Renderer::set_persistence`]),
+//! the renderer keeps an accumulation texture between frames. Each frame,
+//! the texture is first decayed according to the [`Phosphor`] and the time
+//! since the previous frame, then the frame's lines are drawn into it, and
+//! finally it is composited over the output.
+
+use crate::renderer::{create_fullscreen_pipeline, HDR_FORMAT};
+
+/// Decay of a phosphor, which determines how long lines persist after they
+/// are drawn.
+///
+/// Each of the red, green and blue channels decays exponentially with its
+/// own half-life, so that the color of a trail can change as it fades.
+/// Opacity decays with the longest of the half-lives, so that a trail
+/// remains visible while any of its channels do.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Phosphor {
+    /// Half-lives of the red, green and blue channels, in seconds. A
+    /// half-life of zero means that the channel does not persist at all.
+    pub half_life: [f32; 3],
+}
+impl Phosphor {
+    /// Approximation of a P1 phosphor: a green afterglow of medium
+    /// persistence. Green lines leave green trails, while other colors fade
+    /// towards green.
+    pub const P1: Phosphor = Phosphor {
+        half_life: [0.04, 0.12, 0.04],
+    };
+
+    /// Approximation of a P7 phosphor: a brief blue flash, followed by a
+    /// long yellow afterglow. White lines leave yellow trails.
+    pub const P7: Phosphor = Phosphor {
+        half_life: [0.5, 0.4, 0.03],
+    };
+
+    /// Creates a new phosphor.
+    ///
+    /// # Parameters
+    ///
+    /// - `half_life`: Half-lives of the red, green and blue channels, in
+    ///   seconds. These must not be negative.
+    ///
+    /// # Returns
+    ///
+    /// A new phosphor.
+    pub fn new(half_life: [f32; 3]) -> Self {
+        assert!(half_life.iter().all(|h| *h >= 0.0));
+        Phosphor { half_life }
+    }
+
+    /// Returns the factors by which the accumulated red, green, blue and
+    /// alpha channels are multiplied after some time has elapsed.
+    ///
+    /// # Parameters
+    ///
+    /// - `elapsed`: Time elapsed, in seconds. This must not be negative.
+    pub fn decay(&self, elapsed: f32) -> [f32; 4] {
+        assert!(elapsed >= 0.0);
+        let factor = |half_life: f32| {
+            if elapsed == 0.0 {
+                1.0
+            } else if half_life > 0.0 {
+                0.5f32.powf(elapsed / half_life)
+            } else {
+                0.0
+            }
+        };
+        let [r, g, b] = self.half_life.map(factor);
+        [r, g, b, r.max(g).max(b)]
+    }
+}
+
+/// Default time between frames, in seconds, which is used to decay the
+/// accumulation texture until it is set (see
+/// [`crate::Renderer::set_frame_time`]).
+pub const DEFAULT_FRAME_TIME: f32 = 1.0 / 60.0;
+
+/// Accumulation texture, and the pipelines which decay and composite it.
+#[derive(Debug)]
+pub(crate) struct Accumulator {
+    width: u32,
+    height: u32,
+    view: wgpu::TextureView,
+    decay_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    composite_bind_group: wgpu::BindGroup,
+    /// Whether the texture must be cleared before it is next decayed.
+    needs_clear: bool,
+}
+impl Accumulator {
+    /// Creates a new `Accumulator`, with a transparent black texture.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device.
+    /// - `width`: Width of the accumulation texture.
+    /// - `height`: Height of the accumulation texture.
+    /// - `output_format`: Format of the textures that the accumulation
+    ///   texture is composited over.
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Beamline: Accumulation texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let shader = device
+            .create_shader_module(wgpu::include_wgsl!("persistence.wgsl"));
+        let composite_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Beamline: Composite bind group layout."),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float {
+                            filterable: false,
+                        },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }],
+            });
+        let composite_bind_group =
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Beamline: Composite bind group."),
+                layout: &composite_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                }],
+            });
+
+        // The decay pass multiplies the texture by the blend constant.
+        let decay_component = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::Constant,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let decay_pipeline = create_fullscreen_pipeline(
+            device,
+            "Beamline: Accumulation decay pipeline",
+            &shader,
+            "fs_decay",
+            &[],
+            HDR_FORMAT,
+            Some(wgpu::BlendState {
+                color: decay_component,
+                alpha: decay_component,
+            }),
+        );
+        let composite_pipeline = create_fullscreen_pipeline(
+            device,
+            "Beamline: Accumulation composite pipeline",
+            &shader,
+            "fs_composite",
+            &[&composite_layout],
+            output_format,
+            Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+        );
+
+        Accumulator {
+            width,
+            height,
+            view,
+            decay_pipeline,
+            composite_pipeline,
+            composite_bind_group,
+            needs_clear: true,
+        }
+    }
+
+    /// Returns the width of the accumulation texture.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the accumulation texture.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns a view of the accumulation texture, for drawing lines into.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Clears the accumulation texture before it is next decayed, removing
+    /// all afterglow.
+    pub fn clear(&mut self) {
+        self.needs_clear = true;
+    }
+
+    /// Records a render pass which decays the accumulation texture.
+    ///
+    /// # Parameters
+    ///
+    /// - `encoder`: Command encoder to which the pass should be recorded.
+    /// - `factors`: Factors by which the red, green, blue and alpha channels
+    ///   are multiplied (see [`Phosphor::decay`]).
+    pub fn decay(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        factors: [f32; 4],
+    ) {
+        let load = if self.needs_clear {
+            wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+        } else {
+            wgpu::LoadOp::Load
+        };
+        self.needs_clear = false;
+
+        let mut render_pass =
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Beamline: Accumulation decay pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+        let [r, g, b, a] = factors.map(f64::from);
+        render_pass.set_pipeline(&self.decay_pipeline);
+        render_pass.set_blend_constant(wgpu::Color { r, g, b, a });
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Records a render pass which composites the accumulation texture over
+    /// an output texture.
+    ///
+    /// # Parameters
+    ///
+    /// - `encoder`: Command encoder to which the pass should be recorded.
+    /// - `output_texture`: Texture view to composite over. Its size must
+    ///   match the accumulation texture.
+    pub fn composite(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output_texture: &wgpu::TextureView,
+    ) {
+        let mut render_pass =
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Beamline: Accumulation composite pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output_texture,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+        render_pass.set_pipeline(&self.composite_pipeline);
+        render_pass.set_bind_group(0, &self.composite_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{gpu::Gpu, parity::pixel_at},
+        Color, Image, Line, LineCap, LineStyle, Renderer, SoftwareRenderer, P2,
+    };
+
+    #[test]
+    fn test_decay() {
+        let phosphor = Phosphor::new([1.0, 2.0, 0.0]);
+        assert_eq!(phosphor.decay(0.0), [1.0, 1.0, 1.0, 1.0]);
+        let [r, g, b, a] = phosphor.decay(2.0);
+        assert!((r - 0.25).abs() < 1e-6);
+        assert!((g - 0.5).abs() < 1e-6);
+        assert_eq!(b, 0.0);
+        assert_eq!(a, g);
+    }
+
+    /// Renders a line moving across several frames with persistence, on the
+    /// GPU and in software, and checks that every frame matches.
+    ///
+    /// # Parameters
+    ///
+    /// - `texture_format`: Format of the output texture.
+    /// - `phosphor`: Phosphor which decays the lines of earlier frames.
+    ///
+    /// # Returns
+    ///
+    /// The image of the last frame rendered on the GPU.
+    fn check_against_gpu(
+        texture_format: wgpu::TextureFormat,
+        phosphor: Phosphor,
+    ) -> Image {
+        const TOLERANCE: u8 = 2;
+
+        let gpu = Gpu::new();
+        let mut renderer =
+            Renderer::new(&gpu.device, texture_format, 150, 100, 16, 16);
+        let mut software =
+            SoftwareRenderer::new(texture_format, 150, 100, 16, 16);
+        renderer.set_persistence(Some(phosphor));
+        software.set_persistence(Some(phosphor));
+        renderer.set_frame_time(0.05);
+        software.set_frame_time(0.05);
+        let style = LineStyle {
+            width: 6.0,
+            color: Color::new(0.9, 0.8, 1.0, 0.8),
+            ..Default::default()
+        };
+        let mut gpu_image = Image::new(150, 100);
+        for frame in 0..5 {
+            let x = 20.0 + 25.0 * frame as f32;
+            let line = Line::new(P2::new(x, 10.0), P2::new(x + 10.0, 90.0));
+            renderer.line(line.clone(), &style);
+            software.line(line, &style);
+            gpu_image = renderer.render_offscreen(&gpu.device, &gpu.queue);
+            let cpu_image = software.render();
+            let difference = gpu_image.max_difference(&cpu_image);
+            assert!(
+                difference <= TOLERANCE,
+                "GPU and software images differ by {} in frame {}",
+                difference,
+                frame
+            );
+        }
+        gpu_image
+    }
+
+    #[test]
+    fn test_persistence_matches_gpu() {
+        let image =
+            check_against_gpu(wgpu::TextureFormat::Rgba8Unorm, Phosphor::P7);
+        // The lines of earlier frames fade, and the blue of P7 fades fastest.
+        let current = pixel_at(&image, 125.5, 50.5);
+        let previous = pixel_at(&image, 100.5, 50.5);
+        let older = pixel_at(&image, 75.5, 50.5);
+        assert_eq!(current, [184, 163, 204, 204]);
+        assert!(older[3] < previous[3] && previous[3] < current[3]);
+        assert!(previous[2] < current[2] / 2 && previous[0] > current[0] / 2);
+        // Each frame, red, green and blue are multiplied by 0.5^(0.05 / h)
+        // for half-lives h of 0.5, 0.4 and 0.03, and opacity is multiplied
+        // by the largest of these.
+        assert_eq!(previous, [171, 150, 64, 190]);
+        assert!(older[3].abs_diff(178) <= 1 && older[2].abs_diff(20) <= 1);
+        // Nothing was ever drawn between the lines.
+        assert_eq!(pixel_at(&image, 112.5, 50.5), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_persistence_matches_gpu_srgb() {
+        check_against_gpu(wgpu::TextureFormat::Rgba8UnormSrgb, Phosphor::P7);
+    }
+
+    #[test]
+    fn test_persistence_p1_matches_gpu() {
+        let image =
+            check_against_gpu(wgpu::TextureFormat::Rgba8Unorm, Phosphor::P1);
+        // After 0.05 seconds, the red and blue of the previous line are
+        // multiplied by 0.5^(0.05 / 0.04), and its green and opacity by
+        // 0.5^(0.05 / 0.12), so that it turns greener.
+        assert_eq!(pixel_at(&image, 100.5, 50.5), [77, 122, 86, 153]);
+    }
+
+    /// Each channel of a line halves after its half-life, and the afterglow
+    /// can be cleared.
+    #[test]
+    fn test_persistence_half_life() {
+        let mut software = SoftwareRenderer::new(
+            wgpu::TextureFormat::Rgba8Unorm,
+            64,
+            32,
+            16,
+            16,
+        );
+        software.set_persistence(Some(Phosphor::new([0.1, 0.2, 0.0])));
+        software.set_frame_time(0.1);
+        software.line(
+            Line::new(P2::new(0.0, 16.0), P2::new(64.0, 16.0)),
+            &LineStyle {
+                width: 8.0,
+                cap: LineCap::Butt,
+                color: Color::new(1.0, 1.0, 1.0, 1.0),
+                ..Default::default()
+            },
+        );
+        assert_eq!(software.render().pixel(32, 16), [255, 255, 255, 255]);
+        assert_eq!(software.render().pixel(32, 16), [128, 180, 0, 180]);
+        let [r, g, b, a] = software.render().pixel(32, 16);
+        assert_eq!((r, b), (64, 0));
+        assert!(g.abs_diff(128) <= 1 && a == g);
+
+        software.clear_persistence();
+        assert_eq!(software.render(), Image::new(64, 32));
+    }
+}
@@ -0,0 +1,39 @@
+/**
+ * NAME:     persistence.wgsl
+ * PURPOSE:  Shaders which decay and composite the phosphor accumulation
+ *           texture.
+ */
+
+/**** BINDINGS ************************************************************* */
+
+@group(0) @binding(0) var accumulation : texture_2d<f32>;
+
+/**** VERTEX SHADER **********************************************************/
+
+/// Produces a triangle which covers the whole framebuffer.
+@vertex fn vs_fullscreen(
+    @builtin(vertex_index) vertex_index : u32
+) -> @builtin(position) vec4f {
+    let uv = vec2f(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+/**** FRAGMENT SHADERS *******************************************************/
+
+/// Decays the accumulation texture.
+///
+/// The decay is performed entirely by the blend state, which multiplies the
+/// texture by the blend constant. The output of the shader is not used.
+@fragment fn fs_decay() -> @location(0) vec4f {
+    return vec4f(0.0, 0.0, 0.0, 0.0);
+}
+
+/// Composites the accumulation texture over the output.
+///
+/// The accumulation texture contains premultiplied colors, which are
+/// blended over the output by the blend state.
+@fragment fn fs_composite(
+    @builtin(position) position : vec4f
+) -> @location(0) vec4f {
+    return textureLoad(accumulation, vec2i(position.xy), 0);
+}
@@ -2,6 +2,7 @@
 
 use crate::{bbox::Bbox, interval::Interval, P2, V2};
 use cgmath::InnerSpace;
+use std::f32::consts::PI;
 
 /// Closed polygon.
 ///
@@ -10,6 +11,7 @@ use cgmath::InnerSpace;
 /// point by a straight line.
 ///
 /// To construct a Polygon, use [`Polygon::new`].
+#[derive(Debug, Clone)]
 pub struct Polygon {
     /// Vertices of the polygon.
     vertices: Vec<P2>,
@@ -32,6 +34,74 @@ impl Polygon {
         Polygon { vertices }
     }
 
+    /// Creates the convex hull of a set of points.
+    ///
+    /// This uses Andrew's monotone chain algorithm. Points which lie on an
+    /// edge of the hull, or which coincide, are not vertices of the hull.
+    ///
+    /// # Parameters
+    ///
+    /// - `points`: Points to enclose. These must not all lie on one line.
+    ///
+    /// # Returns
+    ///
+    /// A convex `Polygon`, with its vertices in anti-clockwise order.
+    pub fn convex_hull(points: &[P2]) -> Self {
+        let mut sorted = points.to_vec();
+        sorted.sort_by(|p, q| p.x.total_cmp(&q.x).then(p.y.total_cmp(&q.y)));
+        let cross = |o: P2, a: P2, b: P2| {
+            (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x)
+        };
+
+        // Build the lower hull from left to right, then the upper hull from
+        // right to left. Each ends with the first point of the other.
+        let mut hull: Vec<P2> = Vec::with_capacity(sorted.len() + 1);
+        for pass in 0..2 {
+            let start = hull.len();
+            for p in sorted.iter() {
+                while hull.len() >= start + 2
+                    && cross(hull[hull.len() - 2], hull[hull.len() - 1], *p)
+                        <= 0.0
+                {
+                    hull.pop();
+                }
+                hull.push(*p);
+            }
+            hull.pop();
+            if pass == 0 {
+                sorted.reverse();
+            }
+        }
+        Polygon::new(hull)
+    }
+
+    /// Returns a convex polygon which contains every point within a distance
+    /// of this polygon.
+    ///
+    /// This is the convex hull of regular octagons around the vertices,
+    /// which are just large enough to contain circles of the distance.
+    ///
+    /// # Parameters
+    ///
+    /// - `distance`: Distance by which to expand the polygon. This must be
+    ///   positive.
+    ///
+    /// # Returns
+    ///
+    /// A convex `Polygon`, with its vertices in anti-clockwise order.
+    pub fn expanded(&self, distance: f32) -> Polygon {
+        assert!(distance > 0.0);
+        let r = distance / (PI / 8.0).cos();
+        let mut points = Vec::with_capacity(8 * self.vertices.len());
+        for vertex in &self.vertices {
+            for i in 0..8 {
+                let angle = (2 * i + 1) as f32 * PI / 8.0;
+                points.push(vertex + r * V2::new(angle.cos(), angle.sin()));
+            }
+        }
+        Polygon::convex_hull(&points)
+    }
+
     /// Check if a supplied axis is a "separating axis" for two polygons.
     ///
     /// The separating axis test projects both polygons onto a line which is
@@ -50,7 +120,12 @@ impl Polygon {
     /// # Returns
     ///
     /// `true` if the supplied axis was a separating axis, `false` otherwise.
-    pub fn is_separating_axis(&self, other: &Polygon, axis: V2, center: P2) -> bool {
+    pub fn is_separating_axis(
+        &self,
+        other: &Polygon,
+        axis: V2,
+        center: P2,
+    ) -> bool {
         // Produce a 90-degree rotation of the axis. This is a line onto
         // which we should project for the separating axis test.
         let direction = V2::new(-axis.y, axis.x);
@@ -65,6 +140,40 @@ impl Polygon {
         interval_self.disjoint(&interval_other)
     }
 
+    /// Returns the directions of the polygon's edges, for use as candidate
+    /// separating axes.
+    ///
+    /// Edges which are parallel to an earlier edge are skipped, since they
+    /// would produce the same separating axis test. For a rectangle, only
+    /// two directions are returned. Degenerate edges are skipped too.
+    ///
+    /// Only squared magnitudes are compared, so that the binning shader can
+    /// find the same axes with the same rounding.
+    pub fn edge_axes(&self) -> Vec<V2> {
+        let n = self.vertices.len();
+        let edges: Vec<V2> = (0..n)
+            .map(|i| self.vertices[(i + 1) % n] - self.vertices[i])
+            .filter(|edge| edge.magnitude2() > 0.0)
+            .collect();
+        edges
+            .iter()
+            .enumerate()
+            .filter(|(i, edge)| {
+                !edges[..*i].iter().any(|earlier| {
+                    let cross = earlier.x * edge.y - earlier.y * edge.x;
+                    cross * cross
+                        <= 1e-12 * earlier.magnitude2() * edge.magnitude2()
+                })
+            })
+            .map(|(_, edge)| *edge)
+            .collect()
+    }
+
+    /// Returns the vertices of the polygon.
+    pub fn vertices(&self) -> &[P2] {
+        &self.vertices
+    }
+
     /// Returns the axis-aligned bounding box of the polygon.
     pub fn bbox(&self) -> Bbox {
         Bbox::including(self.vertices.iter()).unwrap()
@@ -80,7 +189,11 @@ impl Polygon {
 /// - `direction`: Vector along the direction of the line corresponding to
 ///   positive values, and providing a scale.
 /// - `polygon`: Polygon to project to the line.
-fn project_polygon_to_line(center: P2, direction: V2, polygon: &Polygon) -> Interval {
+fn project_polygon_to_line(
+    center: P2,
+    direction: V2,
+    polygon: &Polygon,
+) -> Interval {
     let mut p_iter = polygon.vertices.iter();
     let p_first = p_iter.next().unwrap(); // must be at least one point
     let mut interval = Interval::singleton((p_first - center).dot(direction));
@@ -89,3 +202,58 @@ fn project_polygon_to_line(center: P2, direction: V2, polygon: &Polygon) -> Inte
     }
     interval
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convex_hull() {
+        let points = [
+            P2::new(0.0, 0.0),
+            P2::new(2.0, 1.0),
+            P2::new(4.0, 0.0),
+            P2::new(4.0, 4.0),
+            P2::new(2.0, 4.0),
+            P2::new(0.0, 4.0),
+            P2::new(0.0, 0.0),
+            P2::new(1.0, 3.0),
+        ];
+        let hull = Polygon::convex_hull(&points);
+        assert_eq!(
+            hull.vertices(),
+            &[
+                P2::new(0.0, 0.0),
+                P2::new(4.0, 0.0),
+                P2::new(4.0, 4.0),
+                P2::new(0.0, 4.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expanded() {
+        let triangle = Polygon::new(vec![
+            P2::new(0.0, 0.0),
+            P2::new(10.0, 0.0),
+            P2::new(0.0, 10.0),
+        ]);
+        let expanded = triangle.expanded(2.0);
+        // Every point at the distance from a vertex is inside, and the
+        // expansion is tight along the axes.
+        let (x_min, x_max) = (expanded.bbox().min_x(), expanded.bbox().max_x());
+        assert!((x_min + 2.0).abs() < 1e-4 && (x_max - 12.0).abs() < 1e-4);
+        for i in 0..64 {
+            let angle = i as f32 * PI / 32.0;
+            let p =
+                P2::new(10.0, 0.0) + 1.999 * V2::new(angle.cos(), angle.sin());
+            let inside =
+                expanded.vertices().iter().enumerate().all(|(j, a)| {
+                    let b = expanded.vertices()
+                        [(j + 1) % expanded.vertices().len()];
+                    (b - a).perp_dot(p - a) >= 0.0
+                });
+            assert!(inside, "{:?}", p);
+        }
+    }
+}
@@ -0,0 +1,544 @@
+//! Polylines.
+
+use crate::{
+    marker::{self, StrokeEnd},
+    primitive::{GradientSpan, Primitive},
+    style::{LineJoin, LineStyle},
+    transform::Transformable,
+    types::v2_rot90_anticlockwise,
+    Line, LineCap, M3, P2,
+};
+use cgmath::{InnerSpace, MetricSpace, Transform};
+
+/// Polyline: a connected sequence of straight segments.
+///
+/// A polyline is rendered as a single shape. Its segments are connected at
+/// their shared vertices using the [`LineJoin`] of its style, and the
+/// [`LineCap`] of its style (or its markers) are only applied at the two
+/// ends of an open polyline.
+///
+/// To construct a polyline, use [`Polyline::new`] (for an open polyline) or
+/// [`Polyline::closed`] (for a closed polyline).
+#[derive(Debug, Clone)]
+pub struct Polyline {
+    /// Vertices of the polyline.
+    points: Vec<P2>,
+    /// Whether the last point is connected back to the first point.
+    closed: bool,
+}
+impl Polyline {
+    /// Creates a new open polyline.
+    ///
+    /// # Parameters
+    ///
+    /// - `points`: Vertices of the polyline. There must be at least 2.
+    ///
+    /// # Returns
+    ///
+    /// A new open polyline.
+    pub fn new(points: Vec<P2>) -> Self {
+        assert!(points.len() >= 2);
+        Polyline {
+            points,
+            closed: false,
+        }
+    }
+
+    /// Creates a new closed polyline.
+    ///
+    /// The last point is connected back to the first point with a segment,
+    /// and all vertices (including the first) are joined.
+    ///
+    /// # Parameters
+    ///
+    /// - `points`: Vertices of the polyline. There must be at least 3. The
+    ///   first point should not be repeated at the end.
+    ///
+    /// # Returns
+    ///
+    /// A new closed polyline.
+    pub fn closed(points: Vec<P2>) -> Self {
+        assert!(points.len() >= 3);
+        Polyline {
+            points,
+            closed: true,
+        }
+    }
+
+    /// Returns the vertices of the polyline.
+    pub fn points(&self) -> &[P2] {
+        &self.points
+    }
+
+    /// Returns `true` if the polyline is closed.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Decomposes the polyline into primitives for rendering.
+    ///
+    /// The polyline becomes a butt-ended segment for each of its edges
+    /// (except that the ends of an open polyline use the style's cap), plus
+    /// a join primitive at each joined vertex. Join primitives overlap the
+    /// ends of their adjacent segments slightly, so that no seams are
+    /// visible when the primitives are unioned.
+    ///
+    /// If the style has a dash pattern, the pattern continues from one
+    /// segment to the next, and joins are only produced at vertices which
+    /// fall within a dash.
+    ///
+    /// If the style has a gradient, each primitive is paired with the part
+    /// of the gradient that it spans. A gradient with a color for each
+    /// vertex is interpolated along each segment, while any other gradient
+    /// runs from its first color to its last color along the whole
+    /// polyline.
+    ///
+    /// If the style is tapered, the width changes linearly with the
+    /// distance along the whole polyline, and each join has the width at
+    /// its vertex. The width of a closed polyline changes abruptly where
+    /// its last segment meets its first.
+    ///
+    /// If the style has markers, they are added at the ends of an open
+    /// polyline, and its first and last segments are trimmed back from any
+    /// arrowheads (see [`marker::trim_distance`]). Closed polylines have no
+    /// markers.
+    ///
+    /// Consecutive vertices which coincide are ignored. If fewer than two
+    /// distinct vertices remain, no primitives are produced.
+    ///
+    /// # Parameters
+    ///
+    /// - `style`: Style of the polyline.
+    pub fn primitives(
+        &self,
+        style: &LineStyle,
+    ) -> Vec<(Primitive, Option<GradientSpan>)> {
+        // Distinct vertices, and the indices of the original vertices that
+        // they came from.
+        let mut points: Vec<P2> = Vec::with_capacity(self.points.len());
+        let mut indices: Vec<usize> = Vec::with_capacity(self.points.len());
+        for (i, p) in self.points.iter().enumerate() {
+            if points.last().is_none_or(|q| !coincident(*q, *p)) {
+                points.push(*p);
+                indices.push(i);
+            }
+        }
+        if self.closed
+            && points.len() > 1
+            && coincident(points[0], points[points.len() - 1])
+        {
+            points.pop();
+            indices.pop();
+        }
+        if points.len() < 2 {
+            return Vec::new();
+        }
+        let closed = self.closed && points.len() > 2;
+
+        let n_points = points.len();
+        let n_segments = if closed { n_points } else { n_points - 1 };
+        let segment =
+            |i: usize| Line::new(points[i], points[(i + 1) % n_points]);
+        let total_length: f32 = (0..n_segments)
+            .map(|i| segment(i).ab_vec().magnitude())
+            .sum();
+
+        // Returns the width at a distance along the polyline.
+        let end_width = style.end_width.unwrap_or(style.width);
+        let width_at = |distance: f32| {
+            let t = (distance / total_length).clamp(0.0, 1.0);
+            style.width + (end_width - style.width) * t
+        };
+
+        // Returns the part of the gradient between two vertices, given the
+        // distances along the polyline at which they lie.
+        let gradient_span = |i: usize, j: usize, start: f32, end: f32| {
+            style.gradient.as_ref().map(|gradient| {
+                let colors = gradient.colors();
+                if colors.len() == self.points.len() {
+                    GradientSpan {
+                        start_color: colors[indices[i]],
+                        end_color: colors[indices[j]],
+                        start: 0.0,
+                        end: if i == j { 0.0 } else { 1.0 },
+                    }
+                } else {
+                    GradientSpan {
+                        start_color: gradient.start(),
+                        end_color: gradient.end(),
+                        start: start / total_length,
+                        end: end / total_length,
+                    }
+                }
+            })
+        };
+
+        // Distances by which the ends of an open polyline are trimmed.
+        let (trim_start, trim_end) = if closed {
+            (0.0, 0.0)
+        } else {
+            let first = segment(0).ab_vec().magnitude();
+            let last = segment(n_segments - 1).ab_vec().magnitude();
+            (
+                marker::trim_distance(style.start_marker, style.width, first),
+                marker::trim_distance(style.end_marker, end_width, last),
+            )
+        };
+
+        let mut primitives = Vec::with_capacity(2 * n_segments + 2);
+        let mut distance = 0.0;
+        for i in 0..n_segments {
+            let is_first = !closed && i == 0;
+            let is_last = !closed && i == n_segments - 1;
+            let start_cap = if is_first {
+                style.start_cap()
+            } else {
+                LineCap::Butt
+            };
+            let end_cap = if is_last {
+                style.end_cap()
+            } else {
+                LineCap::Butt
+            };
+            let line = segment(i);
+            let length = line.ab_vec().magnitude();
+            let from = if is_first { trim_start } else { 0.0 };
+            let to = if is_last { length - trim_end } else { length };
+            let d = line.ab_vec() / length;
+            let next = (i + 1) % n_points;
+            primitives.push((
+                Primitive::Segment {
+                    line: Line::new(
+                        line.start() + from * d,
+                        line.start() + to * d,
+                    ),
+                    start_cap,
+                    end_cap,
+                    distance: distance + from,
+                    start_width: width_at(distance + from),
+                    end_width: width_at(distance + to),
+                },
+                gradient_span(i, next, distance, distance + length)
+                    .map(|span| span.part(from / length, to / length)),
+            ));
+            distance += length;
+
+            // Join this segment to the next one, unless the join falls in a
+            // gap of the dash pattern.
+            let join_visible =
+                style.dash.is_none_or(|dash| dash.is_on(distance));
+            if (closed || i + 1 < n_segments) && join_visible {
+                let next_segment = segment((i + 1) % n_segments);
+                primitives.push((
+                    join_primitive(
+                        &segment(i),
+                        &next_segment,
+                        style.join,
+                        |offset| width_at(distance + offset),
+                    ),
+                    gradient_span(next, next, distance, distance),
+                ));
+            }
+        }
+
+        if !closed {
+            let last = n_points - 1;
+            primitives.extend(marker::end_markers(
+                style,
+                StrokeEnd {
+                    point: points[0],
+                    direction: -segment(0).ab_vec().normalize(),
+                    width: style.width,
+                    gradient: gradient_span(0, 0, 0.0, 0.0),
+                },
+                StrokeEnd {
+                    point: points[last],
+                    direction: segment(n_segments - 1).ab_vec().normalize(),
+                    width: end_width,
+                    gradient: gradient_span(
+                        last,
+                        last,
+                        total_length,
+                        total_length,
+                    ),
+                },
+            ));
+        }
+        primitives
+    }
+}
+impl Transformable for Polyline {
+    fn transformed(&self, matrix: &M3) -> Self {
+        Polyline {
+            points: self
+                .points
+                .iter()
+                .map(|p| matrix.transform_point(*p))
+                .collect(),
+            closed: self.closed,
+        }
+    }
+}
+
+/// Creates the primitive which joins two consecutive segments.
+///
+/// The end of `incoming` must be the start of `outgoing`.
+///
+/// Miter and bevel joins are a polygon that covers the outer corner of the
+/// join. It also reaches back a little way into both segments on the outer
+/// side, and towards the inner corner, so that it overlaps the butt ends of
+/// both segments. Round joins are a disc at the vertex.
+///
+/// # Parameters
+///
+/// - `incoming`: Segment which ends at the vertex.
+/// - `outgoing`: Segment which starts at the vertex.
+/// - `join`: Style of the join.
+/// - `width_at`: Returns the width of the line at a distance along it from
+///   the vertex. Negative distances are along `incoming`.
+fn join_primitive(
+    incoming: &Line,
+    outgoing: &Line,
+    join: LineJoin,
+    width_at: impl Fn(f32) -> f32,
+) -> Primitive {
+    let v = incoming.end();
+    let width = width_at(0.0);
+    if let LineJoin::Round = join {
+        return Primitive::Disc { center: v, width };
+    }
+
+    let w2 = width / 2.0;
+    let d0 = incoming.ab_vec().normalize();
+    let d1 = outgoing.ab_vec().normalize();
+
+    // Outer normals of both segments. A left (anti-clockwise) turn has its
+    // outer corner on the right.
+    let cross = d0.x * d1.y - d0.y * d1.x;
+    let side = if cross >= 0.0 { -1.0 } else { 1.0 };
+    let n0 = side * v2_rot90_anticlockwise(d0);
+    let n1 = side * v2_rot90_anticlockwise(d1);
+
+    // Corners on the outer edges of the segments, at the vertex.
+    let a = v + w2 * n0;
+    let b = v + w2 * n1;
+
+    // Distance by which the polygon reaches back into the segments. This is
+    // limited so that the polygon stays inside both segments.
+    let overlap = w2
+        .min(incoming.ab_vec().magnitude())
+        .min(outgoing.ab_vec().magnitude());
+    let inner_dir = if (d1 - d0).magnitude2() > EPSILON * EPSILON {
+        (d1 - d0).normalize()
+    } else {
+        -n0
+    };
+    let inner = v + overlap * inner_dir;
+
+    // Corners on the outer edges of the segments, where the polygon reaches
+    // back into them. These use the width there, in case the line tapers.
+    let a_back = v - overlap * d0 + (width_at(-overlap) / 2.0) * n0;
+    let b_forward = v + overlap * d1 + (width_at(overlap) / 2.0) * n1;
+
+    let mut vertices = vec![inner, a_back, a];
+    if let LineJoin::Miter { limit } = join {
+        // Ratio of the miter length to the line width is 1 / cos(theta/2),
+        // where theta is the turning angle.
+        let cos_half_theta = ((1.0 + d0.dot(d1)) / 2.0).max(0.0).sqrt();
+        if cos_half_theta > EPSILON && 1.0 / cos_half_theta <= limit {
+            let tip = v + (w2 / cos_half_theta) * (n0 + n1).normalize();
+            vertices.push(tip);
+        }
+    }
+    vertices.push(b);
+    vertices.push(b_forward);
+
+    // Remove (nearly) coincident consecutive vertices, which the shader's
+    // polygon distance function cannot handle.
+    vertices.dedup_by(|p, q| coincident(*p, *q));
+    Primitive::Polygon(vertices)
+}
+
+/// Checks whether two points are close enough to be considered the same.
+fn coincident(p: P2, q: P2) -> bool {
+    p.distance2(q) <= EPSILON * EPSILON
+}
+
+/// Tolerance for treating points and directions as equal.
+const EPSILON: f32 = 1e-4;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::parity::{check_scene, pixel_at, Shape},
+        Color, LineCap, LineJoin, LineStyle, SoftwareRenderer,
+    };
+
+    /// Returns a scene of translucent polylines with all join styles.
+    fn polyline_scene() -> Vec<(Shape, LineStyle)> {
+        let style = |width, cap, join, color| LineStyle {
+            width,
+            cap,
+            join,
+            color,
+            ..Default::default()
+        };
+        let zigzag = |y: f32| {
+            Polyline::new(vec![
+                P2::new(10.0, y),
+                P2::new(40.0, y + 20.0),
+                P2::new(70.0, y),
+                P2::new(75.0, y + 25.0),
+                P2::new(140.0, y + 5.0),
+            ])
+        };
+        vec![
+            (
+                Shape::Polyline(zigzag(5.0)),
+                style(
+                    8.0,
+                    LineCap::Butt,
+                    LineJoin::Miter { limit: 4.0 },
+                    Color::new(0.9, 0.4, 0.4, 0.7),
+                ),
+            ),
+            (
+                Shape::Polyline(zigzag(35.0)),
+                style(
+                    7.0,
+                    LineCap::Square,
+                    LineJoin::Bevel,
+                    Color::new(0.4, 0.9, 0.4, 0.6),
+                ),
+            ),
+            (
+                Shape::Polyline(zigzag(65.0)),
+                style(
+                    9.0,
+                    LineCap::Round,
+                    LineJoin::Round,
+                    Color::new(0.4, 0.4, 0.9, 0.8),
+                ),
+            ),
+            (
+                Shape::Polyline(Polyline::closed(vec![
+                    P2::new(100.0, 20.0),
+                    P2::new(140.0, 50.0),
+                    P2::new(105.0, 90.0),
+                ])),
+                style(
+                    6.0,
+                    LineCap::Butt,
+                    LineJoin::Miter { limit: 2.0 },
+                    Color::new(1.0, 1.0, 0.2, 0.5),
+                ),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_polylines_match_gpu() {
+        let image =
+            check_scene(wgpu::TextureFormat::Rgba8Unorm, &polyline_scene());
+        // The miter join covers the point of its corner, which the bevel join
+        // of the next zigzag cuts off.
+        assert_eq!(pixel_at(&image, 40.5, 28.5), [161, 71, 71, 178]);
+        assert!(pixel_at(&image, 40.5, 58.5)[3] < 16);
+        // The round join is blended once where its segments overlap.
+        assert_eq!(pixel_at(&image, 40.5, 85.5), [82, 82, 184, 204]);
+    }
+
+    /// Returns a scene of right-angled polylines, whose miter joins either
+    /// keep their corners or fall back to bevels at their limits, and of
+    /// open and closed translucent squares.
+    fn join_limit_scene() -> Vec<(Shape, LineStyle)> {
+        let style = |limit| LineStyle {
+            width: 10.0,
+            join: LineJoin::Miter { limit },
+            color: Color::new(0.9, 0.9, 0.4, 0.5),
+            ..Default::default()
+        };
+        let corner = |x: f32| {
+            Polyline::new(vec![
+                P2::new(x, 55.0),
+                P2::new(x + 30.0, 55.0),
+                P2::new(x + 30.0, 90.0),
+            ])
+        };
+        let square = |x: f32| {
+            vec![
+                P2::new(x, 10.0),
+                P2::new(x + 30.0, 10.0),
+                P2::new(x + 30.0, 40.0),
+                P2::new(x, 40.0),
+            ]
+        };
+        let mut open_square = square(90.0);
+        open_square.push(P2::new(90.0, 10.0));
+        vec![
+            (Shape::Polyline(corner(15.0)), style(4.0)),
+            (Shape::Polyline(corner(90.0)), style(1.2)),
+            (Shape::Polyline(Polyline::closed(square(15.0))), style(4.0)),
+            (Shape::Polyline(Polyline::new(open_square)), style(4.0)),
+        ]
+    }
+
+    #[test]
+    fn test_miter_limits_and_closing_joins_match_gpu() {
+        let image =
+            check_scene(wgpu::TextureFormat::Rgba8Unorm, &join_limit_scene());
+        let segment = pixel_at(&image, 30.5, 55.5);
+        assert_eq!(segment[3], 128);
+        // The miter within its limit covers the outer corner, and the miter
+        // beyond its limit is cut off by a bevel.
+        assert_eq!(pixel_at(&image, 48.5, 51.5), segment);
+        assert_eq!(pixel_at(&image, 123.5, 51.5), [0; 4]);
+        // A closed polyline is joined at its first point, where an open
+        // polyline which returns to it has two butt caps.
+        assert_eq!(pixel_at(&image, 11.5, 6.5), segment);
+        assert_eq!(pixel_at(&image, 86.5, 6.5), [0; 4]);
+    }
+
+    /// A translucent polyline is blended once, so its joins are no more
+    /// opaque than its segments.
+    #[test]
+    fn test_joins_blend_once() {
+        for join in [
+            LineJoin::Miter { limit: 4.0 },
+            LineJoin::Bevel,
+            LineJoin::Round,
+        ] {
+            let mut software = SoftwareRenderer::new(
+                wgpu::TextureFormat::Rgba8Unorm,
+                100,
+                100,
+                16,
+                16,
+            );
+            let style = LineStyle {
+                width: 10.0,
+                join,
+                color: Color::new(1.0, 1.0, 1.0, 0.5),
+                ..Default::default()
+            };
+            software.polyline(
+                &Polyline::new(vec![
+                    P2::new(10.0, 50.0),
+                    P2::new(50.0, 50.0),
+                    P2::new(50.0, 90.0),
+                ]),
+                &style,
+            );
+            let image = software.render();
+
+            // Rows are flipped relative to beamline coordinates.
+            let segment = image.pixel(30, 50);
+            let joint = image.pixel(50, 50);
+            let corner = image.pixel(53, 47);
+            assert_eq!(segment[3], 128);
+            assert_eq!(joint, segment, "{:?}", join);
+            assert_eq!(corner, segment, "{:?}", join);
+        }
+    }
+}
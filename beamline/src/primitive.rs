@@ -0,0 +1,544 @@
+//! Rendering primitives.
+//!
+//! Primitives are the pieces of geometry that are assigned to tiles by the
+//! [`crate::tiler::Tiler`] and evaluated by the shader. A single line is one
+//! primitive, while a polyline is made of several primitives (segments and
+//! joins) which are rendered together as one shape.
+
+use crate::{
+    arc::CircularArc,
+    bezier::{control_polygon_length, CubicBezier, QuadraticBezier},
+    clip::ClipRect,
+    ellipse::Ellipse,
+    layer::LayerId,
+    polygon::Polygon,
+    style::{FillRule, LineStyle, Marker},
+    Color, Line, LineCap, P2, V2,
+};
+use cgmath::InnerSpace;
+
+/// Maximum number of vertices in a [`Primitive::Polygon`].
+pub const MAX_POLYGON_VERTICES: usize = 6;
+
+/// Distance from an edge of a filled path within which tiles receive the
+/// edge.
+///
+/// This must be at least half of the antialiasing width, so that tiles
+/// which are filled solidly do not contain any of the antialiased edge.
+pub const FILL_EDGE_MARGIN: f32 = 1.0;
+
+/// Maximum number of pieces that a curve is split into for bounding.
+pub const MAX_CURVE_PIECES: usize = 64;
+
+/// Geometry of a primitive.
+#[derive(Debug, Clone)]
+pub enum Primitive {
+    /// A straight segment with a cap at each end.
+    ///
+    /// `distance` is the distance along the shape at which the segment
+    /// starts. It is used to continue dash patterns from one segment of a
+    /// polyline to the next. The width of the segment changes linearly from
+    /// `start_width` to `end_width`.
+    Segment {
+        line: Line,
+        start_cap: LineCap,
+        end_cap: LineCap,
+        distance: f32,
+        start_width: f32,
+        end_width: f32,
+    },
+    /// A filled polygon, used for miter and bevel joins.
+    ///
+    /// The polygon must be simple, and have between 3 and
+    /// [`MAX_POLYGON_VERTICES`] vertices.
+    Polygon(Vec<P2>),
+    /// A filled disc with a diameter of `width`. This is used for round
+    /// joins.
+    Disc { center: P2, width: f32 },
+    /// A quadratic Bézier curve with a cap at each end.
+    Quadratic {
+        curve: QuadraticBezier,
+        start_cap: LineCap,
+        end_cap: LineCap,
+    },
+    /// A cubic Bézier curve with a cap at each end.
+    Cubic {
+        curve: CubicBezier,
+        start_cap: LineCap,
+        end_cap: LineCap,
+    },
+    /// A circular arc with a cap at each end. Whole circles have no caps.
+    Arc {
+        arc: CircularArc,
+        start_cap: LineCap,
+        end_cap: LineCap,
+    },
+    /// The outline of an ellipse.
+    Ellipse(Ellipse),
+    /// A marker at an end of a stroke (see [`crate::marker`]).
+    ///
+    /// `tip` is the end of the stroke, `direction` is a unit vector pointing
+    /// out of the stroke, and `width` is the width of the stroke at the end.
+    Marker {
+        marker: Marker,
+        tip: P2,
+        direction: V2,
+        width: f32,
+    },
+    /// A tile of a filled path.
+    ///
+    /// Within a tile, this is followed by the edges of the path which are
+    /// near the tile (see [`crate::tiler::Tiler::add_fill`]). `winding` is
+    /// the winding number of the path around the tile's reference point,
+    /// which is the center of the pixel in the bottom-right corner of the
+    /// tile. If there are no edges near the tile, the winding number is the
+    /// same everywhere in the tile, and the tile is filled solidly.
+    Fill { winding: i32, rule: FillRule },
+    /// An edge of a filled path. The direction of the edge determines the
+    /// winding numbers of the regions on either side of it.
+    FillEdge(Line),
+    /// A tile of a clip path.
+    ///
+    /// Within a tile, this is followed by the edges of the clip path which
+    /// are near the tile, and then by the primitives of the shape which it
+    /// clips. It is the same as a [`Primitive::Fill`], except that the
+    /// coverage of the path multiplies the coverage of the shape, instead
+    /// of being drawn. Tiles which are entirely inside the clip path do not
+    /// receive one.
+    Clip { winding: i32, rule: FillRule },
+}
+
+/// The part of a gradient which a primitive spans.
+///
+/// The color of the primitive is interpolated from `start_color` to
+/// `end_color`. It is a fraction `start` of the way between them at the
+/// start of the primitive, and a fraction `end` of the way between them at
+/// the end of the primitive.
+#[derive(Debug, Copy, Clone)]
+pub struct GradientSpan {
+    pub start_color: Color,
+    pub end_color: Color,
+    pub start: f32,
+    pub end: f32,
+}
+impl GradientSpan {
+    /// Creates a span from one color to another, over a whole primitive.
+    pub fn new(start_color: Color, end_color: Color) -> Self {
+        GradientSpan {
+            start_color,
+            end_color,
+            start: 0.0,
+            end: 1.0,
+        }
+    }
+
+    /// Creates the span of the whole of a style's gradient, if it has one.
+    pub fn of_style(style: &LineStyle) -> Option<Self> {
+        style
+            .gradient
+            .as_ref()
+            .map(|gradient| GradientSpan::new(gradient.start(), gradient.end()))
+    }
+
+    /// Returns the part of this span between two fractions of the way along
+    /// the primitive.
+    ///
+    /// # Parameters
+    ///
+    /// - `from`: Fraction of the way along the primitive at which the new
+    ///   span starts.
+    /// - `to`: Fraction of the way along the primitive at which the new
+    ///   span ends.
+    pub fn part(self, from: f32, to: f32) -> Self {
+        let span = self.end - self.start;
+        GradientSpan {
+            start: self.start + span * from,
+            end: self.start + span * to,
+            ..self
+        }
+    }
+}
+
+/// A primitive with an associated style, and the shape it belongs to.
+#[derive(Debug, Clone)]
+pub struct StyledPrimitive {
+    pub primitive: Primitive,
+    pub style: LineStyle,
+    /// Part of the style's gradient which the primitive spans, if the style
+    /// has a gradient.
+    pub gradient: Option<GradientSpan>,
+    /// Identifier of the shape that the primitive is part of.
+    ///
+    /// Consecutive primitives of the same shape within a tile are combined
+    /// (their signed distance functions are unioned) before they are
+    /// composited, so that overlapping pieces of one shape are not blended
+    /// more than once. The edges of a filled path are instead combined with
+    /// the [`Primitive::Fill`] which precedes them.
+    pub shape: u32,
+    /// Rectangle outside which the primitive is not drawn, or `None` if it
+    /// is not clipped by a rectangle.
+    pub clip_rect: Option<ClipRect>,
+    /// Layer that the primitive is drawn in.
+    pub layer: LayerId,
+    /// Group opacity of the layer. This is set when the tiler is drained
+    /// (see [`crate::layer::Layer::opacity`]).
+    pub layer_opacity: f32,
+}
+impl StyledPrimitive {
+    /// Returns a bounding-polygon describing the primitive.
+    ///
+    /// The polygon accounts for the line width (including any taper) and
+    /// end-cap styles. When the style has a dash pattern, the caps of dashes
+    /// which end close to the ends of a segment can extend beyond it, so the
+    /// polygon also accounts for those. The polygon around an edge of a
+    /// filled path is expanded by [`FILL_EDGE_MARGIN`]. If the style has a
+    /// glow, the polygon is expanded by the radius of the glow.
+    ///
+    /// Fill tiles have no bounding polygon; this panics for them.
+    pub fn bounding_polygon(&self) -> Polygon {
+        self.with_glow(self.stroke_bounding_polygon())
+    }
+
+    /// Returns a bounding-polygon describing the primitive, without its
+    /// glow.
+    fn stroke_bounding_polygon(&self) -> Polygon {
+        assert!(
+            self.style.width.max(self.style.end_width.unwrap_or(0.0)) > 0.0
+        );
+        let w2 = self.style.width / 2.0;
+
+        match &self.primitive {
+            Primitive::Segment {
+                line,
+                start_cap,
+                end_cap,
+                start_width,
+                end_width,
+                ..
+            } => {
+                let v = line.ab_vec().normalize();
+                let t = V2::new(-v.y, v.x); // Rotate v by 90 degrees.
+                let (ws, we) = (start_width / 2.0, end_width / 2.0);
+
+                // Extensions of the ends of the line.
+                let dash_extension = |w| match self.style.dash {
+                    Some(_) => cap_extension(self.style.cap, w),
+                    None => 0.0,
+                };
+                let es = cap_extension(*start_cap, ws).max(dash_extension(ws));
+                let ee = cap_extension(*end_cap, we).max(dash_extension(we));
+
+                if ws == we {
+                    let wt = ws * t;
+                    Polygon::new(vec![
+                        line.start() - es * v - wt,
+                        line.start() - es * v + wt,
+                        line.end() + ee * v + wt,
+                        line.end() + ee * v - wt,
+                    ])
+                } else {
+                    // The ends of a tapered line, including their caps and
+                    // the caps of any dashes near them, lie within squares
+                    // around the ends. The caps of dashes in between lie
+                    // within the hull of these squares, since the width
+                    // changes linearly.
+                    let mut points = Vec::with_capacity(8);
+                    for (p, w, e) in
+                        [(line.start(), ws, es), (line.end(), we, ee)]
+                    {
+                        for (a, b) in [(-e, -w), (-e, w), (e, -w), (e, w)] {
+                            points.push(p + a * v + b * t);
+                        }
+                    }
+                    Polygon::convex_hull(&points)
+                }
+            }
+            Primitive::Polygon(vertices) => Polygon::new(vertices.clone()),
+            Primitive::Disc { center, width } => {
+                let r = width / 2.0;
+                Polygon::new(vec![
+                    center + V2::new(-r, -r),
+                    center + V2::new(r, -r),
+                    center + V2::new(r, r),
+                    center + V2::new(-r, r),
+                ])
+            }
+            Primitive::Quadratic {
+                curve,
+                start_cap,
+                end_cap,
+            } => oriented_box(
+                &curve.control_points(),
+                curve_expansion(w2, &[*start_cap, *end_cap]),
+            ),
+            Primitive::Cubic {
+                curve,
+                start_cap,
+                end_cap,
+            } => oriented_box(
+                &curve.control_points(),
+                curve_expansion(w2, &[*start_cap, *end_cap]),
+            ),
+            Primitive::Arc {
+                arc,
+                start_cap,
+                end_cap,
+            } => {
+                let e = curve_expansion(w2, &[*start_cap, *end_cap]);
+                if arc.sweep() < std::f32::consts::PI {
+                    oriented_box(&arc.control_points(), e)
+                } else {
+                    let r = arc.radius() + e;
+                    let c = arc.center();
+                    Polygon::new(vec![
+                        c + V2::new(-r, -r),
+                        c + V2::new(r, -r),
+                        c + V2::new(r, r),
+                        c + V2::new(-r, r),
+                    ])
+                }
+            }
+            Primitive::Ellipse(ellipse) => {
+                let (axis_x, axis_y) = ellipse.axes();
+                let u = (ellipse.radius_x() + w2) * axis_x;
+                let v = (ellipse.radius_y() + w2) * axis_y;
+                let c = ellipse.center();
+                Polygon::new(vec![c - u - v, c + u - v, c + u + v, c - u + v])
+            }
+            Primitive::Marker {
+                marker,
+                tip,
+                direction,
+                width,
+            } => crate::marker::bounding_polygon(
+                marker, *tip, *direction, *width,
+            ),
+            Primitive::Fill { .. } | Primitive::Clip { .. } => {
+                panic!(
+                    "fill and clip tiles are assigned to tiles directly, and \
+                     have no bounds"
+                )
+            }
+            Primitive::FillEdge(line) => {
+                oriented_box(&[line.start(), line.end()], FILL_EDGE_MARGIN)
+            }
+        }
+    }
+
+    /// Returns a set of polygons whose union bounds the primitive.
+    ///
+    /// For most primitives, this is just the bounding polygon (see
+    /// [`StyledPrimitive::bounding_polygon`]). However, the bounding polygon
+    /// of a long curve can be much larger than the curve itself. Curves and
+    /// arcs are instead split into pieces of no more than about
+    /// `piece_length` in length (up to a maximum of [`MAX_CURVE_PIECES`]
+    /// pieces), and each piece is bounded by a box around its control points.
+    /// Like the bounding polygon, each polygon is expanded by the radius of
+    /// any glow.
+    ///
+    /// # Parameters
+    ///
+    /// - `piece_length`: Approximate maximum length of curve pieces. This
+    ///   is usually the size of a tile.
+    pub fn bounding_polygons(&self, piece_length: f32) -> Vec<Polygon> {
+        self.stroke_bounding_polygons(piece_length)
+            .into_iter()
+            .map(|polygon| self.with_glow(polygon))
+            .collect()
+    }
+
+    /// Returns a set of polygons whose union bounds the primitive, without
+    /// its glow.
+    fn stroke_bounding_polygons(&self, piece_length: f32) -> Vec<Polygon> {
+        assert!(
+            self.style.width.max(self.style.end_width.unwrap_or(0.0)) > 0.0
+        );
+        assert!(piece_length > 0.0);
+        let w2 = self.style.width / 2.0;
+
+        match &self.primitive {
+            Primitive::Quadratic {
+                curve,
+                start_cap,
+                end_cap,
+            } => curve_pieces_bounds(
+                &curve.control_points(),
+                piece_length,
+                curve_expansion(w2, &[]),
+                curve_expansion(w2, &[*start_cap]),
+                curve_expansion(w2, &[*end_cap]),
+                |t0, t1| curve.sub_curve(t0, t1).control_points().to_vec(),
+            ),
+            Primitive::Cubic {
+                curve,
+                start_cap,
+                end_cap,
+            } => curve_pieces_bounds(
+                &curve.control_points(),
+                piece_length,
+                curve_expansion(w2, &[]),
+                curve_expansion(w2, &[*start_cap]),
+                curve_expansion(w2, &[*end_cap]),
+                |t0, t1| curve.sub_curve(t0, t1).control_points().to_vec(),
+            ),
+            Primitive::Arc {
+                arc,
+                start_cap,
+                end_cap,
+            } => {
+                // Pieces must span less than half a turn to have control
+                // points. A quarter turn keeps them reasonably tight.
+                let length = arc.radius() * arc.sweep();
+                let n_pieces = ((length / piece_length).ceil() as usize)
+                    .max((arc.sweep() / std::f32::consts::FRAC_PI_2).ceil()
+                        as usize)
+                    .clamp(1, MAX_CURVE_PIECES);
+                let (start_cap, end_cap) = if arc.is_circle() {
+                    (LineCap::Butt, LineCap::Butt)
+                } else {
+                    (*start_cap, *end_cap)
+                };
+                let pieces = arc.split(n_pieces);
+                let n = pieces.len();
+                pieces
+                    .iter()
+                    .enumerate()
+                    .map(|(i, piece)| {
+                        let mut e = curve_expansion(w2, &[]);
+                        if i == 0 {
+                            e = e.max(curve_expansion(w2, &[start_cap]));
+                        }
+                        if i == n - 1 {
+                            e = e.max(curve_expansion(w2, &[end_cap]));
+                        }
+                        oriented_box(&piece.control_points(), e)
+                    })
+                    .collect()
+            }
+            _ => vec![self.stroke_bounding_polygon()],
+        }
+    }
+
+    /// Expands a bounding polygon of the primitive by the radius of the
+    /// style's glow, if it has one.
+    fn with_glow(&self, polygon: Polygon) -> Polygon {
+        match self.style.glow {
+            Some(glow) => polygon.expanded(glow.radius),
+            None => polygon,
+        }
+    }
+}
+
+/// Returns the distance by which the box around a curve's control points
+/// must be expanded to contain the stroke.
+///
+/// A square cap extends half of the width along the tangent of the curve,
+/// which need not be aligned with the box, so its corners can be up to
+/// `sqrt(2)` times half of the width from the end of the curve.
+///
+/// # Parameters
+///
+/// - `half_width`: Half of the width of the line.
+/// - `caps`: Caps at any ends of the curve which the box contains.
+fn curve_expansion(half_width: f32, caps: &[LineCap]) -> f32 {
+    if caps.iter().any(|cap| matches!(cap, LineCap::Square)) {
+        std::f32::consts::SQRT_2 * half_width
+    } else {
+        half_width
+    }
+}
+
+/// Splits a curve into pieces, and returns a bounding box for each piece.
+///
+/// # Parameters
+///
+/// - `control_points`: Control points of the whole curve.
+/// - `piece_length`: Approximate maximum length of the pieces.
+/// - `expansion`: Expansion of the boxes of pieces in the middle.
+/// - `start_expansion`: Expansion of the box of the first piece.
+/// - `end_expansion`: Expansion of the box of the last piece.
+/// - `sub_curve`: Returns the control points of the part of the curve
+///   between two parameter values.
+fn curve_pieces_bounds(
+    control_points: &[P2],
+    piece_length: f32,
+    expansion: f32,
+    start_expansion: f32,
+    end_expansion: f32,
+    sub_curve: impl Fn(f32, f32) -> Vec<P2>,
+) -> Vec<Polygon> {
+    let length = control_polygon_length(control_points);
+    let n_pieces =
+        ((length / piece_length).ceil() as usize).clamp(1, MAX_CURVE_PIECES);
+    (0..n_pieces)
+        .map(|i| {
+            let t0 = i as f32 / n_pieces as f32;
+            let t1 = (i + 1) as f32 / n_pieces as f32;
+            let mut e = expansion;
+            if i == 0 {
+                e = e.max(start_expansion);
+            }
+            if i == n_pieces - 1 {
+                e = e.max(end_expansion);
+            }
+            oriented_box(&sub_curve(t0, t1), e)
+        })
+        .collect()
+}
+
+/// Returns a box around a set of points, expanded by a distance on all
+/// sides.
+///
+/// The box is aligned with the line from the first point to the last point,
+/// which makes it tight around the control points of a gently-curving
+/// piece of a curve.
+///
+/// # Parameters
+///
+/// - `points`: Points which the box must contain.
+/// - `expansion`: Distance by which to expand the box.
+fn oriented_box(points: &[P2], expansion: f32) -> Polygon {
+    let chord = points[points.len() - 1] - points[0];
+    let u = if chord.magnitude2() > 0.0 {
+        chord.normalize()
+    } else {
+        V2::new(1.0, 0.0)
+    };
+    let v = V2::new(-u.y, u.x);
+
+    let origin = points[0];
+    let (mut min_u, mut max_u, mut min_v, mut max_v) =
+        (0.0f32, 0.0f32, 0.0f32, 0.0f32);
+    for p in points {
+        let d = p - origin;
+        min_u = min_u.min(d.dot(u));
+        max_u = max_u.max(d.dot(u));
+        min_v = min_v.min(d.dot(v));
+        max_v = max_v.max(d.dot(v));
+    }
+    min_u -= expansion;
+    max_u += expansion;
+    min_v -= expansion;
+    max_v += expansion;
+
+    Polygon::new(vec![
+        origin + min_u * u + min_v * v,
+        origin + max_u * u + min_v * v,
+        origin + max_u * u + max_v * v,
+        origin + min_u * u + max_v * v,
+    ])
+}
+
+/// Returns the distance that a line cap extends beyond the end of a line.
+///
+/// # Parameters
+///
+/// - `cap`: The line cap.
+/// - `half_width`: Half of the width of the line.
+pub fn cap_extension(cap: LineCap, half_width: f32) -> f32 {
+    match cap {
+        LineCap::Butt => 0.0,
+        LineCap::Square => half_width,
+        LineCap::Round => half_width,
+    }
+}
@@ -1,17 +1,77 @@
 use crate::{
-    buffers::Buffers,
-    style::{LineStyle, StyledLine},
+    arc::CircularArc,
+    bezier::{CubicBezier, QuadraticBezier},
+    binning::{compare_tiles, Binner, TilingBackend, TilingMismatch},
+    bloom::{Bloom, BloomTarget},
+    buffers::{self, Buffers},
+    ellipse::Ellipse,
+    image::Image,
+    layer::{Layer, LayerId},
+    offscreen::OffscreenTarget,
+    path::Path,
+    persistence::{Accumulator, Phosphor, DEFAULT_FRAME_TIME},
+    polyline::Polyline,
+    scene::Scene,
+    style::{FillRule, FillStyle, LineStyle, StyledLine},
     tiler::Tiler,
-    Color, Line,
+    transform::Camera,
+    Color, Line, M3, P2,
 };
 
+/// Initial number of tiles that the tile info buffers can hold.
+const DEFAULT_TILE_INFO_CAPACITY: u32 = 1024;
+
+/// Initial number of lines that the line array buffers can hold.
+const DEFAULT_LINES_BUFFER_CAPACITY: u32 = 1024;
+
+/// Default width of the antialiasing smoothstep.
+pub(crate) const DEFAULT_ANTIALIAS_WIDTH: f32 = 1.55;
+
+/// Default background color of tiles, when tiles are drawn for debugging.
+pub(crate) const DEFAULT_TILE_BACKGROUND: Color = Color {
+    red: 0.2,
+    green: 0.2,
+    blue: 0.3,
+    alpha: 0.7,
+};
+
+/// Default edge color of tiles, when tiles are drawn for debugging.
+pub(crate) const DEFAULT_TILE_EDGES: Color = Color {
+    red: 1.0,
+    green: 1.0,
+    blue: 1.0,
+    alpha: 0.7,
+};
+
+/// Format of the textures which hold HDR colors: the accumulation texture of
+/// phosphor persistence, and the scene and levels of bloom.
+///
+/// Colors in these textures may be brighter than one. Also, slowly-decaying
+/// channels of the accumulation texture are multiplied by factors close to
+/// one every frame. In an 8-bit texture, small values would round back to
+/// themselves, leaving trails which never fade.
+pub(crate) const HDR_FORMAT: wgpu::TextureFormat =
+    wgpu::TextureFormat::Rgba16Float;
+
 #[derive(Debug)]
 pub struct Renderer {
+    texture_format: wgpu::TextureFormat,
     area_width: u32,
     area_height: u32,
     tile_width: u32,
     tile_height: u32,
     tiler: Tiler,
+    /// Backend which assigns primitives to tiles in [`Renderer::render`].
+    tiling_backend: TilingBackend,
+    /// GPU binner, used by the GPU and verifying tiling backends. This is
+    /// created when it is first needed.
+    binner: Option<Binner>,
+    /// Buffers which GPU binning writes to when it is verified. These are
+    /// created when they are first needed.
+    verify_buffers: Option<Buffers>,
+    /// Tiles which differed between CPU tiling and GPU binning in the last
+    /// frame that was verified.
+    tiling_mismatches: Vec<TilingMismatch>,
     antialias_width: f32,
     draw_tiles: bool,
     tile_background: Color,
@@ -20,10 +80,36 @@ pub struct Renderer {
     viewport_layout: wgpu::BindGroupLayout,
     tile_layout: wgpu::BindGroupLayout,
     buffers: Buffers,
+    /// Buffers used by [`Renderer::render_scene`]. These are created when
+    /// they are first needed.
+    scene_buffers: Option<Buffers>,
+    /// Identifier and revision of the scene whose shapes and tiles were last
+    /// uploaded to the scene buffers.
+    scene_upload: Option<(u64, u64)>,
+    /// Target used by [`Renderer::render_offscreen`]. This is created when
+    /// it is first needed.
+    offscreen: Option<OffscreenTarget>,
+    /// Phosphor used to decay lines from earlier frames, if persistence is
+    /// enabled.
+    persistence: Option<Phosphor>,
+    /// Time between frames, in seconds.
+    frame_time: f32,
+    /// Accumulation texture used when persistence is enabled. This is
+    /// created when it is first needed.
+    accumulator: Option<Accumulator>,
+    /// Bloom post-process, if it is enabled.
+    bloom: Option<Bloom>,
+    /// Textures of the bloom post-process. This is created when it is first
+    /// needed.
+    bloom_target: Option<BloomTarget>,
+    /// Pipeline which draws lines into textures of [`HDR_FORMAT`]. This is
+    /// created when it is first needed.
+    hdr_pipeline: Option<wgpu::RenderPipeline>,
 }
 
 impl Renderer {
-    /// Creates a new `Renderer`.
+    /// Creates a new `Renderer`, which assigns primitives to tiles on the
+    /// CPU.
     ///
     /// # Parameters
     ///
@@ -40,20 +126,56 @@ impl Renderer {
         area_height: u32,
         tile_width: u32,
         tile_height: u32,
+    ) -> Self {
+        Self::with_tiling_backend(
+            device,
+            texture_format,
+            area_width,
+            area_height,
+            tile_width,
+            tile_height,
+            TilingBackend::Cpu,
+        )
+    }
+
+    /// Creates a new `Renderer`, with a backend which assigns primitives to
+    /// tiles.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device for rendering.
+    /// - `texture_format`: WGPU texture format.
+    /// - `area_width`: Width of the renderable area.
+    /// - `area_height`: Height of the renderable area.
+    /// - `tile_width`: Width of a single bucketing tile.
+    /// - `tile_height`: Height of a single bucketing tile.
+    /// - `tiling_backend`: Backend which assigns primitives to tiles (see
+    ///   [`Renderer::set_tiling_backend`]).
+    pub fn with_tiling_backend(
+        device: &wgpu::Device,
+        texture_format: wgpu::TextureFormat,
+        area_width: u32,
+        area_height: u32,
+        tile_width: u32,
+        tile_height: u32,
+        tiling_backend: TilingBackend,
     ) -> Self {
         assert!(area_width > 0);
         assert!(area_height > 0);
         assert!(tile_width > 0);
         assert!(tile_height > 0);
 
-        const DEFAULT_TILE_INFO_CAPACITY: u32 = 1024;
-        const DEFAULT_LINES_BUFFER_CAPACITY: u32 = 1024;
-
-        let tiler = Tiler::new(area_width, area_height, tile_width, tile_height);
+        let mut tiler =
+            Tiler::new(area_width, area_height, tile_width, tile_height);
+        tiler.set_defer_binning(tiling_backend != TilingBackend::Cpu);
         let viewport_layout = create_viewport_layout(device);
         let tile_layout = create_tile_layout(device);
-        let render_pipeline =
-            create_render_pipeline(device, texture_format, &viewport_layout, &tile_layout);
+        let render_pipeline = create_render_pipeline(
+            device,
+            texture_format,
+            &viewport_layout,
+            &tile_layout,
+        );
         let buffers = Buffers::new(
             device,
             DEFAULT_TILE_INFO_CAPACITY,
@@ -61,19 +183,33 @@ impl Renderer {
         );
 
         Renderer {
+            texture_format,
             area_width,
             area_height,
             tile_width,
             tile_height,
             tiler,
-            antialias_width: 1.55,
+            tiling_backend,
+            binner: None,
+            verify_buffers: None,
+            tiling_mismatches: Vec::new(),
+            antialias_width: DEFAULT_ANTIALIAS_WIDTH,
             draw_tiles: false,
-            tile_background: Color::new(0.2, 0.2, 0.3, 0.7),
-            tile_edges: Color::new(1.0, 1.0, 1.0, 0.7),
+            tile_background: DEFAULT_TILE_BACKGROUND,
+            tile_edges: DEFAULT_TILE_EDGES,
             render_pipeline,
             viewport_layout,
             tile_layout,
             buffers,
+            scene_buffers: None,
+            scene_upload: None,
+            offscreen: None,
+            persistence: None,
+            frame_time: DEFAULT_FRAME_TIME,
+            accumulator: None,
+            bloom: None,
+            bloom_target: None,
+            hdr_pipeline: None,
         }
     }
 
@@ -93,11 +229,222 @@ impl Renderer {
         })
     }
 
+    /// Adds a polyline to be rendered.
+    ///
+    /// The polyline is rendered as a single shape, so overlapping parts of
+    /// the polyline are not blended more than once. Its segments are
+    /// connected using the join of the style.
+    ///
+    /// # Parameters
+    ///
+    /// - `polyline`: Polyline to render.
+    /// - `style`: Style of the polyline to render.
+    pub fn polyline(&mut self, polyline: &Polyline, style: &LineStyle) {
+        self.tiler.add_polyline(polyline, style)
+    }
+
+    /// Adds a quadratic Bézier curve to be rendered.
+    ///
+    /// The curve is rendered directly, without being flattened into line
+    /// segments. The caps or markers of the style are used at the ends of
+    /// the curve. Dash patterns are not applied to curves.
+    ///
+    /// # Parameters
+    ///
+    /// - `curve`: Curve to render.
+    /// - `style`: Style of the curve to render.
+    pub fn quadratic(&mut self, curve: QuadraticBezier, style: &LineStyle) {
+        self.tiler.add_quadratic(curve, style)
+    }
+
+    /// Adds a cubic Bézier curve to be rendered.
+    ///
+    /// See [`Renderer::quadratic`].
+    ///
+    /// # Parameters
+    ///
+    /// - `curve`: Curve to render.
+    /// - `style`: Style of the curve to render.
+    pub fn cubic(&mut self, curve: CubicBezier, style: &LineStyle) {
+        self.tiler.add_cubic(curve, style)
+    }
+
+    /// Adds a circular arc (or a whole circle) to be rendered.
+    ///
+    /// The arc is rendered directly, without being flattened into line
+    /// segments. The caps or markers of the style are used at the ends of
+    /// the arc. Dash patterns are not applied to arcs.
+    ///
+    /// # Parameters
+    ///
+    /// - `arc`: Arc to render.
+    /// - `style`: Style of the arc to render.
+    pub fn arc(&mut self, arc: CircularArc, style: &LineStyle) {
+        self.tiler.add_arc(arc, style)
+    }
+
+    /// Adds the outline of an ellipse to be rendered.
+    ///
+    /// Dash patterns are not applied to ellipses.
+    ///
+    /// # Parameters
+    ///
+    /// - `ellipse`: Ellipse to render.
+    /// - `style`: Style of the ellipse to render.
+    pub fn ellipse(&mut self, ellipse: Ellipse, style: &LineStyle) {
+        self.tiler.add_ellipse(ellipse, style)
+    }
+
+    /// Adds a filled path to be rendered.
+    ///
+    /// Only tiles near the edges of the path compute coverage per pixel;
+    /// tiles entirely inside the path are filled solidly. Edges are
+    /// antialiased in the same way as lines. To fill a polygon, use
+    /// [`Path::polygon`].
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: Path to fill.
+    /// - `style`: Style of the fill.
+    pub fn fill(&mut self, path: &Path, style: &FillStyle) {
+        self.tiler.add_fill(path, style)
+    }
+
+    /// Pushes a transform, which applies to the shapes added until it is
+    /// popped.
+    ///
+    /// The transform is applied to shapes before any transforms which were
+    /// pushed earlier, and before the view of the camera. Widths of lines
+    /// are scaled by the transform, unless their style is
+    /// [`crate::StrokeScaling::NonScaling`]. The transform is kept from frame
+    /// to frame.
+    ///
+    /// # Parameters
+    ///
+    /// - `matrix`: Affine transform to push. Its last row must be
+    ///   `(0, 0, 1)`, and it must be invertible.
+    pub fn push_transform(&mut self, matrix: M3) {
+        self.tiler.push_transform(matrix);
+    }
+
+    /// Pops the transform which was pushed last.
+    ///
+    /// There must be a transform to pop.
+    pub fn pop_transform(&mut self) {
+        self.tiler.pop_transform();
+    }
+
+    /// Sets the camera, which pans, zooms and rotates the view of all
+    /// shapes, after the transforms on the stack.
+    ///
+    /// # Parameters
+    ///
+    /// - `camera`: Camera, or `None` if shapes are in pixel coordinates.
+    pub fn set_camera(&mut self, camera: Option<Camera>) {
+        self.tiler.set_camera(camera);
+    }
+
+    /// Returns the transform which currently maps the coordinates of shapes
+    /// to pixels.
+    ///
+    /// Its inverse maps pixel positions back to the coordinates of shapes.
+    pub fn transform(&self) -> M3 {
+        self.tiler.transform()
+    }
+
+    /// Pushes a clip rectangle, so that the shapes added until it is popped
+    /// are only drawn inside it.
+    ///
+    /// The rectangle is in the coordinates of shapes, and is mapped through
+    /// the current transform when it is pushed. Nested clips intersect. The
+    /// clip is kept from frame to frame.
+    ///
+    /// # Parameters
+    ///
+    /// - `a`: A corner of the rectangle.
+    /// - `b`: The opposite corner of the rectangle.
+    pub fn push_clip_rect(&mut self, a: P2, b: P2) {
+        self.tiler.push_clip_rect(a, b);
+    }
+
+    /// Pushes a clip path, so that the shapes added until it is popped are
+    /// only drawn inside it.
+    ///
+    /// The path is in the coordinates of shapes, and is mapped through the
+    /// current transform when it is pushed. Shapes are antialiased at its
+    /// edges. Nested clips intersect. The clip is kept from frame to frame.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: Path to clip to. Its subpaths are implicitly closed.
+    /// - `rule`: Rule which decides which points are inside the path.
+    pub fn push_clip_path(&mut self, path: &Path, rule: FillRule) {
+        self.tiler.push_clip_path(path, rule);
+    }
+
+    /// Pops the clip which was pushed last.
+    ///
+    /// There must be a clip to pop.
+    pub fn pop_clip(&mut self) {
+        self.tiler.pop_clip();
+    }
+
+    /// Creates a new layer, which is visible and opaque.
+    ///
+    /// Within each tile, shapes in layers with higher z indices are drawn
+    /// on top, whatever order they are added in. This means that different
+    /// parts of an application can add shapes to their own layers in any
+    /// order. Layers are kept from frame to frame.
+    ///
+    /// # Parameters
+    ///
+    /// - `name`: Name of the layer, which must not be the name of an
+    ///   existing layer.
+    /// - `z`: Z index of the layer. The default layer has a z index of
+    ///   zero.
+    ///
+    /// # Returns
+    ///
+    /// The identifier of the new layer.
+    pub fn add_layer(&mut self, name: &str, z: i32) -> LayerId {
+        self.tiler.add_layer(name, z)
+    }
+
+    /// Returns the identifier of the layer with a name, or `None` if there
+    /// is no such layer.
+    pub fn layer_id(&self, name: &str) -> Option<LayerId> {
+        self.tiler.layer_id(name)
+    }
+
+    /// Returns a layer.
+    pub fn layer(&self, id: LayerId) -> &Layer {
+        self.tiler.layer(id)
+    }
+
+    /// Returns a layer, to change its z index, visibility or group opacity.
+    ///
+    /// Changes apply to the shapes of the layer which are drawn by the next
+    /// render, including shapes which were added before the change.
+    pub fn layer_mut(&mut self, id: LayerId) -> &mut Layer {
+        self.tiler.layer_mut(id)
+    }
+
+    /// Selects the layer which shapes are added to, until another layer is
+    /// selected. The selected layer is kept from frame to frame.
+    ///
+    /// # Parameters
+    ///
+    /// - `id`: Identifier of the layer. Use [`LayerId::DEFAULT`] to return
+    ///   to the default layer.
+    pub fn set_layer(&mut self, id: LayerId) {
+        self.tiler.set_layer(id);
+    }
+
     /// Resizes the renderer.
     ///
     /// When the screen is re-sized, this method must be called. This resets
     /// the renderer, removing any lines that might have been queued for
-    /// rendering.
+    /// rendering, and any afterglow of earlier frames.
     ///
     /// # Parameters
     ///
@@ -110,10 +457,22 @@ impl Renderer {
         self.tiler.resize(area_width, area_height);
         self.area_height = area_height;
         self.area_width = area_width;
+        self.clear_persistence();
     }
 
     /// Render the current set of lines, by adding them to the render queue.
     ///
+    /// The lines are blended over the existing contents of the output
+    /// texture, which is treated as having premultiplied alpha. Surfaces
+    /// which are composited with other content should be configured with
+    /// [`wgpu::CompositeAlphaMode::PreMultiplied`]. When persistence is
+    /// enabled (see [`Renderer::set_persistence`]), the accumulation texture
+    /// is decayed, the lines are drawn into it instead, and it is then
+    /// blended over the output texture. When bloom is enabled (see
+    /// [`Renderer::set_bloom`]), the lines (or the accumulation texture) are
+    /// drawn into an HDR scene, whose bloom is added before it is tone-mapped
+    /// and blended over the output texture.
+    ///
     /// # Parameters
     ///
     /// - `device`: WGPU Device to use.
@@ -127,8 +486,226 @@ impl Renderer {
         queue: &wgpu::Queue,
         output_texture: &wgpu::TextureView,
     ) {
+        let n_instances = match self.tiling_backend {
+            TilingBackend::Cpu => {
+                // Fetch tile info, styled lines and line indices from the
+                // tiler.
+                let (tile_infos, styled_lines, line_indices) =
+                    self.tiler.drain();
+                let n_instances = tile_infos.len() as u32;
+                self.buffers.write_tile_info(device, queue, tile_infos);
+                self.buffers.write_line_array(device, queue, styled_lines);
+                self.buffers.write_line_indices(device, queue, line_indices);
+                Some(n_instances)
+            }
+            TilingBackend::Gpu => {
+                // Bin the records from the tiler straight into the buffers.
+                // The number of tiles is only known on the GPU.
+                let (primitives, records) = self.tiler.take_bin_records();
+                let mut binner = self.take_binner(device);
+                binner.encode(
+                    device,
+                    queue,
+                    encoder,
+                    primitives,
+                    records,
+                    &mut self.buffers,
+                );
+                self.binner = Some(binner);
+                None
+            }
+            TilingBackend::Verify => {
+                // Bin the records on the GPU into separate buffers, and
+                // compare them with the tiles that the CPU backend gives,
+                // which are drawn.
+                let ((primitives, records), tiles) =
+                    self.tiler.take_bin_records_and_tiles();
+                let (tile_infos, styled_lines, line_indices) = tiles;
+                let mut binner = self.take_binner(device);
+                let verify_buffers =
+                    self.verify_buffers.get_or_insert_with(|| {
+                        Buffers::new(
+                            device,
+                            DEFAULT_TILE_INFO_CAPACITY,
+                            DEFAULT_LINES_BUFFER_CAPACITY,
+                        )
+                    });
+                let mut verify_encoder = device.create_command_encoder(
+                    &wgpu::CommandEncoderDescriptor {
+                        label: Some(
+                            "Beamline: Tiling verification command encoder",
+                        ),
+                    },
+                );
+                binner.encode(
+                    device,
+                    queue,
+                    &mut verify_encoder,
+                    primitives,
+                    records,
+                    verify_buffers,
+                );
+                queue.submit(std::iter::once(verify_encoder.finish()));
+                let gpu = binner.read_back(device, queue, verify_buffers);
+                self.binner = Some(binner);
+
+                let cpu_tile_infos: Vec<buffers::TileInfo> = tile_infos
+                    .iter()
+                    .cloned()
+                    .map(buffers::TileInfo::new_from_tiler_tileinfo)
+                    .collect();
+                let cpu_lines: Vec<buffers::StyledLine> = styled_lines
+                    .iter()
+                    .cloned()
+                    .map(buffers::StyledLine::new_from_styled_primitive)
+                    .collect();
+                self.tiling_mismatches = compare_tiles(
+                    (&cpu_tile_infos, &cpu_lines, &line_indices),
+                    (&gpu.0, &gpu.1, &gpu.2),
+                );
+
+                let n_instances = tile_infos.len() as u32;
+                self.buffers.write_tile_info(device, queue, tile_infos);
+                self.buffers.write_line_array(device, queue, styled_lines);
+                self.buffers.write_line_indices(device, queue, line_indices);
+                Some(n_instances)
+            }
+        };
+
+        self.draw(device, encoder, queue, output_texture, n_instances, false);
+    }
+
+    /// Takes the GPU binner, re-creating it if it has not been created yet,
+    /// or if the renderable area has changed size.
+    fn take_binner(&mut self, device: &wgpu::Device) -> Binner {
+        let size = (
+            self.area_width,
+            self.area_height,
+            self.tile_width,
+            self.tile_height,
+        );
+        match self.binner.take() {
+            Some(binner) if binner.has_size(size.0, size.1, size.2, size.3) => {
+                binner
+            }
+            _ => Binner::new(device, size.0, size.1, size.2, size.3),
+        }
+    }
+
+    /// Render a retained-mode scene, by adding it to the render queue.
+    ///
+    /// This renders the shapes of the scene in the same way as
+    /// [`Renderer::render`] renders the shapes which were added to the
+    /// renderer. Only the shapes and tiles of the scene which have changed
+    /// since it was last rendered are uploaded to the GPU. The scene's
+    /// buffers are kept separately from the renderer's own, so
+    /// immediate-mode frames may be interleaved with renders of the scene.
+    /// With persistence enabled, a frame which renders the scene must not
+    /// also call [`Renderer::render`] (see [`Renderer::set_frame_time`]).
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device to use.
+    /// - `encoder`: Command encoder to which commands should be submitted.
+    /// - `queue`: WGPU Queue to use.
+    /// - `output_texture`: Texture view to write the output.
+    /// - `scene`: Scene to render. Its area and tile sizes must match the
+    ///   renderer's.
+    pub fn render_scene(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        output_texture: &wgpu::TextureView,
+        scene: &mut Scene,
+    ) {
+        assert!(
+            scene.has_size(
+                self.area_width,
+                self.area_height,
+                self.tile_width,
+                self.tile_height
+            ),
+            "scene size does not match the renderer"
+        );
+        scene.update_tiles();
+
+        // Upload the shapes and tiles which have changed since the scene was
+        // last uploaded, or all of them if the buffers no longer mirror the
+        // scene's line array and line index array.
+        let buffers = self.scene_buffers.get_or_insert_with(|| {
+            Buffers::new(
+                device,
+                DEFAULT_TILE_INFO_CAPACITY,
+                DEFAULT_LINES_BUFFER_CAPACITY,
+            )
+        });
+        let reallocated = buffers.reserve_lines(device, scene.line_array_len())
+            | buffers.reserve_line_indices(device, scene.line_index_len());
+        let since = match self.scene_upload {
+            Some((id, revision))
+                if !reallocated
+                    && id == scene.id()
+                    && revision >= scene.layout_revision() =>
+            {
+                Some(revision)
+            }
+            _ => None,
+        };
+        for index in scene.changed_shapes(since) {
+            let mut styled_lines = Vec::new();
+            let start = scene.shape_lines(index, &mut styled_lines);
+            buffers.write_lines_at(queue, start, styled_lines);
+        }
+        for tile_ix in scene.changed_tiles(since) {
+            let mut line_indices = Vec::new();
+            let start = scene.tile_line_indices(tile_ix, &mut line_indices);
+            buffers.write_line_indices_at(queue, start, line_indices);
+        }
+        self.scene_upload = Some((scene.id(), scene.revision()));
+
+        let tile_infos = scene.tile_infos();
+        let n_instances = tile_infos.len() as u32;
+        buffers.write_tile_info(device, queue, tile_infos);
+
+        self.draw(
+            device,
+            encoder,
+            queue,
+            output_texture,
+            Some(n_instances),
+            true,
+        );
+    }
+
+    /// Draw the lines in a set of buffers, with any post-processing.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device to use.
+    /// - `encoder`: Command encoder to which commands should be submitted.
+    /// - `queue`: WGPU Queue to use.
+    /// - `output_texture`: Texture view to write the output.
+    /// - `n_instances`: Number of tiles in the tile info buffer, or `None` if
+    ///   it is in the draw arguments of the GPU binner.
+    /// - `scene`: `true` to draw the lines of the scene buffers, or `false`
+    ///   to draw the lines of the renderer's own buffers.
+    fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        output_texture: &wgpu::TextureView,
+        n_instances: Option<u32>,
+        scene: bool,
+    ) {
+        let buffers = match &self.scene_buffers {
+            Some(scene_buffers) if scene => scene_buffers,
+            _ => &self.buffers,
+        };
+
         // Set up the current viewport.
-        self.buffers.write_viewport_buffer(
+        buffers.write_viewport_buffer(
             queue,
             self.area_width,
             self.area_height,
@@ -136,7 +713,7 @@ impl Renderer {
             self.tile_height,
         );
         // Set up the shader options.
-        self.buffers.write_shader_options(
+        buffers.write_shader_options(
             queue,
             self.antialias_width,
             self.draw_tiles,
@@ -145,45 +722,125 @@ impl Renderer {
         );
 
         // Set up viewport bind group.
-        let viewport_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Beamline: Viewport bind group."),
-            layout: &self.viewport_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: self.buffers.viewport_buffer().as_entire_binding(),
-            }],
-        });
-
-        // Fetch tile info and styled lines from the tiler.
-        let (tile_infos, styled_lines) = self.tiler.drain();
-        let n_instances = tile_infos.len() as u32;
-        self.buffers.write_tile_info(device, queue, tile_infos);
-        self.buffers.write_line_array(device, queue, styled_lines);
+        let viewport_bind_group =
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Beamline: Viewport bind group."),
+                layout: &self.viewport_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffers.viewport_buffer().as_entire_binding(),
+                }],
+            });
 
         // Set up the tile bind group.
-        let tile_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Beamline: Tile bind group."),
-            layout: &self.tile_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: self.buffers.shader_options_buffer().as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: self.buffers.tile_info_buffer().as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: self.buffers.lines_buffer().as_entire_binding(),
-                },
-            ],
-        });
+        let tile_bind_group =
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Beamline: Tile bind group."),
+                layout: &self.tile_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffers
+                            .shader_options_buffer()
+                            .as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: buffers
+                            .tile_info_buffer()
+                            .as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: buffers.lines_buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: buffers
+                            .line_indices_buffer()
+                            .as_entire_binding(),
+                    },
+                ],
+            });
+
+        // Decay the afterglow of earlier frames, re-creating the accumulation
+        // texture if persistence has just been enabled, or the renderable
+        // area has changed size.
+        if let Some(phosphor) = self.persistence {
+            let mut accumulator = match self.accumulator.take() {
+                Some(accumulator)
+                    if accumulator.width() == self.area_width
+                        && accumulator.height() == self.area_height =>
+                {
+                    accumulator
+                }
+                _ => Accumulator::new(
+                    device,
+                    self.area_width,
+                    self.area_height,
+                    self.texture_format,
+                ),
+            };
+            accumulator.decay(encoder, phosphor.decay(self.frame_time));
+            self.accumulator = Some(accumulator);
+        }
+
+        // Clear the bloom scene, re-creating the bloom textures if bloom has
+        // just been enabled, or their size has changed. With persistence,
+        // the accumulation texture is used as the scene instead.
+        if let Some(bloom) = self.bloom {
+            let bloom_target = match self.bloom_target.take() {
+                Some(bloom_target)
+                    if bloom_target.matches(
+                        self.area_width,
+                        self.area_height,
+                        bloom.levels,
+                    ) =>
+                {
+                    bloom_target
+                }
+                _ => BloomTarget::new(
+                    device,
+                    self.area_width,
+                    self.area_height,
+                    bloom.levels,
+                    self.texture_format,
+                ),
+            };
+            if self.accumulator.is_none() {
+                bloom_target.clear_scene(encoder);
+            }
+            self.bloom_target = Some(bloom_target);
+        }
+
+        // Lines are drawn into an HDR texture if there is any
+        // post-processing.
+        if self.hdr_pipeline.is_none()
+            && (self.accumulator.is_some() || self.bloom.is_some())
+        {
+            self.hdr_pipeline = Some(create_render_pipeline(
+                device,
+                HDR_FORMAT,
+                &self.viewport_layout,
+                &self.tile_layout,
+            ));
+        }
+        let (line_texture, line_pipeline) =
+            match (&self.accumulator, &self.bloom_target) {
+                (Some(accumulator), _) => {
+                    (accumulator.view(), self.hdr_pipeline.as_ref().unwrap())
+                }
+                (None, Some(bloom_target)) => (
+                    bloom_target.scene_view(),
+                    self.hdr_pipeline.as_ref().unwrap(),
+                ),
+                (None, None) => (output_texture, &self.render_pipeline),
+            };
 
         // Create the render pass.
         {
             let color_attachment = wgpu::RenderPassColorAttachment {
-                view: &output_texture,
+                view: line_texture,
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Load,
@@ -191,19 +848,155 @@ impl Renderer {
                 },
             };
 
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Beamline: Line render pass"),
-                color_attachments: &[Some(color_attachment)],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
+            let mut render_pass =
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Beamline: Line render pass"),
+                    color_attachments: &[Some(color_attachment)],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
 
-            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_pipeline(line_pipeline);
             render_pass.set_bind_group(0, &viewport_bind_group, &[]);
             render_pass.set_bind_group(1, &tile_bind_group, &[]);
-            render_pass.draw(0..6, 0..n_instances);
+            match (n_instances, &self.binner) {
+                (Some(n_instances), _) => {
+                    render_pass.draw(0..6, 0..n_instances)
+                }
+                (None, Some(binner)) => {
+                    render_pass.draw_indirect(binner.draw_args(), 0)
+                }
+                (None, None) => {
+                    unreachable!("tiles were binned without a binner")
+                }
+            }
         }
+
+        // Composite the scene over the output.
+        match (&self.bloom, &self.bloom_target) {
+            (Some(bloom), Some(bloom_target)) => {
+                let scene = match &self.accumulator {
+                    Some(accumulator) => accumulator.view(),
+                    None => bloom_target.scene_view(),
+                };
+                bloom_target.apply(
+                    device,
+                    encoder,
+                    queue,
+                    bloom,
+                    scene,
+                    output_texture,
+                );
+            }
+            _ => {
+                if let Some(accumulator) = &self.accumulator {
+                    accumulator.composite(encoder, output_texture);
+                }
+            }
+        }
+    }
+
+    /// Render the current set of lines into an offscreen texture, and read
+    /// the result back to the CPU.
+    ///
+    /// The renderer owns an [`OffscreenTarget`] with the size of the
+    /// renderable area and the renderer's texture format. It is cleared to
+    /// transparent black before the lines are drawn. The call blocks until
+    /// the GPU has finished rendering, so it is intended for native
+    /// platforms (eg. producing snapshots in tests).
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device to use.
+    /// - `queue`: WGPU Queue to use.
+    ///
+    /// # Returns
+    ///
+    /// The rendered RGBA8 image.
+    pub fn render_offscreen(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Image {
+        self.offscreen(device, queue, |renderer, encoder, view| {
+            renderer.render(device, encoder, queue, view)
+        })
+    }
+
+    /// Render a retained-mode scene into an offscreen texture, and read the
+    /// result back to the CPU.
+    ///
+    /// This is the same as [`Renderer::render_offscreen`], except that it
+    /// renders the scene (see [`Renderer::render_scene`]).
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device to use.
+    /// - `queue`: WGPU Queue to use.
+    /// - `scene`: Scene to render.
+    ///
+    /// # Returns
+    ///
+    /// The rendered RGBA8 image.
+    pub fn render_scene_offscreen(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &mut Scene,
+    ) -> Image {
+        self.offscreen(device, queue, |renderer, encoder, view| {
+            renderer.render_scene(device, encoder, queue, view, scene)
+        })
+    }
+
+    /// Render into the offscreen target, and read the result back to the
+    /// CPU.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: WGPU Device to use.
+    /// - `queue`: WGPU Queue to use.
+    /// - `render`: Called to render into the view of the target, after it
+    ///   has been cleared.
+    fn offscreen(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        render: impl FnOnce(
+            &mut Self,
+            &mut wgpu::CommandEncoder,
+            &wgpu::TextureView,
+        ),
+    ) -> Image {
+        // Re-create the target if the renderable area has changed size.
+        let target = match self.offscreen.take() {
+            Some(target)
+                if target.width() == self.area_width
+                    && target.height() == self.area_height =>
+            {
+                target
+            }
+            _ => OffscreenTarget::new(
+                device,
+                self.area_width,
+                self.area_height,
+                self.texture_format,
+            ),
+        };
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Beamline: Offscreen command encoder"),
+            });
+        target.clear(&mut encoder);
+        render(self, &mut encoder, target.view());
+        target.copy_to_readback(&mut encoder);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let image = target.read_image(device);
+        self.offscreen = Some(target);
+        image
     }
 
     /// Set whether debugging tiles should be draw.
@@ -215,6 +1008,105 @@ impl Renderer {
     pub fn set_draw_tiles(&mut self, value: bool) {
         self.draw_tiles = value;
     }
+
+    /// Set the backend which assigns primitives to tiles.
+    ///
+    /// The backend can be changed at any time. Primitives which have already
+    /// been added are tiled correctly by whichever backend renders them.
+    /// Changing to the CPU backend releases the buffers of GPU binning.
+    ///
+    /// # Parameters
+    ///
+    /// - `backend`: Backend to use for the following frames.
+    pub fn set_tiling_backend(&mut self, backend: TilingBackend) {
+        self.tiling_backend = backend;
+        self.tiler.set_defer_binning(backend != TilingBackend::Cpu);
+        if backend == TilingBackend::Cpu {
+            self.binner = None;
+            self.verify_buffers = None;
+        }
+    }
+
+    /// Returns the backend which assigns primitives to tiles.
+    pub fn tiling_backend(&self) -> TilingBackend {
+        self.tiling_backend
+    }
+
+    /// Returns the tiles whose primitives differed between CPU tiling and
+    /// GPU binning, in the last frame rendered with
+    /// [`TilingBackend::Verify`].
+    ///
+    /// Each frame that is verified replaces the mismatches of the frame
+    /// before it. The tiles are in row order.
+    pub fn tiling_mismatches(&self) -> &[TilingMismatch] {
+        &self.tiling_mismatches
+    }
+
+    /// Set the phosphor persistence of lines.
+    ///
+    /// With persistence enabled, lines remain visible after the frame in
+    /// which they are drawn, fading according to the phosphor's half-lives.
+    /// The renderer keeps an accumulation texture between calls to
+    /// [`Renderer::render`], so every frame must be rendered to a texture of
+    /// the same size as the renderable area. The accumulation texture is
+    /// decayed and blended over the output in every call to
+    /// [`Renderer::render`] or [`Renderer::render_scene`], so each frame must
+    /// be drawn by exactly one of them. Disabling persistence releases the
+    /// accumulation texture, removing any afterglow.
+    ///
+    /// # Parameters
+    ///
+    /// - `phosphor`: Phosphor to emulate, or `None` to disable persistence.
+    ///   Presets are available as [`Phosphor::P1`] and [`Phosphor::P7`].
+    pub fn set_persistence(&mut self, phosphor: Option<Phosphor>) {
+        self.persistence = phosphor;
+        if phosphor.is_none() {
+            self.accumulator = None;
+        }
+    }
+
+    /// Set the time between frames, which determines how much the afterglow
+    /// of earlier frames decays in each call to [`Renderer::render`] or
+    /// [`Renderer::render_scene`].
+    ///
+    /// The afterglow decays by the whole frame time in every such call, so
+    /// a frame which calls both would decay it twice. With persistence
+    /// enabled, each frame must be drawn by exactly one call.
+    ///
+    /// This should be set before every frame if the frame rate varies. It
+    /// defaults to 1/60th of a second.
+    ///
+    /// # Parameters
+    ///
+    /// - `seconds`: Time since the previous frame, in seconds.
+    pub fn set_frame_time(&mut self, seconds: f32) {
+        assert!(seconds >= 0.0);
+        self.frame_time = seconds;
+    }
+
+    /// Set the bloom post-process.
+    ///
+    /// With bloom enabled, lines are drawn into an HDR texture, so colors
+    /// brighter than one are not clamped until the bloom has been added and
+    /// the result has been tone-mapped. Disabling bloom releases its
+    /// textures.
+    ///
+    /// # Parameters
+    ///
+    /// - `bloom`: Bloom to apply, or `None` to disable bloom.
+    pub fn set_bloom(&mut self, bloom: Option<Bloom>) {
+        self.bloom = bloom;
+        if bloom.is_none() {
+            self.bloom_target = None;
+        }
+    }
+
+    /// Remove the afterglow of earlier frames, when persistence is enabled.
+    pub fn clear_persistence(&mut self) {
+        if let Some(accumulator) = &mut self.accumulator {
+            accumulator.clear();
+        }
+    }
 }
 
 /// Create the render pipeline.
@@ -227,11 +1119,12 @@ fn create_render_pipeline(
     let shader_module_descriptor = wgpu::include_wgsl!("line_sdf.wgsl");
     let shader = device.create_shader_module(shader_module_descriptor);
 
-    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("Beamline: Line render pipeline layout."),
-        bind_group_layouts: &[viewport_layout, tile_layout],
-        push_constant_ranges: &[],
-    });
+    let pipeline_layout =
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Beamline: Line render pipeline layout."),
+            bind_group_layouts: &[viewport_layout, tile_layout],
+            push_constant_ranges: &[],
+        });
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some("Beamline: Line render pipeline"),
         layout: Some(&pipeline_layout),
@@ -246,7 +1139,7 @@ fn create_render_pipeline(
             entry_point: Some("fs_main"),
             targets: &[Some(wgpu::ColorTargetState {
                 format: texture_format,
-                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
                 write_mask: wgpu::ColorWrites::ALL,
             })],
             compilation_options: wgpu::PipelineCompilationOptions::default(),
@@ -271,6 +1164,61 @@ fn create_render_pipeline(
     })
 }
 
+/// Creates a pipeline which draws a single triangle covering the whole
+/// framebuffer.
+///
+/// # Parameters
+///
+/// - `device`: WGPU Device.
+/// - `label`: Label of the pipeline.
+/// - `shader`: Module containing the `vs_fullscreen` vertex shader and the
+///   fragment shader.
+/// - `fragment_entry_point`: Name of the fragment shader.
+/// - `bind_group_layouts`: Layouts of the bind groups used by the shaders.
+/// - `format`: Format of the target texture.
+/// - `blend`: Blend state applied to the target texture, if any.
+pub(crate) fn create_fullscreen_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    shader: &wgpu::ShaderModule,
+    fragment_entry_point: &str,
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
+    format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
+) -> wgpu::RenderPipeline {
+    let pipeline_layout =
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_fullscreen"),
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(fragment_entry_point),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
 /// Create the bind group layout for the viewport.
 ///
 /// At render time, this contains the:
@@ -307,12 +1255,14 @@ fn create_tile_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
             bind_group_layout_entry(1, vis_vf, Storage { read_only: true }),
             // Binding 2: Line array.
             bind_group_layout_entry(2, vis_f, Storage { read_only: true }),
+            // Binding 3: Line indices of the tiles.
+            bind_group_layout_entry(3, vis_f, Storage { read_only: true }),
         ],
     })
 }
 
 /// Create a bind group layout entry.
-fn bind_group_layout_entry(
+pub(crate) fn bind_group_layout_entry(
     binding: u32,
     visibility: wgpu::ShaderStages,
     binding_type: wgpu::BufferBindingType,
@@ -328,3 +1278,267 @@ fn bind_group_layout_entry(
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::gpu::Gpu, LineCap, P2};
+
+    /// Renders a single opaque, horizontal, butt-capped line offscreen and
+    /// checks pixels inside and outside of it.
+    ///
+    /// The width of the area is deliberately not a multiple of 64 pixels, so
+    /// that the readback rows are padded.
+    #[test]
+    fn test_render_offscreen_horizontal_line() {
+        let gpu = Gpu::new();
+        let mut renderer = Renderer::new(
+            &gpu.device,
+            wgpu::TextureFormat::Rgba8Unorm,
+            70,
+            40,
+            16,
+            16,
+        );
+        renderer.line(
+            Line::new(P2::new(10.0, 20.0), P2::new(60.0, 20.0)),
+            &LineStyle {
+                width: 10.0,
+                cap: LineCap::Butt,
+                color: Color::new(1.0, 0.0, 0.0, 1.0),
+                ..Default::default()
+            },
+        );
+        let image = renderer.render_offscreen(&gpu.device, &gpu.queue);
+
+        assert_eq!(image.width(), 70);
+        assert_eq!(image.height(), 40);
+        assert_eq!(image.pixel(35, 20), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(35, 5), [0, 0, 0, 0]);
+        assert_eq!(image.pixel(2, 20), [0, 0, 0, 0]);
+        assert_eq!(image.pixel(68, 20), [0, 0, 0, 0]);
+    }
+
+    /// Draws a line whose color comes from premultiplied components, and
+    /// checks that the output, which has premultiplied alpha, holds the same
+    /// components where the line covers it.
+    #[test]
+    fn test_render_offscreen_premultiplied_color() {
+        let gpu = Gpu::new();
+        let mut renderer = Renderer::new(
+            &gpu.device,
+            wgpu::TextureFormat::Rgba8Unorm,
+            32,
+            32,
+            16,
+            16,
+        );
+        let color = Color::from_premultiplied(0.2, 0.4, 0.1, 0.5);
+        renderer.line(
+            Line::new(P2::new(0.0, 16.0), P2::new(32.0, 16.0)),
+            &LineStyle {
+                width: 8.0,
+                cap: LineCap::Butt,
+                color,
+                ..Default::default()
+            },
+        );
+        let image = renderer.render_offscreen(&gpu.device, &gpu.queue);
+        let expected = color.premultiplied().map(|c| (c * 255.0).round() as u8);
+        let pixel = image.pixel(16, 16);
+        for (actual, expected) in pixel.iter().zip(expected) {
+            assert!(actual.abs_diff(expected) <= 1, "{:?}", pixel);
+        }
+        assert_eq!(image.pixel(16, 4), [0, 0, 0, 0]);
+    }
+
+    /// Renders a translucent line over a target which already holds
+    /// translucent content, and checks that the two are composited with
+    /// premultiplied alpha.
+    #[test]
+    fn test_render_over_existing_content() {
+        let gpu = Gpu::new();
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let mut renderer = Renderer::new(&gpu.device, format, 32, 32, 16, 16);
+        let target = OffscreenTarget::new(&gpu.device, 32, 32, format);
+        renderer.line(
+            Line::new(P2::new(0.0, 16.0), P2::new(32.0, 16.0)),
+            &LineStyle {
+                width: 8.0,
+                cap: LineCap::Butt,
+                color: Color::new(1.0, 0.0, 0.0, 0.5),
+                ..Default::default()
+            },
+        );
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        // Half-transparent blue, with premultiplied alpha.
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target.view(),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.0,
+                        g: 0.0,
+                        b: 0.5,
+                        a: 0.5,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        renderer.render(&gpu.device, &mut encoder, &gpu.queue, target.view());
+        target.copy_to_readback(&mut encoder);
+        gpu.queue.submit(std::iter::once(encoder.finish()));
+        let image = target.read_image(&gpu.device);
+
+        // The red line covers half of the blue beneath it.
+        assert_eq!(image.pixel(16, 16), [128, 0, 64, 192]);
+        assert_eq!(image.pixel(16, 4), [0, 0, 128, 128]);
+    }
+
+    /// Checks that BGRA targets are swizzled to RGBA on readback, and that
+    /// the target is cleared between renders.
+    #[test]
+    fn test_render_offscreen_bgra_and_clear() {
+        let gpu = Gpu::new();
+        let mut renderer = Renderer::new(
+            &gpu.device,
+            wgpu::TextureFormat::Bgra8Unorm,
+            32,
+            32,
+            16,
+            16,
+        );
+        renderer.line(
+            Line::new(P2::new(0.0, 16.0), P2::new(32.0, 16.0)),
+            &LineStyle {
+                width: 8.0,
+                cap: LineCap::Butt,
+                color: Color::new(0.0, 0.0, 1.0, 1.0),
+                ..Default::default()
+            },
+        );
+        let image = renderer.render_offscreen(&gpu.device, &gpu.queue);
+        assert_eq!(image.pixel(16, 16), [0, 0, 255, 255]);
+
+        let image = renderer.render_offscreen(&gpu.device, &gpu.queue);
+        assert_eq!(image, Image::new(32, 32));
+    }
+
+    /// Draws the tiles with a blue edge color, and checks that the edges are
+    /// drawn in that color rather than with its channels swizzled.
+    #[test]
+    fn test_tile_edge_color() {
+        let gpu = Gpu::new();
+        let mut renderer = Renderer::new(
+            &gpu.device,
+            wgpu::TextureFormat::Rgba8Unorm,
+            32,
+            32,
+            16,
+            16,
+        );
+        renderer.set_draw_tiles(true);
+        renderer.tile_edges = Color::new(0.0, 0.0, 1.0, 1.0);
+        // Only tiles which contain primitives are drawn.
+        renderer.line(
+            Line::new(P2::new(12.0, 12.0), P2::new(13.0, 12.0)),
+            &LineStyle {
+                width: 1.0,
+                ..Default::default()
+            },
+        );
+        let image = renderer.render_offscreen(&gpu.device, &gpu.queue);
+
+        // The translucent background is drawn over the edges, so a pixel on
+        // the left edge of the tile is the background over blue, and a pixel
+        // in the middle of the tile is only the background. Rows are flipped
+        // relative to beamline coordinates.
+        assert_eq!(image.pixel(0, 24), [36, 36, 130, 255]);
+        assert_eq!(image.pixel(8, 24), [36, 36, 54, 178]);
+    }
+
+    /// Renders the same frame with each tiling backend. GPU binning matches
+    /// CPU tiling tile for tile, so the images are identical.
+    #[test]
+    fn test_tiling_backends_match() {
+        let gpu = Gpu::new();
+        let draw = |renderer: &mut Renderer| {
+            for i in 0..200 {
+                let f = i as f32;
+                let a = P2::new(
+                    100.0 + 90.0 * (f * 1.3).sin(),
+                    80.0 + 70.0 * (f * 0.7).cos(),
+                );
+                let b = P2::new(
+                    100.0 + 90.0 * (f * 2.9).cos(),
+                    80.0 + 70.0 * (f * 1.9).sin(),
+                );
+                renderer.line(
+                    Line::new(a, b),
+                    &LineStyle {
+                        width: 0.5 + f % 5.0,
+                        color: Color::new(
+                            f % 3.0 / 2.0,
+                            0.5,
+                            1.0 - f % 2.0,
+                            0.8,
+                        ),
+                        ..Default::default()
+                    },
+                );
+            }
+            renderer.arc(
+                CircularArc::new(P2::new(100.0, 80.0), 50.0, 0.0, 5.0),
+                &LineStyle::default(),
+            );
+            renderer.fill(
+                &Path::polygon(&[
+                    P2::new(20.0, 20.0),
+                    P2::new(90.0, 40.0),
+                    P2::new(40.0, 120.0),
+                ]),
+                &FillStyle::default(),
+            );
+        };
+
+        let mut renderer = Renderer::new(
+            &gpu.device,
+            wgpu::TextureFormat::Rgba8Unorm,
+            200,
+            160,
+            16,
+            16,
+        );
+        draw(&mut renderer);
+        let expected = renderer.render_offscreen(&gpu.device, &gpu.queue);
+
+        for backend in [TilingBackend::Gpu, TilingBackend::Verify] {
+            renderer.set_tiling_backend(backend);
+            assert_eq!(renderer.tiling_backend(), backend);
+            for _ in 0..2 {
+                draw(&mut renderer);
+                let image = renderer.render_offscreen(&gpu.device, &gpu.queue);
+                assert!(
+                    image == expected,
+                    "{:?} tiling differs from CPU tiling",
+                    backend
+                );
+            }
+        }
+        assert_eq!(renderer.tiling_mismatches(), &[]);
+
+        // An empty frame draws nothing.
+        renderer.set_tiling_backend(TilingBackend::Gpu);
+        let image = renderer.render_offscreen(&gpu.device, &gpu.queue);
+        assert!(image == Image::new(200, 160));
+    }
+}
@@ -0,0 +1,1112 @@
+//! Retained-mode scene, whose shapes persist from frame to frame.
+//!
+//! The [`crate::Renderer`] is immediate-mode: shapes are added every frame,
+//! and the tiler is drained and rebuilt for each render. A [`Scene`]
+//! instead keeps its shapes, which are inserted, updated and removed
+//! through stable [`ShapeHandle`]s. Each shape is tiled once, when it is
+//! inserted or updated, and only the tiles which a change touches are
+//! rebuilt.
+//!
+//! As in immediate mode, each primitive is stored once, and tiles refer to
+//! their primitives through line indices. The primitives of each shape are
+//! kept in a range of a line array, and the line indices of each tile in a
+//! range of a line index array, which mirror the line and line index
+//! buffers on the GPU. A re-tiled shape or a rebuilt tile is written back in
+//! place if it still fits in its range, or is moved to the end of its array
+//! otherwise, so that only the ranges of changed shapes and tiles need to be
+//! uploaded (see [`crate::Renderer::render_scene`]). Both arrays are
+//! compacted when more than half of either is unused.
+
+use crate::{
+    arc::CircularArc,
+    bezier::{CubicBezier, QuadraticBezier},
+    ellipse::Ellipse,
+    layer::{Layer, LayerId},
+    path::Path,
+    polyline::Polyline,
+    primitive::StyledPrimitive,
+    style::{FillStyle, LineStyle, StyledLine},
+    tiler::{TileInfo, Tiler},
+    transform::Camera,
+    Line,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Number of unused entries that the line array and the line index array
+/// may always have before they are compacted.
+const COMPACTION_SLACK: u32 = 4096;
+
+/// Identifier of the next scene to be created.
+static NEXT_SCENE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Stable handle of a shape in a [`Scene`].
+///
+/// A handle stays valid until its shape is removed. Handles of removed
+/// shapes are never re-used for new shapes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ShapeHandle {
+    /// Index of the shape's slot.
+    index: u32,
+    /// Generation of the slot when the shape was inserted.
+    generation: u32,
+}
+
+/// A shape in a [`Scene`], with its style.
+#[derive(Debug, Clone)]
+pub enum SceneShape {
+    /// A line (see [`crate::Renderer::line`]).
+    Line(Line, LineStyle),
+    /// A polyline (see [`crate::Renderer::polyline`]).
+    Polyline(Polyline, LineStyle),
+    /// A quadratic Bézier curve (see [`crate::Renderer::quadratic`]).
+    Quadratic(QuadraticBezier, LineStyle),
+    /// A cubic Bézier curve (see [`crate::Renderer::cubic`]).
+    Cubic(CubicBezier, LineStyle),
+    /// A circular arc (see [`crate::Renderer::arc`]).
+    Arc(CircularArc, LineStyle),
+    /// An ellipse (see [`crate::Renderer::ellipse`]).
+    Ellipse(Ellipse, LineStyle),
+    /// A filled path (see [`crate::Renderer::fill`]).
+    Fill(Path, FillStyle),
+}
+
+/// A shape in a scene, with its primitives.
+#[derive(Debug)]
+struct Entry {
+    shape: SceneShape,
+    /// Layer that the shape is drawn in.
+    layer: LayerId,
+    /// Position of the shape in the order in which shapes are drawn within
+    /// a layer.
+    order: u64,
+    /// Primitives of the shape, each stored once.
+    primitives: Vec<StyledPrimitive>,
+    /// Linear indices of the tiles of the shape, each with the index in
+    /// `primitives` of a primitive in the tile, sorted by tile.
+    lines: Vec<(usize, u32)>,
+    /// Whether the primitives must be placed in the line array again.
+    dirty: bool,
+    /// Start of the shape's range in the line array.
+    start: u32,
+    /// Length of the shape's range in the line array.
+    capacity: u32,
+    /// Revision of the scene at which the primitives were last placed.
+    revision: u64,
+}
+impl Entry {
+    /// Creates an entry for a shape, which is not yet in the line array.
+    fn new(
+        shape: SceneShape,
+        layer: LayerId,
+        order: u64,
+        (primitives, lines): (Vec<StyledPrimitive>, Vec<(usize, u32)>),
+    ) -> Self {
+        Entry {
+            shape,
+            layer,
+            order,
+            primitives,
+            lines,
+            dirty: false,
+            start: 0,
+            capacity: 0,
+            revision: 0,
+        }
+    }
+
+    /// Returns the indices in `primitives` of the primitives of the shape in
+    /// a tile, each with the linear index of the tile.
+    fn tile_lines(&self, tile_ix: usize) -> &[(usize, u32)] {
+        let start = self.lines.partition_point(|(ix, _)| *ix < tile_ix);
+        let end = self.lines.partition_point(|(ix, _)| *ix <= tile_ix);
+        &self.lines[start..end]
+    }
+
+    /// Returns the linear indices of the tiles of the shape.
+    fn tiles(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut previous = None;
+        self.lines.iter().filter_map(move |(ix, _)| {
+            let first = previous != Some(*ix);
+            previous = Some(*ix);
+            first.then_some(*ix)
+        })
+    }
+}
+
+/// A tile of a scene.
+#[derive(Debug, Clone, Default)]
+struct SceneTile {
+    /// Slots of the shapes with primitives in the tile. These are in drawing
+    /// order unless the tile is dirty.
+    shapes: Vec<u32>,
+    /// Whether the tile must be rebuilt.
+    dirty: bool,
+    /// Start of the tile's range in the line index array.
+    start: u32,
+    /// Length of the tile's range in the line index array.
+    capacity: u32,
+    /// Number of primitives in the tile.
+    len: u32,
+    /// Revision of the scene at which the tile was last rebuilt.
+    revision: u64,
+}
+
+/// Retained-mode scene.
+///
+/// A scene holds shapes which are drawn every time it is rendered, until
+/// they are removed. It is rendered with [`crate::Renderer::render_scene`]
+/// or [`crate::SoftwareRenderer::render_scene`], whose area and tile sizes
+/// must match the scene's.
+///
+/// Within each tile, shapes are drawn in the z order of their layers, and
+/// then in the order in which they were inserted. Updating a shape keeps its
+/// place in this order.
+#[derive(Debug)]
+pub struct Scene {
+    /// Unique identifier of the scene.
+    id: u64,
+    /// Tiler used to tile each shape.
+    tiler: Tiler,
+    /// Shapes, indexed by the slots of their handles.
+    slots: Vec<Option<Entry>>,
+    /// Current generation of each slot.
+    generations: Vec<u32>,
+    /// Slots which are not in use.
+    free_slots: Vec<u32>,
+    /// Number of shapes in the scene.
+    n_shapes: usize,
+    /// Position of the next shape to be inserted in the drawing order.
+    next_order: u64,
+    /// Tiles, indexed by their linear indices.
+    tiles: Vec<SceneTile>,
+    /// Linear indices of the dirty tiles.
+    dirty_tiles: Vec<usize>,
+    /// Slots of the shapes whose primitives must be placed in the line array
+    /// again.
+    dirty_shapes: Vec<u32>,
+    /// Number of primitives of the shapes in the scene.
+    n_primitives: u32,
+    /// Length of the line array.
+    line_array_len: u32,
+    /// Length of the line index array.
+    line_index_len: u32,
+    /// Revision of the scene, which increases every time tiles are rebuilt.
+    revision: u64,
+    /// Revision at which the ranges of the tiles were last laid out afresh.
+    layout_revision: u64,
+    /// Whether the ranges of the tiles must be laid out afresh.
+    relayout: bool,
+}
+impl Scene {
+    /// Creates a new, empty scene.
+    ///
+    /// # Parameters
+    ///
+    /// - `area_width`: Width of the renderable area.
+    /// - `area_height`: Height of the renderable area.
+    /// - `tile_width`: Width of a single bucketing tile.
+    /// - `tile_height`: Height of a single bucketing tile.
+    pub fn new(
+        area_width: u32,
+        area_height: u32,
+        tile_width: u32,
+        tile_height: u32,
+    ) -> Self {
+        let tiler =
+            Tiler::new(area_width, area_height, tile_width, tile_height);
+        Scene {
+            id: NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed),
+            tiles: vec![SceneTile::default(); tiler.tile_count()],
+            tiler,
+            slots: Vec::new(),
+            generations: Vec::new(),
+            free_slots: Vec::new(),
+            n_shapes: 0,
+            next_order: 0,
+            dirty_tiles: Vec::new(),
+            dirty_shapes: Vec::new(),
+            n_primitives: 0,
+            line_array_len: 0,
+            line_index_len: 0,
+            revision: 0,
+            layout_revision: 0,
+            relayout: false,
+        }
+    }
+
+    /// Inserts a shape, which is drawn above the shapes inserted before it
+    /// in the same layer.
+    ///
+    /// The shape is drawn in the current layer (see [`Scene::set_layer`]).
+    ///
+    /// # Parameters
+    ///
+    /// - `shape`: Shape to insert.
+    ///
+    /// # Returns
+    ///
+    /// A handle to the shape.
+    pub fn insert(&mut self, shape: SceneShape) -> ShapeHandle {
+        let layer = self.tiler.current_layer();
+        let primitives = self.tile_shape(&shape, layer);
+        let entry = Entry::new(shape, layer, self.next_order, primitives);
+        self.next_order += 1;
+
+        let index = match self.free_slots.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(None);
+                self.generations.push(0);
+                u32::try_from(self.slots.len() - 1).expect("too many shapes")
+            }
+        };
+        self.place_entry(index, entry);
+        self.n_shapes += 1;
+        ShapeHandle {
+            index,
+            generation: self.generations[index as usize],
+        }
+    }
+
+    /// Replaces a shape, keeping its layer and its place in the drawing
+    /// order.
+    ///
+    /// # Parameters
+    ///
+    /// - `handle`: Handle of the shape.
+    /// - `shape`: New shape.
+    ///
+    /// # Returns
+    ///
+    /// The previous shape, or `None` if the handle does not refer to a shape
+    /// in the scene. In that case, the scene is not changed.
+    pub fn update(
+        &mut self,
+        handle: ShapeHandle,
+        shape: SceneShape,
+    ) -> Option<SceneShape> {
+        let mut entry = self.take_entry(handle)?;
+        (entry.primitives, entry.lines) = self.tile_shape(&shape, entry.layer);
+        let previous = std::mem::replace(&mut entry.shape, shape);
+        self.place_entry(handle.index, entry);
+        Some(previous)
+    }
+
+    /// Removes a shape.
+    ///
+    /// # Parameters
+    ///
+    /// - `handle`: Handle of the shape.
+    ///
+    /// # Returns
+    ///
+    /// The removed shape, or `None` if the handle does not refer to a shape
+    /// in the scene.
+    pub fn remove(&mut self, handle: ShapeHandle) -> Option<SceneShape> {
+        let entry = self.take_entry(handle)?;
+        let generation = &mut self.generations[handle.index as usize];
+        *generation = generation.wrapping_add(1);
+        self.free_slots.push(handle.index);
+        self.n_shapes -= 1;
+        Some(entry.shape)
+    }
+
+    /// Returns a shape, or `None` if the handle does not refer to a shape in
+    /// the scene.
+    pub fn get(&self, handle: ShapeHandle) -> Option<&SceneShape> {
+        self.entry(handle).map(|entry| &entry.shape)
+    }
+
+    /// Returns `true` if the handle refers to a shape in the scene.
+    pub fn contains(&self, handle: ShapeHandle) -> bool {
+        self.entry(handle).is_some()
+    }
+
+    /// Returns the number of shapes in the scene.
+    pub fn len(&self) -> usize {
+        self.n_shapes
+    }
+
+    /// Returns `true` if the scene has no shapes.
+    pub fn is_empty(&self) -> bool {
+        self.n_shapes == 0
+    }
+
+    /// Removes all the shapes. Their handles become invalid.
+    pub fn clear(&mut self) {
+        for index in 0..self.slots.len() {
+            if self.slots[index].take().is_some() {
+                self.generations[index] =
+                    self.generations[index].wrapping_add(1);
+                self.free_slots.push(index as u32);
+            }
+        }
+        self.n_shapes = 0;
+        self.n_primitives = 0;
+        self.retile();
+    }
+
+    /// Creates a new layer.
+    ///
+    /// See [`crate::Renderer::add_layer`].
+    pub fn add_layer(&mut self, name: &str, z: i32) -> LayerId {
+        self.tiler.add_layer(name, z)
+    }
+
+    /// Returns the identifier of the layer with a name, or `None` if there
+    /// is no such layer.
+    pub fn layer_id(&self, name: &str) -> Option<LayerId> {
+        self.tiler.layer_id(name)
+    }
+
+    /// Returns a layer.
+    pub fn layer(&self, id: LayerId) -> &Layer {
+        self.tiler.layer(id)
+    }
+
+    /// Returns a layer, to change its z index, visibility or group opacity.
+    ///
+    /// This rebuilds every tile at the next render, since any of them may
+    /// contain shapes of the layer, and uploads the primitives of the
+    /// layer's shapes again, since they hold its opacity.
+    pub fn layer_mut(&mut self, id: LayerId) -> &mut Layer {
+        for tile_ix in 0..self.tiles.len() {
+            if !self.tiles[tile_ix].shapes.is_empty() {
+                self.mark_dirty(tile_ix);
+            }
+        }
+        for index in 0..self.slots.len() {
+            if self.slots[index].as_ref().is_some_and(|e| e.layer == id) {
+                self.mark_shape_dirty(index as u32);
+            }
+        }
+        self.tiler.layer_mut(id)
+    }
+
+    /// Selects the layer which shapes are inserted into.
+    ///
+    /// See [`crate::Renderer::set_layer`].
+    pub fn set_layer(&mut self, id: LayerId) {
+        self.tiler.set_layer(id);
+    }
+
+    /// Sets the camera, which maps the coordinates of shapes to pixels.
+    ///
+    /// This re-tiles every shape.
+    ///
+    /// # Parameters
+    ///
+    /// - `camera`: Camera, or `None` if shapes are in pixel coordinates.
+    pub fn set_camera(&mut self, camera: Option<Camera>) {
+        self.tiler.set_camera(camera);
+        self.retile();
+    }
+
+    /// Resizes the scene for a new renderable area.
+    ///
+    /// This re-tiles every shape.
+    ///
+    /// # Parameters
+    ///
+    /// - `area_width`: Width of the renderable area.
+    /// - `area_height`: Height of the renderable area.
+    pub fn resize(&mut self, area_width: u32, area_height: u32) {
+        self.tiler.resize(area_width, area_height);
+        self.retile();
+    }
+
+    /// Returns `true` if the scene has the given area and tile sizes.
+    pub(crate) fn has_size(
+        &self,
+        area_width: u32,
+        area_height: u32,
+        tile_width: u32,
+        tile_height: u32,
+    ) -> bool {
+        self.tiler.size() == (area_width, area_height, tile_width, tile_height)
+    }
+
+    /// Returns the unique identifier of the scene.
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the revision of the scene, which increases every time tiles
+    /// are rebuilt by [`Scene::update_tiles`].
+    pub(crate) fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns the revision at which the ranges of the shapes in the line
+    /// array and of the tiles in the line index array were last laid out
+    /// afresh. Both arrays must be written again after this.
+    pub(crate) fn layout_revision(&self) -> u64 {
+        self.layout_revision
+    }
+
+    /// Returns the length of the line array.
+    pub(crate) fn line_array_len(&self) -> u32 {
+        self.line_array_len
+    }
+
+    /// Returns the length of the line index array.
+    pub(crate) fn line_index_len(&self) -> u32 {
+        self.line_index_len
+    }
+
+    /// Places the primitives of the changed shapes in the line array, and
+    /// rebuilds the dirty tiles, placing their line indices in the line
+    /// index array.
+    pub(crate) fn update_tiles(&mut self) {
+        if self.dirty_tiles.is_empty()
+            && self.dirty_shapes.is_empty()
+            && !self.relayout
+        {
+            return;
+        }
+        self.revision += 1;
+        if self.relayout {
+            self.line_array_len = 0;
+            self.line_index_len = 0;
+            for entry in self.slots.iter_mut().flatten() {
+                entry.capacity = 0;
+            }
+            for tile in self.tiles.iter_mut() {
+                tile.capacity = 0;
+            }
+        }
+
+        // A shape which no longer fits in its range is moved to the end of
+        // the line array. Its tiles are dirty, since it was re-tiled.
+        for index in std::mem::take(&mut self.dirty_shapes) {
+            let Some(entry) = self.slots[index as usize].as_mut() else {
+                continue;
+            };
+            if !entry.dirty {
+                continue;
+            }
+            entry.dirty = false;
+            let len = entry.primitives.len() as u32;
+            if len > entry.capacity {
+                entry.start = self.line_array_len;
+                entry.capacity = len;
+                self.line_array_len += len;
+            }
+            entry.revision = self.revision;
+        }
+
+        let slots = &self.slots;
+        let tiler = &self.tiler;
+        for tile_ix in std::mem::take(&mut self.dirty_tiles) {
+            let tile = &mut self.tiles[tile_ix];
+            tile.dirty = false;
+            tile.shapes.sort_by_key(|slot| {
+                let entry = slots[*slot as usize].as_ref().unwrap();
+                let layer = tiler.layer(entry.layer);
+                (layer.z, entry.layer.index(), entry.order)
+            });
+            tile.len = tile
+                .shapes
+                .iter()
+                .map(|slot| slots[*slot as usize].as_ref().unwrap())
+                .filter(|entry| tiler.layer(entry.layer).visible)
+                .map(|entry| entry.tile_lines(tile_ix).len() as u32)
+                .sum();
+            if tile.len > tile.capacity {
+                tile.start = self.line_index_len;
+                tile.capacity = tile.len;
+                self.line_index_len += tile.len;
+            }
+            tile.revision = self.revision;
+        }
+
+        // Compact both arrays if most of either is unused. Moving the
+        // shapes changes the line indices of every tile.
+        let n_indices: u32 = self.tiles.iter().map(|tile| tile.len).sum();
+        if self.relayout
+            || self.line_array_len > 2 * self.n_primitives + COMPACTION_SLACK
+            || self.line_index_len > 2 * n_indices + COMPACTION_SLACK
+        {
+            let mut start = 0;
+            for entry in self.slots.iter_mut().flatten() {
+                entry.start = start;
+                entry.capacity = entry.primitives.len() as u32;
+                start += entry.capacity;
+            }
+            self.line_array_len = start;
+            let mut start = 0;
+            for tile in self.tiles.iter_mut() {
+                tile.start = start;
+                tile.capacity = tile.len;
+                start += tile.len;
+            }
+            self.line_index_len = start;
+            self.layout_revision = self.revision;
+            self.relayout = false;
+        }
+    }
+
+    /// Returns the tile information of the tiles which have primitives.
+    ///
+    /// The start index of each tile is the start of its range in the line
+    /// index array. The tiles must not be dirty.
+    pub(crate) fn tile_infos(&self) -> Vec<TileInfo> {
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| tile.len > 0)
+            .map(|(tile_ix, tile)| {
+                let (tile_x, tile_y) = self.tiler.tile_unlindex(tile_ix);
+                TileInfo {
+                    tile_x,
+                    tile_y,
+                    start_index: tile.start,
+                    n_lines: tile.len,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the linear indices of the tiles with primitives which were
+    /// rebuilt after a revision, or all of them.
+    ///
+    /// # Parameters
+    ///
+    /// - `since`: Revision after which tiles were rebuilt, or `None` for all
+    ///   the tiles with primitives.
+    pub(crate) fn changed_tiles(
+        &self,
+        since: Option<u64>,
+    ) -> impl Iterator<Item = usize> + '_ {
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(move |(_, tile)| {
+                tile.len > 0 && since.is_none_or(|r| tile.revision > r)
+            })
+            .map(|(tile_ix, _)| tile_ix)
+    }
+
+    /// Returns the slots of the shapes whose primitives were placed in the
+    /// line array after a revision, or of all the shapes.
+    ///
+    /// # Parameters
+    ///
+    /// - `since`: Revision after which primitives were placed, or `None` for
+    ///   all the shapes.
+    pub(crate) fn changed_shapes(
+        &self,
+        since: Option<u64>,
+    ) -> impl Iterator<Item = u32> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(move |(_, entry)| {
+                entry.as_ref().is_some_and(|entry| {
+                    since.is_none_or(|r| entry.revision > r)
+                })
+            })
+            .map(|(index, _)| index as u32)
+    }
+
+    /// Appends the primitives of a shape to a vector.
+    ///
+    /// The shape is given the index of its slot as its shape identifier.
+    /// The shape must not be dirty.
+    ///
+    /// # Parameters
+    ///
+    /// - `index`: Slot of the shape.
+    /// - `lines`: Vector to append the primitives to.
+    ///
+    /// # Returns
+    ///
+    /// The start of the shape's range in the line array.
+    pub(crate) fn shape_lines(
+        &self,
+        index: u32,
+        lines: &mut Vec<StyledPrimitive>,
+    ) -> u32 {
+        let entry = self.slots[index as usize].as_ref().unwrap();
+        assert!(!entry.dirty);
+        let layer_opacity = self.tiler.layer(entry.layer).opacity;
+        lines.extend(entry.primitives.iter().map(|primitive| {
+            StyledPrimitive {
+                shape: index,
+                layer_opacity: layer_opacity.clamp(0.0, 1.0),
+                ..primitive.clone()
+            }
+        }));
+        entry.start
+    }
+
+    /// Appends the line indices of a tile to a vector, in drawing order.
+    ///
+    /// The line indices refer to the line array. The tile must not be dirty.
+    ///
+    /// # Parameters
+    ///
+    /// - `tile_ix`: Linear index of the tile.
+    /// - `line_indices`: Vector to append the line indices to.
+    ///
+    /// # Returns
+    ///
+    /// The start of the tile's range in the line index array.
+    pub(crate) fn tile_line_indices(
+        &self,
+        tile_ix: usize,
+        line_indices: &mut Vec<u32>,
+    ) -> u32 {
+        let tile = &self.tiles[tile_ix];
+        assert!(!tile.dirty);
+        for slot in tile.shapes.iter() {
+            let entry = self.slots[*slot as usize].as_ref().unwrap();
+            if !self.tiler.layer(entry.layer).visible {
+                continue;
+            }
+            line_indices.extend(
+                entry
+                    .tile_lines(tile_ix)
+                    .iter()
+                    .map(|(_, i)| entry.start + i),
+            );
+        }
+        tile.start
+    }
+
+    /// Returns the tiles, primitives and line indices of the scene, in the
+    /// same form as [`Tiler::drain`].
+    ///
+    /// The shapes and tiles must not be dirty.
+    pub(crate) fn contents(
+        &self,
+    ) -> (Vec<TileInfo>, Vec<StyledPrimitive>, Vec<u32>) {
+        // Gather the primitives without the gaps of the line array, and map
+        // the positions in the line array to those in the gathered vector.
+        let mut lines = Vec::new();
+        let mut positions = vec![u32::MAX; self.line_array_len as usize];
+        for index in self.changed_shapes(None) {
+            let len = lines.len() as u32;
+            let start = self.shape_lines(index, &mut lines);
+            for i in 0..lines.len() as u32 - len {
+                positions[(start + i) as usize] = len + i;
+            }
+        }
+
+        let mut tile_infos = self.tile_infos();
+        let mut line_indices = Vec::new();
+        for (tile_info, tile_ix) in
+            tile_infos.iter_mut().zip(self.changed_tiles(None))
+        {
+            tile_info.start_index = line_indices.len() as u32;
+            let len = line_indices.len();
+            self.tile_line_indices(tile_ix, &mut line_indices);
+            for line_index in line_indices[len..].iter_mut() {
+                *line_index = positions[*line_index as usize];
+            }
+        }
+        (tile_infos, lines, line_indices)
+    }
+
+    /// Tiles a shape.
+    ///
+    /// # Returns
+    ///
+    /// The primitives of the shape, each stored once, and the linear indices
+    /// of their tiles, each with the index of a primitive in the tile,
+    /// sorted by tile.
+    fn tile_shape(
+        &mut self,
+        shape: &SceneShape,
+        layer: LayerId,
+    ) -> (Vec<StyledPrimitive>, Vec<(usize, u32)>) {
+        let current_layer = self.tiler.current_layer();
+        self.tiler.set_layer(layer);
+        match shape {
+            SceneShape::Line(line, style) => self.tiler.add(StyledLine {
+                line: line.clone(),
+                style: style.clone(),
+            }),
+            SceneShape::Polyline(polyline, style) => {
+                self.tiler.add_polyline(polyline, style)
+            }
+            SceneShape::Quadratic(curve, style) => {
+                self.tiler.add_quadratic(curve.clone(), style)
+            }
+            SceneShape::Cubic(curve, style) => {
+                self.tiler.add_cubic(curve.clone(), style)
+            }
+            SceneShape::Arc(arc, style) => {
+                self.tiler.add_arc(arc.clone(), style)
+            }
+            SceneShape::Ellipse(ellipse, style) => {
+                self.tiler.add_ellipse(ellipse.clone(), style)
+            }
+            SceneShape::Fill(path, style) => self.tiler.add_fill(path, style),
+        }
+        self.tiler.set_layer(current_layer);
+        let (primitives, mut lines) = self.tiler.take_primitives();
+        lines.sort_by_key(|(ix, _)| *ix);
+        (primitives, lines)
+    }
+
+    /// Returns the entry of a shape, if the handle refers to a shape in the
+    /// scene.
+    fn entry(&self, handle: ShapeHandle) -> Option<&Entry> {
+        if self.generations.get(handle.index as usize)
+            != Some(&handle.generation)
+        {
+            return None;
+        }
+        self.slots[handle.index as usize].as_ref()
+    }
+
+    /// Takes the entry of a shape out of its slot and its tiles.
+    fn take_entry(&mut self, handle: ShapeHandle) -> Option<Entry> {
+        self.entry(handle)?;
+        let entry = self.slots[handle.index as usize].take().unwrap();
+        for tile_ix in entry.tiles() {
+            self.tiles[tile_ix]
+                .shapes
+                .retain(|slot| *slot != handle.index);
+            self.mark_dirty(tile_ix);
+        }
+        self.n_primitives -= entry.primitives.len() as u32;
+        Some(entry)
+    }
+
+    /// Puts the entry of a shape into its slot and its tiles, to be placed
+    /// in the line array.
+    fn place_entry(&mut self, index: u32, entry: Entry) {
+        for tile_ix in entry.tiles() {
+            self.tiles[tile_ix].shapes.push(index);
+            self.mark_dirty(tile_ix);
+        }
+        self.n_primitives += entry.primitives.len() as u32;
+        self.slots[index as usize] = Some(entry);
+        self.mark_shape_dirty(index);
+    }
+
+    /// Marks a tile as needing to be rebuilt.
+    fn mark_dirty(&mut self, tile_ix: usize) {
+        let tile = &mut self.tiles[tile_ix];
+        if !tile.dirty {
+            tile.dirty = true;
+            self.dirty_tiles.push(tile_ix);
+        }
+    }
+
+    /// Marks a shape as needing its primitives to be placed in the line
+    /// array again.
+    fn mark_shape_dirty(&mut self, index: u32) {
+        let entry = self.slots[index as usize].as_mut().unwrap();
+        if !entry.dirty {
+            entry.dirty = true;
+            self.dirty_shapes.push(index);
+        }
+    }
+
+    /// Re-tiles every shape, after the tiles or the camera have changed.
+    fn retile(&mut self) {
+        self.tiles = vec![SceneTile::default(); self.tiler.tile_count()];
+        self.dirty_tiles.clear();
+        self.relayout = true;
+        for index in 0..self.slots.len() {
+            if let Some(mut entry) = self.slots[index].take() {
+                self.n_primitives -= entry.primitives.len() as u32;
+                (entry.primitives, entry.lines) =
+                    self.tile_shape(&entry.shape, entry.layer);
+                self.place_entry(index as u32, entry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffers, Color, P2};
+    use crate::{
+        tests::gpu::Gpu, Ellipse, FillStyle, Image, Line, LineCap, LineStyle,
+        Path, Renderer, SoftwareRenderer,
+    };
+    use bytemuck::cast_slice;
+
+    /// Returns the GPU form of the primitives of a scene, laid out as in the
+    /// line array.
+    fn gpu_lines(lines: &[StyledPrimitive]) -> Vec<buffers::StyledLine> {
+        lines
+            .iter()
+            .cloned()
+            .map(buffers::StyledLine::new_from_styled_primitive)
+            .collect()
+    }
+
+    /// Writing only the changed shapes and tiles into mirrors of the line
+    /// array and the line index array keeps them equal to the contents of
+    /// the scene, as shapes are inserted, updated and removed.
+    #[test]
+    fn test_incremental_updates_match_contents() {
+        let mut scene = Scene::new(160, 120, 16, 16);
+        let mut mirror: Vec<buffers::StyledLine> = Vec::new();
+        let mut index_mirror: Vec<u32> = Vec::new();
+        let mut synced: Option<u64> = None;
+        // Returns the numbers of shapes and tiles written.
+        let mut sync = |scene: &mut Scene,
+                        mirror: &mut Vec<buffers::StyledLine>,
+                        index_mirror: &mut Vec<u32>| {
+            scene.update_tiles();
+            let since =
+                synced.filter(|revision| *revision >= scene.layout_revision());
+            mirror.resize(
+                scene.line_array_len() as usize,
+                bytemuck::Zeroable::zeroed(),
+            );
+            index_mirror.resize(scene.line_index_len() as usize, 0);
+            let mut n_shapes_written = 0;
+            for index in scene.changed_shapes(since) {
+                let mut lines = Vec::new();
+                let start = scene.shape_lines(index, &mut lines) as usize;
+                mirror[start..start + lines.len()]
+                    .copy_from_slice(&gpu_lines(&lines));
+                n_shapes_written += 1;
+            }
+            let mut n_tiles_written = 0;
+            for tile_ix in scene.changed_tiles(since) {
+                let mut line_indices = Vec::new();
+                let start = scene.tile_line_indices(tile_ix, &mut line_indices);
+                let start = start as usize;
+                index_mirror[start..start + line_indices.len()]
+                    .copy_from_slice(&line_indices);
+                n_tiles_written += 1;
+            }
+            synced = Some(scene.revision());
+
+            // The mirrors hold the same primitives for every tile as the
+            // contents of the scene.
+            let (tile_infos, lines, line_indices) = scene.contents();
+            for (info, laid_out) in tile_infos.iter().zip(scene.tile_infos()) {
+                let range = |start: u32| {
+                    start as usize..(start + info.n_lines) as usize
+                };
+                let expected: Vec<_> = line_indices[range(info.start_index)]
+                    .iter()
+                    .map(|i| lines[*i as usize].clone())
+                    .collect();
+                let actual: Vec<_> = index_mirror[range(laid_out.start_index)]
+                    .iter()
+                    .map(|i| mirror[*i as usize])
+                    .collect();
+                assert_eq!(
+                    cast_slice::<_, u8>(&gpu_lines(&expected)),
+                    cast_slice::<_, u8>(&actual)
+                );
+            }
+            (n_shapes_written, n_tiles_written)
+        };
+
+        let style = |width| LineStyle {
+            width,
+            color: Color::new(0.5, 0.7, 0.9, 1.0),
+            ..Default::default()
+        };
+        let mut handles = Vec::new();
+        for i in 0..20 {
+            let y = 5.0 + 5.5 * i as f32;
+            let line = Line::new(P2::new(5.0, y), P2::new(155.0, 115.0 - y));
+            handles.push(scene.insert(SceneShape::Line(line, style(2.0))));
+        }
+        assert_eq!(scene.len(), 20);
+        let (n_shapes, n_tiles) =
+            sync(&mut scene, &mut mirror, &mut index_mirror);
+        assert_eq!(n_shapes, 20);
+        assert!(n_tiles > 0);
+        // Each line is stored once, however many tiles it crosses.
+        assert_eq!(scene.line_array_len(), 20);
+        assert!(scene.line_index_len() > 20 * 10);
+
+        // Nothing is written when nothing has changed.
+        assert_eq!(sync(&mut scene, &mut mirror, &mut index_mirror), (0, 0));
+
+        // Moving a short line only rewrites the line and the tiles that it
+        // leaves and enters.
+        let short = scene.insert(SceneShape::Line(
+            Line::new(P2::new(20.0, 20.0), P2::new(24.0, 20.0)),
+            style(1.0),
+        ));
+        sync(&mut scene, &mut mirror, &mut index_mirror);
+        let previous = scene.update(
+            short,
+            SceneShape::Line(
+                Line::new(P2::new(100.0, 90.0), P2::new(104.0, 90.0)),
+                style(1.0),
+            ),
+        );
+        assert!(matches!(previous, Some(SceneShape::Line(..))));
+        let (n_shapes, n_tiles) =
+            sync(&mut scene, &mut mirror, &mut index_mirror);
+        assert_eq!(n_shapes, 1);
+        assert!((1..=4).contains(&n_tiles), "{}", n_tiles);
+
+        // Removed handles are invalid, and are not re-used.
+        for handle in handles.drain(..10) {
+            assert!(scene.remove(handle).is_some());
+            assert!(!scene.contains(handle));
+            assert!(scene.remove(handle).is_none());
+        }
+        sync(&mut scene, &mut mirror, &mut index_mirror);
+        let reinserted = scene.insert(SceneShape::Line(
+            Line::new(P2::new(5.0, 60.0), P2::new(155.0, 60.0)),
+            style(6.0),
+        ));
+        assert!(!handles.contains(&reinserted));
+        assert_eq!(scene.len(), 12);
+        sync(&mut scene, &mut mirror, &mut index_mirror);
+
+        // Widening every line moves tiles to the end of the line index
+        // array, and changing a layer rebuilds every tile and rewrites the
+        // primitives of its shapes.
+        for handle in handles.iter() {
+            let SceneShape::Line(line, _) = scene.get(*handle).unwrap().clone()
+            else {
+                panic!("expected a line");
+            };
+            scene.update(*handle, SceneShape::Line(line, style(12.0)));
+        }
+        sync(&mut scene, &mut mirror, &mut index_mirror);
+        scene.layer_mut(LayerId::DEFAULT).opacity = 0.5;
+        let (n_shapes, _) = sync(&mut scene, &mut mirror, &mut index_mirror);
+        assert_eq!(n_shapes, scene.len());
+
+        scene.clear();
+        assert!(scene.is_empty());
+        sync(&mut scene, &mut mirror, &mut index_mirror);
+        assert!(scene.tile_infos().is_empty());
+    }
+
+    /// A retained scene renders the same as its shapes drawn in immediate
+    /// mode, on the GPU and in software, after only its changed tiles are
+    /// uploaded.
+    #[test]
+    fn test_scene_matches_immediate() {
+        const WIDTH: u32 = 150;
+        const HEIGHT: u32 = 100;
+        const TILE_SIZE: u32 = 16;
+        const TOLERANCE: u8 = 2;
+
+        let gpu = Gpu::new();
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let mut renderer = Renderer::new(
+            &gpu.device,
+            format,
+            WIDTH,
+            HEIGHT,
+            TILE_SIZE,
+            TILE_SIZE,
+        );
+        let mut software =
+            SoftwareRenderer::new(format, WIDTH, HEIGHT, TILE_SIZE, TILE_SIZE);
+        let mut scene = Scene::new(WIDTH, HEIGHT, TILE_SIZE, TILE_SIZE);
+
+        let style = |width, red| LineStyle {
+            width,
+            cap: LineCap::Round,
+            color: Color::new(red, 0.6, 0.3, 0.8),
+            ..Default::default()
+        };
+        let line = |i: usize, width| {
+            let x = 5.0 + 7.0 * i as f32;
+            SceneShape::Line(
+                Line::new(P2::new(x, 5.0), P2::new(145.0 - x, 95.0)),
+                style(width, i as f32 / 20.0),
+            )
+        };
+        let mut shapes: Vec<(ShapeHandle, SceneShape)> = Vec::new();
+        for i in 0..20 {
+            shapes.push((scene.insert(line(i, 2.0)), line(i, 2.0)));
+        }
+        let fill = SceneShape::Fill(
+            Path::polygon(&[
+                P2::new(30.0, 20.0),
+                P2::new(120.0, 30.0),
+                P2::new(70.0, 90.0),
+            ]),
+            FillStyle {
+                color: Color::new(0.2, 0.4, 0.9, 0.6),
+                ..Default::default()
+            },
+        );
+        shapes.push((scene.insert(fill.clone()), fill));
+        let ellipse = SceneShape::Ellipse(
+            Ellipse::new(P2::new(75.0, 50.0), 50.0, 20.0, 0.4),
+            style(3.0, 1.0),
+        );
+        shapes.push((scene.insert(ellipse.clone()), ellipse));
+
+        let mut check = |renderer: &mut Renderer,
+                         scene: &mut Scene,
+                         shapes: &[SceneShape]| {
+            let retained =
+                renderer.render_scene_offscreen(&gpu.device, &gpu.queue, scene);
+            for shape in shapes {
+                match shape.clone() {
+                    SceneShape::Line(line, style) => {
+                        renderer.line(line, &style)
+                    }
+                    SceneShape::Ellipse(ellipse, style) => {
+                        renderer.ellipse(ellipse, &style)
+                    }
+                    SceneShape::Fill(path, style) => {
+                        renderer.fill(&path, &style)
+                    }
+                    _ => unreachable!(
+                        "the scene only has lines, ellipses and fills"
+                    ),
+                }
+            }
+            let immediate = renderer.render_offscreen(&gpu.device, &gpu.queue);
+            let difference = retained.max_difference(&immediate);
+            assert!(difference <= TOLERANCE, "images differ by {}", difference);
+            let difference =
+                software.render_scene(scene).max_difference(&retained);
+            assert!(difference <= TOLERANCE, "images differ by {}", difference);
+        };
+        let current =
+            |shapes: &[(ShapeHandle, SceneShape)]| -> Vec<SceneShape> {
+                shapes.iter().map(|(_, shape)| shape.clone()).collect()
+            };
+        check(&mut renderer, &mut scene, &current(&shapes));
+
+        // Widen some lines, which moves their tiles in the line array,
+        // remove others, and insert new ones.
+        for (i, (handle, shape)) in shapes.iter_mut().enumerate().step_by(3) {
+            *shape = line(i, 9.0);
+            scene.update(*handle, shape.clone());
+        }
+        for _ in 0..4 {
+            let (handle, _) = shapes.remove(5);
+            scene.remove(handle);
+        }
+        for i in 0..3 {
+            let shape = line(2 * i + 1, 5.0);
+            shapes.push((scene.insert(shape.clone()), shape));
+        }
+        check(&mut renderer, &mut scene, &current(&shapes));
+
+        // Nothing changes when the scene is rendered again.
+        check(&mut renderer, &mut scene, &current(&shapes));
+
+        // No stale tiles are left when every shape is removed.
+        for (handle, _) in shapes.drain(..) {
+            scene.remove(handle);
+        }
+        assert_eq!(
+            renderer.render_scene_offscreen(
+                &gpu.device,
+                &gpu.queue,
+                &mut scene
+            ),
+            Image::new(WIDTH, HEIGHT)
+        );
+    }
+}
@@ -0,0 +1,2030 @@
+//! CPU reference rasterizer.
+//!
+//! The software renderer evaluates exactly the same per-pixel operations as
+//! `line_sdf.wgsl`, on the CPU. It consumes the same [`Tiler::drain`] output
+//! as the GPU [`crate::Renderer`], so it can be used as a GPU-free oracle for
+//! image tests, and as a fallback on machines without a WGPU adapter.
+//!
+//! The functions in this module deliberately have the same names and
+//! structure as their counterparts in the shader. When the shader changes,
+//! this module must be changed to match. The same goes for the bloom passes
+//! in `bloom.wgsl`.
+
+use crate::{
+    arc::CircularArc,
+    bezier::{CubicBezier, QuadraticBezier},
+    bloom::{level_sizes, Bloom},
+    buffers,
+    ellipse::{sdf_ellipse_local, Ellipse},
+    image::Image,
+    layer::{Layer, LayerId},
+    path::Path,
+    persistence::{Phosphor, DEFAULT_FRAME_TIME},
+    polyline::Polyline,
+    primitive::StyledPrimitive,
+    renderer::{
+        DEFAULT_ANTIALIAS_WIDTH, DEFAULT_TILE_BACKGROUND, DEFAULT_TILE_EDGES,
+    },
+    scene::Scene,
+    style::{FillRule, FillStyle, LineStyle, StyledLine},
+    tiler::{TileInfo, Tiler},
+    transform::Camera,
+    Color, Line, M3, P2, V2,
+};
+use cgmath::{ElementWise, InnerSpace, Vector3, Vector4, VectorSpace};
+
+/// RGB color used in shader computations: a [`Vector3<f32>`].
+type V3 = Vector3<f32>;
+
+/// RGBA color used in shader computations: a [`Vector4<f32>`].
+type V4 = Vector4<f32>;
+
+/// Software renderer.
+///
+/// This has the same lifecycle as [`crate::Renderer`], except that
+/// [`SoftwareRenderer::render`] returns an [`Image`] instead of recording
+/// GPU commands.
+#[derive(Debug)]
+pub struct SoftwareRenderer {
+    texture_format: wgpu::TextureFormat,
+    area_width: u32,
+    area_height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    tiler: Tiler,
+    antialias_width: f32,
+    draw_tiles: bool,
+    tile_background: Color,
+    tile_edges: Color,
+    persistence: Option<Phosphor>,
+    frame_time: f32,
+    bloom: Option<Bloom>,
+    /// Premultiplied colors accumulated while persistence is enabled, in
+    /// framebuffer row order. This is empty until it is first needed.
+    accumulation: Vec<V4>,
+}
+impl SoftwareRenderer {
+    /// Creates a new `SoftwareRenderer`.
+    ///
+    /// # Parameters
+    ///
+    /// - `texture_format`: Texture format being emulated. This determines
+    ///   whether output is sRGB-encoded, as it would be by the GPU.
+    /// - `area_width`: Width of the renderable area.
+    /// - `area_height`: Height of the renderable area.
+    /// - `tile_width`: Width of a single bucketing tile.
+    /// - `tile_height`: Height of a single bucketing tile.
+    pub fn new(
+        texture_format: wgpu::TextureFormat,
+        area_width: u32,
+        area_height: u32,
+        tile_width: u32,
+        tile_height: u32,
+    ) -> Self {
+        assert!(area_width > 0);
+        assert!(area_height > 0);
+        assert!(tile_width > 0);
+        assert!(tile_height > 0);
+
+        SoftwareRenderer {
+            texture_format,
+            area_width,
+            area_height,
+            tile_width,
+            tile_height,
+            tiler: Tiler::new(area_width, area_height, tile_width, tile_height),
+            antialias_width: DEFAULT_ANTIALIAS_WIDTH,
+            draw_tiles: false,
+            tile_background: DEFAULT_TILE_BACKGROUND,
+            tile_edges: DEFAULT_TILE_EDGES,
+            persistence: None,
+            frame_time: DEFAULT_FRAME_TIME,
+            bloom: None,
+            accumulation: Vec::new(),
+        }
+    }
+
+    /// Adds a line to be rendered.
+    ///
+    /// # Parameters
+    ///
+    /// - `line`: Line to render.
+    /// - `style`: Style of the line to render.
+    pub fn line(&mut self, line: Line, style: &LineStyle) {
+        self.tiler.add(StyledLine {
+            line,
+            style: style.clone(),
+        })
+    }
+
+    /// Adds a polyline to be rendered.
+    ///
+    /// # Parameters
+    ///
+    /// - `polyline`: Polyline to render.
+    /// - `style`: Style of the polyline to render.
+    pub fn polyline(&mut self, polyline: &Polyline, style: &LineStyle) {
+        self.tiler.add_polyline(polyline, style)
+    }
+
+    /// Adds a quadratic Bézier curve to be rendered.
+    ///
+    /// See [`crate::Renderer::quadratic`].
+    pub fn quadratic(&mut self, curve: QuadraticBezier, style: &LineStyle) {
+        self.tiler.add_quadratic(curve, style)
+    }
+
+    /// Adds a cubic Bézier curve to be rendered.
+    ///
+    /// See [`crate::Renderer::cubic`].
+    pub fn cubic(&mut self, curve: CubicBezier, style: &LineStyle) {
+        self.tiler.add_cubic(curve, style)
+    }
+
+    /// Adds a circular arc to be rendered.
+    ///
+    /// See [`crate::Renderer::arc`].
+    pub fn arc(&mut self, arc: CircularArc, style: &LineStyle) {
+        self.tiler.add_arc(arc, style)
+    }
+
+    /// Adds the outline of an ellipse to be rendered.
+    ///
+    /// See [`crate::Renderer::ellipse`].
+    pub fn ellipse(&mut self, ellipse: Ellipse, style: &LineStyle) {
+        self.tiler.add_ellipse(ellipse, style)
+    }
+
+    /// Adds a filled path to be rendered.
+    ///
+    /// See [`crate::Renderer::fill`].
+    pub fn fill(&mut self, path: &Path, style: &FillStyle) {
+        self.tiler.add_fill(path, style)
+    }
+
+    /// Pushes a transform, which applies to the shapes added until it is
+    /// popped.
+    ///
+    /// See [`crate::Renderer::push_transform`].
+    pub fn push_transform(&mut self, matrix: M3) {
+        self.tiler.push_transform(matrix);
+    }
+
+    /// Pops the transform which was pushed last.
+    pub fn pop_transform(&mut self) {
+        self.tiler.pop_transform();
+    }
+
+    /// Sets the camera.
+    ///
+    /// See [`crate::Renderer::set_camera`].
+    pub fn set_camera(&mut self, camera: Option<Camera>) {
+        self.tiler.set_camera(camera);
+    }
+
+    /// Returns the transform which currently maps the coordinates of shapes
+    /// to pixels.
+    pub fn transform(&self) -> M3 {
+        self.tiler.transform()
+    }
+
+    /// Pushes a clip rectangle.
+    ///
+    /// See [`crate::Renderer::push_clip_rect`].
+    pub fn push_clip_rect(&mut self, a: P2, b: P2) {
+        self.tiler.push_clip_rect(a, b);
+    }
+
+    /// Pushes a clip path.
+    ///
+    /// See [`crate::Renderer::push_clip_path`].
+    pub fn push_clip_path(&mut self, path: &Path, rule: FillRule) {
+        self.tiler.push_clip_path(path, rule);
+    }
+
+    /// Pops the clip which was pushed last.
+    pub fn pop_clip(&mut self) {
+        self.tiler.pop_clip();
+    }
+
+    /// Creates a new layer.
+    ///
+    /// See [`crate::Renderer::add_layer`].
+    pub fn add_layer(&mut self, name: &str, z: i32) -> LayerId {
+        self.tiler.add_layer(name, z)
+    }
+
+    /// Returns the identifier of the layer with a name, or `None` if there
+    /// is no such layer.
+    pub fn layer_id(&self, name: &str) -> Option<LayerId> {
+        self.tiler.layer_id(name)
+    }
+
+    /// Returns a layer.
+    pub fn layer(&self, id: LayerId) -> &Layer {
+        self.tiler.layer(id)
+    }
+
+    /// Returns a layer, to change its z index, visibility or group opacity.
+    ///
+    /// See [`crate::Renderer::layer_mut`].
+    pub fn layer_mut(&mut self, id: LayerId) -> &mut Layer {
+        self.tiler.layer_mut(id)
+    }
+
+    /// Selects the layer which shapes are added to.
+    ///
+    /// See [`crate::Renderer::set_layer`].
+    pub fn set_layer(&mut self, id: LayerId) {
+        self.tiler.set_layer(id);
+    }
+
+    /// Resizes the renderer, removing any queued lines.
+    ///
+    /// # Parameters
+    ///
+    /// - `area_width`: Width of the rendering area.
+    /// - `area_height`: Height of the rendering area.
+    pub fn resize(&mut self, area_width: u32, area_height: u32) {
+        assert!(area_width > 0);
+        assert!(area_height > 0);
+
+        self.tiler.resize(area_width, area_height);
+        self.area_height = area_height;
+        self.area_width = area_width;
+        self.accumulation.clear();
+    }
+
+    /// Set whether debugging tiles should be drawn.
+    ///
+    /// See [`crate::Renderer::set_draw_tiles`].
+    pub fn set_draw_tiles(&mut self, value: bool) {
+        self.draw_tiles = value;
+    }
+
+    /// Set the phosphor persistence of lines.
+    ///
+    /// See [`crate::Renderer::set_persistence`].
+    pub fn set_persistence(&mut self, phosphor: Option<Phosphor>) {
+        self.persistence = phosphor;
+        if phosphor.is_none() {
+            self.accumulation = Vec::new();
+        }
+    }
+
+    /// Set the time between frames.
+    ///
+    /// See [`crate::Renderer::set_frame_time`].
+    pub fn set_frame_time(&mut self, seconds: f32) {
+        assert!(seconds >= 0.0);
+        self.frame_time = seconds;
+    }
+
+    /// Set the bloom post-process.
+    ///
+    /// See [`crate::Renderer::set_bloom`].
+    pub fn set_bloom(&mut self, bloom: Option<Bloom>) {
+        self.bloom = bloom;
+    }
+
+    /// Remove the afterglow of earlier frames.
+    ///
+    /// See [`crate::Renderer::clear_persistence`].
+    pub fn clear_persistence(&mut self) {
+        self.accumulation.clear();
+    }
+
+    /// Renders the current set of lines into a new image.
+    ///
+    /// The image starts out transparent black, like the target of
+    /// [`crate::Renderer::render_offscreen`]. When persistence is enabled,
+    /// the lines are accumulated over the decayed lines of earlier frames,
+    /// and the image contains the accumulation. When bloom is enabled, the
+    /// image contains the tone-mapped scene and its bloom.
+    pub fn render(&mut self) -> Image {
+        let (tile_infos, styled_lines, line_indices) = self.tiler.drain();
+        if self.persistence.is_some() || self.bloom.is_some() {
+            self.post_process(&tile_infos, &styled_lines, &line_indices)
+        } else {
+            self.rasterize(&tile_infos, &styled_lines, &line_indices)
+        }
+    }
+
+    /// Renders a retained-mode scene into a new image.
+    ///
+    /// This is the same as [`SoftwareRenderer::render`], except that it
+    /// renders the shapes of the scene (see
+    /// [`crate::Renderer::render_scene`]).
+    ///
+    /// # Parameters
+    ///
+    /// - `scene`: Scene to render. Its area and tile sizes must match the
+    ///   renderer's.
+    pub fn render_scene(&mut self, scene: &mut Scene) -> Image {
+        assert!(
+            scene.has_size(
+                self.area_width,
+                self.area_height,
+                self.tile_width,
+                self.tile_height
+            ),
+            "scene size does not match the renderer"
+        );
+        scene.update_tiles();
+        let (tile_infos, styled_lines, line_indices) = scene.contents();
+        if self.persistence.is_some() || self.bloom.is_some() {
+            self.post_process(&tile_infos, &styled_lines, &line_indices)
+        } else {
+            self.rasterize(&tile_infos, &styled_lines, &line_indices)
+        }
+    }
+
+    /// Rasterizes the output of [`Tiler::drain`] into a new image.
+    ///
+    /// # Parameters
+    ///
+    /// - `tile_infos`: Tile information from the tiler.
+    /// - `styled_lines`: Styled primitives from the tiler.
+    /// - `line_indices`: Indices of the styled primitives of each tile, from
+    ///   the tiler.
+    ///
+    /// # Returns
+    ///
+    /// The rendered RGBA8 image.
+    pub fn rasterize(
+        &self,
+        tile_infos: &[TileInfo],
+        styled_lines: &[StyledPrimitive],
+        line_indices: &[u32],
+    ) -> Image {
+        let mut image = Image::new(self.area_width, self.area_height);
+        let srgb = self.texture_format.is_srgb();
+        self.shade(
+            tile_infos,
+            styled_lines,
+            line_indices,
+            |x, fb_row, color| {
+                image.set_pixel(x, fb_row, store_premultiplied(color, srgb));
+            },
+        );
+        image
+    }
+
+    /// Emulates one frame with post-processing enabled.
+    ///
+    /// This mirrors the decay, line and composite passes of
+    /// [`crate::Renderer::render`] when persistence or bloom are enabled,
+    /// compositing over transparent black.
+    ///
+    /// # Parameters
+    ///
+    /// - `tile_infos`: Tile information from the tiler.
+    /// - `styled_lines`: Styled primitives from the tiler.
+    /// - `line_indices`: Indices of the styled primitives of each tile, from
+    ///   the tiler.
+    ///
+    /// # Returns
+    ///
+    /// The rendered RGBA8 image.
+    fn post_process(
+        &mut self,
+        tile_infos: &[TileInfo],
+        styled_lines: &[StyledPrimitive],
+        line_indices: &[u32],
+    ) -> Image {
+        // Decay pass. Without persistence, the scene starts out transparent
+        // black.
+        let n_pixels = (self.area_width * self.area_height) as usize;
+        let mut texels = std::mem::take(&mut self.accumulation);
+        texels.resize(n_pixels, V4::new(0.0, 0.0, 0.0, 0.0));
+        match self.persistence {
+            Some(phosphor) => {
+                let [r, g, b, a] = phosphor.decay(self.frame_time);
+                for value in texels.iter_mut() {
+                    *value = V4::new(
+                        value.x * r,
+                        value.y * g,
+                        value.z * b,
+                        value.w * a,
+                    );
+                }
+            }
+            None => texels.fill(V4::new(0.0, 0.0, 0.0, 0.0)),
+        }
+        let mut scene = HdrTexture {
+            width: self.area_width,
+            height: self.area_height,
+            texels,
+        };
+
+        // Line pass, using the `PREMULTIPLIED_ALPHA_BLENDING` blend state.
+        self.shade(
+            tile_infos,
+            styled_lines,
+            line_indices,
+            |x, fb_row, color| {
+                let value =
+                    &mut scene.texels[(fb_row * self.area_width + x) as usize];
+                *value = alpha_over(color, *value);
+            },
+        );
+
+        // Composite pass, using the `PREMULTIPLIED_ALPHA_BLENDING` blend
+        // state over transparent black.
+        let output = match &self.bloom {
+            Some(bloom) => apply_bloom(bloom, &scene),
+            None => scene.texels.clone(),
+        };
+        let mut image = Image::new(self.area_width, self.area_height);
+        let srgb = self.texture_format.is_srgb();
+        for fb_row in 0..self.area_height {
+            for x in 0..self.area_width {
+                let color = output[(fb_row * self.area_width + x) as usize];
+                image.set_pixel(x, fb_row, store_premultiplied(color, srgb));
+            }
+        }
+
+        if self.persistence.is_some() {
+            self.accumulation = scene.texels;
+        }
+        image
+    }
+
+    /// Evaluates the fragment shader for every pixel covered by a tile.
+    ///
+    /// # Parameters
+    ///
+    /// - `tile_infos`: Tile information from the tiler.
+    /// - `styled_lines`: Styled primitives from the tiler.
+    /// - `line_indices`: Indices of the styled primitives of each tile, from
+    ///   the tiler.
+    /// - `store`: Called with the column, framebuffer row and color of each
+    ///   fragment.
+    fn shade(
+        &self,
+        tile_infos: &[TileInfo],
+        styled_lines: &[StyledPrimitive],
+        line_indices: &[u32],
+        mut store: impl FnMut(u32, u32, V4),
+    ) {
+        let viewport = buffers::Viewport {
+            area_width: self.area_width,
+            area_height: self.area_height,
+            tile_width: self.tile_width,
+            tile_height: self.tile_height,
+        };
+        let shader_options = buffers::ShaderOptions::new(
+            self.antialias_width,
+            self.draw_tiles,
+            self.tile_background,
+            self.tile_edges,
+        );
+        let lines: Vec<buffers::StyledLine> = styled_lines
+            .iter()
+            .cloned()
+            .map(buffers::StyledLine::new_from_styled_primitive)
+            .collect();
+        let shader = Shader {
+            viewport: &viewport,
+            shader_options: &shader_options,
+            lines: &lines,
+            line_indices,
+        };
+
+        for tile_info in tile_infos {
+            // Visit the framebuffer pixels covered by the tile, clipped to
+            // the renderable area. Tile rows count upwards from the bottom
+            // of the framebuffer.
+            let min_x = tile_info.tile_x * self.tile_width;
+            let max_x = (min_x + self.tile_width).min(self.area_width);
+            let min_y = tile_info.tile_y * self.tile_height;
+            let max_y = (min_y + self.tile_height).min(self.area_height);
+            for y in min_y..max_y {
+                for x in min_x..max_x {
+                    // Fragment position at the pixel center, and its uv
+                    // coordinates within the tile.
+                    let fb_row = self.area_height - 1 - y;
+                    let position = V2::new(x as f32 + 0.5, fb_row as f32 + 0.5);
+                    let uv = V2::new(
+                        (x - min_x) as f32 + 0.5,
+                        (y - min_y) as f32 + 0.5,
+                    );
+                    let uv = V2::new(
+                        uv.x / self.tile_width as f32,
+                        uv.y / self.tile_height as f32,
+                    );
+
+                    store(x, fb_row, shader.fs_main(tile_info, uv, position));
+                }
+            }
+        }
+    }
+}
+
+/// Emulates the fixed-function stage for one fragment.
+///
+/// This applies the `PREMULTIPLIED_ALPHA_BLENDING` blend state of the
+/// render pipelines over a transparent black target, followed by sRGB
+/// encoding (if required) and quantization to 8 bits.
+fn store_premultiplied(color: V4, srgb: bool) -> [u8; 4] {
+    let encode = |c: f32| if srgb { linear_to_srgb(c) } else { c };
+    [
+        unorm8(encode(color.x)),
+        unorm8(encode(color.y)),
+        unorm8(encode(color.z)),
+        unorm8(color.w),
+    ]
+}
+
+/// Converts a value to an 8-bit unsigned normalized value.
+///
+/// NaN values become zero, as they do on GPUs.
+fn unorm8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Encodes a linear color channel value using the sRGB transfer function.
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// CPU mirror of the shader's global bindings.
+struct Shader<'a> {
+    viewport: &'a buffers::Viewport,
+    shader_options: &'a buffers::ShaderOptions,
+    lines: &'a [buffers::StyledLine],
+    line_indices: &'a [u32],
+}
+impl Shader<'_> {
+    /// Mirror of the `fs_main` fragment shader.
+    ///
+    /// # Parameters
+    ///
+    /// - `tile_info`: Tile being rendered.
+    /// - `uv`: uv coordinates of the fragment within the tile.
+    /// - `position`: Framebuffer position of the fragment.
+    fn fs_main(&self, tile_info: &TileInfo, uv: V2, position: V2) -> V4 {
+        let p = self.framebuffer_to_beamline(position);
+
+        let fg_color = self.render_all_lines(
+            tile_info.start_index,
+            tile_info.n_lines,
+            self.tile_fill_reference(tile_info.tile_x, tile_info.tile_y),
+            p,
+        );
+
+        let mut bg_color = V4::new(0.0, 0.0, 0.0, 0.0);
+        if self.shader_options.draw_tiles == 1 {
+            let edge_amount = line_factor(
+                self.shader_options.antialias_width,
+                tile_shortest_edge_distance_uv(uv)
+                    * self.viewport.tile_width as f32
+                    - TILE_EDGE_WIDTH,
+            );
+            let edge_color =
+                premultiply(V4::from(self.shader_options.tile_edges))
+                    * edge_amount;
+            bg_color = alpha_over(
+                premultiply(V4::from(self.shader_options.tile_background)),
+                edge_color,
+            );
+        }
+
+        alpha_over(fg_color, bg_color)
+    }
+
+    /// Mirror of `framebuffer_to_beamline`.
+    fn framebuffer_to_beamline(&self, coord_fb: V2) -> V2 {
+        V2::new(coord_fb.x, self.viewport.area_height as f32 - coord_fb.y)
+    }
+
+    /// Mirror of `tile_line`.
+    fn tile_line(&self, i: usize) -> &buffers::StyledLine {
+        &self.lines[self.line_indices[i] as usize]
+    }
+
+    /// Mirror of `render_all_lines`.
+    fn render_all_lines(
+        &self,
+        start_index: u32,
+        n_lines: u32,
+        reference: V2,
+        p: V2,
+    ) -> V4 {
+        let mut color = V4::new(0.0, 0.0, 0.0, 0.0);
+        let mut group = V4::new(0.0, 0.0, 0.0, 0.0);
+        let mut group_layer = NO_LAYER;
+        let mut group_opacity = 1.0;
+        let end_index = (start_index + n_lines) as usize;
+        let mut i = start_index as usize;
+        while i < end_index {
+            let mut line = self.tile_line(i);
+            i += 1;
+
+            let mut clip = 1.0;
+            while line.kind == buffers::PRIMITIVE_CLIP && i < end_index {
+                let mut winding = line.winding;
+                let mut dist = DIST_FAR;
+                while i < end_index
+                    && self.tile_line(i).kind == buffers::PRIMITIVE_FILL_EDGE
+                {
+                    let edge = self.tile_line(i);
+                    let (a, b) = (V2::from(edge.start), V2::from(edge.end));
+                    winding += fill_winding(a, b, reference, p);
+                    dist = dist.min(sdf_rounded_line(a, b, 0.0, p));
+                    i += 1;
+                }
+                let clip_sdf = if fill_inside(line.fill_rule, winding) {
+                    -dist
+                } else {
+                    dist
+                };
+                clip *=
+                    line_factor(self.shader_options.antialias_width, clip_sdf);
+                line = self.tile_line(i);
+                i += 1;
+            }
+
+            if line.layer != group_layer {
+                color = alpha_over(group * group_opacity, color);
+                group = V4::new(0.0, 0.0, 0.0, 0.0);
+                group_layer = line.layer;
+                group_opacity = line.layer_opacity;
+            }
+
+            let mut shape_color = V4::from(line.color);
+            let sdf = if line.kind == buffers::PRIMITIVE_FILL {
+                let mut winding = line.winding;
+                let mut dist = DIST_FAR;
+                while i < end_index && self.tile_line(i).shape == line.shape {
+                    let edge = self.tile_line(i);
+                    let (a, b) = (V2::from(edge.start), V2::from(edge.end));
+                    winding += fill_winding(a, b, reference, p);
+                    dist = dist.min(sdf_rounded_line(a, b, 0.0, p));
+                    i += 1;
+                }
+                if fill_inside(line.fill_rule, winding) {
+                    -dist
+                } else {
+                    dist
+                }
+            } else {
+                let mut sdf = sdf_styled_line(line, p);
+                shape_color = styled_line_color(line, p);
+                while i < end_index && self.tile_line(i).shape == line.shape {
+                    let line_sdf = sdf_styled_line(self.tile_line(i), p);
+                    if line_sdf < sdf {
+                        sdf = line_sdf;
+                        shape_color = styled_line_color(self.tile_line(i), p);
+                    }
+                    i += 1;
+                }
+                sdf
+            };
+
+            let line_alpha_mul = glow_line_factor(
+                self.shader_options.antialias_width,
+                line,
+                sdf,
+            ) * clip
+                * clip_rect_coverage(line, p);
+            let line_color = premultiply(shape_color) * line_alpha_mul;
+            if line_color.w > THRESHOLD_MIN_ALPHA {
+                if group_opacity < 1.0 {
+                    group = blend_over(line.blend_mode, line_color, group);
+                } else {
+                    color = blend_over(line.blend_mode, line_color, color);
+                }
+            }
+        }
+        alpha_over(group * group_opacity, color)
+    }
+
+    /// Mirror of `tile_fill_reference`.
+    fn tile_fill_reference(&self, tile_x: u32, tile_y: u32) -> V2 {
+        V2::new(
+            ((tile_x + 1) * self.viewport.tile_width) as f32 - 0.5,
+            (tile_y * self.viewport.tile_height) as f32 + 0.5,
+        )
+    }
+}
+
+/// Mirror of `clip_rect_coverage`.
+fn clip_rect_coverage(styled_line: &buffers::StyledLine, p: V2) -> f32 {
+    let low_x = styled_line.clip_min[0].max(p.x - 0.5);
+    let low_y = styled_line.clip_min[1].max(p.y - 0.5);
+    let high_x = styled_line.clip_max[0].min(p.x + 0.5);
+    let high_y = styled_line.clip_max[1].min(p.y + 0.5);
+    (high_x - low_x).max(0.0) * (high_y - low_y).max(0.0)
+}
+
+/// Mirror of `fill_winding`.
+fn fill_winding(a: V2, b: V2, reference: V2, p: V2) -> i32 {
+    let mut winding = 0;
+
+    if (a.x <= reference.x) != (b.x <= reference.x) {
+        let y = a.y + (reference.x - a.x) * (b.y - a.y) / (b.x - a.x);
+        let rising = (b.y - a.y) * (b.x - a.x) > 0.0;
+        let above_start = y > reference.y || (y == reference.y && rising);
+        let below_end = y < p.y || (y == p.y && !rising);
+        if above_start && below_end {
+            winding += if b.x > a.x { 1 } else { -1 };
+        }
+    }
+
+    if (a.y <= p.y) != (b.y <= p.y) {
+        let x = a.x + (p.y - a.y) * (b.x - a.x) / (b.y - a.y);
+        if x > p.x && x <= reference.x {
+            winding += if b.y > a.y { 1 } else { -1 };
+        }
+    }
+
+    winding
+}
+
+/// Mirror of `fill_inside`.
+fn fill_inside(fill_rule: u32, winding: i32) -> bool {
+    if fill_rule == FILL_RULE_EVEN_ODD {
+        return (winding & 1) != 0;
+    }
+    winding != 0
+}
+
+/// Mirror of `premultiply`.
+fn premultiply(color: V4) -> V4 {
+    (color.truncate() * color.w).extend(color.w)
+}
+
+/// Mirror of `alpha_over`.
+fn alpha_over(a: V4, b: V4) -> V4 {
+    a + b * (1.0 - a.w)
+}
+
+/// Mirror of `blend_over`.
+fn blend_over(mode: u32, a: V4, b: V4) -> V4 {
+    if mode == BLEND_MODE_ADDITIVE {
+        return (a.truncate() + b.truncate()).extend((a.w + b.w).min(1.0));
+    }
+    let color = a.truncate() * (1.0 - b.w)
+        + b.truncate() * (1.0 - a.w)
+        + blend_channels(mode, a, b);
+    color.extend(a.w + b.w - a.w * b.w)
+}
+
+/// Mirror of `blend_channels`.
+fn blend_channels(mode: u32, a: V4, b: V4) -> V3 {
+    let (a_color, b_color) = (a.truncate(), b.truncate());
+    match mode {
+        BLEND_MODE_SCREEN => {
+            a_color * b.w + b_color * a.w - a_color.mul_element_wise(b_color)
+        }
+        BLEND_MODE_MAX => {
+            let (a_color, b_color) = (a_color * b.w, b_color * a.w);
+            V3::new(
+                a_color.x.max(b_color.x),
+                a_color.y.max(b_color.y),
+                a_color.z.max(b_color.z),
+            )
+        }
+        BLEND_MODE_MULTIPLY => a_color.mul_element_wise(b_color),
+        _ => a_color * b.w,
+    }
+}
+
+/// Mirror of `tile_shortest_edge_distance_uv`.
+fn tile_shortest_edge_distance_uv(uv: V2) -> f32 {
+    let min_x = uv.x.min(1.0 - uv.x);
+    let min_y = uv.y.min(1.0 - uv.y);
+    min_x.min(min_y)
+}
+
+/// Mirror of `line_factor`.
+fn line_factor(antialias_width: f32, dist: f32) -> f32 {
+    let aw2 = antialias_width / 2.0;
+    1.0 - smoothstep(-aw2, aw2, dist)
+}
+
+/// Mirror of `glow_line_factor`.
+fn glow_line_factor(
+    antialias_width: f32,
+    styled_line: &buffers::StyledLine,
+    dist: f32,
+) -> f32 {
+    let core = line_factor(antialias_width, dist);
+    if styled_line.glow_radius <= 0.0 {
+        return core;
+    }
+    let t = (dist / styled_line.glow_radius).clamp(0.0, 1.0);
+    let glow = (styled_line.glow_intensity
+        * glow_falloff(styled_line.glow_falloff, t))
+    .min(1.0);
+    core + (1.0 - core) * glow
+}
+
+/// Mirror of `glow_falloff`.
+fn glow_falloff(falloff: u32, t: f32) -> f32 {
+    let s = 1.0 - t;
+    if falloff == GLOW_FALLOFF_QUADRATIC {
+        s * s
+    } else if falloff == GLOW_FALLOFF_GAUSSIAN {
+        let floor = (-GLOW_GAUSSIAN_K).exp();
+        ((-GLOW_GAUSSIAN_K * t * t).exp() - floor) / (1.0 - floor)
+    } else {
+        s
+    }
+}
+
+/// Mirror of `styled_line_color`.
+fn styled_line_color(styled_line: &buffers::StyledLine, p: V2) -> V4 {
+    if styled_line.interpolation == 0 {
+        return V4::from(styled_line.color);
+    }
+    let t = mix(
+        styled_line.gradient_start,
+        styled_line.gradient_end,
+        styled_line_param(styled_line, p),
+    );
+    interpolate_color(
+        V4::from(styled_line.color),
+        V4::from(styled_line.end_color),
+        t,
+        styled_line.interpolation,
+    )
+}
+
+/// Mirror of `styled_line_param`.
+fn styled_line_param(styled_line: &buffers::StyledLine, p: V2) -> f32 {
+    let cp = styled_line.points.map(V2::from);
+    if styled_line.kind == buffers::PRIMITIVE_POLYGON
+        || styled_line.kind == buffers::PRIMITIVE_DISC
+        || styled_line.kind == buffers::PRIMITIVE_ELLIPSE
+        || styled_line.kind == buffers::PRIMITIVE_MARKER
+    {
+        return 0.0;
+    } else if styled_line.kind == buffers::PRIMITIVE_QUADRATIC {
+        return closest_param_quadratic(cp[0], cp[1], cp[2], p);
+    } else if styled_line.kind == buffers::PRIMITIVE_CUBIC {
+        return closest_param_cubic(cp[0], cp[1], cp[2], cp[3], p);
+    } else if styled_line.kind == buffers::PRIMITIVE_ARC {
+        return arc_param(V2::from(styled_line.start), cp[0].y, cp[1], p);
+    }
+    let pa = p - V2::from(styled_line.start);
+    let ba = V2::from(styled_line.end) - V2::from(styled_line.start);
+    (pa.dot(ba) / ba.dot(ba)).clamp(0.0, 1.0)
+}
+
+/// Mirror of `arc_param`.
+fn arc_param(center: V2, half_angle: f32, mid_dir: V2, p: V2) -> f32 {
+    let w = p - center;
+    let angle = w.dot(V2::new(mid_dir.y, -mid_dir.x)).atan2(w.dot(mid_dir));
+    (0.5 - angle / (2.0 * half_angle)).clamp(0.0, 1.0)
+}
+
+/// Mirror of `interpolate_color`.
+fn interpolate_color(a: V4, b: V4, t: f32, interpolation: u32) -> V4 {
+    let alpha = mix(a.w, b.w, t);
+    let rgb = if interpolation == COLOR_INTERPOLATION_SRGB {
+        srgb_decode(
+            srgb_encode(a.truncate()).lerp(srgb_encode(b.truncate()), t),
+        )
+    } else if interpolation == COLOR_INTERPOLATION_OKLAB {
+        oklab_to_linear(
+            linear_to_oklab(a.truncate())
+                .lerp(linear_to_oklab(b.truncate()), t),
+        )
+    } else {
+        a.truncate().lerp(b.truncate(), t)
+    };
+    rgb.extend(alpha)
+}
+
+/// Mirror of `srgb_encode`.
+fn srgb_encode(c: V3) -> V3 {
+    c.map(|x| {
+        if x <= 0.0031308 {
+            x * 12.92
+        } else {
+            1.055 * x.max(0.0).powf(1.0 / 2.4) - 0.055
+        }
+    })
+}
+
+/// Mirror of `srgb_decode`.
+fn srgb_decode(c: V3) -> V3 {
+    c.map(|x| {
+        if x <= 0.04045 {
+            x / 12.92
+        } else {
+            ((x + 0.055) / 1.055).max(0.0).powf(2.4)
+        }
+    })
+}
+
+/// Mirror of `linear_to_oklab`.
+fn linear_to_oklab(c: V3) -> V3 {
+    let l = cbrt(0.412_221_46 * c.x + 0.536_332_55 * c.y + 0.051_445_995 * c.z);
+    let m = cbrt(0.211_903_5 * c.x + 0.680_699_5 * c.y + 0.107_396_96 * c.z);
+    let s = cbrt(0.088_302_46 * c.x + 0.281_718_85 * c.y + 0.629_978_7 * c.z);
+    V3::new(
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    )
+}
+
+/// Mirror of `oklab_to_linear`.
+fn oklab_to_linear(lab: V3) -> V3 {
+    let l_ = lab.x + 0.396_337_78 * lab.y + 0.215_803_76 * lab.z;
+    let m_ = lab.x - 0.105_561_346 * lab.y - 0.063_854_17 * lab.z;
+    let s_ = lab.x - 0.089_484_18 * lab.y - 1.291_485_5 * lab.z;
+    let l = l_ * l_ * l_;
+    let m = m_ * m_ * m_;
+    let s = s_ * s_ * s_;
+    V3::new(
+        4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+        -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+        -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+    )
+}
+
+/// Mirror of `cbrt`.
+fn cbrt(x: f32) -> f32 {
+    sign(x) * x.abs().max(CBRT_TINY).powf(1.0 / 3.0)
+}
+
+/// Mirror of the builtin `mix`.
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+
+/// Mirror of `sdf_styled_line`.
+fn sdf_styled_line(styled_line: &buffers::StyledLine, p: V2) -> f32 {
+    let width_2 = styled_line.width / 2.0;
+    let start = V2::from(styled_line.start);
+
+    if styled_line.kind == buffers::PRIMITIVE_POLYGON {
+        sdf_polygon(&styled_line.points, styled_line.n_points, p)
+    } else if styled_line.kind == buffers::PRIMITIVE_DISC {
+        (p - start).magnitude() - width_2
+    } else if styled_line.kind == buffers::PRIMITIVE_QUADRATIC {
+        let cp = styled_line.points.map(V2::from);
+        sdf_quadratic(
+            cp[0],
+            cp[1],
+            cp[2],
+            width_2,
+            styled_line.start_cap,
+            styled_line.end_cap,
+            p,
+        )
+    } else if styled_line.kind == buffers::PRIMITIVE_ARC {
+        let params = styled_line.points.map(V2::from);
+        sdf_arc(
+            start,
+            params[0].x,
+            params[0].y,
+            params[1],
+            width_2,
+            styled_line.start_cap,
+            styled_line.end_cap,
+            p,
+        )
+    } else if styled_line.kind == buffers::PRIMITIVE_ELLIPSE {
+        let params = styled_line.points.map(V2::from);
+        sdf_ellipse(start, params[0], params[1], width_2, p)
+    } else if styled_line.kind == buffers::PRIMITIVE_MARKER {
+        sdf_marker(
+            start,
+            V2::from(styled_line.end),
+            width_2,
+            styled_line.marker,
+            styled_line.points[0][0],
+            p,
+        )
+    } else if styled_line.kind == buffers::PRIMITIVE_CUBIC {
+        let cp = styled_line.points.map(V2::from);
+        sdf_cubic(
+            cp[0],
+            cp[1],
+            cp[2],
+            cp[3],
+            width_2,
+            styled_line.start_cap,
+            styled_line.end_cap,
+            p,
+        )
+    } else if styled_line.dash_count > 0 {
+        sdf_dashed_segment(styled_line, p)
+    } else {
+        sdf_segment(
+            start,
+            V2::from(styled_line.end),
+            width_2,
+            styled_line.end_width / 2.0,
+            styled_line.start_cap,
+            styled_line.end_cap,
+            p,
+        )
+    }
+}
+
+/// Mirror of `sdf_segment`.
+fn sdf_segment(
+    start: V2,
+    end: V2,
+    start_half_width: f32,
+    end_half_width: f32,
+    start_cap: u32,
+    end_cap: u32,
+    p: V2,
+) -> f32 {
+    if start_half_width != end_half_width {
+        return sdf_tapered_segment(
+            start,
+            end,
+            start_half_width,
+            end_half_width,
+            start_cap,
+            end_cap,
+            p,
+        );
+    }
+
+    let half_width = start_half_width;
+    let start_round = start_cap != END_CAP_BUTT && start_cap != END_CAP_SQUARE;
+    let end_round = end_cap != END_CAP_BUTT && end_cap != END_CAP_SQUARE;
+    if start_round && end_round {
+        return sdf_rounded_line(start, end, half_width, p);
+    }
+
+    let extend_start = if start_cap == END_CAP_SQUARE {
+        half_width
+    } else {
+        0.0
+    };
+    let extend_end = if end_cap == END_CAP_SQUARE {
+        half_width
+    } else {
+        0.0
+    };
+    let mut dist =
+        sdf_square_line(start, end, half_width, extend_start, extend_end, p);
+    if start_round {
+        dist = dist.min((p - start).magnitude() - half_width);
+    }
+    if end_round {
+        dist = dist.min((p - end).magnitude() - half_width);
+    }
+    dist
+}
+
+/// Mirror of `sdf_tapered_segment`.
+fn sdf_tapered_segment(
+    start: V2,
+    end: V2,
+    start_half_width: f32,
+    end_half_width: f32,
+    start_cap: u32,
+    end_cap: u32,
+    p: V2,
+) -> f32 {
+    let start_round = start_cap != END_CAP_BUTT && start_cap != END_CAP_SQUARE;
+    let end_round = end_cap != END_CAP_BUTT && end_cap != END_CAP_SQUARE;
+    if start_round && end_round {
+        return sdf_uneven_capsule(
+            start,
+            end,
+            start_half_width,
+            end_half_width,
+            p,
+        );
+    }
+
+    let v = end - start;
+    let len = v.magnitude();
+    let d = v / len;
+    let w = p - start;
+    let q = V2::new(w.dot(d), (d.x * w.y - d.y * w.x).abs());
+
+    let extend_start = if start_cap == END_CAP_SQUARE {
+        start_half_width
+    } else {
+        0.0
+    };
+    let extend_end = if end_cap == END_CAP_SQUARE {
+        end_half_width
+    } else {
+        0.0
+    };
+    let mut dist = sdf_tapered_body(
+        len,
+        start_half_width,
+        end_half_width,
+        extend_start,
+        extend_end,
+        q,
+    );
+    if start_round {
+        dist = dist.min((p - start).magnitude() - start_half_width);
+    }
+    if end_round {
+        dist = dist.min((p - end).magnitude() - end_half_width);
+    }
+    dist
+}
+
+/// Mirror of `sdf_tapered_body`.
+fn sdf_tapered_body(
+    len: f32,
+    ra: f32,
+    rb: f32,
+    extend_a: f32,
+    extend_b: f32,
+    q: V2,
+) -> f32 {
+    let x0 = -extend_a;
+    let x1 = len + extend_b;
+    let mut dist = distance_to_segment(V2::new(x0, 0.0), V2::new(x0, ra), q);
+    dist = dist.min(distance_to_segment(V2::new(x0, ra), V2::new(0.0, ra), q));
+    dist = dist.min(distance_to_segment(V2::new(0.0, ra), V2::new(len, rb), q));
+    dist = dist.min(distance_to_segment(V2::new(len, rb), V2::new(x1, rb), q));
+    dist = dist.min(distance_to_segment(V2::new(x1, rb), V2::new(x1, 0.0), q));
+
+    let height = if q.x < 0.0 {
+        ra
+    } else if q.x > len {
+        rb
+    } else {
+        mix(ra, rb, q.x / len)
+    };
+    let inside = q.x > x0 && q.x < x1 && q.y < height;
+    if inside {
+        -dist
+    } else {
+        dist
+    }
+}
+
+/// Mirror of `distance_to_segment`.
+fn distance_to_segment(a: V2, b: V2, p: V2) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let h = (pa.dot(ba) / ba.dot(ba).max(CURVE_TINY)).clamp(0.0, 1.0);
+    (pa - ba * h).magnitude()
+}
+
+/// Mirror of `sdf_uneven_capsule`.
+fn sdf_uneven_capsule(a: V2, b: V2, ra: f32, rb: f32, p: V2) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let h = ba.dot(ba);
+    let dr = ra - rb;
+    if dr * dr >= h {
+        return (pa.magnitude() - ra).min((p - b).magnitude() - rb);
+    }
+
+    let q = V2::new(pa.dot(V2::new(ba.y, -ba.x)).abs(), pa.dot(ba)) / h;
+    let c = V2::new((h - dr * dr).sqrt(), dr);
+
+    let k = c.x * q.y - c.y * q.x;
+    if k < 0.0 {
+        return (h * q.dot(q)).sqrt() - ra;
+    } else if k > c.x {
+        return (h * (q.dot(q) + 1.0 - 2.0 * q.y)).sqrt() - rb;
+    }
+    c.dot(q) - ra
+}
+
+/// Mirror of `sdf_dashed_segment`.
+fn sdf_dashed_segment(styled_line: &buffers::StyledLine, p: V2) -> f32 {
+    let start = V2::from(styled_line.start);
+    let v = V2::from(styled_line.end) - start;
+    let len = v.magnitude();
+    let d = v / len;
+    let start_half_width = styled_line.width / 2.0;
+    let end_half_width = styled_line.end_width / 2.0;
+    let lengths = &styled_line.dash_lengths;
+
+    let t = (p - start).dot(d);
+    let n = (d.x * (p.y - start.y) - d.y * (p.x - start.x)).abs();
+
+    let mut period = 0.0;
+    for length in &lengths[..styled_line.dash_count as usize] {
+        period += length;
+    }
+
+    let s = styled_line.dash_phase + t.clamp(0.0, len);
+    let base = (s / period).floor() * period - styled_line.dash_phase;
+
+    let mut dist = DIST_FAR;
+    for k in -1..=1 {
+        let mut u = base + k as f32 * period;
+        for i in (0..styled_line.dash_count as usize - 1).step_by(2) {
+            let dash_start = u;
+            let dash_end = u + lengths[i];
+            u = dash_end + lengths[i + 1];
+            if dash_end < 0.0 || dash_start > len {
+                continue;
+            }
+
+            let cap_a = if dash_start <= 0.0 {
+                styled_line.start_cap
+            } else {
+                styled_line.dash_cap
+            };
+            let cap_b = if dash_end >= len {
+                styled_line.end_cap
+            } else {
+                styled_line.dash_cap
+            };
+            let a = dash_start.max(0.0);
+            let b = dash_end.min(len);
+            let hw_a = mix(start_half_width, end_half_width, a / len);
+            let hw_b = mix(start_half_width, end_half_width, b / len);
+            dist = dist.min(sdf_dash(a, b, hw_a, hw_b, cap_a, cap_b, t, n));
+        }
+    }
+    dist
+}
+
+/// Mirror of `sdf_dash`.
+#[allow(clippy::too_many_arguments)]
+fn sdf_dash(
+    a: f32,
+    b: f32,
+    half_width_a: f32,
+    half_width_b: f32,
+    cap_a: u32,
+    cap_b: u32,
+    t: f32,
+    n: f32,
+) -> f32 {
+    if half_width_a != half_width_b {
+        return sdf_tapered_segment(
+            V2::new(a, 0.0),
+            V2::new(b, 0.0),
+            half_width_a,
+            half_width_b,
+            cap_a,
+            cap_b,
+            V2::new(t, n),
+        );
+    }
+
+    let half_width = half_width_a;
+    let round_a = cap_a != END_CAP_BUTT && cap_a != END_CAP_SQUARE;
+    let round_b = cap_b != END_CAP_BUTT && cap_b != END_CAP_SQUARE;
+    let extend_a = if cap_a == END_CAP_SQUARE {
+        half_width
+    } else {
+        0.0
+    };
+    let extend_b = if cap_b == END_CAP_SQUARE {
+        half_width
+    } else {
+        0.0
+    };
+
+    let mut dist = DIST_FAR;
+    if b - a + extend_a + extend_b > 0.0 {
+        let q =
+            V2::new((a - extend_a - t).max(t - b - extend_b), n - half_width);
+        dist = V2::new(q.x.max(0.0), q.y.max(0.0)).magnitude()
+            + q.x.max(q.y).min(0.0);
+    }
+    if round_a {
+        dist = dist.min(V2::new(t - a, n).magnitude() - half_width);
+    }
+    if round_b {
+        dist = dist.min(V2::new(t - b, n).magnitude() - half_width);
+    }
+    dist
+}
+
+/// Mirror of `sdf_rounded_line`.
+pub(crate) fn sdf_rounded_line(start: V2, end: V2, radius: f32, p: V2) -> f32 {
+    let pa = p - start;
+    let ba = end - start;
+    let h = (pa.dot(ba) / ba.dot(ba)).clamp(0.0, 1.0);
+    let line_dist = (pa - ba * h).magnitude();
+
+    line_dist - radius
+}
+
+/// Mirror of `sdf_square_line`.
+fn sdf_square_line(
+    start: V2,
+    end: V2,
+    half_width: f32,
+    extend_start: f32,
+    extend_end: f32,
+    p: V2,
+) -> f32 {
+    let v = end - start;
+    let a = v.magnitude();
+    let d = v / a;
+    let l = a + extend_start + extend_end;
+    let center = (start + end) / 2.0 + d * (extend_end - extend_start) / 2.0;
+
+    let q = p - center;
+    let q = V2::new(d.x * q.x + d.y * q.y, -d.y * q.x + d.x * q.y);
+    let q = V2::new(q.x.abs() - 0.5 * l, q.y.abs() - half_width);
+    V2::new(q.x.max(0.0), q.y.max(0.0)).magnitude() + q.x.max(q.y).min(0.0)
+}
+
+/// Mirror of `sdf_marker`.
+fn sdf_marker(
+    tip: V2,
+    direction: V2,
+    half_width: f32,
+    shape: u32,
+    marker_length: f32,
+    p: V2,
+) -> f32 {
+    let n = V2::new(-direction.y, direction.x);
+    let l2 = marker_length / 2.0;
+    let back = tip - marker_length * direction;
+    let mut vertices = [[0.0; 2]; 6];
+
+    if shape == MARKER_ARROW {
+        vertices[0] = tip.into();
+        vertices[1] = (back + l2 * n).into();
+        vertices[2] = (back - l2 * n).into();
+        sdf_polygon(&vertices, 3, p)
+    } else if shape == MARKER_OPEN_ARROW {
+        sdf_rounded_line(tip, back + l2 * n, half_width, p)
+            .min(sdf_rounded_line(tip, back - l2 * n, half_width, p))
+    } else if shape == MARKER_DIAMOND {
+        vertices[0] = (tip + l2 * direction).into();
+        vertices[1] = (tip + l2 * n).into();
+        vertices[2] = (tip - l2 * direction).into();
+        vertices[3] = (tip - l2 * n).into();
+        sdf_polygon(&vertices, 4, p)
+    } else if shape == MARKER_BAR {
+        sdf_square_line(tip - l2 * n, tip + l2 * n, half_width, 0.0, 0.0, p)
+    } else {
+        (p - tip).magnitude() - l2
+    }
+}
+
+/// Mirror of `sdf_quadratic`.
+fn sdf_quadratic(
+    p0: V2,
+    p1: V2,
+    p2: V2,
+    half_width: f32,
+    start_cap: u32,
+    end_cap: u32,
+    p: V2,
+) -> f32 {
+    let t = closest_param_quadratic(p0, p1, p2, p);
+    sdf_curve(
+        eval_quadratic(p0, p1, p2, t),
+        p0,
+        -curve_direction(p1 - p0, p2 - p0, p2 - p0),
+        p2,
+        curve_direction(p2 - p1, p2 - p0, p2 - p0),
+        half_width,
+        start_cap,
+        end_cap,
+        p,
+    )
+}
+
+/// Mirror of `sdf_cubic`.
+#[allow(clippy::too_many_arguments)]
+fn sdf_cubic(
+    p0: V2,
+    p1: V2,
+    p2: V2,
+    p3: V2,
+    half_width: f32,
+    start_cap: u32,
+    end_cap: u32,
+    p: V2,
+) -> f32 {
+    let t = closest_param_cubic(p0, p1, p2, p3, p);
+    sdf_curve(
+        eval_cubic(p0, p1, p2, p3, t),
+        p0,
+        -curve_direction(p1 - p0, p2 - p0, p3 - p0),
+        p3,
+        curve_direction(p3 - p2, p3 - p1, p3 - p0),
+        half_width,
+        start_cap,
+        end_cap,
+        p,
+    )
+}
+
+/// Mirror of `sdf_curve`.
+#[allow(clippy::too_many_arguments)]
+fn sdf_curve(
+    closest: V2,
+    start: V2,
+    start_dir: V2,
+    end: V2,
+    end_dir: V2,
+    half_width: f32,
+    start_cap: u32,
+    end_cap: u32,
+    p: V2,
+) -> f32 {
+    let mut dist = (p - closest).magnitude() - half_width;
+    dist = sdf_curve_end(dist, start, start_dir, half_width, start_cap, p);
+    dist = sdf_curve_end(dist, end, end_dir, half_width, end_cap, p);
+    dist
+}
+
+/// Mirror of `sdf_curve_end`.
+fn sdf_curve_end(
+    dist: f32,
+    point: V2,
+    dir: V2,
+    half_width: f32,
+    cap: u32,
+    p: V2,
+) -> f32 {
+    let round = cap != END_CAP_BUTT && cap != END_CAP_SQUARE;
+    let extend = if cap == END_CAP_SQUARE {
+        half_width
+    } else {
+        0.0
+    };
+    let w = p - point;
+    if round || w.magnitude() > 2.0 * half_width + extend + CURVE_CAP_MARGIN {
+        return dist;
+    }
+
+    let beyond = w.dot(dir);
+    if beyond <= 0.0 {
+        return dist.max(beyond - extend);
+    }
+    let q = V2::new(
+        beyond - extend,
+        (dir.x * w.y - dir.y * w.x).abs() - half_width,
+    );
+    V2::new(q.x.max(0.0), q.y.max(0.0)).magnitude() + q.x.max(q.y).min(0.0)
+}
+
+/// Mirror of `sdf_arc`.
+#[allow(clippy::too_many_arguments)]
+fn sdf_arc(
+    center: V2,
+    radius: f32,
+    half_angle: f32,
+    mid_dir: V2,
+    half_width: f32,
+    start_cap: u32,
+    end_cap: u32,
+    p: V2,
+) -> f32 {
+    let w = p - center;
+    if half_angle >= std::f32::consts::PI {
+        return (w.magnitude() - radius).abs() - half_width;
+    }
+
+    let x = w.dot(V2::new(mid_dir.y, -mid_dir.x));
+    let q = V2::new(x.abs(), w.dot(mid_dir));
+
+    let sc = V2::new(half_angle.sin(), half_angle.cos());
+    let end_point = radius * sc;
+    let mut dist = (q.magnitude() - radius).abs() - half_width;
+    if sc.y * q.x > sc.x * q.y {
+        dist = (q - end_point).magnitude() - half_width;
+    }
+
+    let cap = if x >= 0.0 { start_cap } else { end_cap };
+    sdf_curve_end(dist, end_point, V2::new(sc.y, -sc.x), half_width, cap, q)
+}
+
+/// Mirror of `sdf_ellipse`.
+fn sdf_ellipse(center: V2, radii: V2, axis: V2, half_width: f32, p: V2) -> f32 {
+    let w = p - center;
+    let local = V2::new(w.dot(axis), w.dot(V2::new(-axis.y, axis.x)));
+    sdf_ellipse_local(radii, local).abs() - half_width
+}
+
+/// Mirror of `curve_direction`.
+fn curve_direction(a: V2, b: V2, c: V2) -> V2 {
+    if a.dot(a) > CURVE_EPSILON {
+        a.normalize()
+    } else if b.dot(b) > CURVE_EPSILON {
+        b.normalize()
+    } else if c.dot(c) > CURVE_EPSILON {
+        c.normalize()
+    } else {
+        V2::new(1.0, 0.0)
+    }
+}
+
+/// Mirror of `eval_quadratic`.
+fn eval_quadratic(p0: V2, p1: V2, p2: V2, t: f32) -> V2 {
+    let s = 1.0 - t;
+    s * s * p0 + 2.0 * s * t * p1 + t * t * p2
+}
+
+/// Mirror of `eval_cubic`.
+fn eval_cubic(p0: V2, p1: V2, p2: V2, p3: V2, t: f32) -> V2 {
+    let s = 1.0 - t;
+    s * s * s * p0
+        + 3.0 * s * s * t * p1
+        + 3.0 * s * t * t * p2
+        + t * t * t * p3
+}
+
+/// Mirror of `closest_param_quadratic`.
+fn closest_param_quadratic(p0: V2, p1: V2, p2: V2, p: V2) -> f32 {
+    let a = p1 - p0;
+    let b = p0 - 2.0 * p1 + p2;
+    let c = a * 2.0;
+    let d = p0 - p;
+
+    if b.dot(b) <= CURVE_STRAIGHT * c.dot(c) {
+        if c.dot(c) <= CURVE_EPSILON {
+            return 0.0;
+        }
+        return (-d.dot(c) / c.dot(c)).clamp(0.0, 1.0);
+    }
+
+    let kk = 1.0 / b.dot(b);
+    let kx = kk * a.dot(b);
+    let ky = kk * (2.0 * a.dot(a) + d.dot(b)) / 3.0;
+    let kz = kk * d.dot(a);
+    let pp = ky - kx * kx;
+    let q = kx * (2.0 * kx * kx - 3.0 * ky) + kz;
+    let h = q * q + 4.0 * pp * pp * pp;
+    if h >= 0.0 {
+        let hs = h.sqrt();
+        let x = V2::new((hs - q) / 2.0, (-hs - q) / 2.0);
+        let cbrt = |x: f32| sign(x) * x.abs().max(CURVE_TINY).powf(1.0 / 3.0);
+        return (cbrt(x.x) + cbrt(x.y) - kx).clamp(0.0, 1.0);
+    }
+
+    let z = (-pp).sqrt();
+    let v = (q / (pp * z * 2.0)).clamp(-1.0, 1.0).acos() / 3.0;
+    let m = v.cos();
+    let n = v.sin() * 1.732_050_8;
+    let tx = ((m + m) * z - kx).clamp(0.0, 1.0);
+    let ty = ((-n - m) * z - kx).clamp(0.0, 1.0);
+    let qx = d + (c + b * tx) * tx;
+    let qy = d + (c + b * ty) * ty;
+    if qx.dot(qx) <= qy.dot(qy) {
+        tx
+    } else {
+        ty
+    }
+}
+
+/// Mirror of `closest_param_cubic`.
+fn closest_param_cubic(p0: V2, p1: V2, p2: V2, p3: V2, p: V2) -> f32 {
+    let mut best_t = 0.0;
+    let mut best_d = (p0 - p).dot(p0 - p);
+    for i in 1..=CUBIC_SAMPLES {
+        let t = i as f32 / CUBIC_SAMPLES as f32;
+        let w = eval_cubic(p0, p1, p2, p3, t) - p;
+        let d = w.dot(w);
+        if d < best_d {
+            best_t = t;
+            best_d = d;
+        }
+    }
+
+    let mut t = best_t;
+    for _ in 0..CUBIC_NEWTON_STEPS {
+        let s = 1.0 - t;
+        let w = eval_cubic(p0, p1, p2, p3, t) - p;
+        let d1 = 3.0
+            * (s * s * (p1 - p0) + 2.0 * s * t * (p2 - p1) + t * t * (p3 - p2));
+        let d2 = 6.0 * (s * (p2 - 2.0 * p1 + p0) + t * (p3 - 2.0 * p2 + p1));
+        let f = w.dot(d1);
+        let df = d1.dot(d1) + w.dot(d2);
+        if df > 0.0 {
+            t = (t - f / df).clamp(0.0, 1.0);
+        }
+    }
+
+    let w = eval_cubic(p0, p1, p2, p3, t) - p;
+    if w.dot(w) < best_d {
+        t
+    } else {
+        best_t
+    }
+}
+
+/// Mirror of the WGSL `sign` built-in, which is zero at zero.
+fn sign(x: f32) -> f32 {
+    if x > 0.0 {
+        1.0
+    } else if x < 0.0 {
+        -1.0
+    } else {
+        0.0
+    }
+}
+
+/// Mirror of `sdf_polygon`.
+fn sdf_polygon(points: &[[f32; 2]; 6], n_points: u32, p: V2) -> f32 {
+    let v: Vec<V2> = points.iter().map(|point| V2::from(*point)).collect();
+    let mut d = (p - v[0]).dot(p - v[0]);
+    let mut s = 1.0;
+    let mut j = n_points as usize - 1;
+    for i in 0..n_points as usize {
+        let e = v[j] - v[i];
+        let w = p - v[i];
+        let b = w - e * (w.dot(e) / e.dot(e)).clamp(0.0, 1.0);
+        d = d.min(b.dot(b));
+        let c = [p.y >= v[i].y, p.y < v[j].y, e.x * w.y > e.y * w.x];
+        if c.iter().all(|c| *c) || !c.iter().any(|c| *c) {
+            s = -s;
+        }
+        j = i;
+    }
+    s * d.sqrt()
+}
+
+/// Mirror of the WGSL `smoothstep` built-in.
+fn smoothstep(low: f32, high: f32, x: f32) -> f32 {
+    let t = ((x - low) / (high - low)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Mirror of the color interpolation constants.
+const COLOR_INTERPOLATION_SRGB: u32 = 1;
+const COLOR_INTERPOLATION_OKLAB: u32 = 3;
+
+/// Mirror of the glow falloff constants.
+const GLOW_FALLOFF_QUADRATIC: u32 = 2;
+const GLOW_FALLOFF_GAUSSIAN: u32 = 3;
+const GLOW_GAUSSIAN_K: f32 = 4.5;
+
+/// Mirror of the blend mode constants.
+const BLEND_MODE_ADDITIVE: u32 = 2;
+const BLEND_MODE_SCREEN: u32 = 3;
+const BLEND_MODE_MAX: u32 = 4;
+const BLEND_MODE_MULTIPLY: u32 = 5;
+
+/// Mirror of `NO_LAYER`.
+const NO_LAYER: u32 = u32::MAX;
+
+/// Mirror of `CBRT_TINY`.
+const CBRT_TINY: f32 = 1.0e-30;
+
+/// Mirror of `FILL_RULE_EVEN_ODD`.
+const FILL_RULE_EVEN_ODD: u32 = 2;
+
+/// Mirror of the curve constants.
+const CURVE_EPSILON: f32 = 1.0e-8;
+const CURVE_STRAIGHT: f32 = 1.0e-8;
+const CURVE_TINY: f32 = 1.0e-30;
+const CURVE_CAP_MARGIN: f32 = 2.0;
+const CUBIC_SAMPLES: u32 = 16;
+const CUBIC_NEWTON_STEPS: u32 = 4;
+
+/// Mirror of `DIST_FAR`.
+const DIST_FAR: f32 = 1.0e30;
+
+/// Mirror of the end cap constants.
+const END_CAP_BUTT: u32 = 1;
+const END_CAP_SQUARE: u32 = 3;
+
+/// Mirror of the marker shape constants.
+const MARKER_ARROW: u32 = 1;
+const MARKER_OPEN_ARROW: u32 = 2;
+const MARKER_DIAMOND: u32 = 4;
+const MARKER_BAR: u32 = 5;
+
+/// Mirror of `TILE_EDGE_WIDTH`.
+const TILE_EDGE_WIDTH: f32 = 3.0;
+
+/// Mirror of `THRESHOLD_MIN_ALPHA`.
+const THRESHOLD_MIN_ALPHA: f32 = 0.001;
+
+/// HDR texture, in framebuffer row order.
+///
+/// This is a mirror of a texture of [`crate::renderer::HDR_FORMAT`], except
+/// that its texels have full precision.
+struct HdrTexture {
+    width: u32,
+    height: u32,
+    texels: Vec<V4>,
+}
+impl HdrTexture {
+    /// Mirror of `textureLoad`, with the coordinates clamped to the edges.
+    fn load_clamped(&self, x: i32, y: i32) -> V4 {
+        let x = x.clamp(0, self.width as i32 - 1) as u32;
+        let y = y.clamp(0, self.height as i32 - 1) as u32;
+        self.texels[(y * self.width + x) as usize]
+    }
+}
+
+/// Emulates a pass which draws a fullscreen triangle into a new texture.
+///
+/// # Parameters
+///
+/// - `width`: Width of the target texture.
+/// - `height`: Height of the target texture.
+/// - `fragment`: Fragment shader, which is called with the framebuffer
+///   position of each texel's center.
+fn fullscreen_pass(
+    width: u32,
+    height: u32,
+    fragment: impl Fn(V2) -> V4,
+) -> HdrTexture {
+    let mut texels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            texels.push(fragment(V2::new(x as f32 + 0.5, y as f32 + 0.5)));
+        }
+    }
+    HdrTexture {
+        width,
+        height,
+        texels,
+    }
+}
+
+/// Emulates the bloom passes of [`crate::bloom::BloomTarget::apply`].
+///
+/// # Parameters
+///
+/// - `bloom`: Bloom options.
+/// - `scene`: HDR scene.
+///
+/// # Returns
+///
+/// The premultiplied colors output by the composite pass, in framebuffer
+/// row order.
+fn apply_bloom(bloom: &Bloom, scene: &HdrTexture) -> Vec<V4> {
+    let sizes = level_sizes(scene.width, scene.height, bloom.levels);
+    let options = bloom.options(sizes.len() as u32);
+
+    let mut levels: Vec<HdrTexture> = Vec::with_capacity(sizes.len());
+    for (level, (width, height)) in sizes.into_iter().enumerate() {
+        let extracted = match level {
+            0 => fullscreen_pass(width, height, |p| {
+                BloomShader::pass(&options, scene).fs_extract(p)
+            }),
+            _ => fullscreen_pass(width, height, |p| {
+                BloomShader::pass(&options, &levels[level - 1]).fs_downsample(p)
+            }),
+        };
+        let scratch = fullscreen_pass(width, height, |p| {
+            BloomShader::pass(&options, &extracted).fs_blur_horizontal(p)
+        });
+        levels.push(fullscreen_pass(width, height, |p| {
+            BloomShader::pass(&options, &scratch).fs_blur_vertical(p)
+        }));
+    }
+
+    let composite = BloomShader {
+        bloom_options: &options,
+        source: scene,
+        bloom_levels: &levels,
+    };
+    fullscreen_pass(scene.width, scene.height, |p| composite.fs_composite(p))
+        .texels
+}
+
+/// Mirror of `BLUR_RADIUS`.
+const BLUR_RADIUS: i32 = 4;
+
+/// Mirror of `BLUR_SIGMA`.
+const BLUR_SIGMA: f32 = 2.0;
+
+/// CPU mirror of the bloom shader's bindings.
+struct BloomShader<'a> {
+    bloom_options: &'a buffers::BloomOptions,
+    source: &'a HdrTexture,
+    bloom_levels: &'a [HdrTexture],
+}
+impl<'a> BloomShader<'a> {
+    /// Returns the bindings of the passes before the composite pass, which
+    /// do not use the bloom levels.
+    fn pass(
+        bloom_options: &'a buffers::BloomOptions,
+        source: &'a HdrTexture,
+    ) -> Self {
+        BloomShader {
+            bloom_options,
+            source,
+            bloom_levels: &[],
+        }
+    }
+
+    /// Mirror of `fs_extract`.
+    fn fs_extract(&self, position: V2) -> V4 {
+        self.bright_pass(self.downsample(position.x as i32, position.y as i32))
+    }
+
+    /// Mirror of `fs_downsample`.
+    fn fs_downsample(&self, position: V2) -> V4 {
+        self.downsample(position.x as i32, position.y as i32)
+    }
+
+    /// Mirror of `fs_blur_horizontal`.
+    fn fs_blur_horizontal(&self, position: V2) -> V4 {
+        self.blur(position.x as i32, position.y as i32, 1, 0)
+    }
+
+    /// Mirror of `fs_blur_vertical`.
+    fn fs_blur_vertical(&self, position: V2) -> V4 {
+        self.blur(position.x as i32, position.y as i32, 0, 1)
+    }
+
+    /// Mirror of `fs_composite`.
+    fn fs_composite(&self, position: V2) -> V4 {
+        let scene = self
+            .source
+            .load_clamped(position.x as i32, position.y as i32);
+
+        let mut bloom = V3::new(0.0, 0.0, 0.0);
+        for level in 0..self.bloom_options.n_levels {
+            bloom += self.upsample(level, position).truncate();
+        }
+        bloom *=
+            self.bloom_options.intensity / self.bloom_options.n_levels as f32;
+
+        let color = self.tone_map(scene.truncate() + bloom);
+        let alpha = scene
+            .w
+            .max(color.x.max(color.y.max(color.z)))
+            .clamp(0.0, 1.0);
+        V4::new(
+            color.x.min(alpha),
+            color.y.min(alpha),
+            color.z.min(alpha),
+            alpha,
+        )
+    }
+
+    /// Mirror of `load_source`.
+    fn load_source(&self, x: i32, y: i32) -> V4 {
+        self.source.load_clamped(x, y)
+    }
+
+    /// Mirror of `downsample`.
+    fn downsample(&self, x: i32, y: i32) -> V4 {
+        let (sx, sy) = (2 * x, 2 * y);
+        (self.load_source(sx, sy)
+            + self.load_source(sx + 1, sy)
+            + self.load_source(sx, sy + 1)
+            + self.load_source(sx + 1, sy + 1))
+            * 0.25
+    }
+
+    /// Mirror of `bright_pass`.
+    fn bright_pass(&self, color: V4) -> V4 {
+        let brightness = color.x.max(color.y.max(color.z));
+        let excess = (brightness - self.bloom_options.threshold).max(0.0);
+        color * (excess / brightness.max(1e-4))
+    }
+
+    /// Mirror of `blur`.
+    fn blur(&self, x: i32, y: i32, dx: i32, dy: i32) -> V4 {
+        let mut sum = V4::new(0.0, 0.0, 0.0, 0.0);
+        let mut total = 0.0;
+        for i in -BLUR_RADIUS..=BLUR_RADIUS {
+            let weight = blur_weight(i);
+            sum += self.load_source(x + i * dx, y + i * dy) * weight;
+            total += weight;
+        }
+        sum / total
+    }
+
+    /// Mirror of `upsample`.
+    fn upsample(&self, level: u32, position: V2) -> V4 {
+        let texture = &self.bloom_levels[level as usize];
+        let scale = (2u32 << level) as f32;
+        let q = position / scale - V2::new(0.5, 0.5);
+        let base = V2::new(q.x.floor(), q.y.floor());
+        let f = q - base;
+        let (x, y) = (base.x as i32, base.y as i32);
+        let c00 = texture.load_clamped(x, y);
+        let c10 = texture.load_clamped(x + 1, y);
+        let c01 = texture.load_clamped(x, y + 1);
+        let c11 = texture.load_clamped(x + 1, y + 1);
+        c00.lerp(c10, f.x).lerp(c01.lerp(c11, f.x), f.y)
+    }
+
+    /// Mirror of `tone_map`.
+    fn tone_map(&self, color: V3) -> V3 {
+        let map = |c: f32| match self.bloom_options.tone_map {
+            1 => c / (1.0 + c),
+            2 => {
+                let numerator = c * (2.51 * c + 0.03);
+                let denominator = c * (2.43 * c + 0.59) + 0.14;
+                (numerator / denominator).clamp(0.0, 1.0)
+            }
+            _ => c.clamp(0.0, 1.0),
+        };
+        V3::new(map(color.x), map(color.y), map(color.z))
+    }
+}
+
+/// Mirror of `blur_weight`.
+fn blur_weight(offset: i32) -> f32 {
+    let x = offset as f32;
+    (-x * x / (2.0 * BLUR_SIGMA * BLUR_SIGMA)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::parity::{check_scene, pixel_at, Shape},
+        BlendMode, LineCap,
+    };
+
+    /// Returns a scene of overlapping, translucent lines with all cap styles.
+    fn test_scene() -> Vec<(Shape, LineStyle)> {
+        let style = |width, cap, color| LineStyle {
+            width,
+            cap,
+            color,
+            ..Default::default()
+        };
+        vec![
+            (
+                Shape::Line(Line::new(
+                    P2::new(10.0, 20.0),
+                    P2::new(140.0, 30.0),
+                )),
+                style(12.0, LineCap::Round, Color::new(0.9, 0.4, 0.4, 0.8)),
+            ),
+            (
+                Shape::Line(Line::new(
+                    P2::new(20.0, 90.0),
+                    P2::new(120.0, 5.0),
+                )),
+                style(9.5, LineCap::Square, Color::new(0.4, 0.9, 0.4, 0.6)),
+            ),
+            (
+                Shape::Line(Line::new(
+                    P2::new(75.0, 10.0),
+                    P2::new(76.0, 95.0),
+                )),
+                style(3.0, LineCap::Butt, Color::new(0.4, 0.4, 0.9, 1.0)),
+            ),
+            (
+                Shape::Line(Line::new(
+                    P2::new(100.0, 70.0),
+                    P2::new(170.0, 80.0),
+                )),
+                style(20.0, LineCap::Round, Color::new(1.0, 1.0, 0.2, 0.5)),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_software_matches_gpu() {
+        let image = check_scene(wgpu::TextureFormat::Rgba8Unorm, &test_scene());
+        // The round cap covers a point behind the start of the line.
+        assert_eq!(pixel_at(&image, 5.5, 19.5), [184, 82, 82, 204]);
+        // The butt cap ends exactly at the end point, with an opaque body.
+        assert_eq!(pixel_at(&image, 75.5, 7.5), [0, 0, 0, 0]);
+        assert_eq!(pixel_at(&image, 75.5, 12.5), [102, 102, 230, 255]);
+        // The square cap extends behind the start, where a butt cap would
+        // leave the pixel empty.
+        assert_eq!(pixel_at(&image, 17.5, 91.5), [61, 138, 61, 153]);
+        check_scene(wgpu::TextureFormat::Rgba8UnormSrgb, &test_scene());
+    }
+
+    /// Checks the blend functions where two opaque colors overlap, and that
+    /// every blend mode gives the top color where nothing is beneath it.
+    #[test]
+    fn test_blend_over() {
+        let a = V4::new(0.5, 0.25, 1.0, 1.0);
+        let b = V4::new(0.5, 1.0, 0.0, 1.0);
+        let cases = [
+            (BlendMode::Normal, V4::new(0.5, 0.25, 1.0, 1.0)),
+            (BlendMode::Additive, V4::new(1.0, 1.25, 1.0, 1.0)),
+            (BlendMode::Screen, V4::new(0.75, 1.0, 1.0, 1.0)),
+            (BlendMode::Max, V4::new(0.5, 1.0, 1.0, 1.0)),
+            (BlendMode::Multiply, V4::new(0.25, 0.25, 0.0, 1.0)),
+        ];
+        let transparent = V4::new(0.0, 0.0, 0.0, 0.0);
+        for (mode, expected) in cases {
+            let mode = mode as u32;
+            assert!((blend_over(mode, a, b) - expected).magnitude() < 1e-6);
+            assert!(
+                (blend_over(mode, a * 0.5, transparent) - a * 0.5).magnitude()
+                    < 1e-6
+            );
+            assert_eq!(blend_over(mode, transparent, transparent), transparent);
+        }
+
+        // Translucent colors: normal blending is alpha-over, and additive
+        // blending sums the premultiplied colors.
+        let half_red = V4::new(0.5, 0.0, 0.0, 0.5);
+        let half_green = V4::new(0.0, 0.5, 0.0, 0.5);
+        let over = blend_over(BlendMode::Normal as u32, half_red, half_green);
+        assert!((over - V4::new(0.5, 0.25, 0.0, 0.75)).magnitude() < 1e-6);
+        let sum = blend_over(BlendMode::Additive as u32, half_red, half_green);
+        assert!((sum - V4::new(0.5, 0.5, 0.0, 1.0)).magnitude() < 1e-6);
+    }
+}
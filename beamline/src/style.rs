@@ -1,7 +1,6 @@
 //! Line styles.
 
-use crate::{polygon::Polygon, Line, V2};
-use cgmath::InnerSpace;
+use crate::Line;
 
 /// Describes the cap at the end of lines.
 #[repr(u32)]
@@ -17,8 +16,318 @@ pub enum LineCap {
     Square = 3,
 }
 
-/// Color for a line.
+/// Shape of a [`Marker`].
+///
+/// The size of each shape is its length, which is the marker's size
+/// multiplied by the width of the line at the end that it decorates.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MarkerShape {
+    /// A filled triangular arrowhead, as long as it is wide, with its tip
+    /// at the end of the line. The line stops short of the tip, so that the
+    /// tip is sharp.
+    Arrow = 1,
+    /// An open arrowhead: two strokes with the width of the line, which
+    /// meet at the end of the line. It is as long as it is wide.
+    OpenArrow = 2,
+    /// A filled circle centered on the end of the line. Its length is its
+    /// diameter.
+    Circle = 3,
+    /// A filled square centered on the end of the line, with its diagonals
+    /// along and across the line. Its length is its diagonal.
+    Diamond = 4,
+    /// A bar across the end of the line, with the width of the line.
+    Bar = 5,
+}
+
+/// Decoration at an end of a line, such as an arrowhead.
+///
+/// A marker replaces the cap at its end of the line, which becomes a butt
+/// cap. Markers are drawn in the color of the line at that end, and are
+/// part of the same shape as the line, so they are not blended over it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Marker {
+    /// Shape of the marker.
+    pub shape: MarkerShape,
+    /// Size of the marker, as a multiple of the width of the line.
+    pub size: f32,
+}
+impl Marker {
+    /// Creates a new marker.
+    ///
+    /// # Parameters
+    ///
+    /// - `shape`: Shape of the marker.
+    /// - `size`: Size of the marker, as a multiple of the width of the line.
+    ///   This must be positive.
+    ///
+    /// # Returns
+    ///
+    /// A new marker.
+    pub fn new(shape: MarkerShape, size: f32) -> Self {
+        assert!(size > 0.0);
+        Marker { shape, size }
+    }
+
+    /// Returns the length of the marker, at the end of a line of a given
+    /// width.
+    pub fn length(&self, width: f32) -> f32 {
+        self.size * width
+    }
+}
+
+/// Falloff curve of a [`Glow`].
+///
+/// Each curve gives the strength of the glow in terms of `t`, the distance
+/// from the edge of the line as a fraction of the glow's radius. Every
+/// curve is one at the edge of the line, and zero at the radius.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GlowFalloff {
+    /// Falls linearly: `1 - t`.
+    Linear = 1,
+    /// Falls quadratically: `(1 - t)^2`. This is brighter close to the line
+    /// and softer further away.
+    Quadratic = 2,
+    /// A Gaussian, `exp(-4.5 t^2)`, shifted and scaled so that it reaches
+    /// zero at the radius. This stays bright further from the line.
+    Gaussian = 3,
+}
+
+/// How a line is composited over the lines beneath it.
+///
+/// Apart from [`BlendMode::Additive`], the blend modes are the separable
+/// blend modes of the W3C compositing specification: where both the line
+/// and what is beneath it are opaque, the result is the blend function of
+/// their colors; where only one of them is present, the result is that one.
+/// Lines are only blended with other lines in the same tile, not with the
+/// contents of the output texture.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Alpha-over: the line covers what is beneath it.
+    Normal = 1,
+    /// The colors are summed, weighted by their alphas, so crossing lines
+    /// get brighter. Sums brighter than one are clamped, except with bloom
+    /// (see [`crate::Bloom`]).
+    Additive = 2,
+    /// `a + b - a b`: lightens, like projecting both colors onto a screen.
+    Screen = 3,
+    /// The larger of each channel.
+    Max = 4,
+    /// `a b`: darkens, like overlaying filters.
+    Multiply = 5,
+}
+
+/// How the widths of a line are affected by the transform it is drawn with
+/// (see [`crate::Renderer::push_transform`] and [`crate::Camera`]).
+///
+/// The widths of a line include its tapered width, and the lengths of its
+/// dash pattern and the radius of its glow.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StrokeScaling {
+    /// Widths are in the same units as the coordinates of the line, so they
+    /// scale with the transform. A transform which stretches more in one
+    /// direction than another scales widths by the square root of its
+    /// scaling of areas.
+    Scaling,
+    /// Widths are in pixels, whatever the transform.
+    NonScaling,
+}
+
+/// Glow around a line, for the look of a vector display.
+///
+/// The line itself is the core of the glow, and has the width of its
+/// style. The glow surrounds the core in the color of the line, out to a
+/// radius from its edge. Its opacity is the opacity of the line, multiplied
+/// by the intensity and the falloff curve, and limited to that of the line.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Glow {
+    /// Distance from the edge of the line at which the glow ends.
+    pub radius: f32,
+    /// How the glow fades with the distance from the line.
+    pub falloff: GlowFalloff,
+    /// Opacity of the glow next to the line, as a fraction of the opacity
+    /// of the line. Values above one make the glow saturate near the line.
+    pub intensity: f32,
+}
+impl Glow {
+    /// Creates a new glow.
+    ///
+    /// # Parameters
+    ///
+    /// - `radius`: Distance from the edge of the line at which the glow
+    ///   ends. This must be positive.
+    /// - `falloff`: How the glow fades with the distance from the line.
+    /// - `intensity`: Opacity of the glow next to the line, as a fraction
+    ///   of the opacity of the line. This must not be negative.
+    ///
+    /// # Returns
+    ///
+    /// A new glow.
+    pub fn new(radius: f32, falloff: GlowFalloff, intensity: f32) -> Self {
+        assert!(radius > 0.0);
+        assert!(intensity >= 0.0);
+        Glow {
+            radius,
+            falloff,
+            intensity,
+        }
+    }
+}
+
+/// Describes how the segments of a polyline are joined at its vertices.
 #[derive(Debug, Copy, Clone)]
+pub enum LineJoin {
+    /// Sharp corners, formed by extending the outer edges of the segments
+    /// until they meet.
+    ///
+    /// The miter length is the distance from the inner corner to the outer
+    /// corner. If the ratio of the miter length to the line width would
+    /// exceed `limit`, a bevel join is used instead.
+    Miter { limit: f32 },
+    /// Corners cut off by a straight line between the outer edges of the
+    /// segments.
+    Bevel,
+    /// Rounded corners. Each corner is a circle with radius equal to half of
+    /// the line width.
+    Round,
+}
+
+/// Maximum number of lengths in a [`DashPattern`].
+pub const MAX_DASH_LENGTHS: usize = 8;
+
+/// Describes a pattern of dashes along a line.
+///
+/// A dash pattern is a list of lengths which alternately describe "on"
+/// dashes and "off" gaps, starting with a dash. The pattern repeats along the
+/// line. Each dash is drawn with the cap of the line style at both of its
+/// ends, so zero-length dashes with round caps can be used to draw dots.
+///
+/// The offset shifts the pattern along the line: the line starts at a
+/// distance `offset` into the pattern. Increasing the offset over time moves
+/// the dashes backwards along the line, which produces "marching ants".
+///
+/// To construct a dash pattern, use [`DashPattern::new`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DashPattern {
+    /// Alternating dash and gap lengths. Only the first `n_lengths` are used.
+    lengths: [f32; MAX_DASH_LENGTHS],
+    /// Number of lengths in the pattern.
+    n_lengths: usize,
+    /// Distance into the pattern at which lines start.
+    offset: f32,
+}
+impl DashPattern {
+    /// Creates a new dash pattern.
+    ///
+    /// # Parameters
+    ///
+    /// - `lengths`: Alternating dash and gap lengths, starting with a dash.
+    ///   There must be an even number of lengths, no more than
+    ///   [`MAX_DASH_LENGTHS`]. All lengths must be non-negative, and their
+    ///   sum must be positive.
+    /// - `offset`: Distance into the pattern at which lines start.
+    ///
+    /// # Returns
+    ///
+    /// A new dash pattern.
+    pub fn new(lengths: &[f32], offset: f32) -> Self {
+        assert!(!lengths.is_empty());
+        assert_eq!(lengths.len() % 2, 0);
+        assert!(lengths.len() <= MAX_DASH_LENGTHS);
+        assert!(lengths.iter().all(|length| *length >= 0.0));
+        assert!(lengths.iter().sum::<f32>() > 0.0);
+        assert!(offset.is_finite());
+
+        let mut pattern = DashPattern {
+            lengths: [0.0; MAX_DASH_LENGTHS],
+            n_lengths: lengths.len(),
+            offset,
+        };
+        pattern.lengths[..lengths.len()].copy_from_slice(lengths);
+        pattern
+    }
+
+    /// Returns a copy of the dash pattern with a different offset.
+    ///
+    /// This is convenient for animating the offset.
+    ///
+    /// # Parameters
+    ///
+    /// - `offset`: Distance into the pattern at which lines start.
+    pub fn with_offset(&self, offset: f32) -> Self {
+        assert!(offset.is_finite());
+        DashPattern { offset, ..*self }
+    }
+
+    /// Returns the alternating dash and gap lengths.
+    pub fn lengths(&self) -> &[f32] {
+        &self.lengths[..self.n_lengths]
+    }
+
+    /// Returns the distance into the pattern at which lines start.
+    pub fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    /// Returns the total length of one repeat of the pattern.
+    pub fn period(&self) -> f32 {
+        self.lengths().iter().sum()
+    }
+
+    /// Returns a copy of the dash pattern with its lengths and offset
+    /// multiplied by a factor.
+    ///
+    /// # Parameters
+    ///
+    /// - `factor`: Factor to multiply by. This must be positive.
+    pub fn scaled(&self, factor: f32) -> Self {
+        assert!(factor > 0.0);
+        DashPattern {
+            lengths: self.lengths.map(|length| length * factor),
+            offset: self.offset * factor,
+            ..*self
+        }
+    }
+
+    /// Checks whether a point along a line falls within a dash.
+    ///
+    /// Points exactly on the end of a dash are considered to be within it.
+    ///
+    /// # Parameters
+    ///
+    /// - `distance`: Distance of the point along the line (not including the
+    ///   offset of the pattern).
+    pub fn is_on(&self, distance: f32) -> bool {
+        let period = self.period();
+        let s = distance + self.offset;
+        let mut u = s - period * (s / period).floor();
+        for dash_gap in self.lengths().chunks_exact(2) {
+            if u <= dash_gap[0] {
+                return true;
+            }
+            u -= dash_gap[0] + dash_gap[1];
+            if u < 0.0 {
+                return false;
+            }
+        }
+        // Rounding may leave `u` a fraction past the end of the period, which
+        // is the start of the first dash.
+        true
+    }
+}
+
+/// Color for a line.
+///
+/// The red, green and blue components are linear, and are not multiplied
+/// by the alpha (see [`Color::premultiplied`]). They may be greater than
+/// one. Such bright colors are clamped when they are drawn, except with
+/// bloom (see [`crate::Bloom`]).
+///
+/// Colors are converted to premultiplied alpha before they are composited,
+/// and the renderer's output has premultiplied alpha.
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Color {
     pub red: f32,
     pub green: f32,
@@ -35,21 +344,318 @@ impl Color {
         }
     }
 
+    /// Creates a color from components with premultiplied alpha, such as
+    /// the colors of other engines' premultiplied surfaces.
+    ///
+    /// The red, green and blue components are divided by the alpha. A color
+    /// with zero alpha is transparent black.
+    ///
+    /// ```
+    /// use beamline::Color;
+    ///
+    /// let color = Color::from_premultiplied(0.4, 0.2, 1.0, 0.5);
+    /// assert_eq!(color, Color::new(0.8, 0.4, 2.0, 0.5));
+    /// assert_eq!(color.premultiplied(), [0.4, 0.2, 1.0, 0.5]);
+    /// ```
+    pub fn from_premultiplied(
+        red: f32,
+        green: f32,
+        blue: f32,
+        alpha: f32,
+    ) -> Color {
+        let alpha = alpha.clamp(0.0, 1.0);
+        if alpha == 0.0 {
+            return Color::new(0.0, 0.0, 0.0, 0.0);
+        }
+        Color::new(red / alpha, green / alpha, blue / alpha, alpha)
+    }
+
     /// Return the color components as an RGBA array.
     pub fn as_array(&self) -> [f32; 4] {
         [self.red, self.green, self.blue, self.alpha]
     }
+
+    /// Return the color components as an RGBA array, with the red, green
+    /// and blue components multiplied by the alpha.
+    ///
+    /// This is the color which the renderer writes where it draws the color
+    /// at full coverage over a transparent, linear (non-sRGB) target.
+    pub fn premultiplied(&self) -> [f32; 4] {
+        [
+            self.red * self.alpha,
+            self.green * self.alpha,
+            self.blue * self.alpha,
+            self.alpha,
+        ]
+    }
+}
+
+/// Color space in which the colors of a [`Gradient`] are interpolated.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorInterpolation {
+    /// Interpolate the sRGB-encoded color components. This matches the
+    /// gradients of most other graphics software, but the middle of a
+    /// gradient between complementary colors is darker than its ends.
+    Srgb = 1,
+    /// Interpolate the linear color components.
+    Linear = 2,
+    /// Interpolate in the OKLab perceptual color space, so that lightness
+    /// and hue change evenly along the gradient.
+    Oklab = 3,
+}
+
+/// Describes how the color of a line changes along it.
+///
+/// A gradient has a color at each end of the line, or a color at each
+/// vertex of a polyline. Between these, colors (including their alpha) are
+/// interpolated in the color space given by the [`ColorInterpolation`].
+///
+/// To construct a gradient, use [`Gradient::new`] or
+/// [`Gradient::per_vertex`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    /// Colors along the gradient.
+    colors: Vec<Color>,
+    /// Color space in which colors are interpolated.
+    interpolation: ColorInterpolation,
+}
+impl Gradient {
+    /// Creates a gradient from the start to the end of a line.
+    ///
+    /// For a polyline, the gradient runs along the whole polyline, in
+    /// proportion to the distance along it.
+    ///
+    /// # Parameters
+    ///
+    /// - `start`: Color at the start of the line.
+    /// - `end`: Color at the end of the line.
+    /// - `interpolation`: Color space in which colors are interpolated.
+    ///
+    /// # Returns
+    ///
+    /// A new gradient.
+    pub fn new(
+        start: Color,
+        end: Color,
+        interpolation: ColorInterpolation,
+    ) -> Self {
+        Gradient {
+            colors: vec![start, end],
+            interpolation,
+        }
+    }
+
+    /// Creates a gradient with a color at each vertex of a polyline.
+    ///
+    /// Colors are interpolated along each segment of the polyline. When the
+    /// gradient is used for anything other than a polyline with the same
+    /// number of vertices, only the first and last colors are used.
+    ///
+    /// # Parameters
+    ///
+    /// - `colors`: Colors at the vertices. There must be at least 2.
+    /// - `interpolation`: Color space in which colors are interpolated.
+    ///
+    /// # Returns
+    ///
+    /// A new gradient.
+    pub fn per_vertex(
+        colors: Vec<Color>,
+        interpolation: ColorInterpolation,
+    ) -> Self {
+        assert!(colors.len() >= 2);
+        Gradient {
+            colors,
+            interpolation,
+        }
+    }
+
+    /// Returns the colors of the gradient.
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    /// Returns the color at the start of the gradient.
+    pub fn start(&self) -> Color {
+        self.colors[0]
+    }
+
+    /// Returns the color at the end of the gradient.
+    pub fn end(&self) -> Color {
+        self.colors[self.colors.len() - 1]
+    }
+
+    /// Returns the color space in which colors are interpolated.
+    pub fn interpolation(&self) -> ColorInterpolation {
+        self.interpolation
+    }
 }
 
 /// Style attributes of a line.
 #[derive(Debug, Clone)]
 pub struct LineStyle {
-    /// Width of the line.
+    /// Width of the line, or its width at the start if it is tapered.
     pub width: f32,
+    /// Width at the end of the line, or `None` for a line of constant width.
+    ///
+    /// A tapered line's width changes linearly with the distance along it,
+    /// from `width` at the start to `end_width` at the end. Either of them
+    /// may be zero, so that the line tapers to a point. Tapering applies
+    /// to lines and polylines; curves, arcs and ellipses have a constant
+    /// `width`.
+    pub end_width: Option<f32>,
     /// Line cap.
     pub cap: LineCap,
-    /// Color of the line.
+    /// Line join, used at the vertices of polylines.
+    pub join: LineJoin,
+    /// Color of the line. This is not used if the line has a gradient.
+    pub color: Color,
+    /// Gradient of the line, or `None` for a line of a single color.
+    ///
+    /// Gradients apply to lines, polylines, curves and arcs. Ellipses are
+    /// drawn in the first color of the gradient.
+    pub gradient: Option<Gradient>,
+    /// Dash pattern of the line, or `None` for a solid line.
+    ///
+    /// Dash patterns apply to lines and polylines, but not to curves, arcs
+    /// or ellipses.
+    pub dash: Option<DashPattern>,
+    /// Marker at the start of the line, or `None` to use the cap.
+    ///
+    /// Markers apply to lines, open polylines, curves and arcs which are
+    /// not whole circles.
+    pub start_marker: Option<Marker>,
+    /// Marker at the end of the line, or `None` to use the cap.
+    pub end_marker: Option<Marker>,
+    /// Glow around the line, or `None` for a line without a glow.
+    ///
+    /// The glow surrounds the whole shape of the line, including its caps,
+    /// joins and markers.
+    pub glow: Option<Glow>,
+    /// How the line is composited over the lines beneath it.
+    pub blend_mode: BlendMode,
+    /// Whether the widths of the line scale with its transform.
+    pub stroke_scaling: StrokeScaling,
+}
+impl LineStyle {
+    /// Returns the radius of the style's glow, or zero if it has no glow.
+    pub fn glow_radius(&self) -> f32 {
+        self.glow.map_or(0.0, |glow| glow.radius)
+    }
+
+    /// Returns a copy of the style with its widths multiplied by a factor.
+    ///
+    /// The widths include the tapered width, the lengths of the dash pattern
+    /// and the radius of the glow. Marker sizes are relative to the width,
+    /// so markers are scaled too.
+    ///
+    /// # Parameters
+    ///
+    /// - `factor`: Factor to multiply by. This must be positive.
+    pub fn scaled(&self, factor: f32) -> LineStyle {
+        assert!(factor > 0.0);
+        LineStyle {
+            width: self.width * factor,
+            end_width: self.end_width.map(|width| width * factor),
+            dash: self.dash.map(|dash| dash.scaled(factor)),
+            glow: self.glow.map(|glow| Glow {
+                radius: glow.radius * factor,
+                ..glow
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the cap at the start of the line.
+    ///
+    /// This is a butt cap if the line has a start marker, or the style's cap
+    /// otherwise.
+    pub fn start_cap(&self) -> LineCap {
+        match self.start_marker {
+            Some(_) => LineCap::Butt,
+            None => self.cap,
+        }
+    }
+
+    /// Returns the cap at the end of the line.
+    ///
+    /// This is a butt cap if the line has an end marker, or the style's cap
+    /// otherwise.
+    pub fn end_cap(&self) -> LineCap {
+        match self.end_marker {
+            Some(_) => LineCap::Butt,
+            None => self.cap,
+        }
+    }
+}
+impl Default for LineStyle {
+    /// Returns a style for opaque white lines, one unit wide and not
+    /// tapered, with butt caps and miter joins (with a miter limit of 4),
+    /// which have no gradient, markers or glow, are not dashed, are blended
+    /// normally, and whose widths scale with their transform.
+    fn default() -> Self {
+        LineStyle {
+            width: 1.0,
+            end_width: None,
+            cap: LineCap::Butt,
+            join: LineJoin::Miter { limit: 4.0 },
+            color: Color::new(1.0, 1.0, 1.0, 1.0),
+            gradient: None,
+            dash: None,
+            start_marker: None,
+            end_marker: None,
+            glow: None,
+            blend_mode: BlendMode::Normal,
+            stroke_scaling: StrokeScaling::Scaling,
+        }
+    }
+}
+
+/// Describes which points are inside a filled path.
+///
+/// Both rules count how many times the path winds around a point, adding one
+/// for each anti-clockwise turn and subtracting one for each clockwise turn.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FillRule {
+    /// Points are inside if the path winds around them a non-zero number of
+    /// times.
+    NonZero = 1,
+    /// Points are inside if the path winds around them an odd number of
+    /// times.
+    EvenOdd = 2,
+}
+impl FillRule {
+    /// Returns `true` if a point with the given winding number is inside.
+    pub fn is_inside(&self, winding: i32) -> bool {
+        match self {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+/// Style attributes of a filled path.
+#[derive(Debug, Clone)]
+pub struct FillStyle {
+    /// Color of the fill.
     pub color: Color,
+    /// Rule which decides which points are inside the path.
+    pub rule: FillRule,
+    /// How the fill is composited over the lines beneath it.
+    pub blend_mode: BlendMode,
+}
+impl Default for FillStyle {
+    /// Returns a style for an opaque white fill, using the non-zero rule,
+    /// which is blended normally.
+    fn default() -> Self {
+        FillStyle {
+            color: Color::new(1.0, 1.0, 1.0, 1.0),
+            rule: FillRule::NonZero,
+            blend_mode: BlendMode::Normal,
+        }
+    }
 }
 
 /// A line with an associated style.
@@ -58,35 +664,791 @@ pub struct StyledLine {
     pub line: Line,
     pub style: LineStyle,
 }
-impl StyledLine {
-    // Returns a bounding-polygon describing the line.
-    //
-    // The polygon accounts for the line width and end-cap style.
-    pub fn bounding_polygon(&self) -> Polygon {
-        assert!(self.style.width > 0.0);
-
-        let w2 = self.style.width / 2.0;
-        let v = self.line.ab_vec().normalize();
-        let t = V2::new(-v.y, v.x); // Rotate v by 90 degrees.
-
-        // Offset for the end of the line.
-        let ofs = match self.style.cap {
-            LineCap::Butt => 0.0,
-            LineCap::Square => w2,
-            LineCap::Round => w2,
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::parity::{check_scene, pixel_at, Shape},
+        CircularArc, CubicBezier, Ellipse, Marker, MarkerShape, Path, Polyline,
+        SoftwareRenderer, P2,
+    };
+
+    #[test]
+    fn test_dash_pattern_is_on() {
+        let dash = DashPattern::new(&[2.0, 1.0, 0.0, 1.0], 0.0);
+        assert_eq!(dash.period(), 4.0);
+        assert!(dash.is_on(0.0));
+        assert!(dash.is_on(1.5));
+        assert!(!dash.is_on(2.5));
+        assert!(dash.is_on(3.0));
+        assert!(!dash.is_on(3.5));
+        assert!(dash.is_on(5.0));
+        assert!(!dash.is_on(-0.5));
+
+        let dash = dash.with_offset(2.5);
+        assert!(!dash.is_on(0.0));
+        assert!(dash.is_on(0.5));
+        assert!(dash.is_on(1.5));
+    }
+
+    #[test]
+    fn test_scaled_style() {
+        let style = LineStyle {
+            width: 2.0,
+            end_width: Some(1.0),
+            dash: Some(DashPattern::new(&[3.0, 1.0], 0.5)),
+            glow: Some(Glow::new(4.0, GlowFalloff::Linear, 1.0)),
+            ..Default::default()
+        };
+        let scaled = style.scaled(1.5);
+        assert_eq!(scaled.width, 3.0);
+        assert_eq!(scaled.end_width, Some(1.5));
+        assert_eq!(scaled.dash, Some(DashPattern::new(&[4.5, 1.5], 0.75)));
+        assert_eq!(scaled.glow_radius(), 6.0);
+    }
+
+    #[test]
+    fn test_premultiplied_color() {
+        let color = Color::new(0.8, 0.4, 2.0, 0.5);
+        assert_eq!(color.premultiplied(), [0.4, 0.2, 1.0, 0.5]);
+        assert_eq!(Color::from_premultiplied(0.4, 0.2, 1.0, 0.5), color);
+        assert_eq!(
+            Color::from_premultiplied(0.4, 0.2, 1.0, 0.0),
+            Color::new(0.0, 0.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_fill_rule_is_inside() {
+        assert!(!FillRule::NonZero.is_inside(0));
+        assert!(FillRule::NonZero.is_inside(2));
+        assert!(FillRule::NonZero.is_inside(-1));
+        assert!(!FillRule::EvenOdd.is_inside(0));
+        assert!(!FillRule::EvenOdd.is_inside(-2));
+        assert!(FillRule::EvenOdd.is_inside(-1));
+        assert!(FillRule::EvenOdd.is_inside(3));
+    }
+
+    /// Returns a scene of dashed lines and polylines.
+    fn dash_scene() -> Vec<(Shape, LineStyle)> {
+        let style = |width, cap, lengths: &[f32], offset| LineStyle {
+            width,
+            cap,
+            color: Color::new(0.9, 0.9, 0.4, 0.8),
+            dash: Some(DashPattern::new(lengths, offset)),
+            ..Default::default()
+        };
+        vec![
+            (
+                Shape::Line(Line::new(
+                    P2::new(10.0, 10.0),
+                    P2::new(140.0, 20.0),
+                )),
+                style(4.0, LineCap::Butt, &[10.0, 5.0], 0.0),
+            ),
+            (
+                Shape::Line(Line::new(
+                    P2::new(10.0, 32.0),
+                    P2::new(140.0, 32.0),
+                )),
+                style(5.0, LineCap::Square, &[8.0, 10.0, 2.0, 10.0], 3.5),
+            ),
+            (
+                Shape::Line(Line::new(
+                    P2::new(140.0, 52.0),
+                    P2::new(10.0, 52.0),
+                )),
+                style(6.0, LineCap::Round, &[0.0, 10.0], -2.0),
+            ),
+            (
+                Shape::Polyline(Polyline::closed(vec![
+                    P2::new(20.0, 65.0),
+                    P2::new(130.0, 65.0),
+                    P2::new(130.0, 92.0),
+                    P2::new(20.0, 92.0),
+                ])),
+                style(3.0, LineCap::Butt, &[6.0, 4.0], 7.25),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_dashes_match_gpu() {
+        let image = check_scene(wgpu::TextureFormat::Rgba8Unorm, &dash_scene());
+        // The first dash of the butt-capped line is drawn, and the gap after
+        // it is empty.
+        let on = pixel_at(&image, 15.5, 10.5);
+        assert_eq!(on, [184, 184, 82, 204]);
+        assert_eq!(pixel_at(&image, 22.5, 10.5), [0, 0, 0, 0]);
+        // The offset moves the short dash of the square-capped line to start
+        // 14.5 along it, and its cap reaches back into the gap before it.
+        assert_eq!(pixel_at(&image, 23.5, 32.5), on);
+        assert_eq!(pixel_at(&image, 19.5, 32.5), [0, 0, 0, 0]);
+        // Dashes of zero length with round caps are dots, the second of
+        // which is 12 along the line from its start on the right.
+        assert_eq!(pixel_at(&image, 127.5, 52.5), on);
+        assert!(pixel_at(&image, 130.5, 54.5)[3] < 16);
+        assert_eq!(pixel_at(&image, 122.5, 52.5), [0, 0, 0, 0]);
+        // The pattern runs on around the closed polyline, into the segment
+        // which closes it.
+        assert_eq!(pixel_at(&image, 20.5, 82.5), on);
+        assert_eq!(pixel_at(&image, 20.5, 88.5), [0, 0, 0, 0]);
+    }
+
+    /// Dashes are drawn where the pattern is on, and moving the offset moves
+    /// the dashes backwards along the line.
+    #[test]
+    fn test_dash_offset() {
+        let render = |offset| {
+            let mut software = SoftwareRenderer::new(
+                wgpu::TextureFormat::Rgba8Unorm,
+                100,
+                20,
+                16,
+                16,
+            );
+            let style = LineStyle {
+                width: 6.0,
+                dash: Some(DashPattern::new(&[10.0, 10.0], offset)),
+                ..Default::default()
+            };
+            software.line(
+                Line::new(P2::new(0.0, 10.0), P2::new(100.0, 10.0)),
+                &style,
+            );
+            software.render()
+        };
+
+        // Columns are sampled at the pixel centers, so column 5 is 5.5 along.
+        let image = render(0.0);
+        assert_eq!(image.pixel(5, 10), [255, 255, 255, 255]);
+        assert_eq!(image.pixel(15, 10), [0, 0, 0, 0]);
+        assert_eq!(image.pixel(25, 10), [255, 255, 255, 255]);
+
+        let image = render(5.0);
+        assert_eq!(image.pixel(2, 10), [255, 255, 255, 255]);
+        assert_eq!(image.pixel(10, 10), [0, 0, 0, 0]);
+        assert_eq!(image.pixel(18, 10), [255, 255, 255, 255]);
+    }
+
+    /// Returns a scene of strokes with gradients in every interpolation
+    /// space.
+    fn gradient_scene() -> Vec<(Shape, LineStyle)> {
+        let (red, green, blue) = (
+            Color::new(1.0, 0.1, 0.1, 0.9),
+            Color::new(0.1, 1.0, 0.1, 0.6),
+            Color::new(0.1, 0.1, 1.0, 1.0),
+        );
+        let style = |width, cap, gradient| LineStyle {
+            width,
+            cap,
+            gradient: Some(gradient),
+            ..Default::default()
+        };
+        vec![
+            (
+                Shape::Line(Line::new(
+                    P2::new(10.0, 10.0),
+                    P2::new(140.0, 20.0),
+                )),
+                style(
+                    10.0,
+                    LineCap::Round,
+                    Gradient::new(red, blue, ColorInterpolation::Srgb),
+                ),
+            ),
+            (
+                Shape::Polyline(Polyline::new(vec![
+                    P2::new(10.0, 40.0),
+                    P2::new(50.0, 70.0),
+                    P2::new(90.0, 40.0),
+                    P2::new(140.0, 60.0),
+                ])),
+                style(
+                    8.0,
+                    LineCap::Butt,
+                    Gradient::per_vertex(
+                        vec![red, green, blue, red],
+                        ColorInterpolation::Oklab,
+                    ),
+                ),
+            ),
+            (
+                Shape::Polyline(Polyline::new(vec![
+                    P2::new(10.0, 90.0),
+                    P2::new(60.0, 80.0),
+                    P2::new(140.0, 95.0),
+                ])),
+                LineStyle {
+                    dash: Some(DashPattern::new(&[12.0, 5.0], 2.0)),
+                    ..style(
+                        5.0,
+                        LineCap::Square,
+                        Gradient::new(green, red, ColorInterpolation::Linear),
+                    )
+                },
+            ),
+            (
+                Shape::Cubic(CubicBezier::new(
+                    P2::new(20.0, 20.0),
+                    P2::new(40.0, 100.0),
+                    P2::new(100.0, -20.0),
+                    P2::new(130.0, 80.0),
+                )),
+                style(
+                    6.0,
+                    LineCap::Round,
+                    Gradient::new(blue, green, ColorInterpolation::Oklab),
+                ),
+            ),
+            (
+                Shape::Arc(CircularArc::new(
+                    P2::new(75.0, 50.0),
+                    30.0,
+                    -0.5,
+                    4.0,
+                )),
+                style(
+                    7.0,
+                    LineCap::Butt,
+                    Gradient::new(red, green, ColorInterpolation::Srgb),
+                ),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_gradients_match_gpu() {
+        let image =
+            check_scene(wgpu::TextureFormat::Rgba8Unorm, &gradient_scene());
+        // The first line runs from red at its start to blue at its end.
+        let [r, _, b, _] = pixel_at(&image, 15.5, 10.5);
+        assert!(r > 200 && b < 40, "{} {}", r, b);
+        let [r, _, b, _] = pixel_at(&image, 135.5, 19.5);
+        assert!(r < 40 && b > 200, "{} {}", r, b);
+        // Its middle is mixed in sRGB, which is darker than a linear mix.
+        let close = |actual: [u8; 4], expected: [u8; 4]| {
+            let near =
+                actual.iter().zip(expected).all(|(a, e)| a.abs_diff(e) <= 2);
+            assert!(near, "{:?} != {:?}", actual, expected);
+        };
+        close(pixel_at(&image, 75.5, 15.5), [99, 24, 101, 242]);
+        // The polyline takes each vertex's color at that vertex, and mixes
+        // green into blue through OKLab along its second segment.
+        close(pixel_at(&image, 90.5, 41.5), [25, 27, 253, 255]);
+        close(pixel_at(&image, 70.5, 55.5), [15, 97, 115, 204]);
+        check_scene(wgpu::TextureFormat::Rgba8UnormSrgb, &gradient_scene());
+    }
+
+    #[test]
+    fn test_gradient_interpolation() {
+        let (red, blue) = (
+            Color::new(1.0, 0.0, 0.0, 1.0),
+            Color::new(0.0, 0.0, 1.0, 1.0),
+        );
+        let midpoint = |interpolation| {
+            let mut software = SoftwareRenderer::new(
+                wgpu::TextureFormat::Rgba8Unorm,
+                100,
+                20,
+                16,
+                16,
+            );
+            software.line(
+                Line::new(P2::new(0.0, 10.0), P2::new(100.0, 10.0)),
+                &LineStyle {
+                    width: 10.0,
+                    cap: LineCap::Butt,
+                    gradient: Some(Gradient::new(red, blue, interpolation)),
+                    ..Default::default()
+                },
+            );
+            let image = software.render();
+            assert!(image.pixel(2, 10)[0] > 240);
+            assert!(image.pixel(97, 10)[2] > 240);
+            image.pixel(50, 10)
+        };
+        // Pixel 50 is centered just past the middle of the line.
+        assert_eq!(midpoint(ColorInterpolation::Linear), [126, 0, 129, 255]);
+        // Halfway between the encoded values is darker in linear light.
+        let srgb = midpoint(ColorInterpolation::Srgb);
+        assert!(srgb[0] < 60 && srgb[2] < 60, "{:?}", srgb);
+        let oklab = midpoint(ColorInterpolation::Oklab);
+        assert!(oklab[0] > srgb[0] && oklab[2] > srgb[2], "{:?}", oklab);
+    }
+
+    #[test]
+    fn test_constant_gradient_matches_plain_color() {
+        let color = Color::new(0.3, 0.6, 0.9, 0.7);
+        for interpolation in [
+            ColorInterpolation::Srgb,
+            ColorInterpolation::Linear,
+            ColorInterpolation::Oklab,
+        ] {
+            let mut software = SoftwareRenderer::new(
+                wgpu::TextureFormat::Rgba8Unorm,
+                150,
+                100,
+                16,
+                16,
+            );
+            let polyline = Polyline::new(vec![
+                P2::new(10.0, 10.0),
+                P2::new(70.0, 90.0),
+                P2::new(140.0, 20.0),
+            ]);
+            let style = LineStyle {
+                width: 9.0,
+                color,
+                ..Default::default()
+            };
+            software.polyline(&polyline, &style);
+            let plain = software.render();
+            software.polyline(
+                &polyline,
+                &LineStyle {
+                    gradient: Some(Gradient::new(color, color, interpolation)),
+                    ..style
+                },
+            );
+            let gradient = software.render();
+            assert!(
+                plain.max_difference(&gradient) <= 1,
+                "{:?}",
+                interpolation
+            );
+        }
+    }
+
+    /// Returns a scene of tapered lines and polylines.
+    fn tapered_scene() -> Vec<(Shape, LineStyle)> {
+        let style = |width, end_width, cap, join, color| LineStyle {
+            width,
+            end_width: Some(end_width),
+            cap,
+            join,
+            color,
+            ..Default::default()
         };
-        let ov = ofs * v;
-        let wt = w2 * t;
-        let ovp = ov + wt;
-        let ovn = ov - wt;
-
-        let polygon = Polygon::new(vec![
-            self.line.start() - ovp,
-            self.line.start() - ovn,
-            self.line.end() + ovp,
-            self.line.end() + ovn,
+        let zigzag = |y: f32| {
+            Polyline::new(vec![
+                P2::new(10.0, y),
+                P2::new(40.0, y + 20.0),
+                P2::new(70.0, y),
+                P2::new(100.0, y + 15.0),
+                P2::new(140.0, y + 5.0),
+            ])
+        };
+        vec![
+            (
+                Shape::Line(Line::new(
+                    P2::new(10.0, 10.0),
+                    P2::new(70.0, 25.0),
+                )),
+                style(
+                    16.0,
+                    2.0,
+                    LineCap::Round,
+                    LineJoin::Round,
+                    Color::new(0.9, 0.4, 0.4, 0.8),
+                ),
+            ),
+            (
+                Shape::Line(Line::new(
+                    P2::new(85.0, 10.0),
+                    P2::new(140.0, 30.0),
+                )),
+                style(
+                    1.0,
+                    12.0,
+                    LineCap::Square,
+                    LineJoin::Round,
+                    Color::new(0.4, 0.9, 0.4, 0.6),
+                ),
+            ),
+            (
+                Shape::Line(Line::new(
+                    P2::new(120.0, 50.0),
+                    P2::new(125.0, 52.0),
+                )),
+                style(
+                    20.0,
+                    4.0,
+                    LineCap::Round,
+                    LineJoin::Round,
+                    Color::new(1.0, 1.0, 0.2, 0.5),
+                ),
+            ),
+            (
+                Shape::Polyline(zigzag(35.0)),
+                style(
+                    2.0,
+                    10.0,
+                    LineCap::Butt,
+                    LineJoin::Miter { limit: 4.0 },
+                    Color::new(0.4, 0.4, 0.9, 0.8),
+                ),
+            ),
+            (
+                Shape::Polyline(zigzag(55.0)),
+                LineStyle {
+                    dash: Some(DashPattern::new(&[9.0, 4.0], 0.0)),
+                    ..style(
+                        9.0,
+                        0.0,
+                        LineCap::Round,
+                        LineJoin::Bevel,
+                        Color::new(0.2, 1.0, 1.0, 0.7),
+                    )
+                },
+            ),
+            (
+                Shape::Polyline(zigzag(75.0)),
+                style(
+                    12.0,
+                    3.0,
+                    LineCap::Square,
+                    LineJoin::Round,
+                    Color::new(1.0, 0.2, 1.0, 0.6),
+                ),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_tapered_strokes_match_gpu() {
+        let image =
+            check_scene(wgpu::TextureFormat::Rgba8Unorm, &tapered_scene());
+        // A point 6.4 from the center of the first line is inside it near
+        // its wide start, and outside it near its narrow end.
+        assert_eq!(pixel_at(&image, 13.5, 17.5), [184, 82, 82, 204]);
+        assert_eq!(pixel_at(&image, 61.5, 29.5), [0, 0, 0, 0]);
+        // The second line widens from 1 to 12: a point 3 from its axis is
+        // outside it near its start, and inside it two thirds of the way
+        // along. Its square end cap is as long as half its end width.
+        assert_eq!(pixel_at(&image, 92.5, 17.5), [0, 0, 0, 0]);
+        assert_eq!(pixel_at(&image, 121.5, 26.5), [61, 138, 61, 153]);
+        assert_eq!(pixel_at(&image, 143.5, 31.5), [61, 138, 61, 153]);
+        assert_eq!(pixel_at(&image, 146.5, 32.5), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_tapered_line_width() {
+        let mut software = SoftwareRenderer::new(
+            wgpu::TextureFormat::Rgba8Unorm,
+            150,
+            100,
+            16,
+            16,
+        );
+        software.line(
+            Line::new(P2::new(10.0, 50.0), P2::new(140.0, 50.0)),
+            &LineStyle {
+                width: 30.0,
+                end_width: Some(4.0),
+                ..Default::default()
+            },
+        );
+        let image = software.render();
+
+        // The width at pixel column x is 30 - 26 * (x + 0.5 - 10) / 130.
+        // Rows are flipped relative to beamline coordinates.
+        for (x, half_width) in [(12, 14.75), (75, 8.45), (137, 2.25)] {
+            let inside = (half_width - 1.0f32).floor() as u32;
+            let outside = (half_width + 1.0f32).ceil() as u32;
+            assert_eq!(image.pixel(x, 50 - inside)[3], 255, "{}", x);
+            assert_eq!(image.pixel(x, 49 + inside)[3], 255, "{}", x);
+            assert_eq!(image.pixel(x, 50 - outside)[3], 0, "{}", x);
+            assert_eq!(image.pixel(x, 49 + outside)[3], 0, "{}", x);
+        }
+    }
+
+    #[test]
+    fn test_slight_taper_matches_constant_width() {
+        let polyline = Polyline::new(vec![
+            P2::new(10.0, 20.0),
+            P2::new(60.0, 80.0),
+            P2::new(100.0, 30.0),
+            P2::new(140.0, 50.0),
         ]);
+        let dash = DashPattern::new(&[15.0, 6.0, 0.0, 6.0], 3.0);
+        for cap in [LineCap::Butt, LineCap::Round, LineCap::Square] {
+            for dash in [None, Some(dash)] {
+                let style = LineStyle {
+                    width: 10.0,
+                    cap,
+                    join: LineJoin::Round,
+                    dash,
+                    ..Default::default()
+                };
+                let mut software = SoftwareRenderer::new(
+                    wgpu::TextureFormat::Rgba8Unorm,
+                    150,
+                    100,
+                    16,
+                    16,
+                );
+                software.polyline(&polyline, &style);
+                let constant = software.render();
+                software.polyline(
+                    &polyline,
+                    &LineStyle {
+                        end_width: Some(10.001),
+                        ..style
+                    },
+                );
+                let tapered = software.render();
+                // Just inside the edge near a round cap, the distance to a
+                // constant-width dash (a rectangle unioned with discs) is
+                // slightly less exact than the distance to an uneven
+                // capsule.
+                assert!(constant.max_difference(&tapered) <= 3, "{:?}", cap);
+            }
+        }
+    }
 
-        polygon
+    /// Returns a scene of glowing strokes with every falloff curve.
+    fn glow_scene() -> Vec<(Shape, LineStyle)> {
+        let style = |width, glow, color| LineStyle {
+            width,
+            cap: LineCap::Round,
+            glow: Some(glow),
+            color,
+            ..Default::default()
+        };
+        vec![
+            (
+                Shape::Line(Line::new(
+                    P2::new(10.0, 12.0),
+                    P2::new(70.0, 20.0),
+                )),
+                style(
+                    2.0,
+                    Glow::new(8.0, GlowFalloff::Linear, 0.8),
+                    Color::new(0.4, 0.8, 1.0, 1.0),
+                ),
+            ),
+            (
+                Shape::Polyline(Polyline::new(vec![
+                    P2::new(85.0, 10.0),
+                    P2::new(110.0, 35.0),
+                    P2::new(140.0, 12.0),
+                ])),
+                LineStyle {
+                    end_width: Some(6.0),
+                    end_marker: Some(Marker::new(MarkerShape::Arrow, 2.0)),
+                    ..style(
+                        1.0,
+                        Glow::new(6.0, GlowFalloff::Quadratic, 2.0),
+                        Color::new(0.4, 1.0, 0.4, 0.8),
+                    )
+                },
+            ),
+            (
+                Shape::Cubic(CubicBezier::new(
+                    P2::new(10.0, 40.0),
+                    P2::new(30.0, 70.0),
+                    P2::new(50.0, 20.0),
+                    P2::new(70.0, 50.0),
+                )),
+                style(
+                    3.0,
+                    Glow::new(10.0, GlowFalloff::Gaussian, 0.6),
+                    Color::new(1.0, 0.5, 0.2, 0.9),
+                ),
+            ),
+            (
+                Shape::Arc(CircularArc::new(
+                    P2::new(110.0, 70.0),
+                    18.0,
+                    0.5,
+                    4.0,
+                )),
+                LineStyle {
+                    cap: LineCap::Butt,
+                    ..style(
+                        2.0,
+                        Glow::new(7.0, GlowFalloff::Linear, 1.0),
+                        Color::new(1.0, 0.2, 1.0, 0.7),
+                    )
+                },
+            ),
+            (
+                Shape::Ellipse(Ellipse::new(
+                    P2::new(40.0, 78.0),
+                    25.0,
+                    10.0,
+                    0.2,
+                )),
+                style(
+                    1.5,
+                    Glow::new(5.0, GlowFalloff::Gaussian, 1.0),
+                    Color::new(0.9, 0.9, 0.2, 1.0),
+                ),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_glow_matches_gpu() {
+        let image = check_scene(wgpu::TextureFormat::Rgba8Unorm, &glow_scene());
+        // The glow of the first line fades with the distance beyond its edge,
+        // and ends at the glow radius.
+        let near = pixel_at(&image, 39.5, 19.5)[3];
+        let far = pixel_at(&image, 39.5, 21.5)[3];
+        assert!(near < 255 && far < near && far > 0, "{} {}", near, far);
+        assert_eq!(pixel_at(&image, 38.5, 26.5), [0, 0, 0, 0]);
+        // 4.5 beyond the edge, the linear glow is 0.8 * (1 - 4.5 / 8).
+        assert_eq!(pixel_at(&image, 39.5, 21.5), [36, 71, 89, 89]);
+        // An intensity of 2 saturates the quadratic glow of the polyline
+        // near its edge, where it has the opacity of the line.
+        assert_eq!(pixel_at(&image, 87.5, 14.5), [82, 204, 82, 204]);
+        // Halfway out, the Gaussian glow behind the start of the cubic is
+        // 0.6 * (exp(-4.5 / 4) - exp(-4.5)) / (1 - exp(-4.5)).
+        assert_eq!(pixel_at(&image, 6.5, 34.5), [43, 22, 9, 43]);
+    }
+
+    #[test]
+    fn test_glow_falloff() {
+        let falloffs = [
+            GlowFalloff::Linear,
+            GlowFalloff::Quadratic,
+            GlowFalloff::Gaussian,
+        ];
+        // Opacity of the glow at pixel rows 3.5, 5.5 and 9.5 away from the
+        // edge of the line, which is 2 wide.
+        let mut alphas = Vec::new();
+        for falloff in falloffs {
+            let mut software = SoftwareRenderer::new(
+                wgpu::TextureFormat::Rgba8Unorm,
+                150,
+                100,
+                16,
+                16,
+            );
+            software.line(
+                Line::new(P2::new(10.0, 50.0), P2::new(140.0, 50.0)),
+                &LineStyle {
+                    width: 2.0,
+                    glow: Some(Glow::new(10.0, falloff, 0.5)),
+                    ..Default::default()
+                },
+            );
+            let image = software.render();
+            // Rows are flipped relative to beamline coordinates.
+            assert_eq!(image.pixel(75, 38)[3], 0);
+            let alpha = [45, 43, 39].map(|row| image.pixel(75, row)[3]);
+            assert!(alpha[0] > alpha[1] && alpha[1] > alpha[2], "{:?}", alpha);
+            assert!(alpha[0] <= 128);
+            alphas.push(alpha);
+        }
+        // At 0.35 of the radius, the linear falloff is 0.65, the quadratic
+        // falloff is 0.42, and the Gaussian falloff is 0.57.
+        let expected = [0.65, 0.4225, 0.5715];
+        for (alpha, expected) in alphas.iter().zip(expected) {
+            let expected = (0.5 * expected * 255.0f32).round() as i32;
+            assert!((alpha[0] as i32 - expected).abs() <= 1, "{:?}", alphas);
+        }
+    }
+
+    /// Returns a scene of translucent lines in every blend mode, each
+    /// crossing a pair of colored lines, and a multiplied fill.
+    fn blend_scene() -> Vec<(Shape, LineStyle)> {
+        let modes = [
+            BlendMode::Normal,
+            BlendMode::Additive,
+            BlendMode::Screen,
+            BlendMode::Max,
+            BlendMode::Multiply,
+        ];
+        let mut scene = vec![
+            (
+                Shape::Line(Line::new(
+                    P2::new(5.0, 30.0),
+                    P2::new(145.0, 30.0),
+                )),
+                LineStyle {
+                    width: 12.0,
+                    color: Color::new(0.9, 0.3, 0.1, 1.0),
+                    ..Default::default()
+                },
+            ),
+            (
+                Shape::Line(Line::new(
+                    P2::new(5.0, 70.0),
+                    P2::new(145.0, 70.0),
+                )),
+                LineStyle {
+                    width: 12.0,
+                    color: Color::new(0.2, 0.5, 0.8, 0.6),
+                    cap: LineCap::Round,
+                    ..Default::default()
+                },
+            ),
+        ];
+        for (i, blend_mode) in modes.into_iter().enumerate() {
+            let x = 15.0 + 28.0 * i as f32;
+            scene.push((
+                Shape::Line(Line::new(
+                    P2::new(x, 10.0),
+                    P2::new(x + 12.0, 90.0),
+                )),
+                LineStyle {
+                    width: 8.0,
+                    color: Color::new(0.4, 0.8, 0.6, 0.75),
+                    cap: LineCap::Round,
+                    blend_mode,
+                    ..Default::default()
+                },
+            ));
+        }
+        scene.push((
+            Shape::Fill(
+                Path::polygon(&[
+                    P2::new(100.0, 20.0),
+                    P2::new(140.0, 50.0),
+                    P2::new(110.0, 85.0),
+                ]),
+                FillStyle {
+                    color: Color::new(1.0, 0.9, 0.2, 0.8),
+                    blend_mode: BlendMode::Multiply,
+                    ..Default::default()
+                },
+            ),
+            LineStyle::default(),
+        ));
+        scene
+    }
+
+    #[test]
+    fn test_blend_modes_match_gpu() {
+        let image =
+            check_scene(wgpu::TextureFormat::Rgba8Unorm, &blend_scene());
+        // Where the lines cross the opaque orange line, additive, screen and
+        // max blending are no darker than the orange in any channel, and
+        // multiply is no brighter.
+        let orange = pixel_at(&image, 8.5, 30.5);
+        for x in [46.5, 74.5, 102.5] {
+            let pixel = pixel_at(&image, x, 30.5);
+            assert!((0..3).all(|i| pixel[i] >= orange[i]), "{:?}", pixel);
+        }
+        let multiplied = pixel_at(&image, 130.5, 30.5);
+        assert!(
+            (0..3).all(|i| multiplied[i] < orange[i]),
+            "{:?}",
+            multiplied
+        );
+        // Over the orange, the premultiplied color of the lines is
+        // (0.3, 0.6, 0.45, 0.75). Normal blending covers three quarters of
+        // the orange, additive blending adds to it, screen blending inverts
+        // the product of the inverses, max blending takes the larger of the
+        // two, and multiply blending takes their product.
+        assert_eq!(pixel_at(&image, 18.5, 30.5), [134, 172, 121, 255]);
+        assert_eq!(pixel_at(&image, 46.5, 30.5), [255, 230, 140, 255]);
+        assert_eq!(pixel_at(&image, 74.5, 30.5), [237, 184, 129, 255]);
+        assert_eq!(pixel_at(&image, 98.5, 25.5), [230, 172, 121, 255]);
+        assert_eq!(multiplied, [126, 65, 18, 255]);
+        check_scene(wgpu::TextureFormat::Rgba8UnormSrgb, &blend_scene());
     }
 }
@@ -0,0 +1,154 @@
+//! Rendering of test scenes on the GPU and with the software renderer.
+
+use crate::{
+    tests::gpu::Gpu, CircularArc, CubicBezier, Ellipse, FillRule, FillStyle,
+    Image, Line, LineStyle, Path, Polyline, QuadraticBezier, Renderer,
+    SoftwareRenderer, P2,
+};
+
+/// A shape in a test scene.
+pub enum Shape {
+    Line(Line),
+    Polyline(Polyline),
+    Quadratic(QuadraticBezier),
+    Cubic(CubicBezier),
+    Arc(CircularArc),
+    Ellipse(Ellipse),
+    /// A filled path. The line style paired with it is not used.
+    Fill(Path, FillStyle),
+    /// Pushes a clip rectangle. The line style paired with it is not
+    /// used.
+    PushClipRect(P2, P2),
+    /// Pushes a clip path. The line style paired with it is not used.
+    PushClipPath(Path, FillRule),
+    /// Pops a clip. The line style paired with it is not used.
+    PopClip,
+    /// Selects a layer by name, first creating it with a z index and
+    /// group opacity if it does not exist. The line style paired with it
+    /// is not used.
+    Layer(&'static str, i32, f32),
+}
+
+/// Renders the same scene on the GPU and with the software renderer,
+/// and checks that the images agree within a small tolerance.
+///
+/// # Returns
+///
+/// The image rendered on the GPU.
+fn check_against_gpu(
+    texture_format: wgpu::TextureFormat,
+    draw_tiles: bool,
+    shapes: &[(Shape, LineStyle)],
+) -> Image {
+    const WIDTH: u32 = 150;
+    const HEIGHT: u32 = 100;
+    const TILE_SIZE: u32 = 16;
+    const TOLERANCE: u8 = 2;
+
+    let gpu = Gpu::new();
+    let mut renderer = Renderer::new(
+        &gpu.device,
+        texture_format,
+        WIDTH,
+        HEIGHT,
+        TILE_SIZE,
+        TILE_SIZE,
+    );
+    let mut software = SoftwareRenderer::new(
+        texture_format,
+        WIDTH,
+        HEIGHT,
+        TILE_SIZE,
+        TILE_SIZE,
+    );
+    renderer.set_draw_tiles(draw_tiles);
+    software.set_draw_tiles(draw_tiles);
+    for (shape, style) in shapes {
+        match shape {
+            Shape::Line(line) => {
+                renderer.line(line.clone(), style);
+                software.line(line.clone(), style);
+            }
+            Shape::Polyline(polyline) => {
+                renderer.polyline(polyline, style);
+                software.polyline(polyline, style);
+            }
+            Shape::Quadratic(curve) => {
+                renderer.quadratic(curve.clone(), style);
+                software.quadratic(curve.clone(), style);
+            }
+            Shape::Cubic(curve) => {
+                renderer.cubic(curve.clone(), style);
+                software.cubic(curve.clone(), style);
+            }
+            Shape::Arc(arc) => {
+                renderer.arc(arc.clone(), style);
+                software.arc(arc.clone(), style);
+            }
+            Shape::Ellipse(ellipse) => {
+                renderer.ellipse(ellipse.clone(), style);
+                software.ellipse(ellipse.clone(), style);
+            }
+            Shape::Fill(path, fill_style) => {
+                renderer.fill(path, fill_style);
+                software.fill(path, fill_style);
+            }
+            Shape::PushClipRect(a, b) => {
+                renderer.push_clip_rect(*a, *b);
+                software.push_clip_rect(*a, *b);
+            }
+            Shape::PushClipPath(path, rule) => {
+                renderer.push_clip_path(path, *rule);
+                software.push_clip_path(path, *rule);
+            }
+            Shape::PopClip => {
+                renderer.pop_clip();
+                software.pop_clip();
+            }
+            Shape::Layer(name, z, opacity) => {
+                let id = renderer
+                    .layer_id(name)
+                    .unwrap_or_else(|| renderer.add_layer(name, *z));
+                renderer.layer_mut(id).opacity = *opacity;
+                renderer.set_layer(id);
+                let id = software
+                    .layer_id(name)
+                    .unwrap_or_else(|| software.add_layer(name, *z));
+                software.layer_mut(id).opacity = *opacity;
+                software.set_layer(id);
+            }
+        }
+    }
+
+    let gpu_image = renderer.render_offscreen(&gpu.device, &gpu.queue);
+    let cpu_image = software.render();
+    let difference = gpu_image.max_difference(&cpu_image);
+    assert!(
+        difference <= TOLERANCE,
+        "GPU and software images differ by {} (drawing tiles: {})",
+        difference,
+        draw_tiles
+    );
+    gpu_image
+}
+
+/// Checks a scene against the GPU (see [`check_against_gpu`]), both with
+/// and without the tiles drawn.
+///
+/// # Returns
+///
+/// The image rendered on the GPU without the tiles drawn, so that tests
+/// can check the pixels which they expect each feature to draw.
+pub fn check_scene(
+    texture_format: wgpu::TextureFormat,
+    shapes: &[(Shape, LineStyle)],
+) -> Image {
+    check_against_gpu(texture_format, true, shapes);
+    check_against_gpu(texture_format, false, shapes)
+}
+
+/// Returns the pixel of an image which contains a point, in beamline
+/// coordinates. Rows of the image are counted from the top.
+pub fn pixel_at(image: &Image, x: f32, y: f32) -> [u8; 4] {
+    image.pixel(x.floor() as u32, image.height() - 1 - y.floor() as u32)
+}
@@ -1,14 +1,27 @@
 //! Assign lines to tiles.
 
 use crate::{
+    arc::CircularArc,
     bbox::Bbox,
+    bezier::{CubicBezier, QuadraticBezier},
+    clip::ClipRect,
+    ellipse::Ellipse,
+    layer::{Layer, LayerId, DEFAULT_LAYER_NAME},
+    marker::{self, StrokeEnd},
+    path::{ray_crossing, Path, FLATTEN_TOLERANCE},
     polygon::Polygon,
-    style::StyledLine,
+    polyline::Polyline,
+    primitive::{GradientSpan, Primitive, StyledPrimitive},
+    style::{
+        FillRule, FillStyle, LineJoin, LineStyle, StrokeScaling, StyledLine,
+    },
+    transform::{self, Camera, TransformStack, Transformable},
     types::{ceil_div_u32, v2_rot90_anticlockwise},
-    Line, P2, V2,
+    Line, LineCap, M3, P2, V2,
 };
+use cgmath::{InnerSpace, MetricSpace, SquareMatrix, Transform};
 use itertools::Itertools;
-use std::ops::RangeInclusive;
+use std::{borrow::Cow, ops::RangeInclusive};
 
 /// Tiler: Assigns lines to a regular grid of tiles.
 ///
@@ -16,47 +29,113 @@ use std::ops::RangeInclusive;
 ///
 /// 1. It should be created with [`Tiler::new`] at the start of the renderer,
 ///    and re-used for each frame.
-/// 2. Within a frame, lines should be added to the tiler using [`Tiler::add`].
+/// 2. Within a frame, lines should be added to the tiler using [`Tiler::add`],
+///    [`Tiler::add_polyline`], [`Tiler::add_quadratic`],
+///    [`Tiler::add_cubic`], [`Tiler::add_arc`], [`Tiler::add_ellipse`] and
+///    [`Tiler::add_fill`].
 /// 3. When a frame is to be drawn, [`Tiler::drain`] should be called to
 ///    produce the necessary rendering structures.
 ///
 /// Re-using the tiler means that the vector containing the styled line
 /// information is re-used at its full capacity, and not re-allocated more
 /// than necessary.
+///
+/// Shapes are mapped through the current transform as they are added (see
+/// [`Tiler::transform`]). The transform is kept from frame to frame.
+///
+/// Shapes are also clipped to the clips on the clip stack (see
+/// [`Tiler::push_clip_rect`] and [`Tiler::push_clip_path`]), which are kept
+/// from frame to frame in the same way.
+///
+/// Shapes are drawn in the current layer (see [`Tiler::set_layer`]). The
+/// layers and the current layer are also kept from frame to frame.
+///
+/// The tiler can also defer the assignment of primitives to tiles, so that
+/// it can be done by GPU binning instead (see [`Tiler::set_defer_binning`]).
 #[derive(Debug)]
 pub struct Tiler {
+    area_width: u32,
+    area_height: u32,
     tile_width: u32,
     tile_height: u32,
     n_x_tiles: u32,
     n_y_tiles: u32,
-    /// Vector of tuples containing a linear tile index and a styled line
-    /// that has been placed in that tile.
-    lines: Vec<(usize, StyledLine)>,
+    /// Stack of model transforms.
+    transforms: TransformStack,
+    /// Camera, or `None` if shapes are in pixel coordinates.
+    camera: Option<Camera>,
+    /// Product of the view of the camera and the model transforms, or `None`
+    /// if it is the identity.
+    transform: Option<M3>,
+    /// Stack of clips, with the innermost last.
+    clips: Vec<Clip>,
+    /// Layers, indexed by their identifiers.
+    layers: Vec<Layer>,
+    /// Layer that shapes are added to.
+    layer: LayerId,
+    /// Primitives which have been added. Each primitive is stored once,
+    /// however many tiles it is placed in.
+    primitives: Vec<StyledPrimitive>,
+    /// Vector of tuples containing a linear tile index and the index in
+    /// `primitives` of a primitive that has been placed in that tile.
+    lines: Vec<(usize, u32)>,
+    /// `true` if primitives which only need a separating axis test are
+    /// deferred, instead of being assigned to tiles.
+    defer_binning: bool,
+    /// Primitives whose assignment to tiles has been deferred, in the order
+    /// in which they were added.
+    deferred: Vec<DeferredPrimitive>,
+    /// `true` if primitives with an exact test are tested exactly against
+    /// tiles, as well as against their bounding polygons.
+    exact_tile_tests: bool,
+    /// Shape identifier to use for the next shape that is added.
+    next_shape: u32,
 }
 impl Tiler {
     /// Creates a new `Tiler` for the specified area and tile sizes.
-    pub fn new(area_width: u32, area_height: u32, tile_width: u32, tile_height: u32) -> Self {
+    pub fn new(
+        area_width: u32,
+        area_height: u32,
+        tile_width: u32,
+        tile_height: u32,
+    ) -> Self {
         assert!(area_width > 0);
         assert!(area_height > 0);
         assert!(tile_width > 0);
         assert!(tile_height > 0);
 
         // Compute numbers of x and y tiles using a "ceiling" integer divide.
-        let (n_x_tiles, n_y_tiles) = n_tiles(area_width, area_height, tile_width, tile_height);
+        let (n_x_tiles, n_y_tiles) =
+            n_tiles(area_width, area_height, tile_width, tile_height);
 
         Tiler {
+            area_width,
+            area_height,
             tile_width,
             tile_height,
             n_x_tiles,
             n_y_tiles,
+            transforms: TransformStack::new(),
+            camera: None,
+            transform: None,
+            clips: Vec::new(),
+            layers: vec![Layer::new(DEFAULT_LAYER_NAME, 0)],
+            layer: LayerId::DEFAULT,
+            primitives: Vec::new(),
             lines: Vec::new(),
+            defer_binning: false,
+            deferred: Vec::new(),
+            exact_tile_tests: true,
+            next_shape: 0,
         }
     }
 
     /// Resize the tiler to account for a new renderable area.
     ///
     /// This clears the buffer inside the tiler, meaning that it will have no
-    /// recorded lines after this operation.
+    /// recorded lines after this operation. The transform is kept, with the
+    /// view of the camera centered on the new area. Clips are kept at the
+    /// same pixels.
     ///
     /// # Parameters
     ///
@@ -68,64 +147,1304 @@ impl Tiler {
 
         let (n_x_tiles, n_y_tiles) =
             n_tiles(area_width, area_height, self.tile_width, self.tile_height);
+        self.area_width = area_width;
+        self.area_height = area_height;
         self.n_x_tiles = n_x_tiles;
         self.n_y_tiles = n_y_tiles;
+        self.primitives.clear();
         self.lines.clear();
+        self.deferred.clear();
+        self.next_shape = 0;
+        self.update_transform();
+        let mut clips = std::mem::take(&mut self.clips);
+        for path in clips.iter_mut().filter_map(|clip| clip.path.as_mut()) {
+            path.tiles = self.path_tiles(&path.edges);
+        }
+        self.clips = clips;
+    }
+
+    /// Pushes a model transform, which applies to the shapes added until it
+    /// is popped.
+    ///
+    /// The transform is applied to shapes before any transforms which were
+    /// pushed earlier, and before the view of the camera.
+    ///
+    /// # Parameters
+    ///
+    /// - `matrix`: Affine transform to push. Its last row must be
+    ///   `(0, 0, 1)`, and it must be invertible.
+    pub fn push_transform(&mut self, matrix: M3) {
+        self.transforms.push(matrix);
+        self.update_transform();
+    }
+
+    /// Pops the model transform which was pushed last.
+    ///
+    /// There must be a transform to pop.
+    pub fn pop_transform(&mut self) {
+        self.transforms.pop();
+        self.update_transform();
+    }
+
+    /// Sets the camera, which maps world coordinates to pixels after the
+    /// model transforms.
+    ///
+    /// # Parameters
+    ///
+    /// - `camera`: Camera, or `None` if shapes are in pixel coordinates.
+    pub fn set_camera(&mut self, camera: Option<Camera>) {
+        self.camera = camera;
+        self.update_transform();
+    }
+
+    /// Returns the transform which maps the coordinates of shapes to pixels.
+    ///
+    /// This is the view of the camera, if there is one, applied after the
+    /// product of the model transforms.
+    pub fn transform(&self) -> M3 {
+        self.transform.unwrap_or_else(M3::identity)
+    }
+
+    /// Pushes a clip rectangle, which applies to the shapes added until it is
+    /// popped.
+    ///
+    /// The rectangle is mapped through the current transform when it is
+    /// pushed. If the transform rotates it away from the axes, it is pushed
+    /// as a clip path instead (see [`Tiler::push_clip_path`]).
+    ///
+    /// # Parameters
+    ///
+    /// - `a`: A corner of the rectangle.
+    /// - `b`: The opposite corner of the rectangle.
+    pub fn push_clip_rect(&mut self, a: P2, b: P2) {
+        let m = self.transform();
+        let axis_aligned =
+            (m.x.y == 0.0 && m.y.x == 0.0) || (m.x.x == 0.0 && m.y.y == 0.0);
+        if !axis_aligned {
+            let corners = [a, P2::new(b.x, a.y), b, P2::new(a.x, b.y)];
+            self.push_clip_path(&Path::polygon(&corners), FillRule::NonZero);
+            return;
+        }
+        let rect = ClipRect::new(m.transform_point(a), m.transform_point(b));
+        let rect = match self.clip_rect() {
+            Some(outer) => outer.intersection(&rect),
+            None => rect,
+        };
+        self.clips.push(Clip {
+            rect: Some(rect),
+            path: None,
+        });
+    }
+
+    /// Pushes a clip path, which applies to the shapes added until it is
+    /// popped.
+    ///
+    /// The path is mapped through the current transform when it is pushed.
+    /// Shapes are antialiased at its edges in the same way as fills.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: Path to clip to. Its subpaths are implicitly closed.
+    /// - `rule`: Rule which decides which points are inside the path.
+    pub fn push_clip_path(&mut self, path: &Path, rule: FillRule) {
+        let edges = self.map_ref(path).edges();
+        let tiles = self.path_tiles(&edges);
+        self.clips.push(Clip {
+            rect: self.clip_rect(),
+            path: Some(ClipPath { edges, rule, tiles }),
+        });
+    }
+
+    /// Pops the clip which was pushed last.
+    ///
+    /// There must be a clip to pop.
+    pub fn pop_clip(&mut self) {
+        self.clips.pop().expect("clip stack is empty");
+    }
+
+    /// Creates a new layer.
+    ///
+    /// # Parameters
+    ///
+    /// - `name`: Name of the layer, which must not be the name of an
+    ///   existing layer.
+    /// - `z`: Z index of the layer. Layers with higher z indices are drawn
+    ///   on top.
+    ///
+    /// # Returns
+    ///
+    /// The identifier of the new layer.
+    pub fn add_layer(&mut self, name: &str, z: i32) -> LayerId {
+        assert!(
+            self.layer_id(name).is_none(),
+            "layer {name:?} already exists"
+        );
+        self.layers.push(Layer::new(name, z));
+        LayerId::new(self.layers.len() - 1)
+    }
+
+    /// Returns the identifier of the layer with a name, or `None` if there
+    /// is no such layer.
+    pub fn layer_id(&self, name: &str) -> Option<LayerId> {
+        self.layers
+            .iter()
+            .position(|layer| layer.name() == name)
+            .map(LayerId::new)
+    }
+
+    /// Returns a layer.
+    pub fn layer(&self, id: LayerId) -> &Layer {
+        &self.layers[id.index() as usize]
+    }
+
+    /// Returns a layer, to change its z index, visibility or opacity.
+    ///
+    /// Changes apply to all the shapes of the layer which have not yet been
+    /// drained, including shapes which were added before the change.
+    pub fn layer_mut(&mut self, id: LayerId) -> &mut Layer {
+        &mut self.layers[id.index() as usize]
+    }
+
+    /// Selects the layer which shapes are added to, until another layer is
+    /// selected.
+    ///
+    /// # Parameters
+    ///
+    /// - `id`: Identifier of the layer.
+    pub fn set_layer(&mut self, id: LayerId) {
+        assert!((id.index() as usize) < self.layers.len());
+        self.layer = id;
+    }
+
+    /// Returns the layer which shapes are added to.
+    pub fn current_layer(&self) -> LayerId {
+        self.layer
+    }
+
+    /// Returns the intersection of the clip rectangles on the stack, or
+    /// `None` if there are none.
+    fn clip_rect(&self) -> Option<ClipRect> {
+        self.clips.last().and_then(|clip| clip.rect)
+    }
+
+    /// Recomputes the transform after the camera, the model transforms or
+    /// the area have changed.
+    fn update_transform(&mut self) {
+        let model = self.transforms.current();
+        self.transform = match self.camera {
+            Some(camera) => Some(
+                camera.view_matrix(self.area_width, self.area_height) * model,
+            ),
+            None if model != M3::identity() => Some(model),
+            None => None,
+        };
+    }
+
+    /// Returns a shape mapped through the transform.
+    fn map<T: Transformable>(&self, shape: T) -> T {
+        match &self.transform {
+            Some(matrix) => shape.transformed(matrix),
+            None => shape,
+        }
+    }
+
+    /// Returns a borrowed shape mapped through the transform.
+    fn map_ref<'a, T: Transformable + Clone>(
+        &self,
+        shape: &'a T,
+    ) -> Cow<'a, T> {
+        match &self.transform {
+            Some(matrix) => Cow::Owned(shape.transformed(matrix)),
+            None => Cow::Borrowed(shape),
+        }
+    }
+
+    /// Returns a style with its widths scaled by the transform, unless they
+    /// are non-scaling.
+    fn map_style<'a>(&self, style: &'a LineStyle) -> Cow<'a, LineStyle> {
+        match &self.transform {
+            Some(matrix) if style.stroke_scaling == StrokeScaling::Scaling => {
+                Cow::Owned(style.scaled(transform::stroke_scale(matrix)))
+            }
+            _ => Cow::Borrowed(style),
+        }
     }
 
     /// Add a styled line to the tiler.
     ///
     /// This checks the line against the tiles and adds it into a list of
-    /// line-tile allocations.
+    /// line-tile allocations. Any markers of the style are added at the ends
+    /// of the line, which is trimmed back from arrowheads.
     pub fn add(&mut self, styled_line: StyledLine) {
-        // Compute the bounding-polygon and bounding box of the line.
-        // These include the line width and end style information.
-        let bounding_polygon = styled_line.bounding_polygon();
-        let bounding_box = bounding_polygon.bbox();
-
-        // Find the tiles that the line's bounding box intersects.
-        let opt_tiles_intersection =
-            TilesIntersection::from_bbox(self.tile_width, self.tile_height, &bounding_box)
-                .clip_to_area(self.n_x_tiles, self.n_y_tiles);
+        let line = self.map(styled_line.line);
+        let style = &*self.map_style(&styled_line.style);
+        let (a, b) = (line.start(), line.end());
+        let length = a.distance(b);
+        let end_width = style.end_width.unwrap_or(style.width);
+        let (d, from, to) = if length > 0.0 {
+            let trim_start =
+                marker::trim_distance(style.start_marker, style.width, length);
+            let trim_end =
+                marker::trim_distance(style.end_marker, end_width, length);
+            let d = line.ab_vec() / length;
+            (d, trim_start / length, 1.0 - trim_end / length)
+        } else {
+            (V2::new(1.0, 0.0), 0.0, 1.0)
+        };
+        let width_at = |t: f32| style.width + (end_width - style.width) * t;
+
+        let segment = Primitive::Segment {
+            line: Line::new(a + from * length * d, a + to * length * d),
+            start_cap: style.start_cap(),
+            end_cap: style.end_cap(),
+            distance: from * length,
+            start_width: width_at(from),
+            end_width: width_at(to),
+        };
+        let gradient = GradientSpan::of_style(style);
+        let mut primitives =
+            vec![(segment, gradient.map(|g| g.part(from, to)))];
+        primitives.extend(marker::end_markers(
+            style,
+            StrokeEnd {
+                point: a,
+                direction: -d,
+                width: style.width,
+                gradient: gradient.map(|g| g.part(0.0, 0.0)),
+            },
+            StrokeEnd {
+                point: b,
+                direction: d,
+                width: end_width,
+                gradient: gradient.map(|g| g.part(1.0, 1.0)),
+            },
+        ));
+        self.add_shape(primitives, style);
+    }
+
+    /// Add a styled polyline to the tiler.
+    ///
+    /// The polyline is decomposed into primitives (see
+    /// [`Polyline::primitives`]), which are all added as parts of a single
+    /// shape.
+    pub fn add_polyline(&mut self, polyline: &Polyline, style: &LineStyle) {
+        let style = self.map_style(style);
+        let primitives = self.map_ref(polyline).primitives(&style);
+        self.add_shape(primitives, &style);
+    }
+
+    /// Add a styled quadratic Bézier curve to the tiler.
+    ///
+    /// The caps of the style are used at the ends of the curve. Any markers
+    /// of the style are added at its ends, and the curve is trimmed back
+    /// from arrowheads.
+    pub fn add_quadratic(&mut self, curve: QuadraticBezier, style: &LineStyle) {
+        let curve = self.map(curve);
+        let style = &*self.map_style(style);
+        let control_points = curve.control_points();
+        let (t0, t1) = marker::curve_trim_params(style, &control_points, |t| {
+            curve.eval_param(t)
+        });
+        let primitive = Primitive::Quadratic {
+            curve: curve.sub_curve(t0, t1),
+            start_cap: style.start_cap(),
+            end_cap: style.end_cap(),
+        };
+        self.add_curve_shape(primitive, &control_points, t0, t1, style);
+    }
+
+    /// Add a styled cubic Bézier curve to the tiler.
+    ///
+    /// The caps of the style are used at the ends of the curve. Any markers
+    /// of the style are added at its ends, and the curve is trimmed back
+    /// from arrowheads.
+    pub fn add_cubic(&mut self, curve: CubicBezier, style: &LineStyle) {
+        let curve = self.map(curve);
+        let style = &*self.map_style(style);
+        let control_points = curve.control_points();
+        let (t0, t1) = marker::curve_trim_params(style, &control_points, |t| {
+            curve.eval_param(t)
+        });
+        let primitive = Primitive::Cubic {
+            curve: curve.sub_curve(t0, t1),
+            start_cap: style.start_cap(),
+            end_cap: style.end_cap(),
+        };
+        self.add_curve_shape(primitive, &control_points, t0, t1, style);
+    }
+
+    /// Adds a trimmed curve, and the markers at the ends of the untrimmed
+    /// curve, as a shape.
+    ///
+    /// # Parameters
+    ///
+    /// - `primitive`: The trimmed curve.
+    /// - `control_points`: Control points of the untrimmed curve.
+    /// - `t0`: Parameter of the start of the trimmed curve.
+    /// - `t1`: Parameter of the end of the trimmed curve.
+    /// - `style`: Style of the curve.
+    fn add_curve_shape(
+        &mut self,
+        primitive: Primitive,
+        control_points: &[P2],
+        t0: f32,
+        t1: f32,
+        style: &LineStyle,
+    ) {
+        let gradient = GradientSpan::of_style(style);
+        let (start_direction, end_direction) =
+            marker::curve_end_directions(control_points);
+        let mut primitives =
+            vec![(primitive, gradient.map(|g| g.part(t0, t1)))];
+        primitives.extend(marker::end_markers(
+            style,
+            StrokeEnd {
+                point: control_points[0],
+                direction: start_direction,
+                width: style.width,
+                gradient: gradient.map(|g| g.part(0.0, 0.0)),
+            },
+            StrokeEnd {
+                point: control_points[control_points.len() - 1],
+                direction: end_direction,
+                width: style.width,
+                gradient: gradient.map(|g| g.part(1.0, 1.0)),
+            },
+        ));
+        self.add_shape(primitives, style);
+    }
+
+    /// Add a styled circular arc to the tiler.
+    ///
+    /// The caps of the style are used at the ends of the arc. Any markers of
+    /// the style are added at its ends, and the arc is trimmed back from
+    /// arrowheads. Whole circles have no ends, so they have no markers.
+    ///
+    /// An arc stays a circular arc under a transform which is a uniform
+    /// scale and rotation. Under other transforms, a whole circle becomes
+    /// the ellipse that it is mapped to, and any other arc is flattened into
+    /// a polyline with round joins.
+    pub fn add_arc(&mut self, arc: CircularArc, style: &LineStyle) {
+        let style = self.map_style(style);
+        let Some(matrix) = self.transform else {
+            return self.add_arc_shape(arc, &style);
+        };
+        if let Some(arc) = arc.transformed(&matrix) {
+            return self.add_arc_shape(arc, &style);
+        }
+        if arc.is_circle() {
+            let (center, r) = (arc.center(), arc.radius());
+            let ellipse = Ellipse::new(center, r, r, 0.0).transformed(&matrix);
+            let gradient = GradientSpan::of_style(&style);
+            let primitive = Primitive::Ellipse(ellipse);
+            return self.add_shape(vec![(primitive, gradient)], &style);
+        }
+        let (_, max_scale, _) =
+            transform::unit_circle_image(transform::linear_part(&matrix));
+        let polyline = arc
+            .flatten(FLATTEN_TOLERANCE / max_scale)
+            .transformed(&matrix);
+        let style = LineStyle {
+            join: LineJoin::Round,
+            end_width: None,
+            dash: None,
+            ..style.into_owned()
+        };
+        self.add_shape(polyline.primitives(&style), &style);
+    }
+
+    /// Adds an arc, and the markers at its ends, as a shape.
+    fn add_arc_shape(&mut self, arc: CircularArc, style: &LineStyle) {
+        let gradient = GradientSpan::of_style(style);
+        if arc.is_circle() {
+            let primitive = Primitive::Arc {
+                arc,
+                start_cap: style.cap,
+                end_cap: style.cap,
+            };
+            self.add_shape(vec![(primitive, gradient)], style);
+            return;
+        }
+
+        // Angle subtended by a chord of the trim distance at each end.
+        let (r, sweep) = (arc.radius(), arc.sweep());
+        let trim_angle = |marker| {
+            let trim = marker::trim_distance(marker, style.width, r * sweep);
+            2.0 * (trim / (2.0 * r)).min(1.0).asin()
+        };
+        let (trim_start, trim_end) =
+            (trim_angle(style.start_marker), trim_angle(style.end_marker));
+        let (a0, a1) = (arc.start_angle(), arc.end_angle());
+        let primitive = Primitive::Arc {
+            arc: CircularArc::new(
+                arc.center(),
+                r,
+                a0 + trim_start,
+                a1 - trim_end,
+            ),
+            start_cap: style.start_cap(),
+            end_cap: style.end_cap(),
+        };
+        let (from, to) = (trim_start / sweep, 1.0 - trim_end / sweep);
+        let mut primitives =
+            vec![(primitive, gradient.map(|g| g.part(from, to)))];
+        primitives.extend(marker::end_markers(
+            style,
+            StrokeEnd {
+                point: arc.start(),
+                direction: V2::new(a0.sin(), -a0.cos()),
+                width: style.width,
+                gradient: gradient.map(|g| g.part(0.0, 0.0)),
+            },
+            StrokeEnd {
+                point: arc.end(),
+                direction: V2::new(-a1.sin(), a1.cos()),
+                width: style.width,
+                gradient: gradient.map(|g| g.part(1.0, 1.0)),
+            },
+        ));
+        self.add_shape(primitives, style);
+    }
+
+    /// Add a styled ellipse to the tiler.
+    pub fn add_ellipse(&mut self, ellipse: Ellipse, style: &LineStyle) {
+        let ellipse = self.map(ellipse);
+        let style = &*self.map_style(style);
+        let gradient = GradientSpan::of_style(style);
+        self.add_shape(vec![(Primitive::Ellipse(ellipse), gradient)], style);
+    }
+
+    /// Add a filled path to the tiler.
+    ///
+    /// Each tile near an edge of the path receives a [`Primitive::Fill`]
+    /// followed by the edges of the path near the tile, which the shader
+    /// uses to compute the coverage of each pixel. Tiles which are not near
+    /// any edge are entirely inside or entirely outside the path. Those
+    /// inside receive only a [`Primitive::Fill`], and are filled solidly,
+    /// while those outside receive nothing.
+    pub fn add_fill(&mut self, path: &Path, style: &FillStyle) {
+        let shape = self.next_shape;
+        self.next_shape = self.next_shape.wrapping_add(1);
+
+        // Fill primitives only use the color and blend mode of a line style.
+        let line_style = LineStyle {
+            color: style.color,
+            blend_mode: style.blend_mode,
+            ..Default::default()
+        };
+        let edges = self.map_ref(path).edges();
+        let Some(path_tiles) = self.path_tiles(&edges) else {
+            return;
+        };
+
+        // The tiles share the fill primitives of each winding number, and
+        // the primitives of each edge.
+        let mut fills: Vec<(i32, u32)> = Vec::new();
+        let mut edge_primitives: Vec<Option<u32>> = vec![None; edges.len()];
+        let first = self.lines.len();
+        for tile_y in path_tiles.tiles.y_tiles() {
+            for tile_x in path_tiles.tiles.x_tiles() {
+                let (winding, near_edges) =
+                    path_tiles.get(tile_x, tile_y).unwrap();
+                if near_edges.is_empty() && !style.rule.is_inside(*winding) {
+                    continue;
+                }
+
+                let tile_ix = self.tile_ix(tile_x, tile_y);
+                let fill = match fills.iter().find(|(w, _)| w == winding) {
+                    Some((_, fill)) => *fill,
+                    None => {
+                        let fill = self.push_primitive(StyledPrimitive {
+                            primitive: Primitive::Fill {
+                                winding: *winding,
+                                rule: style.rule,
+                            },
+                            style: line_style.clone(),
+                            gradient: None,
+                            shape,
+                            clip_rect: None,
+                            layer: self.layer,
+                            layer_opacity: 1.0,
+                        });
+                        fills.push((*winding, fill));
+                        fill
+                    }
+                };
+                self.lines.push((tile_ix, fill));
+                for i in near_edges {
+                    let edge = match edge_primitives[*i] {
+                        Some(edge) => edge,
+                        None => {
+                            let edge = self.push_primitive(StyledPrimitive {
+                                primitive: Primitive::FillEdge(
+                                    edges[*i].clone(),
+                                ),
+                                style: line_style.clone(),
+                                gradient: None,
+                                shape,
+                                clip_rect: None,
+                                layer: self.layer,
+                                layer_opacity: 1.0,
+                            });
+                            edge_primitives[*i] = Some(edge);
+                            edge
+                        }
+                    };
+                    self.lines.push((tile_ix, edge));
+                }
+            }
+        }
+        self.clip_shape(first, shape);
+    }
+
+    /// Find the tiles within the bounding box of a path, with the winding
+    /// number of the path at the reference point of each tile and the edges
+    /// of the path near it.
+    ///
+    /// # Parameters
+    ///
+    /// - `edges`: Edges of the path, in pixels.
+    ///
+    /// # Returns
+    ///
+    /// The tiles of the path, or `None` if the path is outside the area.
+    fn path_tiles(&self, edges: &[Line]) -> Option<PathTiles> {
+        let edge_polygons: Vec<Polygon> = edges
+            .iter()
+            .map(|edge| {
+                StyledPrimitive {
+                    primitive: Primitive::FillEdge(edge.clone()),
+                    style: LineStyle::default(),
+                    gradient: None,
+                    shape: 0,
+                    clip_rect: None,
+                    layer: LayerId::DEFAULT,
+                    layer_opacity: 1.0,
+                }
+                .bounding_polygon()
+            })
+            .collect();
+
+        // Find the tiles that the path's bounding box intersects. Tiles
+        // outside of it are outside the path.
+        let bounding_box = Bbox::including(
+            edge_polygons.iter().flat_map(|polygon| polygon.vertices()),
+        )?;
+        let tiles_intersection = TilesIntersection::from_bbox(
+            self.tile_width,
+            self.tile_height,
+            &bounding_box,
+        )
+        .clip_to_area(self.n_x_tiles, self.n_y_tiles)?;
+
+        // Find the edges near each tile, using a separating axis test
+        // against the bounding polygon of each edge.
+        let mut path_tiles = PathTiles {
+            tile_edges: vec![
+                (0, Vec::new());
+                tiles_intersection.x_tiles().count()
+                    * tiles_intersection.y_tiles().count()
+            ],
+            tiles: tiles_intersection,
+        };
+        for (i, polygon) in edge_polygons.iter().enumerate() {
+            let edge_bbox = Bbox::including(polygon.vertices().iter()).unwrap();
+            let edge_tiles = TilesIntersection::from_bbox(
+                self.tile_width,
+                self.tile_height,
+                &edge_bbox,
+            );
+            let mut test_axes = polygon.edge_axes();
+            test_axes.push(V2::new(1.0, 0.0));
+            test_axes.push(V2::new(0.0, 1.0));
+            let x_tiles = tiles_intersection
+                .x_tiles()
+                .filter(|x| edge_tiles.x_tiles().contains(x));
+            let y_tiles = tiles_intersection
+                .y_tiles()
+                .filter(|y| edge_tiles.y_tiles().contains(y));
+            for (tile_y, tile_x) in y_tiles.cartesian_product(x_tiles) {
+                if self.tile_intersects_polygon(
+                    tile_x, tile_y, &test_axes, polygon,
+                ) {
+                    path_tiles.get_mut(tile_x, tile_y).1.push(i);
+                }
+            }
+        }
+
+        // Find the winding number at the reference point of each tile, by
+        // counting the edges which cross a ray towards positive x from it.
+        for tile_y in tiles_intersection.y_tiles() {
+            let reference_y = (tile_y * self.tile_height) as f32 + 0.5;
+            let crossings: Vec<(f32, i32)> = edges
+                .iter()
+                .filter_map(|edge| ray_crossing(edge, reference_y))
+                .collect();
+            for tile_x in tiles_intersection.x_tiles() {
+                let reference_x = ((tile_x + 1) * self.tile_width) as f32 - 0.5;
+                path_tiles.get_mut(tile_x, tile_y).0 = crossings
+                    .iter()
+                    .filter(|(x, _)| *x > reference_x)
+                    .map(|(_, direction)| direction)
+                    .sum();
+            }
+        }
+        Some(path_tiles)
+    }
+
+    /// Clip the primitives of a shape, which were added to the tiler from
+    /// index `first` onwards, to the clips on the stack.
+    ///
+    /// Tiles outside any of the clips are culled. Each remaining tile which
+    /// is near the edge of a clip path receives a [`Primitive::Clip`] for
+    /// it, followed by the edges of the clip path near the tile, before the
+    /// primitives of the shape. The primitives are given the clip rectangle.
+    ///
+    /// # Parameters
+    ///
+    /// - `first`: Index of the first primitive of the shape in the list of
+    ///   primitive-tile allocations.
+    /// - `shape`: Shape identifier of the shape.
+    fn clip_shape(&mut self, first: usize, shape: u32) {
+        let Some(innermost) = self.clips.last() else {
+            return;
+        };
+        let clip_rect = innermost.rect;
+        let added = self.lines.split_off(first);
+        let rect_tiles = match clip_rect {
+            Some(rect) if rect.is_empty() => return,
+            Some(rect) => {
+                let bbox =
+                    Bbox::including([rect.min, rect.max].iter()).unwrap();
+                Some(TilesIntersection::from_bbox(
+                    self.tile_width,
+                    self.tile_height,
+                    &bbox,
+                ))
+            }
+            None => None,
+        };
+
+        let mut tile_ixs: Vec<usize> =
+            added.iter().map(|(tile_ix, _)| *tile_ix).collect();
+        tile_ixs.sort_unstable();
+        tile_ixs.dedup();
+        let mut kept_tile_ixs = Vec::with_capacity(tile_ixs.len());
+        // The tiles share the primitives of each edge of the clip paths.
+        let mut edge_primitives: Vec<Vec<Option<u32>>> = self
+            .clips
+            .iter()
+            .filter_map(|clip| clip.path.as_ref())
+            .map(|path| vec![None; path.edges.len()])
+            .collect();
+        'tiles: for tile_ix in tile_ixs {
+            let (tile_x, tile_y) = self.tile_unlindex(tile_ix);
+            if let Some(tiles) = &rect_tiles {
+                if !tiles.contains(tile_x, tile_y) {
+                    continue;
+                }
+            }
+            // The clip primitives are placed directly in `self.primitives`,
+            // as `self.clips` is borrowed.
+            let primitives = &mut self.primitives;
+            let mut push_clip_primitive = |primitive| {
+                primitives.push(StyledPrimitive {
+                    primitive,
+                    style: LineStyle::default(),
+                    gradient: None,
+                    shape,
+                    clip_rect: None,
+                    layer: self.layer,
+                    layer_opacity: 1.0,
+                });
+                (primitives.len() - 1) as u32
+            };
+            let mut clip_lines = Vec::new();
+            let paths = self.clips.iter().filter_map(|clip| clip.path.as_ref());
+            for (path_i, path) in paths.enumerate() {
+                let Some((winding, near_edges)) = path
+                    .tiles
+                    .as_ref()
+                    .and_then(|tiles| tiles.get(tile_x, tile_y))
+                else {
+                    continue 'tiles;
+                };
+                if near_edges.is_empty() {
+                    if path.rule.is_inside(*winding) {
+                        continue;
+                    }
+                    continue 'tiles;
+                }
+                clip_lines.push(push_clip_primitive(Primitive::Clip {
+                    winding: *winding,
+                    rule: path.rule,
+                }));
+                for i in near_edges {
+                    let edge =
+                        *edge_primitives[path_i][*i].get_or_insert_with(|| {
+                            push_clip_primitive(Primitive::FillEdge(
+                                path.edges[*i].clone(),
+                            ))
+                        });
+                    clip_lines.push(edge);
+                }
+            }
+            self.lines.extend(
+                clip_lines.into_iter().map(|primitive| (tile_ix, primitive)),
+            );
+            kept_tile_ixs.push(tile_ix);
+        }
+
+        // Sorting by tile is stable, so the clips of each tile stay in front
+        // of the primitives of the shape.
+        for (tile_ix, primitive) in added {
+            if kept_tile_ixs.binary_search(&tile_ix).is_ok() {
+                self.primitives[primitive as usize].clip_rect = clip_rect;
+                self.lines.push((tile_ix, primitive));
+            }
+        }
+    }
+
+    /// Add the primitives of a single shape to the tiler.
+    ///
+    /// All primitives are given the same, new, shape identifier. Each
+    /// primitive is paired with the part of the style's gradient that it
+    /// spans, if the style has a gradient.
+    fn add_shape(
+        &mut self,
+        primitives: Vec<(Primitive, Option<GradientSpan>)>,
+        style: &LineStyle,
+    ) {
+        let shape = self.next_shape;
+        self.next_shape = self.next_shape.wrapping_add(1);
+        let first = self.lines.len();
+        for (primitive, gradient) in primitives {
+            self.add_primitive(StyledPrimitive {
+                primitive,
+                style: style.clone(),
+                gradient,
+                shape,
+                clip_rect: None,
+                layer: self.layer,
+                layer_opacity: 1.0,
+            });
+        }
+        self.clip_shape(first, shape);
+    }
+
+    /// Add a styled primitive to the tiler.
+    ///
+    /// This checks the primitive against the tiles and adds it into a list
+    /// of primitive-tile allocations. If binning is deferred, primitives
+    /// which need no more than a separating axis test, and which are not
+    /// clipped, are instead kept with their bounding polygons for GPU
+    /// binning.
+    fn add_primitive(&mut self, styled_primitive: StyledPrimitive) {
+        // Compute the bounding-polygons and bounding box of the primitive.
+        // These include the line width and end style information. Most
+        // primitives have a single bounding polygon, but curves are split
+        // into pieces of about the size of a tile.
+        let piece_length = self.tile_width.min(self.tile_height) as f32;
+        let bounding_polygons =
+            styled_primitive.bounding_polygons(piece_length);
+        let bounding_box = Bbox::including(
+            bounding_polygons
+                .iter()
+                .flat_map(|polygon| polygon.vertices().iter()),
+        )
+        .unwrap();
+
+        // Find the tiles that the primitive's bounding box intersects.
+        let opt_tiles_intersection = TilesIntersection::from_bbox(
+            self.tile_width,
+            self.tile_height,
+            &bounding_box,
+        )
+        .clip_to_area(self.n_x_tiles, self.n_y_tiles);
         let tiles_intersection = match opt_tiles_intersection {
             // If we clip the tiles intersection to the active area and we
-            // find there's no intersection, then the line is not visible
-            // and we don't have to do anything.
+            // find there's no intersection, then the primitive is not
+            // visible and we don't have to do anything.
             None => return,
             Some(x) => x,
         };
 
-        // For all tiles in the intersecting area, use a separating axis test
-        // to see if each tile intersects the line.
-        for tile_y in tiles_intersection.y_tiles() {
-            for tile_x in tiles_intersection.x_tiles() {
-                if self.tile_intersects_line(tile_x, tile_y, &styled_line.line, &bounding_polygon) {
-                    self.lines
-                        .push((self.tile_ix(tile_x, tile_y), styled_line.clone()))
+        // Binning tests capsules exactly too, but not the primitives with
+        // other exact tests.
+        let capsule =
+            capsule_of(&styled_primitive).filter(|_| self.exact_tile_tests);
+        if self.defer_binning
+            && self.clips.is_empty()
+            && (capsule.is_some()
+                || !has_exact_tile_test(&styled_primitive.primitive))
+        {
+            let primitive = self.push_primitive(styled_primitive);
+            self.deferred.push(DeferredPrimitive {
+                position: self.lines.len(),
+                record: BinRecord {
+                    primitive,
+                    tiles: tiles_intersection,
+                    polygons: bounding_polygons,
+                    capsule,
+                },
+            });
+            return;
+        }
+        let polygons_and_axes = with_separating_axes(bounding_polygons);
+        let tiles = self.primitive_tiles(
+            &styled_primitive,
+            &polygons_and_axes,
+            &tiles_intersection,
+        );
+
+        // The primitive is stored if it intersects any tile.
+        if !tiles.is_empty() {
+            let primitive = self.push_primitive(styled_primitive);
+            self.lines
+                .extend(tiles.into_iter().map(|ix| (ix, primitive)));
+        }
+    }
+
+    /// Finds the tiles which a primitive intersects.
+    ///
+    /// # Parameters
+    ///
+    /// - `styled_primitive`: The primitive.
+    /// - `polygons_and_axes`: Bounding polygons of the primitive, with their
+    ///   separating axes.
+    /// - `tiles_intersection`: Tiles of the bounding box of the polygons.
+    ///
+    /// # Returns
+    ///
+    /// The linear indices of the tiles, in the order in which they are
+    /// found.
+    fn primitive_tiles(
+        &self,
+        styled_primitive: &StyledPrimitive,
+        polygons_and_axes: &[(Polygon, Vec<V2>)],
+        tiles_intersection: &TilesIntersection,
+    ) -> Vec<usize> {
+        // Line segments are walked along, so that only the tiles near them
+        // are tested. Other primitives test all tiles in the intersecting
+        // area.
+        let spans = match &styled_primitive.primitive {
+            Primitive::Segment { line, .. } => self.segment_tile_spans(
+                line,
+                polygons_and_axes,
+                tiles_intersection,
+            ),
+            _ => None,
+        }
+        .unwrap_or_else(|| {
+            tiles_intersection
+                .y_tiles()
+                .map(|tile_y| (tile_y, tiles_intersection.x_tiles()))
+                .collect()
+        });
+
+        // Use a separating axis test to see if each tile intersects any of
+        // the bounding polygons. Some primitives have an exact test, which
+        // is only run on the tiles that pass the separating axis test.
+        let mut tiles = Vec::new();
+        for (tile_y, x_tiles) in spans {
+            for tile_x in x_tiles {
+                let intersects = self.tile_intersects_any_polygon(
+                    tile_x,
+                    tile_y,
+                    polygons_and_axes,
+                ) && self.tile_intersects_exactly(
+                    tile_x,
+                    tile_y,
+                    styled_primitive,
+                );
+                if intersects {
+                    tiles.push(self.tile_ix(tile_x, tile_y));
                 }
             }
         }
+        tiles
+    }
+
+    /// Find the tiles near a line segment, by walking along it.
+    ///
+    /// Testing every tile of a line's bounding box takes time proportional
+    /// to the area of the box, which is large for long diagonal lines. This
+    /// instead walks along the rows of tiles that the line crosses, in the
+    /// manner of Amanatides and Woo, widened by the stroke: each row band is
+    /// widened by the distance from the center line which contains the
+    /// bounding polygons, the parameters at which the center line crosses
+    /// the widened band give the part of the line in the row, and the tiles
+    /// of the row are those within the same distance of that part. This
+    /// takes time proportional to the number of tiles along the line.
+    ///
+    /// The tiles found include every tile which intersects the bounding
+    /// polygons, and a few more which are near them, so they must still be
+    /// tested against the polygons. The result of tiling is the same as
+    /// testing every tile of the bounding box.
+    ///
+    /// # Parameters
+    ///
+    /// - `line`: Center line of the segment.
+    /// - `polygons`: Bounding polygons of the segment.
+    /// - `tiles`: Tiles of the bounding box of the polygons.
+    ///
+    /// # Returns
+    ///
+    /// For each row of tiles near the line, in order, its y coordinate and
+    /// the range of x coordinates of the tiles near the line, or `None` if
+    /// the line is degenerate.
+    fn segment_tile_spans(
+        &self,
+        line: &Line,
+        polygons: &[(Polygon, Vec<V2>)],
+        tiles: &TilesIntersection,
+    ) -> Option<Vec<(u32, RangeInclusive<u32>)>> {
+        let (a, d) = (line.start(), line.ab_vec());
+        let radius = polygons
+            .iter()
+            .flat_map(|(polygon, _)| polygon.vertices().iter())
+            .map(|vertex| segment_point_distance(line, *vertex))
+            .fold(0.0, f32::max)
+            + TILE_WALK_MARGIN;
+        if d.magnitude2() == 0.0 || !radius.is_finite() {
+            return None;
+        }
+
+        let (tile_width, tile_height) =
+            (self.tile_width as f32, self.tile_height as f32);
+        let x_tiles = tiles.x_tiles();
+        let spans = tiles
+            .y_tiles()
+            .filter_map(|tile_y| {
+                // Find the parameters at which the center line is in the
+                // widened row band.
+                let min_y = tile_y as f32 * tile_height - radius;
+                let max_y = min_y + tile_height + 2.0 * radius;
+                let (t0, t1) = if d.y == 0.0 {
+                    if a.y < min_y || a.y > max_y {
+                        return None;
+                    }
+                    (0.0, 1.0)
+                } else {
+                    let (t_min, t_max) =
+                        ((min_y - a.y) / d.y, (max_y - a.y) / d.y);
+                    (t_min.min(t_max).max(0.0), t_min.max(t_max).min(1.0))
+                };
+                if t0 > t1 {
+                    return None;
+                }
+
+                // Find the tiles within the radius of that part of the line.
+                let (x0, x1) = (a.x + t0 * d.x, a.x + t1 * d.x);
+                let first = ((x0.min(x1) - radius) / tile_width)
+                    .floor()
+                    .max(*x_tiles.start() as f32);
+                let last = ((x0.max(x1) + radius) / tile_width)
+                    .floor()
+                    .min(*x_tiles.end() as f32);
+                (first <= last).then_some((tile_y, first as u32..=last as u32))
+            })
+            .collect();
+        Some(spans)
+    }
+
+    /// Stores a primitive.
+    ///
+    /// # Returns
+    ///
+    /// The index of the primitive, which tiles refer to it by.
+    fn push_primitive(&mut self, styled_primitive: StyledPrimitive) -> u32 {
+        self.primitives.push(styled_primitive);
+        (self.primitives.len() - 1) as u32
     }
 
     /// Drain the tiler to Collect all tiles and the lines they contain.
     ///
     /// This empties the `Tiler`.
     ///
-    /// It returns two components:
+    /// It returns three components:
     ///
-    /// 1. A vector of `StyledLine`, which is a list of lines organized
-    ///    over the tiles.
-    /// 2. A vector of `TileInfo`, which indicates, for each tile location,
-    ///    the start index in the `StyledLine` vector and the number of
-    ///    lines each tile contains.
+    /// 1. A vector of `TileInfo`, which indicates, for each tile location,
+    ///    the start index in the index vector and the number of primitives
+    ///    each tile contains.
+    /// 2. A vector of `StyledPrimitive`, which holds each primitive once,
+    ///    however many tiles it is in. The primitives of hidden layers are
+    ///    dropped.
+    /// 3. A vector of indices into the `StyledPrimitive` vector, which is a
+    ///    list of primitives organized over the tiles. Within a tile,
+    ///    primitives are ordered by the z index of their layer, then by the
+    ///    order in which their layers were created, and then by the order in
+    ///    which they were added.
     ///
     /// This has the complexity of a sort over the lines, coupled with two
     /// linear passes over the sorted lines.
-    pub fn drain(&mut self) -> (Vec<TileInfo>, Vec<StyledLine>) {
-        // Sort the lines according to their linear index.
-        let mut lines: Vec<(usize, StyledLine)> = self.lines.drain(..).collect();
+    ///
+    /// Any primitives whose binning has been deferred are assigned to tiles
+    /// here, on the CPU.
+    pub fn drain(&mut self) -> (Vec<TileInfo>, Vec<StyledPrimitive>, Vec<u32>) {
+        if !self.deferred.is_empty() {
+            let (primitives, records) = self.take_bin_records();
+            return self.bin_records(&primitives, &records);
+        }
+
+        let primitives = std::mem::take(&mut self.primitives);
+        let lines = std::mem::take(&mut self.lines);
+        self.next_shape = 0;
+        self.sort_tiles(primitives, lines)
+    }
+
+    /// Set whether the assignment of primitives to tiles is deferred, so
+    /// that it can be done by GPU binning.
+    ///
+    /// Primitives which need no more than a separating axis test against
+    /// their bounding polygons are deferred, and so are those whose exact
+    /// test is a capsule test, which binning also runs: lines of constant
+    /// width with round caps, and round joins. Fills, clipped shapes, arcs
+    /// and ellipses are still assigned to tiles as they are added.
+    ///
+    /// # Parameters
+    ///
+    /// - `defer`: `true` to defer binning.
+    pub(crate) fn set_defer_binning(&mut self, defer: bool) {
+        self.defer_binning = defer;
+    }
+
+    /// Set whether primitives with an exact test are tested exactly against
+    /// tiles (see [`Tiler::tile_intersects_exactly`]), which they are by
+    /// default. Otherwise they are only tested against their bounding
+    /// polygons, and are placed in more tiles than they intersect. This is
+    /// only intended for measuring the effect of the exact tests, and is
+    /// only public with the `bench` feature.
+    ///
+    /// # Parameters
+    ///
+    /// - `exact`: `true` to test primitives exactly.
+    #[cfg(any(test, feature = "bench"))]
+    pub fn set_exact_tile_tests(&mut self, exact: bool) {
+        self.exact_tile_tests = exact;
+    }
+
+    /// Takes the primitives which have been added, as records for binning.
+    ///
+    /// This empties the `Tiler`, like [`Tiler::drain`]. Primitives which
+    /// have already been assigned to tiles are given one record per tile.
+    /// The records are ordered in the same way as the primitives within a
+    /// tile (see [`Tiler::drain`]), and the primitives of hidden layers are
+    /// dropped.
+    ///
+    /// # Returns
+    ///
+    /// The primitives, each stored once, and the records which refer to
+    /// them. The records can be binned by [`Tiler::bin_records`] or on the
+    /// GPU.
+    pub(crate) fn take_bin_records(
+        &mut self,
+    ) -> (Vec<StyledPrimitive>, Vec<BinRecord>) {
+        let primitives = std::mem::take(&mut self.primitives);
+        let lines = std::mem::take(&mut self.lines);
+        let mut deferred =
+            std::mem::take(&mut self.deferred).into_iter().peekable();
+        self.next_shape = 0;
+
+        // Merge the deferred primitives back amongst the allocations, in the
+        // order in which they were added.
+        let mut records = Vec::with_capacity(lines.len() + deferred.len());
+        for (position, (ix, primitive)) in lines.into_iter().enumerate() {
+            while let Some(deferred_primitive) =
+                deferred.next_if(|p| p.position <= position)
+            {
+                records.push(deferred_primitive.record);
+            }
+            let (tile_x, tile_y) = self.tile_unlindex(ix);
+            records.push(BinRecord {
+                primitive,
+                tiles: TilesIntersection {
+                    min_x_tile: tile_x,
+                    max_x_tile: tile_x,
+                    min_y_tile: tile_y,
+                    max_y_tile: tile_y,
+                },
+                polygons: Vec::new(),
+                capsule: None,
+            });
+        }
+        records.extend(
+            deferred.map(|deferred_primitive| deferred_primitive.record),
+        );
+
+        records.retain(|record| {
+            self.is_visible(&primitives[record.primitive as usize])
+        });
+        records.sort_by_key(|record| {
+            self.layer_order(&primitives[record.primitive as usize])
+        });
+        let primitives = self.compact_primitives(
+            primitives,
+            records.iter_mut().map(|record| &mut record.primitive),
+        );
+        (primitives, records)
+    }
+
+    /// Takes the primitives which have been added, as records for binning,
+    /// and also assigns them to tiles on the CPU, as they are assigned when
+    /// binning is not deferred.
+    ///
+    /// This empties the `Tiler`, like [`Tiler::drain`]. Deferred primitives
+    /// are assigned to tiles by the same tests as primitives which are
+    /// tiled as they are added, so the tiles are those that
+    /// [`Tiler::drain`] returns when binning is not deferred. Binning the
+    /// records on the GPU should give the same tiles.
+    ///
+    /// # Returns
+    ///
+    /// The primitives and records, as from [`Tiler::take_bin_records`], and
+    /// the tile information, primitives and indices, as from
+    /// [`Tiler::drain`].
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn take_bin_records_and_tiles(
+        &mut self,
+    ) -> (
+        (Vec<StyledPrimitive>, Vec<BinRecord>),
+        (Vec<TileInfo>, Vec<StyledPrimitive>, Vec<u32>),
+    ) {
+        // Merge the tiles of the deferred primitives back amongst the
+        // allocations, in the order in which they were added.
+        let mut lines = Vec::with_capacity(self.lines.len());
+        let mut deferred = self.deferred.iter().peekable();
+        for position in 0..=self.lines.len() {
+            while let Some(deferred_primitive) =
+                deferred.next_if(|p| p.position <= position)
+            {
+                let record = &deferred_primitive.record;
+                let tiles = self.primitive_tiles(
+                    &self.primitives[record.primitive as usize],
+                    &with_separating_axes(record.polygons.clone()),
+                    &record.tiles,
+                );
+                lines
+                    .extend(tiles.into_iter().map(|ix| (ix, record.primitive)));
+            }
+            lines.extend(self.lines.get(position));
+        }
+        let tiles = self.sort_tiles(self.primitives.clone(), lines);
+
+        (self.take_bin_records(), tiles)
+    }
+
+    /// Assigns records for binning to tiles on the CPU.
+    ///
+    /// This is the reference for GPU binning. A record is placed in each
+    /// tile of its range which intersects any of its bounding polygons, and
+    /// its capsule if it has one, using the same tests as [`Tiler::add`].
+    ///
+    /// # Parameters
+    ///
+    /// - `primitives`: Primitives from [`Tiler::take_bin_records`].
+    /// - `records`: Records from [`Tiler::take_bin_records`].
+    ///
+    /// # Returns
+    ///
+    /// The tile information, primitives and indices, as from
+    /// [`Tiler::drain`].
+    pub(crate) fn bin_records(
+        &self,
+        primitives: &[StyledPrimitive],
+        records: &[BinRecord],
+    ) -> (Vec<TileInfo>, Vec<StyledPrimitive>, Vec<u32>) {
+        let mut lines = Vec::new();
+        for record in records {
+            let polygons_and_axes =
+                with_separating_axes(record.polygons.clone());
+            for tile_y in record.tiles.y_tiles() {
+                for tile_x in record.tiles.x_tiles() {
+                    let intersects = (polygons_and_axes.is_empty()
+                        || self.tile_intersects_any_polygon(
+                            tile_x,
+                            tile_y,
+                            &polygons_and_axes,
+                        ))
+                        && match &record.capsule {
+                            Some((line, radius)) => self
+                                .tile_intersects_capsule(
+                                    tile_x, tile_y, line, *radius,
+                                ),
+                            None => true,
+                        };
+                    if intersects {
+                        lines.push((
+                            self.tile_ix(tile_x, tile_y),
+                            record.primitive,
+                        ));
+                    }
+                }
+            }
+        }
         lines.sort_by_key(|(ix, _)| *ix);
+        self.collect_tiles(primitives.to_vec(), lines)
+    }
+
+    /// `true` if the layer of a primitive is visible.
+    fn is_visible(&self, styled_primitive: &StyledPrimitive) -> bool {
+        self.layers[styled_primitive.layer.index() as usize].visible
+    }
 
+    /// Returns the key which orders primitives within a tile by their
+    /// layers: the z index of the layer, and then its index.
+    fn layer_order(&self, styled_primitive: &StyledPrimitive) -> (i32, u32) {
+        let layer = styled_primitive.layer.index();
+        (self.layers[layer as usize].z, layer)
+    }
+
+    /// Sorts primitives by tile, dropping those of hidden layers, and
+    /// collects them into tiles.
+    ///
+    /// The lines are sorted by their linear index and layer. The sort is
+    /// stable, so this preserves the order in which they were added.
+    ///
+    /// # Parameters
+    ///
+    /// - `primitives`: Primitives which the lines refer to.
+    /// - `lines`: Indices of primitives, paired with the linear indices of
+    ///   their tiles, in the order in which they were added.
+    ///
+    /// # Returns
+    ///
+    /// The tile information, primitives and indices, as from
+    /// [`Tiler::drain`].
+    fn sort_tiles(
+        &self,
+        primitives: Vec<StyledPrimitive>,
+        lines: Vec<(usize, u32)>,
+    ) -> (Vec<TileInfo>, Vec<StyledPrimitive>, Vec<u32>) {
+        let mut lines: Vec<(usize, u32)> = lines
+            .into_iter()
+            .filter(|(_, primitive)| {
+                self.is_visible(&primitives[*primitive as usize])
+            })
+            .collect();
+        lines.sort_by_key(|(ix, primitive)| {
+            (*ix, self.layer_order(&primitives[*primitive as usize]))
+        });
+        self.collect_tiles(primitives, lines)
+    }
+
+    /// Collects primitives which are sorted by tile into tiles.
+    ///
+    /// # Parameters
+    ///
+    /// - `primitives`: Primitives which the lines refer to.
+    /// - `lines`: Indices of primitives, paired with the linear indices of
+    ///   their tiles, and sorted by them.
+    ///
+    /// # Returns
+    ///
+    /// The tile information, primitives and indices, as from
+    /// [`Tiler::drain`].
+    fn collect_tiles(
+        &self,
+        primitives: Vec<StyledPrimitive>,
+        mut lines: Vec<(usize, u32)>,
+    ) -> (Vec<TileInfo>, Vec<StyledPrimitive>, Vec<u32>) {
         // Process the lines to find the tile offsets.
         let mut start_index: u32 = 0;
         let tile_infos = lines
@@ -151,82 +1470,458 @@ impl Tiler {
             })
             .collect();
 
-        // Create the vector of styled lines by dropping the linear index.
-        let lines_vec: Vec<StyledLine> = lines.into_iter().map(|(_, line)| line).collect();
+        // Keep only the primitives which are in tiles, and drop the linear
+        // index to leave the indices of the primitives.
+        let primitives = self.compact_primitives(
+            primitives,
+            lines.iter_mut().map(|(_, primitive)| primitive),
+        );
+        let indices: Vec<u32> =
+            lines.into_iter().map(|(_, primitive)| primitive).collect();
+
+        (tile_infos, primitives, indices)
+    }
+
+    /// Keeps only the primitives which are referred to, in the order in
+    /// which they are first referred to, and applies their layers to them.
+    ///
+    /// # Parameters
+    ///
+    /// - `primitives`: Primitives.
+    /// - `references`: Indices of primitives, which are updated to index the
+    ///   kept primitives.
+    ///
+    /// # Returns
+    ///
+    /// The kept primitives, with the opacities of their layers.
+    fn compact_primitives<'a>(
+        &self,
+        primitives: Vec<StyledPrimitive>,
+        references: impl Iterator<Item = &'a mut u32>,
+    ) -> Vec<StyledPrimitive> {
+        let mut new_indices = vec![u32::MAX; primitives.len()];
+        let mut order = Vec::new();
+        for reference in references {
+            let new_index = &mut new_indices[*reference as usize];
+            if *new_index == u32::MAX {
+                *new_index = order.len() as u32;
+                order.push(*reference);
+            }
+            *reference = *new_index;
+        }
+
+        let mut primitives: Vec<Option<StyledPrimitive>> =
+            primitives.into_iter().map(Some).collect();
+        order
+            .into_iter()
+            .map(|i| {
+                let mut styled_primitive =
+                    primitives[i as usize].take().unwrap();
+                let layer =
+                    &self.layers[styled_primitive.layer.index() as usize];
+                styled_primitive.layer_opacity = layer.opacity.clamp(0.0, 1.0);
+                styled_primitive
+            })
+            .collect()
+    }
+
+    /// Takes the primitives which have been added, without sorting them
+    /// into tiles.
+    ///
+    /// This empties the `Tiler`, like [`Tiler::drain`].
+    ///
+    /// # Returns
+    ///
+    /// The primitives, each stored once, and the linear indices of their
+    /// tiles, each paired with the index of a primitive in the tile, in the
+    /// order in which they were added.
+    pub(crate) fn take_primitives(
+        &mut self,
+    ) -> (Vec<StyledPrimitive>, Vec<(usize, u32)>) {
+        assert!(self.deferred.is_empty());
+        self.next_shape = 0;
+        (
+            std::mem::take(&mut self.primitives),
+            std::mem::take(&mut self.lines),
+        )
+    }
+
+    /// Returns the number of tiles.
+    pub(crate) fn tile_count(&self) -> usize {
+        self.n_x_tiles as usize * self.n_y_tiles as usize
+    }
+
+    /// Returns the area width and height, and the tile width and height.
+    pub(crate) fn size(&self) -> (u32, u32, u32, u32) {
+        (
+            self.area_width,
+            self.area_height,
+            self.tile_width,
+            self.tile_height,
+        )
+    }
+
+    /// Computes the linear index of a tile.
+    ///
+    /// # Parameters
+    ///
+    /// - `tile_x`: The horizontal tile index.
+    /// - `tile_y`: The vertical tile index.
+    ///
+    /// # Returns
+    ///
+    /// A linear index of the tile into `self.tiles`.
+    fn tile_ix(&self, tile_x: u32, tile_y: u32) -> usize {
+        assert!(tile_x < self.n_x_tiles);
+        assert!(tile_y < self.n_y_tiles);
+        self.n_x_tiles as usize * tile_y as usize + tile_x as usize
+    }
+
+    /// Compute the (x,y) index of a tile from its linear index.
+    ///
+    /// # Parameters
+    ///
+    /// - `lindex`: Linear index of the tile.
+    ///
+    /// # Returns
+    ///
+    /// An `(tile_x, tile_y)` pair.
+    pub(crate) fn tile_unlindex(&self, lindex: usize) -> (u32, u32) {
+        let tile_y = (lindex / self.n_x_tiles as usize) as u32;
+        let tile_x = (lindex % self.n_x_tiles as usize) as u32;
+        assert!(self.tile_ix(tile_x, tile_y) == lindex); // check inverse
+        (tile_x, tile_y)
+    }
+
+    /// Check if a tile intersects a supplied bounding polygon.
+    ///
+    /// # Parameters
+    ///
+    /// - `tile_x`: X coordinate of a tile.
+    /// - `tile_y`: Y coordinate of a tile.
+    /// - `test_axes`: candidate separating axes.
+    /// - `polygon`: the bounding polygon to check.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the tile intersects the polygon, `false` otherwise.
+    fn tile_intersects_polygon(
+        &self,
+        tile_x: u32,
+        tile_y: u32,
+        test_axes: &[V2],
+        polygon: &Polygon,
+    ) -> bool {
+        let center = P2::new(0.0, 0.0);
+        let tile = self.tile_polygon(tile_x, tile_y);
+
+        for axis in test_axes {
+            if polygon.is_separating_axis(&tile, *axis, center) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Check if a tile intersects any of a set of bounding polygons.
+    ///
+    /// # Parameters
+    ///
+    /// - `tile_x`: X coordinate of a tile.
+    /// - `tile_y`: Y coordinate of a tile.
+    /// - `polygons`: The bounding polygons, each with its candidate
+    ///   separating axes.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the tile intersects any of the polygons, `false` otherwise.
+    fn tile_intersects_any_polygon(
+        &self,
+        tile_x: u32,
+        tile_y: u32,
+        polygons: &[(Polygon, Vec<V2>)],
+    ) -> bool {
+        polygons.iter().any(|(polygon, test_axes)| {
+            self.tile_intersects_polygon(tile_x, tile_y, test_axes, polygon)
+        })
+    }
+
+    /// Check exactly whether a tile intersects a primitive.
+    ///
+    /// This is only implemented for some primitives. For other primitives,
+    /// it returns `true`, and the result of the separating axis test against
+    /// their bounding polygons is used on its own.
+    ///
+    /// # Parameters
+    ///
+    /// - `tile_x`: X coordinate of a tile.
+    /// - `tile_y`: Y coordinate of a tile.
+    /// - `styled_primitive`: The primitive to check.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the tile intersects the primitive (or if there is no exact
+    /// test for the primitive, or exact tests are turned off), `false`
+    /// otherwise.
+    fn tile_intersects_exactly(
+        &self,
+        tile_x: u32,
+        tile_y: u32,
+        styled_primitive: &StyledPrimitive,
+    ) -> bool {
+        if !self.exact_tile_tests {
+            return true;
+        }
+        let half_width = styled_primitive.style.width / 2.0;
+        let glow = styled_primitive.style.glow_radius();
+        match &styled_primitive.primitive {
+            Primitive::Arc {
+                arc,
+                start_cap,
+                end_cap,
+            } => self.tile_intersects_arc(
+                tile_x, tile_y, arc, *start_cap, *end_cap, half_width, glow,
+            ),
+            Primitive::Ellipse(ellipse) => self.tile_intersects_ellipse(
+                tile_x,
+                tile_y,
+                ellipse,
+                half_width + glow,
+            ),
+            _ => match capsule_of(styled_primitive) {
+                Some((line, radius)) => {
+                    self.tile_intersects_capsule(tile_x, tile_y, &line, radius)
+                }
+                None => true,
+            },
+        }
+    }
+
+    /// Check exactly whether a tile intersects the stroke of an arc.
+    ///
+    /// The stroke is made of an annular sector and the caps at the ends of
+    /// the arc. If the stroke has a glow, the sector is widened by its
+    /// radius, and each end is tested against a disc which contains its cap
+    /// and the glow around it, so the test is no longer exact near the
+    /// ends. The arc is split into pieces which span at most a quarter
+    /// turn, so that the wedge containing each piece is convex. The tile is
+    /// clipped to each wedge, and the clipped polygon intersects the
+    /// annulus if and only if its nearest point to the center is inside the
+    /// outer circle and its furthest point is outside the inner circle.
+    ///
+    /// # Parameters
+    ///
+    /// - `tile_x`: X coordinate of a tile.
+    /// - `tile_y`: Y coordinate of a tile.
+    /// - `arc`: The arc.
+    /// - `start_cap`: Cap at the start of the arc.
+    /// - `end_cap`: Cap at the end of the arc.
+    /// - `half_width`: Half of the width of the stroke.
+    /// - `glow`: Radius of the stroke's glow, or zero if it has no glow.
+    #[allow(clippy::too_many_arguments)]
+    fn tile_intersects_arc(
+        &self,
+        tile_x: u32,
+        tile_y: u32,
+        arc: &CircularArc,
+        start_cap: LineCap,
+        end_cap: LineCap,
+        half_width: f32,
+        glow: f32,
+    ) -> bool {
+        let tile = self.tile_polygon(tile_x, tile_y);
+        let center = arc.center();
+
+        // Caps.
+        if !arc.is_circle() {
+            let ends = [
+                (arc.start(), arc.start_angle(), -1.0, start_cap),
+                (arc.end(), arc.end_angle(), 1.0, end_cap),
+            ];
+            for (point, angle, sign, cap) in ends {
+                let radial = V2::new(angle.cos(), angle.sin());
+                let outward = sign * v2_rot90_anticlockwise(radial);
+                let intersects = match cap {
+                    _ if glow > 0.0 => {
+                        let reach = match cap {
+                            LineCap::Square => {
+                                std::f32::consts::SQRT_2 * half_width
+                            }
+                            _ => half_width,
+                        };
+                        polygon_point_distance(tile.vertices(), point)
+                            <= reach + glow
+                    }
+                    LineCap::Butt => false,
+                    LineCap::Round => {
+                        polygon_point_distance(tile.vertices(), point)
+                            <= half_width
+                    }
+                    LineCap::Square => {
+                        let n = half_width * radial;
+                        let t = half_width * outward;
+                        let cap_polygon = Polygon::new(vec![
+                            point - n,
+                            point + n,
+                            point + n + t,
+                            point - n + t,
+                        ]);
+                        let mut test_axes = cap_polygon.edge_axes();
+                        test_axes.push(V2::new(1.0, 0.0));
+                        test_axes.push(V2::new(0.0, 1.0));
+                        self.tile_intersects_polygon(
+                            tile_x,
+                            tile_y,
+                            &test_axes,
+                            &cap_polygon,
+                        )
+                    }
+                };
+                if intersects {
+                    return true;
+                }
+            }
+        }
 
-        (tile_infos, lines_vec)
+        // Annular sector.
+        let inner = arc.radius() - half_width - glow;
+        let outer = arc.radius() + half_width + glow;
+        let n_pieces = (arc.sweep() / std::f32::consts::FRAC_PI_2)
+            .ceil()
+            .max(1.0) as usize;
+        for piece in arc.split(n_pieces) {
+            let start_dir = piece.start() - center;
+            let end_dir = piece.end() - center;
+            let clipped = clip_to_half_plane(
+                &clip_to_half_plane(
+                    tile.vertices(),
+                    center,
+                    v2_rot90_anticlockwise(start_dir),
+                ),
+                center,
+                -v2_rot90_anticlockwise(end_dir),
+            );
+            if clipped.is_empty() {
+                continue;
+            }
+            let nearest = polygon_point_distance(&clipped, center);
+            let furthest = clipped
+                .iter()
+                .map(|p| p.distance(center))
+                .fold(0.0, f32::max);
+            if nearest <= outer && furthest >= inner {
+                return true;
+            }
+        }
+        false
     }
 
-    /// Computes the linear index of a tile.
-    ///
-    /// # Parameters
+    /// Check exactly whether a tile intersects the stroke of an ellipse.
     ///
-    /// - `tile_x`: The horizontal tile index.
-    /// - `tile_y`: The vertical tile index.
+    /// The stroke is the set of points whose signed distance from the
+    /// ellipse is between `-half_width` and `half_width`. The signed
+    /// distance to a convex shape is a convex function, so over the tile its
+    /// maximum is at a corner, and its minimum is either at the center of
+    /// the ellipse (if the tile contains it) or on an edge of the tile, where
+    /// it can be found by ternary search.
     ///
-    /// # Returns
+    /// # Parameters
     ///
-    /// A linear index of the tile into `self.tiles`.
-    fn tile_ix(&self, tile_x: u32, tile_y: u32) -> usize {
-        assert!(tile_x < self.n_x_tiles);
-        assert!(tile_y < self.n_y_tiles);
-        let ix = self.n_x_tiles as usize * tile_y as usize + tile_x as usize;
-        ix
+    /// - `tile_x`: X coordinate of a tile.
+    /// - `tile_y`: Y coordinate of a tile.
+    /// - `ellipse`: The ellipse.
+    /// - `half_width`: Half of the width of the stroke.
+    fn tile_intersects_ellipse(
+        &self,
+        tile_x: u32,
+        tile_y: u32,
+        ellipse: &Ellipse,
+        half_width: f32,
+    ) -> bool {
+        let tile = self.tile_polygon(tile_x, tile_y);
+        let corners = tile.vertices();
+
+        // Tiles inside the hole of the stroke.
+        let max = corners
+            .iter()
+            .map(|p| ellipse.signed_distance(*p))
+            .fold(f32::MIN, f32::max);
+        if max < -half_width {
+            return false;
+        }
+
+        // Tiles which contain the center of the ellipse.
+        let c = ellipse.center();
+        let (min_corner, max_corner) = (corners[0], corners[2]);
+        if c.x >= min_corner.x
+            && c.x <= max_corner.x
+            && c.y >= min_corner.y
+            && c.y <= max_corner.y
+        {
+            return true;
+        }
+
+        (0..corners.len()).any(|i| {
+            let a = corners[i];
+            let b = corners[(i + 1) % corners.len()];
+            let (mut lo, mut hi) = (0.0f32, 1.0f32);
+            for _ in 0..ELLIPSE_SEARCH_ITERATIONS {
+                let m1 = lo + (hi - lo) / 3.0;
+                let m2 = hi - (hi - lo) / 3.0;
+                if ellipse.signed_distance(a + m1 * (b - a))
+                    <= ellipse.signed_distance(a + m2 * (b - a))
+                {
+                    hi = m2;
+                } else {
+                    lo = m1;
+                }
+            }
+            ellipse.signed_distance(a + (lo + hi) / 2.0 * (b - a)) <= half_width
+        })
     }
 
-    /// Compute the (x,y) index of a tile from its linear index.
+    /// Check exactly whether a tile intersects a capsule: the points within
+    /// a distance of a line segment.
     ///
-    /// # Parameters
-    ///
-    /// - `lindex`: Linear index of the tile.
-    ///
-    /// # Returns
+    /// This is the stroke of a line of constant width with round caps,
+    /// which the bounding polygon treats as square, and of a disc, whose
+    /// segment is a single point. If the stroke has a glow, the distance is
+    /// widened by its radius, and the test is still exact. The tile
+    /// intersects the capsule if and only if the distance between the tile
+    /// and the segment is at most the radius of the capsule. This distance is
+    /// zero if the segment enters the tile, and otherwise is the distance
+    /// between an end of the segment and the tile, or between a corner of the
+    /// tile and the segment, whichever is least.
     ///
-    /// An `(tile_x, tile_y)` pair.
-    fn tile_unlindex(&self, lindex: usize) -> (u32, u32) {
-        let tile_y = (lindex / self.n_x_tiles as usize) as u32;
-        let tile_x = (lindex % self.n_x_tiles as usize) as u32;
-        assert!(self.tile_ix(tile_x, tile_y) == lindex); // check inverse
-        (tile_x, tile_y)
-    }
-
-    /// Check if a tile intersects a supplied line.
+    /// GPU binning runs the same test (see `capsule_intersects_tile` in
+    /// `binning.wgsl`). Squared distances are compared, so that the test
+    /// only multiplies, adds and subtracts, and rounds in the same way
+    /// there.
     ///
     /// # Parameters
     ///
     /// - `tile_x`: X coordinate of a tile.
     /// - `tile_y`: Y coordinate of a tile.
-    /// - `line`: the line to check.
-    /// - `polygon`: the bounding polygon around the line.
-    ///
-    /// # Returns
-    ///
-    /// `true` if the tile intersects the line, `false` otherwise.
-    fn tile_intersects_line(
+    /// - `line`: The segment at the center of the capsule.
+    /// - `radius`: Radius of the capsule.
+    fn tile_intersects_capsule(
         &self,
         tile_x: u32,
         tile_y: u32,
         line: &Line,
-        polygon: &Polygon,
+        radius: f32,
     ) -> bool {
-        // Compute the test vectors we need for a separating axis test. There
-        // are only 4 of them for a line. This means we do half the work of a
-        // naive separating axis test.
-        let test_axes = vec![
-            line.ab_vec(),
-            v2_rot90_anticlockwise(line.ab_vec()),
-            V2::new(1.0, 0.0),
-            V2::new(0.0, 1.0),
-        ];
-        let center = P2::new(0.0, 0.0);
         let tile = self.tile_polygon(tile_x, tile_y);
-
-        for axis in test_axes {
-            if polygon.is_separating_axis(&tile, axis, center) {
-                return false;
-            }
-        }
-        true
+        let corners = tile.vertices();
+        let (min_corner, max_corner) = (corners[0], corners[2]);
+        let radius2 = radius * radius;
+        segment_enters_rect(line, corners)
+            || [line.start(), line.end()].into_iter().any(|p| {
+                rect_point_distance2(min_corner, max_corner, p) <= radius2
+            })
+            || corners
+                .iter()
+                .any(|c| segment_point_is_within(line, *c, radius2))
     }
 
     /// Returns a polygon representing a tile.
@@ -246,15 +1941,234 @@ impl Tiler {
     }
 }
 
+/// Clips a convex polygon to a half-plane.
+///
+/// # Parameters
+///
+/// - `vertices`: Vertices of the polygon.
+/// - `origin`: A point on the edge of the half-plane.
+/// - `normal`: Normal of the edge of the half-plane, pointing into it.
+///
+/// # Returns
+///
+/// The vertices of the part of the polygon inside the half-plane. This is
+/// empty if the polygon is entirely outside the half-plane.
+fn clip_to_half_plane(vertices: &[P2], origin: P2, normal: V2) -> Vec<P2> {
+    let mut clipped = Vec::with_capacity(vertices.len() + 1);
+    for i in 0..vertices.len() {
+        let a = vertices[i];
+        let b = vertices[(i + 1) % vertices.len()];
+        let da = (a - origin).dot(normal);
+        let db = (b - origin).dot(normal);
+        if da >= 0.0 {
+            clipped.push(a);
+        }
+        if (da >= 0.0) != (db >= 0.0) {
+            clipped.push(a + (da / (da - db)) * (b - a));
+        }
+    }
+    clipped
+}
+
+/// Returns the distance from a point to a convex polygon.
+///
+/// The distance is zero if the point is inside the polygon.
+///
+/// # Parameters
+///
+/// - `vertices`: Vertices of the polygon, in either winding order.
+/// - `p`: The point.
+fn polygon_point_distance(vertices: &[P2], p: P2) -> f32 {
+    let n = vertices.len();
+    let mut inside_left = true;
+    let mut inside_right = true;
+    let mut distance = f32::MAX;
+    for i in 0..n {
+        let a = vertices[i];
+        let b = vertices[(i + 1) % n];
+        let e = b - a;
+        let w = p - a;
+        let cross = e.x * w.y - e.y * w.x;
+        inside_left &= cross >= 0.0;
+        inside_right &= cross <= 0.0;
+        let h = if e.magnitude2() > 0.0 {
+            (w.dot(e) / e.magnitude2()).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        distance = distance.min((w - h * e).magnitude());
+    }
+    if inside_left || inside_right {
+        0.0
+    } else {
+        distance
+    }
+}
+
+/// Pairs bounding polygons with the test vectors needed for a separating
+/// axis test against the tiles.
+///
+/// These are the edge directions of each bounding polygon, plus the edge
+/// directions of the tiles. For a line, there are only 4 of them. This means
+/// we do half the work of a naive separating axis test.
+fn with_separating_axes(polygons: Vec<Polygon>) -> Vec<(Polygon, Vec<V2>)> {
+    polygons
+        .into_iter()
+        .map(|polygon| {
+            let mut test_axes = polygon.edge_axes();
+            test_axes.push(V2::new(1.0, 0.0));
+            test_axes.push(V2::new(0.0, 1.0));
+            (polygon, test_axes)
+        })
+        .collect()
+}
+
+/// Checks if a line segment has any point in an axis-aligned rectangle,
+/// including its edges, with a separating axis test. The candidate axes are
+/// the edges of the rectangle and the segment.
+///
+/// # Parameters
+///
+/// - `line`: The segment.
+/// - `corners`: Corners of the rectangle, starting with the corner with the
+///   least coordinates.
+fn segment_enters_rect(line: &Line, corners: &[P2]) -> bool {
+    let (a, b) = (line.start(), line.end());
+    let (min_corner, max_corner) = (corners[0], corners[2]);
+    if a.x.max(b.x) < min_corner.x
+        || a.x.min(b.x) > max_corner.x
+        || a.y.max(b.y) < min_corner.y
+        || a.y.min(b.y) > max_corner.y
+    {
+        return false;
+    }
+    // The rectangle must have corners on both sides of the segment's line
+    // (or on it).
+    let e = line.ab_vec();
+    let (min_side, max_side) = corners
+        .iter()
+        .map(|corner| e.perp_dot(*corner - a))
+        .fold((f32::MAX, f32::MIN), |(lo, hi), side| {
+            (lo.min(side), hi.max(side))
+        });
+    min_side <= 0.0 && max_side >= 0.0
+}
+
+/// Squared distance between a point and an axis-aligned rectangle, which is
+/// zero for points inside it.
+fn rect_point_distance2(min_corner: P2, max_corner: P2, p: P2) -> f32 {
+    let dx = (min_corner.x - p.x).max(p.x - max_corner.x).max(0.0);
+    let dy = (min_corner.y - p.y).max(p.y - max_corner.y).max(0.0);
+    dx * dx + dy * dy
+}
+
+/// Checks if a point is within a distance of a line segment, without
+/// dividing or taking square roots.
+///
+/// # Parameters
+///
+/// - `line`: The segment.
+/// - `p`: The point.
+/// - `distance2`: Square of the distance.
+fn segment_point_is_within(line: &Line, p: P2, distance2: f32) -> bool {
+    let (a, e) = (line.start(), line.ab_vec());
+    let w = p - a;
+    let t = w.dot(e);
+    if t <= 0.0 {
+        // The start is the closest point of the segment.
+        w.magnitude2() <= distance2
+    } else if t >= e.magnitude2() {
+        // The end is the closest point of the segment.
+        (p - line.end()).magnitude2() <= distance2
+    } else {
+        // The closest point is inside the segment, at a distance of
+        // `|e × w| / |e|` from the point.
+        let cross = e.perp_dot(w);
+        cross * cross <= distance2 * e.magnitude2()
+    }
+}
+
+/// Distance between a point and a line segment.
+fn segment_point_distance(line: &Line, p: P2) -> f32 {
+    let (a, e) = (line.start(), line.ab_vec());
+    let w = p - a;
+    let h = if e.magnitude2() > 0.0 {
+        (w.dot(e) / e.magnitude2()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (w - h * e).magnitude()
+}
+
+/// Distance, in pixels, by which the walk along a line segment is widened,
+/// so that rounding never causes it to miss a tile which intersects the
+/// segment's bounding polygons.
+const TILE_WALK_MARGIN: f32 = 1.0;
+
+/// Number of ternary search iterations used to find the closest point on a
+/// tile edge to an ellipse.
+const ELLIPSE_SEARCH_ITERATIONS: u32 = 32;
+
+/// Returns the capsule which a primitive is tested against exactly (see
+/// [`Tiler::tile_intersects_capsule`]): the stroke of a line of constant
+/// width with round caps, or of a disc.
+///
+/// # Returns
+///
+/// The segment at the center of the capsule and its radius, including any
+/// glow, or `None` if the primitive is not tested as a capsule.
+fn capsule_of(styled_primitive: &StyledPrimitive) -> Option<(Line, f32)> {
+    let glow = styled_primitive.style.glow_radius();
+    match &styled_primitive.primitive {
+        Primitive::Segment {
+            line,
+            start_cap: LineCap::Round,
+            end_cap: LineCap::Round,
+            start_width,
+            end_width,
+            ..
+        } if start_width == end_width => {
+            Some((line.clone(), start_width / 2.0 + glow))
+        }
+        Primitive::Disc { center, width } => {
+            Some((Line::new(*center, *center), width / 2.0 + glow))
+        }
+        _ => None,
+    }
+}
+
+/// Checks if [`Tiler::tile_intersects_exactly`] has an exact test for a
+/// primitive.
+fn has_exact_tile_test(primitive: &Primitive) -> bool {
+    match primitive {
+        Primitive::Arc { .. }
+        | Primitive::Ellipse(_)
+        | Primitive::Disc { .. } => true,
+        Primitive::Segment {
+            start_cap: LineCap::Round,
+            end_cap: LineCap::Round,
+            start_width,
+            end_width,
+            ..
+        } => start_width == end_width,
+        _ => false,
+    }
+}
+
 /// Compute the number of required tiles.
-fn n_tiles(area_width: u32, area_height: u32, tile_width: u32, tile_height: u32) -> (u32, u32) {
+fn n_tiles(
+    area_width: u32,
+    area_height: u32,
+    tile_width: u32,
+    tile_height: u32,
+) -> (u32, u32) {
     let n_x_tiles = ceil_div_u32(area_width, tile_width);
     let n_y_tiles = ceil_div_u32(area_height, tile_height);
     (n_x_tiles, n_y_tiles)
 }
 
 /// Information about a tile.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TileInfo {
     /// X (horizontal) coordinate of the tile.
     pub tile_x: u32,
@@ -266,9 +2180,38 @@ pub struct TileInfo {
     pub n_lines: u32,
 }
 
+/// Primitive to be assigned to tiles by binning.
+#[derive(Debug)]
+pub(crate) struct BinRecord {
+    /// Index of the primitive.
+    pub(crate) primitive: u32,
+    /// Tiles which the primitive may intersect, clipped to the area.
+    pub(crate) tiles: TilesIntersection,
+    /// Bounding polygons of the primitive, whose candidate separating axes
+    /// are found as they are binned. If there are none, the primitive is
+    /// placed in every tile of `tiles`.
+    pub(crate) polygons: Vec<Polygon>,
+    /// Capsule which contains the primitive, as its center segment and
+    /// radius, if it is tested exactly as a capsule (see
+    /// [`Tiler::tile_intersects_capsule`]). A tile must then also intersect
+    /// the capsule.
+    pub(crate) capsule: Option<(Line, f32)>,
+}
+
+/// Primitive whose assignment to tiles has been deferred.
+#[derive(Debug)]
+struct DeferredPrimitive {
+    /// Number of primitive-tile allocations which had been made when the
+    /// primitive was added.
+    position: usize,
+    /// Record for binning the primitive.
+    record: BinRecord,
+}
+
 /// Represents the intersection of something (usually a bounding box) with
 /// the tile indices.
-struct TilesIntersection {
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct TilesIntersection {
     min_x_tile: u32,
     max_x_tile: u32,
     min_y_tile: u32,
@@ -319,4 +2262,1087 @@ impl TilesIntersection {
     pub fn y_tiles(&self) -> RangeInclusive<u32> {
         self.min_y_tile..=self.max_y_tile
     }
+
+    /// Returns `true` if the intersection contains a tile.
+    pub fn contains(&self, tile_x: u32, tile_y: u32) -> bool {
+        self.x_tiles().contains(&tile_x) && self.y_tiles().contains(&tile_y)
+    }
+}
+
+/// Tiles within the bounding box of a path, with the winding number of the
+/// path at the reference point of each tile and the edges near it.
+#[derive(Debug, Clone)]
+struct PathTiles {
+    /// Tiles within the bounding box of the path, clipped to the area.
+    tiles: TilesIntersection,
+    /// Winding number and indices of the near edges of each tile, in row
+    /// order.
+    tile_edges: Vec<(i32, Vec<usize>)>,
+}
+impl PathTiles {
+    /// Returns the winding number and near edges of a tile, or `None` if the
+    /// tile is outside the bounding box of the path.
+    fn get(&self, tile_x: u32, tile_y: u32) -> Option<&(i32, Vec<usize>)> {
+        if !self.tiles.contains(tile_x, tile_y) {
+            return None;
+        }
+        Some(&self.tile_edges[self.local_ix(tile_x, tile_y)])
+    }
+
+    /// Returns the winding number and near edges of a tile, which must be
+    /// within the bounding box of the path.
+    fn get_mut(&mut self, tile_x: u32, tile_y: u32) -> &mut (i32, Vec<usize>) {
+        assert!(self.tiles.contains(tile_x, tile_y));
+        let ix = self.local_ix(tile_x, tile_y);
+        &mut self.tile_edges[ix]
+    }
+
+    /// Returns the index of a tile within the bounding box.
+    fn local_ix(&self, tile_x: u32, tile_y: u32) -> usize {
+        let n_x_tiles = self.tiles.x_tiles().count();
+        (tile_y - self.tiles.min_y_tile) as usize * n_x_tiles
+            + (tile_x - self.tiles.min_x_tile) as usize
+    }
+}
+
+/// Clip on the clip stack of a [`Tiler`].
+#[derive(Debug, Clone)]
+struct Clip {
+    /// Intersection of the clip rectangles up to and including this clip,
+    /// or `None` if there are none.
+    rect: Option<ClipRect>,
+    /// Clip path, or `None` if this clip is a rectangle.
+    path: Option<ClipPath>,
+}
+
+/// Clip path, in pixels.
+#[derive(Debug, Clone)]
+struct ClipPath {
+    /// Edges of the path.
+    edges: Vec<Line>,
+    /// Rule which decides which points are inside the path.
+    rule: FillRule,
+    /// Tiles of the path, or `None` if it is outside the area.
+    tiles: Option<PathTiles>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{style::FillRule, Glow, GlowFalloff, Marker, MarkerShape};
+    use cgmath::EuclideanSpace;
+    use proptest::prelude::*;
+
+    /// Returns the primitives of a tile, from the output of
+    /// [`Tiler::drain`].
+    fn tile_primitives<'a>(
+        info: &TileInfo,
+        primitives: &'a [StyledPrimitive],
+        indices: &[u32],
+    ) -> Vec<&'a StyledPrimitive> {
+        indices[info.start_index as usize
+            ..(info.start_index + info.n_lines) as usize]
+            .iter()
+            .map(|i| &primitives[*i as usize])
+            .collect()
+    }
+
+    /// Returns the number of tiles that a shape was assigned to.
+    fn n_tiles_used(tiler: &mut Tiler) -> usize {
+        let (tile_infos, _, _) = tiler.drain();
+        tile_infos.len()
+    }
+
+    /// Curves are split into pieces, so that they occupy far fewer tiles
+    /// than the box around their control points, but still occupy every
+    /// tile that they pass through.
+    #[test]
+    fn test_curve_tiles_are_tight() {
+        let curve = CubicBezier::new(
+            P2::new(10.0, 10.0),
+            P2::new(10.0, 300.0),
+            P2::new(300.0, 300.0),
+            P2::new(300.0, 10.0),
+        );
+        let style = LineStyle {
+            width: 4.0,
+            ..Default::default()
+        };
+
+        let mut tiler = Tiler::new(320, 320, 16, 16);
+        tiler.add_cubic(curve.clone(), &style);
+        let n_curve_tiles = n_tiles_used(&mut tiler);
+
+        let whole = StyledPrimitive {
+            primitive: Primitive::Cubic {
+                curve: curve.clone(),
+                start_cap: style.cap,
+                end_cap: style.cap,
+            },
+            style: style.clone(),
+            gradient: None,
+            shape: 0,
+            clip_rect: None,
+            layer: LayerId::DEFAULT,
+            layer_opacity: 1.0,
+        };
+
+        let bounding_polygon = whole.bounding_polygon();
+        let mut test_axes = bounding_polygon.edge_axes();
+        test_axes.push(V2::new(1.0, 0.0));
+        test_axes.push(V2::new(0.0, 1.0));
+        let n_box_tiles = (0..20)
+            .cartesian_product(0..20)
+            .filter(|(x, y)| {
+                tiler.tile_intersects_polygon(
+                    *x,
+                    *y,
+                    &test_axes,
+                    &bounding_polygon,
+                )
+            })
+            .count();
+        assert!(4 * n_curve_tiles < n_box_tiles);
+
+        // Every tile that contains a point of the curve must be used.
+        tiler.add_cubic(curve.clone(), &style);
+        let (tile_infos, _, _) = tiler.drain();
+        for i in 0..=1000 {
+            let p = curve.eval_param(i as f32 / 1000.0);
+            let (tile_x, tile_y) = ((p.x / 16.0) as u32, (p.y / 16.0) as u32);
+            assert!(
+                tile_infos
+                    .iter()
+                    .any(|info| info.tile_x == tile_x && info.tile_y == tile_y),
+                "Tile ({}, {}) is missing",
+                tile_x,
+                tile_y
+            );
+        }
+    }
+
+    /// Checks that every tile containing a sampled point of a stroke is
+    /// used.
+    fn assert_points_covered(
+        tiler: &mut Tiler,
+        points: impl Iterator<Item = P2>,
+    ) {
+        let (tile_infos, _, _) = tiler.drain();
+        for p in points {
+            let (tile_x, tile_y) = ((p.x / 16.0) as u32, (p.y / 16.0) as u32);
+            assert!(
+                tile_infos
+                    .iter()
+                    .any(|info| info.tile_x == tile_x && info.tile_y == tile_y),
+                "Tile ({}, {}) is missing",
+                tile_x,
+                tile_y
+            );
+        }
+    }
+
+    /// Tapered lines occupy every tile that their strokes and caps pass
+    /// through, including when one end is much wider than the other.
+    #[test]
+    fn test_tapered_tiles_cover_stroke() {
+        let mut tiler = Tiler::new(320, 320, 16, 16);
+        let lines = [
+            (P2::new(20.0, 30.0), P2::new(290.0, 120.0), 40.0, 2.0),
+            (P2::new(300.0, 300.0), P2::new(40.0, 200.0), 0.0, 30.0),
+            (P2::new(150.0, 150.0), P2::new(160.0, 162.0), 60.0, 8.0),
+        ];
+        for cap in [LineCap::Butt, LineCap::Round, LineCap::Square] {
+            for (a, b, start_width, end_width) in lines {
+                let style = LineStyle {
+                    width: start_width,
+                    end_width: Some(end_width),
+                    cap,
+                    ..Default::default()
+                };
+                tiler.add(StyledLine {
+                    line: Line::new(a, b),
+                    style,
+                });
+
+                // Points across the stroke, and around the ends.
+                let d = (b - a).normalize();
+                let n = V2::new(-d.y, d.x);
+                let points = (0..=100).flat_map(|i| {
+                    let s = i as f32 / 100.0;
+                    let hw = 0.99
+                        * (start_width + (end_width - start_width) * s)
+                        / 2.0;
+                    let p = a + (b - a) * s;
+                    [-hw, 0.0, hw].map(|offset| p + offset * n)
+                });
+                let end_points = [(a, -d, start_width), (b, d, end_width)]
+                    .into_iter()
+                    .flat_map(|(p, out, width)| {
+                        let hw = 0.99 * width / 2.0;
+                        let extension = match cap {
+                            LineCap::Butt => 0.0,
+                            _ => hw,
+                        };
+                        (0..=16).map(move |i| {
+                            let angle = i as f32 * std::f32::consts::PI / 16.0;
+                            let radial = angle.cos() * n + angle.sin() * out;
+                            match cap {
+                                LineCap::Round => p + hw * radial,
+                                _ => {
+                                    p + extension * out
+                                        + (2.0 * i as f32 / 16.0 - 1.0) * hw * n
+                                }
+                            }
+                        })
+                    });
+                let points: Vec<P2> = points.chain(end_points).collect();
+                assert_points_covered(&mut tiler, points.into_iter());
+            }
+        }
+    }
+
+    /// Adds a stroke to a tiler.
+    type AddStroke<'a> = Box<dyn Fn(&mut Tiler) + 'a>;
+
+    /// Markers occupy every tile that they pass through, on every kind of
+    /// stroke which can have them.
+    #[test]
+    fn test_marker_tiles_cover_markers() {
+        let shapes = [
+            MarkerShape::Arrow,
+            MarkerShape::OpenArrow,
+            MarkerShape::Circle,
+            MarkerShape::Diamond,
+            MarkerShape::Bar,
+        ];
+        let mut tiler = Tiler::new(320, 320, 16, 16);
+        for shape in shapes {
+            let style = LineStyle {
+                width: 6.0,
+                start_marker: Some(Marker::new(shape, 4.0)),
+                end_marker: Some(Marker::new(shape, 3.0)),
+                ..Default::default()
+            };
+            let (a, b) = (P2::new(60.0, 70.0), P2::new(250.0, 190.0));
+            let d = (b - a).normalize();
+            let strokes: [(AddStroke, [(P2, V2); 2]); 3] = [
+                (
+                    Box::new(|tiler: &mut Tiler| {
+                        tiler.add(StyledLine {
+                            line: Line::new(a, b),
+                            style: style.clone(),
+                        })
+                    }),
+                    [(a, -d), (b, d)],
+                ),
+                (
+                    Box::new(|tiler: &mut Tiler| {
+                        tiler.add_polyline(
+                            &Polyline::new(vec![a, P2::new(200.0, 40.0), b]),
+                            &style,
+                        )
+                    }),
+                    [
+                        (a, V2::new(-140.0, 30.0).normalize()),
+                        (b, V2::new(50.0, 150.0).normalize()),
+                    ],
+                ),
+                (
+                    Box::new(|tiler: &mut Tiler| {
+                        tiler.add_arc(
+                            CircularArc::new(
+                                P2::new(160.0, 160.0),
+                                100.0,
+                                0.0,
+                                2.0,
+                            ),
+                            &style,
+                        )
+                    }),
+                    [
+                        (P2::new(260.0, 160.0), V2::new(0.0, -1.0)),
+                        (
+                            P2::new(
+                                160.0 + 100.0 * 2.0f32.cos(),
+                                160.0 + 100.0 * 2.0f32.sin(),
+                            ),
+                            V2::new(-(2.0f32.sin()), 2.0f32.cos()),
+                        ),
+                    ],
+                ),
+            ];
+            for (add, ends) in strokes {
+                add(&mut tiler);
+                // Points just inside the extremes of each marker.
+                let points = ends.into_iter().zip([4.0, 3.0]).flat_map(
+                    |((tip, d), size)| {
+                        let n = V2::new(-d.y, d.x);
+                        let length = size * style.width;
+                        let l2 = length / 2.0;
+                        let back = tip - length * d;
+                        let extremes = match shape {
+                            MarkerShape::Arrow | MarkerShape::OpenArrow => {
+                                vec![tip, back + l2 * n, back - l2 * n]
+                            }
+                            MarkerShape::Bar => {
+                                vec![tip + l2 * n, tip - l2 * n]
+                            }
+                            _ => vec![
+                                tip + l2 * d,
+                                tip + l2 * n,
+                                tip - l2 * d,
+                                tip - l2 * n,
+                            ],
+                        };
+                        let center = P2::from_vec(
+                            extremes.iter().map(|p| p.to_vec()).sum::<V2>()
+                                / extremes.len() as f32,
+                        );
+                        extremes
+                            .into_iter()
+                            .map(move |p| p + 0.02 * (center - p))
+                    },
+                );
+                assert_points_covered(&mut tiler, points);
+            }
+        }
+    }
+
+    /// Strokes with a glow occupy every tile that their glow passes through.
+    #[test]
+    fn test_glow_tiles_cover_glow() {
+        let style = LineStyle {
+            width: 4.0,
+            cap: LineCap::Round,
+            glow: Some(Glow::new(20.0, GlowFalloff::Linear, 1.0)),
+            ..Default::default()
+        };
+        let reach = 0.99 * (style.width / 2.0 + style.glow_radius());
+        let mut tiler = Tiler::new(320, 320, 16, 16);
+
+        // Points around the centerline, as far away as the glow reaches.
+        let around = |centerline: Vec<P2>| {
+            centerline.into_iter().flat_map(move |p| {
+                (0..16).map(move |i| {
+                    let angle = i as f32 * std::f32::consts::PI / 8.0;
+                    p + reach * V2::new(angle.cos(), angle.sin())
+                })
+            })
+        };
+        let samples = |n: usize, f: &dyn Fn(f32) -> P2| {
+            (0..=n).map(|i| f(i as f32 / n as f32)).collect::<Vec<P2>>()
+        };
+
+        let (a, b) = (P2::new(40.0, 50.0), P2::new(270.0, 130.0));
+        tiler.add(StyledLine {
+            line: Line::new(a, b),
+            style: style.clone(),
+        });
+        assert_points_covered(
+            &mut tiler,
+            around(samples(100, &|t| a + t * (b - a))),
+        );
+
+        let arc = CircularArc::new(P2::new(160.0, 160.0), 90.0, 0.5, 4.0);
+        tiler.add_arc(arc.clone(), &style);
+        let on_arc =
+            |t: f32| arc.point_at_angle(arc.start_angle() + t * arc.sweep());
+        assert_points_covered(&mut tiler, around(samples(200, &on_arc)));
+
+        let ellipse = Ellipse::new(P2::new(160.0, 160.0), 100.0, 40.0, 0.6);
+        tiler.add_ellipse(ellipse.clone(), &style);
+        let (axis_x, axis_y) = ellipse.axes();
+        let on_ellipse = |t: f32| {
+            let angle = t * std::f32::consts::TAU;
+            ellipse.center()
+                + ellipse.radius_x() * angle.cos() * axis_x
+                + ellipse.radius_y() * angle.sin() * axis_y
+        };
+        assert_points_covered(&mut tiler, around(samples(200, &on_ellipse)));
+    }
+
+    /// Arcs and ellipses are tested exactly against tiles, so that they
+    /// occupy far fewer tiles than their bounding boxes, but still occupy
+    /// every tile that their strokes pass through.
+    #[test]
+    fn test_arc_and_ellipse_tiles_are_exact() {
+        let style = LineStyle {
+            width: 6.0,
+            cap: LineCap::Square,
+            ..Default::default()
+        };
+        let hw = style.width / 2.0;
+        let mut tiler = Tiler::new(320, 320, 16, 16);
+
+        let circle = CircularArc::circle(P2::new(160.0, 160.0), 130.0);
+        tiler.add_arc(circle.clone(), &style);
+        assert!(4 * n_tiles_used(&mut tiler) < 2 * 19 * 19);
+
+        // Points on both edges of the stroke and beyond the square caps.
+        let arc = CircularArc::new(P2::new(150.0, 170.0), 120.0, 0.5, 4.0);
+        tiler.add_arc(arc.clone(), &style);
+        let points = (0..=1000).flat_map(|i| {
+            let angle = arc.start_angle() + arc.sweep() * i as f32 / 1000.0;
+            let dir = V2::new(angle.cos(), angle.sin());
+            [-hw, 0.0, hw]
+                .map(|offset| arc.center() + (arc.radius() + offset) * dir)
+        });
+        let cap_points = [
+            (arc.start(), arc.start_angle(), -1.0),
+            (arc.end(), arc.end_angle(), 1.0),
+        ]
+        .into_iter()
+        .flat_map(|(p, angle, side)| {
+            let tangent = side * V2::new(-angle.sin(), angle.cos());
+            let normal = V2::new(angle.cos(), angle.sin());
+            [-hw, 0.0, hw]
+                .map(|offset| p + hw * 0.99 * tangent + offset * 0.99 * normal)
+        });
+        assert_points_covered(&mut tiler, points.chain(cap_points));
+
+        let ellipse = Ellipse::new(P2::new(160.0, 160.0), 140.0, 50.0, 0.6);
+        tiler.add_ellipse(ellipse.clone(), &style);
+        let n_ellipse_tiles = n_tiles_used(&mut tiler);
+        let whole = StyledPrimitive {
+            primitive: Primitive::Ellipse(ellipse.clone()),
+            style: style.clone(),
+            gradient: None,
+            shape: 0,
+            clip_rect: None,
+            layer: LayerId::DEFAULT,
+            layer_opacity: 1.0,
+        };
+        let bounding_polygon = whole.bounding_polygon();
+        let mut test_axes = bounding_polygon.edge_axes();
+        test_axes.push(V2::new(1.0, 0.0));
+        test_axes.push(V2::new(0.0, 1.0));
+        let n_box_tiles = (0..20)
+            .cartesian_product(0..20)
+            .filter(|(x, y)| {
+                tiler.tile_intersects_polygon(
+                    *x,
+                    *y,
+                    &test_axes,
+                    &bounding_polygon,
+                )
+            })
+            .count();
+        assert!(2 * n_ellipse_tiles < n_box_tiles);
+
+        tiler.add_ellipse(ellipse.clone(), &style);
+        let (axis_x, axis_y) = ellipse.axes();
+        let points = (0..=1000).flat_map(|i| {
+            let angle = i as f32 * std::f32::consts::TAU / 1000.0;
+            let on_ellipse = ellipse.center()
+                + ellipse.radius_x() * angle.cos() * axis_x
+                + ellipse.radius_y() * angle.sin() * axis_y;
+            // Normal of the ellipse at the point.
+            let normal = (angle.cos() / ellipse.radius_x() * axis_x
+                + angle.sin() / ellipse.radius_y() * axis_y)
+                .normalize();
+            [-hw, 0.0, hw].map(|offset| on_ellipse + 0.99 * offset * normal)
+        });
+        assert_points_covered(&mut tiler, points);
+    }
+
+    /// Lines with round caps, and round joins, are tested exactly against
+    /// tiles, so that they do not occupy the tiles beyond the corners of
+    /// their bounding polygons, but still occupy every tile that their
+    /// strokes pass through.
+    #[test]
+    fn test_round_caps_tiles_are_exact() {
+        let style = |cap| LineStyle {
+            width: 40.0,
+            cap,
+            ..Default::default()
+        };
+        let line = Line::new(P2::new(60.0, 60.0), P2::new(100.0, 100.0));
+        let mut tiler = Tiler::new(320, 320, 16, 16);
+        tiler.add(StyledLine {
+            line: line.clone(),
+            style: style(LineCap::Square),
+        });
+        let n_square_tiles = n_tiles_used(&mut tiler);
+        tiler.add(StyledLine {
+            line: line.clone(),
+            style: style(LineCap::Round),
+        });
+        let (tile_infos, _, _) = tiler.drain();
+        assert!(tile_infos.len() < n_square_tiles);
+
+        // Without the exact test, round caps occupy the tiles of square
+        // caps.
+        let mut inexact = Tiler::new(320, 320, 16, 16);
+        inexact.set_exact_tile_tests(false);
+        inexact.add(StyledLine {
+            line: line.clone(),
+            style: style(LineCap::Round),
+        });
+        assert_eq!(n_tiles_used(&mut inexact), n_square_tiles);
+
+        // A tile is occupied exactly when it is within the radius of the
+        // line, which is found by sampling the edges of the tile.
+        for (tile_x, tile_y) in (0..20).cartesian_product(0..20) {
+            let min = P2::new(tile_x as f32 * 16.0, tile_y as f32 * 16.0);
+            let distance = (0..=64)
+                .flat_map(|i| {
+                    let s = i as f32 * 0.25;
+                    [
+                        min + V2::new(s, 0.0),
+                        min + V2::new(0.0, s),
+                        min + V2::new(s, 16.0),
+                        min + V2::new(16.0, s),
+                    ]
+                })
+                .map(|p| segment_point_distance(&line, p))
+                .fold(f32::MAX, f32::min);
+            let occupied = tile_infos
+                .iter()
+                .any(|info| info.tile_x == tile_x && info.tile_y == tile_y);
+            if occupied {
+                assert!(
+                    distance <= 20.2,
+                    "({}, {}): {}",
+                    tile_x,
+                    tile_y,
+                    distance
+                );
+            } else {
+                assert!(
+                    distance > 20.0,
+                    "({}, {}): {}",
+                    tile_x,
+                    tile_y,
+                    distance
+                );
+            }
+        }
+
+        // Round joins are discs, which are also tested exactly.
+        let join_style = LineStyle {
+            width: 30.0,
+            join: LineJoin::Round,
+            ..Default::default()
+        };
+        let vertex = P2::new(160.0, 100.0);
+        let polyline = Polyline::new(vec![
+            P2::new(40.0, 200.0),
+            vertex,
+            P2::new(280.0, 200.0),
+        ]);
+        tiler.add_polyline(&polyline, &join_style);
+        let points = (0..360).map(|i| {
+            let angle = (i as f32).to_radians();
+            vertex + 14.9 * V2::new(angle.cos(), angle.sin())
+        });
+        assert_points_covered(&mut tiler, points);
+    }
+
+    /// In a dense scene of round-capped lines, as in the tile occupancy
+    /// benchmark, the exact tests place fewer primitives in tiles than the
+    /// bounding polygons alone.
+    #[test]
+    fn test_exact_tests_reduce_occupancy() {
+        let lines: Vec<StyledLine> = (0..400)
+            .map(|i| {
+                let (x, y) = ((i * 37 % 320) as f32, (i * 53 % 240) as f32);
+                let angle = i as f32 * 0.7;
+                let length = 4.0 + (i % 13) as f32 * 4.0;
+                StyledLine {
+                    line: Line::new(
+                        P2::new(x, y),
+                        P2::new(x, y)
+                            + length * V2::new(angle.cos(), angle.sin()),
+                    ),
+                    style: LineStyle {
+                        width: 4.0 + (i % 7) as f32 * 4.0,
+                        cap: LineCap::Round,
+                        ..Default::default()
+                    },
+                }
+            })
+            .collect();
+        let n_primitives = |exact| {
+            let mut tiler = Tiler::new(320, 240, 16, 16);
+            tiler.set_exact_tile_tests(exact);
+            for line in &lines {
+                tiler.add(line.clone());
+            }
+            let (_, _, line_indices) = tiler.drain();
+            line_indices.len()
+        };
+        let (exact, bounding) = (n_primitives(true), n_primitives(false));
+        assert!(exact < bounding, "{} >= {}", exact, bounding);
+    }
+
+    /// Only the tiles near the edges of a filled path receive its edges.
+    /// Tiles inside it receive a single fill primitive, and tiles outside
+    /// it receive nothing.
+    #[test]
+    fn test_fill_interior_tiles_are_solid() {
+        let mut tiler = Tiler::new(320, 320, 16, 16);
+        let path = Path::polygon(&[
+            P2::new(40.0, 40.0),
+            P2::new(280.0, 40.0),
+            P2::new(280.0, 280.0),
+            P2::new(40.0, 280.0),
+        ]);
+        tiler.add_fill(&path, &FillStyle::default());
+        let (tile_infos, primitives, indices) = tiler.drain();
+
+        for info in tile_infos.iter() {
+            let tile_primitives = tile_primitives(info, &primitives, &indices);
+            let Primitive::Fill { winding, .. } = tile_primitives[0].primitive
+            else {
+                panic!("Tile does not start with a fill");
+            };
+            assert!(tile_primitives[1..]
+                .iter()
+                .all(|p| matches!(p.primitive, Primitive::FillEdge(_))));
+
+            // The edges of the square are in tiles 2 and 17.
+            let on_edge = |t: u32| t == 2 || t == 17;
+            let inside = |t: u32| (2..=17).contains(&t);
+            assert!(inside(info.tile_x) && inside(info.tile_y));
+            let expect_edges = on_edge(info.tile_x) || on_edge(info.tile_y);
+            assert_eq!(info.n_lines > 1, expect_edges, "{:?}", info);
+            if !expect_edges {
+                assert_eq!(winding, 1);
+            }
+        }
+        assert_eq!(tile_infos.len(), 16 * 16);
+    }
+
+    #[test]
+    fn test_clips_cull_tiles() {
+        let mut tiler = Tiler::new(320, 320, 16, 16);
+        let square = |min: f32, max: f32| {
+            Path::polygon(&[
+                P2::new(min, min),
+                P2::new(max, min),
+                P2::new(max, max),
+                P2::new(min, max),
+            ])
+        };
+
+        // A clip rectangle culls the tiles outside it.
+        tiler.push_clip_rect(P2::new(80.0, 80.0), P2::new(159.0, 159.0));
+        tiler.add_fill(&square(0.0, 320.0), &FillStyle::default());
+        let (tile_infos, primitives, _) = tiler.drain();
+        assert_eq!(tile_infos.len(), 5 * 5);
+        for info in tile_infos.iter() {
+            assert!(
+                (5..=9).contains(&info.tile_x)
+                    && (5..=9).contains(&info.tile_y)
+            );
+        }
+        let clip_rect =
+            ClipRect::new(P2::new(80.0, 80.0), P2::new(159.0, 159.0));
+        assert!(primitives.iter().all(|p| p.clip_rect == Some(clip_rect)));
+
+        // A clip path inside it culls more tiles, and is drawn first in the
+        // tiles near its edges.
+        tiler.push_clip_path(&square(100.0, 140.0), FillRule::NonZero);
+        tiler.add_fill(&square(0.0, 320.0), &FillStyle::default());
+        tiler.pop_clip();
+        tiler.pop_clip();
+        let (tile_infos, primitives, indices) = tiler.drain();
+        assert_eq!(tile_infos.len(), 3 * 3);
+        for info in tile_infos.iter() {
+            assert!(
+                (6..=8).contains(&info.tile_x)
+                    && (6..=8).contains(&info.tile_y)
+            );
+            let tile_primitives = tile_primitives(info, &primitives, &indices);
+            let interior = info.tile_x == 7 && info.tile_y == 7;
+            let clipped =
+                matches!(tile_primitives[0].primitive, Primitive::Clip { .. });
+            assert_eq!(clipped, !interior, "{:?}", info);
+            assert!(matches!(
+                tile_primitives.last().unwrap().primitive,
+                Primitive::Fill { .. }
+            ));
+        }
+
+        // Shapes added after the clips are popped are not clipped.
+        tiler.add_fill(&square(0.0, 320.0), &FillStyle::default());
+        assert_eq!(n_tiles_used(&mut tiler), 20 * 20);
+    }
+
+    #[test]
+    fn test_drain_orders_layers() {
+        let mut tiler = Tiler::new(64, 64, 16, 16);
+        let overlay = tiler.add_layer("overlay", 1);
+        let background = tiler.add_layer("background", -1);
+        let hidden = tiler.add_layer("hidden", 0);
+        assert_eq!(tiler.layer_id("overlay"), Some(overlay));
+        assert_eq!(tiler.layer_id("default"), Some(LayerId::DEFAULT));
+        assert_eq!(tiler.layer_id("missing"), None);
+        tiler.layer_mut(overlay).opacity = 0.5;
+        tiler.layer_mut(hidden).visible = false;
+
+        let line = Line::new(P2::new(8.0, 8.0), P2::new(9.0, 8.0));
+        let style = |width| LineStyle {
+            width,
+            ..Default::default()
+        };
+        for (layer, width) in [
+            (overlay, 1.0),
+            (LayerId::DEFAULT, 2.0),
+            (hidden, 3.0),
+            (background, 4.0),
+            (overlay, 5.0),
+            (LayerId::DEFAULT, 6.0),
+        ] {
+            tiler.set_layer(layer);
+            tiler.add(StyledLine {
+                line: line.clone(),
+                style: style(width),
+            });
+        }
+        assert_eq!(tiler.current_layer(), LayerId::DEFAULT);
+
+        let (tile_infos, primitives, indices) = tiler.drain();
+        assert_eq!(tile_infos.len(), 1);
+        let primitives = tile_primitives(&tile_infos[0], &primitives, &indices);
+        let widths: Vec<f32> =
+            primitives.iter().map(|p| p.style.width).collect();
+        assert_eq!(widths, vec![4.0, 2.0, 6.0, 1.0, 5.0]);
+        let opacities: Vec<f32> =
+            primitives.iter().map(|p| p.layer_opacity).collect();
+        assert_eq!(opacities, vec![1.0, 1.0, 1.0, 0.5, 0.5]);
+    }
+
+    /// Adds shapes of every kind to a tiler: lines, including one with a
+    /// marker, curves, arcs, a fill, a clipped polyline, and shapes in
+    /// another layer and in a hidden layer.
+    fn add_mixed_shapes(tiler: &mut Tiler) {
+        let style = |width: f32| LineStyle {
+            width,
+            ..Default::default()
+        };
+        let hidden = tiler.add_layer("hidden", 0);
+        let overlay = tiler.add_layer("overlay", 1);
+        tiler.layer_mut(hidden).visible = false;
+        for i in 0..40 {
+            let f = i as f32;
+            let a = P2::new(
+                150.0 + 140.0 * (f * 1.7).sin(),
+                150.0 + 140.0 * (f * 2.3).cos(),
+            );
+            let b = P2::new(
+                150.0 + 140.0 * (f * 0.9).cos(),
+                150.0 + 140.0 * (f * 3.1).sin(),
+            );
+            tiler.set_layer([LayerId::DEFAULT, overlay, hidden][i % 3]);
+            tiler.add(StyledLine {
+                line: Line::new(a, b),
+                style: style(1.0 + f % 7.0),
+            });
+        }
+        tiler.set_layer(LayerId::DEFAULT);
+        tiler.add(StyledLine {
+            line: Line::new(P2::new(20.0, 280.0), P2::new(200.0, 250.0)),
+            style: LineStyle {
+                end_marker: Some(Marker::new(MarkerShape::Arrow, 3.0)),
+                ..style(2.0)
+            },
+        });
+        tiler.add_polyline(
+            &Polyline::new(vec![
+                P2::new(30.0, 40.0),
+                P2::new(120.0, 60.0),
+                P2::new(60.0, 140.0),
+            ]),
+            &LineStyle {
+                cap: LineCap::Round,
+                join: LineJoin::Round,
+                ..style(9.0)
+            },
+        );
+        tiler.add_cubic(
+            CubicBezier::new(
+                P2::new(10.0, 10.0),
+                P2::new(10.0, 290.0),
+                P2::new(290.0, 10.0),
+                P2::new(290.0, 290.0),
+            ),
+            &style(3.0),
+        );
+        tiler.add_arc(
+            CircularArc::new(P2::new(150.0, 150.0), 60.0, 0.5, 4.0),
+            &style(4.0),
+        );
+        let path = Path::polygon(&[
+            P2::new(100.0, 100.0),
+            P2::new(260.0, 120.0),
+            P2::new(180.0, 270.0),
+        ]);
+        tiler.add_fill(&path, &FillStyle::default());
+        tiler.push_clip_rect(P2::new(40.0, 40.0), P2::new(120.0, 200.0));
+        tiler.add_polyline(
+            &Polyline::new(vec![
+                P2::new(0.0, 0.0),
+                P2::new(150.0, 90.0),
+                P2::new(60.0, 250.0),
+            ]),
+            &style(5.0),
+        );
+        tiler.pop_clip();
+        tiler.set_layer(overlay);
+        tiler.add_quadratic(
+            QuadraticBezier::new(
+                P2::new(5.0, 150.0),
+                P2::new(150.0, 5.0),
+                P2::new(295.0, 150.0),
+            ),
+            &style(2.0),
+        );
+    }
+
+    /// Deferring binning, and then binning the records on the CPU, places
+    /// the same primitives in the same order as tiling them as they are
+    /// added.
+    #[test]
+    fn test_deferred_binning_matches_drain() {
+        let mut tiler = Tiler::new(300, 300, 16, 16);
+        add_mixed_shapes(&mut tiler);
+        let expected = format!("{:?}", tiler.drain());
+
+        let mut deferred = Tiler::new(300, 300, 16, 16);
+        deferred.set_defer_binning(true);
+        add_mixed_shapes(&mut deferred);
+        assert!(!deferred.deferred.is_empty());
+        let (primitives, records) = deferred.take_bin_records();
+        assert!(records.iter().any(|record| record.polygons.is_empty()));
+        assert!(records.iter().any(|record| record.capsule.is_some()));
+        assert_eq!(
+            format!("{:?}", deferred.bin_records(&primitives, &records)),
+            expected
+        );
+
+        // Draining a tiler with deferred primitives bins them.
+        let mut deferred = Tiler::new(300, 300, 16, 16);
+        deferred.set_defer_binning(true);
+        add_mixed_shapes(&mut deferred);
+        assert_eq!(format!("{:?}", deferred.drain()), expected);
+
+        // The deferred primitives are also tiled as they are when binning is
+        // not deferred, alongside the same records.
+        let mut deferred = Tiler::new(300, 300, 16, 16);
+        deferred.set_defer_binning(true);
+        add_mixed_shapes(&mut deferred);
+        let (bin_records, tiles) = deferred.take_bin_records_and_tiles();
+        assert_eq!(format!("{:?}", tiles), expected);
+        assert_eq!(
+            format!("{:?}", bin_records),
+            format!("{:?}", (primitives, records))
+        );
+        assert!(deferred.deferred.is_empty() && deferred.lines.is_empty());
+    }
+
+    /// A primitive is stored once, however many tiles it is in, and the
+    /// tiles of a fill share its primitives.
+    #[test]
+    fn test_primitives_are_stored_once() {
+        let mut tiler = Tiler::new(320, 320, 16, 16);
+        tiler.add(StyledLine {
+            line: Line::new(P2::new(10.0, 10.0), P2::new(310.0, 300.0)),
+            style: LineStyle {
+                width: 40.0,
+                ..Default::default()
+            },
+        });
+        let (tile_infos, primitives, indices) = tiler.drain();
+        assert_eq!(primitives.len(), 1);
+        assert!(tile_infos.len() > 20);
+        assert_eq!(indices.len(), tile_infos.len());
+        assert!(indices.iter().all(|i| *i == 0));
+
+        // All the interior tiles of a square share a single fill primitive,
+        // and the tiles along an edge share the primitive of the edge.
+        let path = Path::polygon(&[
+            P2::new(40.0, 40.0),
+            P2::new(280.0, 40.0),
+            P2::new(280.0, 280.0),
+            P2::new(40.0, 280.0),
+        ]);
+        tiler.add_fill(&path, &FillStyle::default());
+        let (tile_infos, primitives, indices) = tiler.drain();
+        assert_eq!(tile_infos.len(), 16 * 16);
+        let n_fills = primitives
+            .iter()
+            .filter(|p| matches!(p.primitive, Primitive::Fill { .. }))
+            .count();
+        let n_edges = primitives
+            .iter()
+            .filter(|p| matches!(p.primitive, Primitive::FillEdge(_)))
+            .count();
+        assert!(n_fills <= 2, "{}", n_fills);
+        assert_eq!(n_edges, 4);
+        // Each tile refers to a fill, and each of the 16 tiles along a side
+        // refers to the edge of that side.
+        assert_eq!(indices.len(), 16 * 16 + 4 * 16);
+    }
+
+    /// Returns a segment primitive for a line, as added by [`Tiler::add`]
+    /// for a style without markers.
+    fn segment(line: Line, style: LineStyle) -> StyledPrimitive {
+        StyledPrimitive {
+            primitive: Primitive::Segment {
+                line,
+                start_cap: style.cap,
+                end_cap: style.cap,
+                distance: 0.0,
+                start_width: style.width,
+                end_width: style.end_width.unwrap_or(style.width),
+            },
+            style,
+            gradient: None,
+            shape: 0,
+            clip_rect: None,
+            layer: LayerId::DEFAULT,
+            layer_opacity: 1.0,
+        }
+    }
+
+    /// Returns the linear indices of the tiles which a primitive is placed
+    /// in, by testing every tile of its bounding box, followed by the exact
+    /// test.
+    fn scanned_tiles(
+        tiler: &Tiler,
+        styled_primitive: &StyledPrimitive,
+    ) -> Vec<usize> {
+        let piece_length = tiler.tile_width.min(tiler.tile_height) as f32;
+        let polygons = with_separating_axes(
+            styled_primitive.bounding_polygons(piece_length),
+        );
+        let bbox = Bbox::including(
+            polygons
+                .iter()
+                .flat_map(|(polygon, _)| polygon.vertices().iter()),
+        )
+        .unwrap();
+        let Some(tiles) = TilesIntersection::from_bbox(
+            tiler.tile_width,
+            tiler.tile_height,
+            &bbox,
+        )
+        .clip_to_area(tiler.n_x_tiles, tiler.n_y_tiles) else {
+            return Vec::new();
+        };
+        let mut scanned = Vec::new();
+        for tile_y in tiles.y_tiles() {
+            for tile_x in tiles.x_tiles() {
+                if tiler.tile_intersects_any_polygon(tile_x, tile_y, &polygons)
+                    && tiler.tile_intersects_exactly(
+                        tile_x,
+                        tile_y,
+                        styled_primitive,
+                    )
+                {
+                    scanned.push(tiler.tile_ix(tile_x, tile_y));
+                }
+            }
+        }
+        scanned
+    }
+
+    /// Checks that walking along a segment places it in the same tiles, in
+    /// the same order, as testing every tile of its bounding box.
+    fn assert_walk_matches_scan(
+        tiler_size: (u32, u32, u32, u32),
+        line: Line,
+        style: LineStyle,
+    ) -> Result<(), TestCaseError> {
+        let (area_width, area_height, tile_width, tile_height) = tiler_size;
+        let mut tiler =
+            Tiler::new(area_width, area_height, tile_width, tile_height);
+        let styled_primitive = segment(line, style);
+        let scanned = scanned_tiles(&tiler, &styled_primitive);
+        tiler.add_primitive(styled_primitive);
+        let walked: Vec<usize> =
+            tiler.lines.iter().map(|(ix, _)| *ix).collect();
+        prop_assert_eq!(walked, scanned);
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(1000))]
+
+        /// Walking along a segment finds exactly the tiles that the
+        /// separating axis test finds over its whole bounding box.
+        #[test]
+        fn test_walked_tiles_match_scan(
+            a in (-100.0f32..420.0, -100.0f32..340.0),
+            b in (-100.0f32..420.0, -100.0f32..340.0),
+            width in 0.1f32..60.0,
+            end_width in proptest::option::of(0.0f32..60.0),
+            cap in prop_oneof![
+                Just(LineCap::Butt),
+                Just(LineCap::Round),
+                Just(LineCap::Square)
+            ],
+            glow in proptest::option::of(0.1f32..30.0),
+            tile_size in (1u32..40, 1u32..40),
+        ) {
+            let style = LineStyle {
+                width,
+                end_width,
+                cap,
+                glow: glow.map(|radius| {
+                    Glow::new(radius, GlowFalloff::Linear, 1.0)
+                }),
+                ..Default::default()
+            };
+            let line = Line::new(P2::new(a.0, a.1), P2::new(b.0, b.1));
+            assert_walk_matches_scan(
+                (320, 240, tile_size.0, tile_size.1),
+                line,
+                style,
+            )?;
+        }
+    }
+
+    /// Walking along axis-aligned lines, lines along tile edges and
+    /// degenerate lines finds the same tiles as the separating axis test,
+    /// and walking along a long diagonal line tests far fewer tiles than
+    /// its bounding box contains.
+    #[test]
+    fn test_walked_tiles_edge_cases() {
+        let style = |width: f32| LineStyle {
+            width,
+            ..Default::default()
+        };
+        let size = (320, 240, 16, 16);
+        for (a, b, width) in [
+            ((10.0, 32.0), (300.0, 32.0), 2.0),
+            ((10.0, 31.0), (300.0, 31.0), 2.0),
+            ((48.0, 5.0), (48.0, 230.0), 4.0),
+            ((0.0, 0.0), (240.0, 240.0), 1.0),
+            ((320.0, 0.0), (80.0, 240.0), 16.0),
+            ((-50.0, 100.0), (500.0, 100.0), 3.0),
+            ((100.0, 100.0), (100.0, 100.0), 3.0),
+        ] {
+            let line = Line::new(P2::new(a.0, a.1), P2::new(b.0, b.1));
+            assert_walk_matches_scan(size, line, style(width)).unwrap();
+        }
+
+        let tiler = Tiler::new(1920, 1080, 16, 16);
+        let line = Line::new(P2::new(0.0, 0.0), P2::new(1920.0, 1080.0));
+        let polygons =
+            with_separating_axes(vec![segment(line.clone(), style(2.0))
+                .bounding_polygons(16.0)
+                .remove(0)]);
+        let bbox = Bbox::including([line.start(), line.end()].iter()).unwrap();
+        let tiles = TilesIntersection::from_bbox(16, 16, &bbox)
+            .clip_to_area(tiler.n_x_tiles, tiler.n_y_tiles)
+            .unwrap();
+        let spans = tiler.segment_tile_spans(&line, &polygons, &tiles).unwrap();
+        let n_walked: usize = spans
+            .iter()
+            .map(|(_, x_tiles)| x_tiles.clone().count())
+            .sum();
+        assert!(n_walked * 10 < tiler.tile_count(), "{}", n_walked);
+    }
 }
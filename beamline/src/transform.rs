@@ -0,0 +1,507 @@
+//! Affine transforms of the coordinates of shapes.
+//!
+//! Shapes are mapped through the current transform as they are added to the
+//! [`crate::tiler::Tiler`], before they are assigned to tiles. The current
+//! transform is the view of the [`Camera`], if there is one, applied after
+//! the product of a [`TransformStack`] of model transforms.
+//!
+//! Transforms are [`M3`] matrices acting on homogeneous 2D coordinates, so
+//! that the last row of every transform must be `(0, 0, 1)`.
+
+use crate::{types::v2_rot90_anticlockwise, M3, P2, V2};
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix2, Rad, SquareMatrix, Transform,
+};
+
+/// Relative tolerance within which a transform is treated as a similarity
+/// (see [`similarity`]).
+const SIMILARITY_TOLERANCE: f32 = 1.0e-5;
+
+/// Shapes which can be mapped through an affine transform.
+pub trait Transformable {
+    /// Returns a copy of the shape, mapped through an affine transform.
+    ///
+    /// # Parameters
+    ///
+    /// - `matrix`: Affine transform to apply.
+    fn transformed(&self, matrix: &M3) -> Self;
+}
+
+/// Camera: a view of a 2D world, which can be panned, zoomed and rotated.
+///
+/// The camera maps world coordinates to pixels, so that its center is shown
+/// at the center of the renderable area (see [`Camera::view_matrix`]).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camera {
+    /// Point of the world which is shown at the center of the renderable
+    /// area.
+    pub center: P2,
+    /// Number of pixels per unit of the world.
+    pub zoom: f32,
+    /// Angle, in radians, by which the world is rotated anti-clockwise about
+    /// the center.
+    pub rotation: f32,
+}
+impl Camera {
+    /// Creates a new camera.
+    ///
+    /// # Parameters
+    ///
+    /// - `center`: Point of the world which is shown at the center of the
+    ///   renderable area.
+    /// - `zoom`: Number of pixels per unit of the world. This must be
+    ///   positive.
+    /// - `rotation`: Angle, in radians, by which the world is rotated
+    ///   anti-clockwise about the center.
+    ///
+    /// # Returns
+    ///
+    /// A new camera.
+    pub fn new(center: P2, zoom: f32, rotation: f32) -> Self {
+        assert!(zoom > 0.0 && zoom.is_finite());
+        assert!(rotation.is_finite());
+        Camera {
+            center,
+            zoom,
+            rotation,
+        }
+    }
+
+    /// Returns the transform from world coordinates to pixels.
+    ///
+    /// # Parameters
+    ///
+    /// - `area_width`: Width of the renderable area.
+    /// - `area_height`: Height of the renderable area.
+    pub fn view_matrix(&self, area_width: u32, area_height: u32) -> M3 {
+        let area_center = V2::new(area_width as f32, area_height as f32) / 2.0;
+        M3::from_translation(area_center)
+            * M3::from_angle_z(Rad(self.rotation))
+            * M3::from_scale(self.zoom)
+            * M3::from_translation(-self.center.to_vec())
+    }
+
+    /// Returns the point of the world which is shown at a pixel position.
+    ///
+    /// This is convenient for interpreting the position of a pointer.
+    ///
+    /// # Parameters
+    ///
+    /// - `p`: Pixel position.
+    /// - `area_width`: Width of the renderable area.
+    /// - `area_height`: Height of the renderable area.
+    pub fn screen_to_world(
+        &self,
+        p: P2,
+        area_width: u32,
+        area_height: u32,
+    ) -> P2 {
+        let inverse = self
+            .view_matrix(area_width, area_height)
+            .invert()
+            .expect("camera zoom is positive");
+        inverse.transform_point(p)
+    }
+
+    /// Pans the camera by a distance in pixels, so that the world appears
+    /// to move by that distance.
+    ///
+    /// # Parameters
+    ///
+    /// - `delta`: Distance to move the world on screen, in pixels.
+    pub fn pan(&mut self, delta: V2) {
+        let world_delta = Matrix2::from_angle(Rad(-self.rotation)) * delta;
+        self.center -= world_delta / self.zoom;
+    }
+
+    /// Multiplies the zoom, keeping the point of the world under a pixel
+    /// position fixed.
+    ///
+    /// This is convenient for zooming towards a pointer.
+    ///
+    /// # Parameters
+    ///
+    /// - `p`: Pixel position which stays fixed.
+    /// - `factor`: Factor by which to multiply the zoom. This must be
+    ///   positive.
+    /// - `area_width`: Width of the renderable area.
+    /// - `area_height`: Height of the renderable area.
+    pub fn zoom_about(
+        &mut self,
+        p: P2,
+        factor: f32,
+        area_width: u32,
+        area_height: u32,
+    ) {
+        assert!(factor > 0.0 && factor.is_finite());
+        let anchor = self.screen_to_world(p, area_width, area_height);
+        self.zoom *= factor;
+        self.center = anchor + (self.center - anchor) / factor;
+    }
+}
+
+/// Stack of model transforms.
+///
+/// Each transform pushed onto the stack is applied to shapes before the
+/// transforms beneath it, so that a transform pushed for a group of shapes
+/// places them within the coordinates of the enclosing group.
+#[derive(Debug, Clone)]
+pub struct TransformStack {
+    /// Product of all the transforms on the stack.
+    current: M3,
+    /// Products before each transform on the stack was pushed.
+    saved: Vec<M3>,
+}
+impl TransformStack {
+    /// Creates a new empty stack, whose product is the identity.
+    pub fn new() -> Self {
+        TransformStack {
+            current: M3::identity(),
+            saved: Vec::new(),
+        }
+    }
+
+    /// Pushes a transform onto the stack.
+    ///
+    /// # Parameters
+    ///
+    /// - `matrix`: Affine transform to push. Its last row must be
+    ///   `(0, 0, 1)`, and it must be invertible.
+    pub fn push(&mut self, matrix: M3) {
+        assert!(matrix.x.z == 0.0 && matrix.y.z == 0.0 && matrix.z.z == 1.0);
+        let determinant = linear_part(&matrix).determinant();
+        assert!(determinant != 0.0 && determinant.is_finite());
+        self.saved.push(self.current);
+        self.current = self.current * matrix;
+    }
+
+    /// Pops the transform which was pushed last.
+    ///
+    /// The stack must not be empty.
+    pub fn pop(&mut self) {
+        self.current = self.saved.pop().expect("transform stack is empty");
+    }
+
+    /// Returns the number of transforms on the stack.
+    pub fn depth(&self) -> usize {
+        self.saved.len()
+    }
+
+    /// Returns the product of all the transforms on the stack.
+    pub fn current(&self) -> M3 {
+        self.current
+    }
+}
+impl Default for TransformStack {
+    fn default() -> Self {
+        TransformStack::new()
+    }
+}
+
+/// Returns the factor by which a transform scales the widths of strokes.
+///
+/// This is the square root of the factor by which the transform scales
+/// areas, so that strokes are widened uniformly even when the transform
+/// stretches them more in one direction than another.
+///
+/// # Parameters
+///
+/// - `matrix`: Affine transform.
+pub fn stroke_scale(matrix: &M3) -> f32 {
+    linear_part(matrix).determinant().abs().sqrt()
+}
+
+/// Returns the linear part of an affine transform, without its translation.
+pub(crate) fn linear_part(matrix: &M3) -> Matrix2<f32> {
+    Matrix2::new(matrix.x.x, matrix.x.y, matrix.y.x, matrix.y.y)
+}
+
+/// Returns the scale and rotation of a transform, if it is a similarity: a
+/// uniform scale and a rotation, without reflection.
+///
+/// Similarities map circles to circles, keeping their direction.
+///
+/// # Parameters
+///
+/// - `matrix`: Affine transform.
+///
+/// # Returns
+///
+/// The scale factor and the anti-clockwise rotation angle, or `None` if the
+/// transform is not a similarity.
+pub(crate) fn similarity(matrix: &M3) -> Option<(f32, f32)> {
+    let x_axis = V2::new(matrix.x.x, matrix.x.y);
+    let y_axis = V2::new(matrix.y.x, matrix.y.y);
+    let scale = x_axis.magnitude();
+    let error = (y_axis - v2_rot90_anticlockwise(x_axis)).magnitude();
+    (error <= SIMILARITY_TOLERANCE * scale)
+        .then(|| (scale, x_axis.y.atan2(x_axis.x)))
+}
+
+/// Returns the ellipse which is the image of the unit circle under a linear
+/// map.
+///
+/// This is the singular value decomposition of the map, computed in closed
+/// form.
+///
+/// # Parameters
+///
+/// - `a`: Linear map.
+///
+/// # Returns
+///
+/// The angle of the first axis of the ellipse, anti-clockwise from the x
+/// axis, followed by the radii along its first and second axes. The first
+/// radius is the larger.
+pub(crate) fn unit_circle_image(a: Matrix2<f32>) -> (f32, f32, f32) {
+    let e = (a.x.x + a.y.y) / 2.0;
+    let f = (a.x.x - a.y.y) / 2.0;
+    let g = (a.x.y + a.y.x) / 2.0;
+    let h = (a.x.y - a.y.x) / 2.0;
+    let q = e.hypot(h);
+    let r = f.hypot(g);
+    let angle = (g.atan2(f) + h.atan2(e)) / 2.0;
+    (angle, q + r, (q - r).abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        CircularArc, DashPattern, Ellipse, FillStyle, Line, LineCap, LineStyle,
+        Path, SoftwareRenderer, StrokeScaling,
+    };
+    use cgmath::{Rad, SquareMatrix};
+    use std::{
+        f32::consts::{FRAC_PI_2, PI},
+        ops::Range,
+    };
+
+    #[test]
+    fn test_camera() {
+        let mut camera = Camera::new(P2::new(10.0, 20.0), 2.0, FRAC_PI_2);
+        let view = camera.view_matrix(100, 60);
+        let center = view.transform_point(P2::new(10.0, 20.0));
+        assert!((center - P2::new(50.0, 30.0)).magnitude() < 1e-4);
+        let right = view.transform_point(P2::new(11.0, 20.0));
+        assert!((right - P2::new(50.0, 32.0)).magnitude() < 1e-4);
+
+        let p = P2::new(80.0, 10.0);
+        let world = camera.screen_to_world(p, 100, 60);
+        assert!((view.transform_point(world) - p).magnitude() < 1e-4);
+
+        camera.zoom_about(p, 3.0, 100, 60);
+        assert_eq!(camera.zoom, 6.0);
+        let zoomed = camera.view_matrix(100, 60).transform_point(world);
+        assert!((zoomed - p).magnitude() < 1e-3);
+
+        camera.pan(V2::new(5.0, -4.0));
+        let panned = camera.view_matrix(100, 60).transform_point(world);
+        assert!((panned - (p + V2::new(5.0, -4.0))).magnitude() < 1e-3);
+    }
+
+    #[test]
+    fn test_transform_stack() {
+        let mut stack = TransformStack::new();
+        stack.push(M3::from_translation(V2::new(10.0, 0.0)));
+        stack.push(M3::from_scale(2.0));
+        assert_eq!(stack.depth(), 2);
+        let p = stack.current().transform_point(P2::new(1.0, 1.0));
+        assert_eq!(p, P2::new(12.0, 2.0));
+        stack.pop();
+        let p = stack.current().transform_point(P2::new(1.0, 1.0));
+        assert_eq!(p, P2::new(11.0, 1.0));
+        stack.pop();
+        assert_eq!(stack.current(), M3::identity());
+    }
+
+    #[test]
+    fn test_similarity_and_unit_circle_image() {
+        let rotation = M3::from_angle_z(Rad(0.5));
+        let (scale, angle) =
+            similarity(&(rotation * M3::from_scale(3.0))).unwrap();
+        assert!((scale - 3.0).abs() < 1e-5);
+        assert!((angle - 0.5).abs() < 1e-5);
+        assert!(similarity(&M3::from_nonuniform_scale(1.0, -1.0)).is_none());
+        assert!(similarity(&M3::from_nonuniform_scale(2.0, 1.0)).is_none());
+
+        let stretch = rotation * M3::from_nonuniform_scale(2.0, 0.5);
+        let (angle, radius_x, radius_y) =
+            unit_circle_image(linear_part(&stretch));
+        assert!((angle - 0.5).abs() < 1e-5);
+        assert!((radius_x - 2.0).abs() < 1e-5);
+        assert!((radius_y - 0.5).abs() < 1e-5);
+        assert!((stroke_scale(&stretch) - 1.0).abs() < 1e-5);
+    }
+
+    /// Shapes drawn with a camera and a pushed transform land where the
+    /// transforms map them, with their widths scaled unless they are
+    /// non-scaling.
+    #[test]
+    fn test_transform_places_and_scales_shapes() {
+        // Tolerance for widths and lengths measured from the coverage of a
+        // row or column of pixels.
+        const TOLERANCE: f32 = 0.1;
+
+        let mut renderer = SoftwareRenderer::new(
+            wgpu::TextureFormat::Rgba8Unorm,
+            150,
+            100,
+            16,
+            16,
+        );
+        // The camera maps a world point `(x, y)` to the pixel position
+        // `(55 + 2x, 50 + 2y)`, and the pushed transform maps a point
+        // `(x, y)` to the world point `(15 - y, x)`. Together they map
+        // `(x, y)` to `(85 - 2y, 50 + 2x)`, doubling widths.
+        renderer.set_camera(Some(Camera::new(P2::new(10.0, 0.0), 2.0, 0.0)));
+        renderer.push_transform(
+            M3::from_translation(V2::new(15.0, 0.0))
+                * M3::from_angle_z(Rad(FRAC_PI_2)),
+        );
+        // Vertical line at x = 85, from y = 30 to 70, 6 pixels wide.
+        renderer.line(
+            Line::new(P2::new(-10.0, 0.0), P2::new(10.0, 0.0)),
+            &LineStyle {
+                width: 3.0,
+                ..Default::default()
+            },
+        );
+        // Vertical line at x = 105, from y = 30 to 70, 2 pixels wide, with
+        // round caps.
+        renderer.line(
+            Line::new(P2::new(-10.0, -10.0), P2::new(10.0, -10.0)),
+            &LineStyle {
+                width: 2.0,
+                cap: LineCap::Round,
+                stroke_scaling: StrokeScaling::NonScaling,
+                ..Default::default()
+            },
+        );
+        // Vertical line at x = 96, 2 pixels wide, dashed from y = 30 to 62
+        // with dashes 6 pixels long and gaps of 2 pixels.
+        renderer.line(
+            Line::new(P2::new(-10.0, -5.5), P2::new(6.0, -5.5)),
+            &LineStyle {
+                dash: Some(DashPattern::new(&[3.0, 1.0], 0.0)),
+                ..Default::default()
+            },
+        );
+        // Circle around (35, 50), with a radius of 10 and 2 pixels wide.
+        renderer.arc(
+            CircularArc::circle(P2::new(0.0, 25.0), 5.0),
+            &LineStyle::default(),
+        );
+        // Ellipse around (135, 50), with a radius of 6 along x and of 12
+        // along y, and 2 pixels wide.
+        renderer.ellipse(
+            Ellipse::new(P2::new(0.0, -25.0), 6.0, 3.0, 0.0),
+            &LineStyle::default(),
+        );
+        // Rectangle from (75, 76) to (95, 86).
+        renderer.fill(
+            &Path::polygon(&[
+                P2::new(13.0, -5.0),
+                P2::new(18.0, -5.0),
+                P2::new(18.0, 5.0),
+                P2::new(13.0, 5.0),
+            ]),
+            &FillStyle::default(),
+        );
+        let image = renderer.render();
+
+        // Coverage of a pixel, whose rows count upwards from the bottom.
+        let alpha = |x: u32, y: u32| image.pixel(x, 99 - y)[3];
+        // Area covered within a rectangle of pixels.
+        let area = |xs: Range<u32>, ys: Range<u32>| -> f32 {
+            let pixels = xs.flat_map(|x| ys.clone().map(move |y| (x, y)));
+            pixels.map(|(x, y)| alpha(x, y) as f32 / 255.0).sum()
+        };
+        // Sums of the coverage of the pixels in a row or a column.
+        let row = |y: u32, x0: u32, x1: u32| area(x0..x1, y..y + 1);
+        let column = |x: u32, y0: u32, y1: u32| area(x..x + 1, y0..y1);
+        let assert_near = |measured: f32, expected: f32| {
+            assert!(
+                (measured - expected).abs() < TOLERANCE,
+                "measured {}, expected {}",
+                measured,
+                expected
+            );
+        };
+        // Scaling line.
+        assert_eq!(alpha(83, 50), 255);
+        assert_eq!(alpha(86, 50), 255);
+        assert_eq!(alpha(80, 50), 0);
+        assert_eq!(alpha(89, 50), 0);
+        assert_near(row(50, 76, 94), 6.0);
+        assert_near(column(85, 20, 74), 40.0);
+        // Non-scaling line.
+        assert_eq!(alpha(102, 50), 0);
+        assert_eq!(alpha(107, 50), 0);
+        assert_near(row(50, 100, 110), 2.0);
+        // The antialiased curved edge of a round cap covers a little more
+        // than the half disc.
+        let cap = area(100..110, 66..80) - 8.0;
+        assert!((cap - PI / 2.0).abs() < 0.25, "cap covers {}", cap);
+        // Dashed line, whose dashes and gaps are scaled.
+        for start in [30, 38, 46, 54] {
+            assert_near(row(start + 3, 90, 100), 2.0);
+            assert!(row(start + 6, 90, 100) < 0.25);
+            assert!(row(start + 7, 90, 100) < 0.25);
+        }
+        assert_eq!(row(64, 90, 100), 0.0);
+        // Circle.
+        assert_eq!(alpha(35, 50), 0);
+        assert_near(row(50, 20, 30), 2.0);
+        assert_near(row(50, 40, 50), 2.0);
+        assert_near(column(35, 35, 45), 2.0);
+        // Ellipse.
+        assert_eq!(alpha(135, 50), 0);
+        assert_near(row(50, 125, 133), 2.0);
+        assert_near(row(50, 137, 145), 2.0);
+        assert_near(column(135, 33, 43), 2.0);
+        assert_near(column(135, 57, 67), 2.0);
+        // Fill.
+        assert_eq!(image.pixel(85, 99 - 81), [255, 255, 255, 255]);
+        assert_near(row(81, 70, 100), 20.0);
+        assert_near(column(90, 72, 90), 10.0);
+
+        renderer.pop_transform();
+        renderer.set_camera(None);
+        assert_eq!(renderer.transform(), M3::identity());
+    }
+
+    /// Under a transform which is not a similarity, a circle is drawn as the
+    /// ellipse that it is mapped to.
+    #[test]
+    fn test_stretched_circle_matches_ellipse() {
+        let style = LineStyle {
+            width: 4.0,
+            stroke_scaling: StrokeScaling::NonScaling,
+            ..Default::default()
+        };
+        let new_renderer = || {
+            SoftwareRenderer::new(
+                wgpu::TextureFormat::Rgba8Unorm,
+                150,
+                100,
+                16,
+                16,
+            )
+        };
+        let mut stretched = new_renderer();
+        stretched.push_transform(
+            M3::from_translation(V2::new(75.0, 50.0))
+                * M3::from_nonuniform_scale(2.0, 1.0),
+        );
+        stretched.arc(CircularArc::circle(P2::new(0.0, 0.0), 30.0), &style);
+        let mut ellipse = new_renderer();
+        ellipse.ellipse(
+            Ellipse::new(P2::new(75.0, 50.0), 60.0, 30.0, 0.0),
+            &style,
+        );
+
+        let difference = stretched.render().max_difference(&ellipse.render());
+        assert_eq!(difference, 0);
+    }
+}